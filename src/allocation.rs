@@ -0,0 +1,398 @@
+/*
+    Determines which sectors of a volume are in use: the volume recognition sequence, anchors,
+    volume descriptor sequences and integrity sequence, the metadata files, and everything
+    reachable from the file set, i.e. its descriptor, all ICBs and the recorded extents of
+    files and directories.
+
+    What is in use is also compared with the space bitmaps and tables of the partitions,
+    which record the blocks a writer considers allocated.
+*/
+
+use std::error::Error;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
+
+use log::warn;
+use nom_derive::Parse;
+
+use crate::file::{AllocDesc, AllocType, LongAD, ICB, PHD, SBD};
+use crate::partition::PartitionKind;
+use crate::stats::IoCategory;
+use crate::volume::{LSN, PD};
+use crate::{check_tag_crc, read_avd, read_avd_at, BLOCKSIZE, UDF};
+
+/// sectors of `sector_size` bytes spanned by `len` bytes starting at sector `start`
+fn sectors(start: LSN, len: u64, sector_size: u64) -> Range<LSN> {
+    start..start.saturating_add(len.div_ceil(sector_size) as LSN)
+}
+
+/// the sector block `lbn` of a partition starting at sector `part_start` begins in, `None`
+/// if it can't be addressed
+pub(crate) fn part_sector(part_start: LSN, lbn: u32, block_sectors: u32) -> Option<LSN> {
+    lbn.checked_mul(block_sectors)
+        .and_then(|sector| part_start.checked_add(sector))
+}
+
+/// sorts `ranges` and merges overlapping and adjacent ones
+pub fn merge_ranges(mut ranges: Vec<Range<LSN>>) -> Vec<Range<LSN>> {
+    ranges.retain(|r| !r.is_empty());
+    ranges.sort_by_key(|r| r.start);
+    let mut merged: Vec<Range<LSN>> = Vec::with_capacity(ranges.len());
+    for r in ranges {
+        match merged.last_mut() {
+            Some(last) if r.start <= last.end => last.end = last.end.max(r.end),
+            _ => merged.push(r),
+        }
+    }
+    merged
+}
+
+/// the parts of the sorted non-overlapping ranges `a` not covered by those of `b`
+fn subtract_ranges(a: &[Range<u32>], b: &[Range<u32>]) -> Vec<Range<u32>> {
+    let mut result = Vec::new();
+    let mut rest = b;
+    for r in a {
+        let mut start = r.start;
+        while let Some(cut) = rest.first() {
+            if cut.end <= start {
+                rest = &rest[1..];
+                continue;
+            }
+            if cut.start >= r.end {
+                break;
+            }
+            if cut.start > start {
+                result.push(start..cut.start);
+            }
+            start = start.max(cut.end);
+            if cut.end > r.end {
+                break;
+            }
+            rest = &rest[1..];
+        }
+        if start < r.end {
+            result.push(start..r.end);
+        }
+    }
+    result
+}
+
+/// blocks of a partition recording which of its blocks are free
+struct SpaceDesc {
+    blocks: Range<u32>,
+    is_bitmap: bool,
+}
+
+/// blocks of a partition whose allocation state disagrees with what refers to them
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OrphanBlocks {
+    pub part_num: u16,
+    /// blocks marked as allocated that nothing refers to, e.g. remnants of deleted files
+    pub unreferenced: Vec<Range<u32>>,
+    /// blocks in use that are marked as free, which a writer would hand out again
+    pub unallocated: Vec<Range<u32>>,
+}
+
+/// blocks of a partition holding nothing but remnants of deleted data
+#[cfg(feature = "writer")]
+pub(crate) struct UnusedBlocks {
+    pub part_start: LSN,
+    pub blocks: Vec<Range<u32>>,
+}
+
+impl<IO: Read + Seek> UDF<IO> {
+    /// sectors holding volume structures or metadata and data reachable from the current
+    /// file set, as sorted non-overlapping ranges
+    pub fn allocated_sectors(&mut self) -> Result<Vec<Range<LSN>>, Box<dyn Error>> {
+        self.referenced_sectors(false)
+    }
+
+    /// like `allocated_sectors`, with `unrecorded` also including extents that are allocated
+    /// but not recorded
+    fn referenced_sectors(&mut self, unrecorded: bool) -> Result<Vec<Range<LSN>>, Box<dyn Error>> {
+        let start = self.options.session_start;
+        let bs = self.sector_size();
+        let lbs = self.block_size();
+        let block_sectors = self.block_sectors();
+        let mut ranges = Vec::new();
+
+        // the recognition sequence starts 32768 bytes into the session, its descriptors are
+        // 2048 bytes long but start on a sector boundary each
+        let vrs_start = ((start as u64 * bs + 16 * BLOCKSIZE) / bs) as LSN;
+        let vrs = self.recognition_sequence()?;
+        let vrs_end = vrs
+            .descriptors
+            .last()
+            .map_or(vrs_start, |&(lsn, _)| sectors(lsn, BLOCKSIZE, bs).end);
+        ranges.push(vrs_start..vrs_end);
+
+        let num_sectors = (self.io.seek(SeekFrom::End(0))? / bs) as LSN;
+        let anchors = [
+            Some(start + 256),
+            num_sectors.checked_sub(256),
+            num_sectors.checked_sub(1),
+        ];
+        for lsn in anchors.into_iter().flatten() {
+            if read_avd_at(&mut self.io, lsn, &self.options).is_ok() {
+                ranges.push(lsn..lsn + 1);
+            }
+        }
+        let avd = read_avd(&mut self.io, &self.options)?;
+        for vds in [&avd.main_vds, &avd.reserve_vds] {
+            ranges.push(sectors(vds.loc, vds.len as u64, bs));
+        }
+        let lvis = &self.logical_vol_desc.integr_seq_ext;
+        ranges.push(sectors(lvis.loc, lvis.len as u64, bs));
+
+        // the metadata file, its mirror and its bitmap, recorded in the physical partition
+        for map in self.part_maps.clone() {
+            let (PartitionKind::Metadata(meta), Some(pd)) = (&map.kind, &map.pd) else {
+                continue;
+            };
+            for loc in [meta.meta_file_loc, meta.meta_mirror_loc, meta.meta_bmp_loc] {
+                if loc == u32::MAX {
+                    continue;
+                }
+                let Some(lsn) = part_sector(pd.part_start, loc, block_sectors) else {
+                    warn!("metadata file at block {} can't be addressed, skipped", loc);
+                    continue;
+                };
+                let mut buf = vec![0; lbs as usize];
+                self.read_bytes(IoCategory::Metadata, lsn as u64 * bs, &mut buf)?;
+                let icb = ICB::parse(&buf)
+                    .or(Err("error parsing metadata file ICB"))?
+                    .1;
+                ranges.push(sectors(lsn, lbs, bs));
+                for ad in icb.get_alloc_descs() {
+                    if ad.ext_type() < 2 {
+                        let lbn = ad.lb_addr(map.part_ref).lbn;
+                        match part_sector(pd.part_start, lbn, block_sectors) {
+                            Some(start) => ranges.push(sectors(start, ad.len() as u64, bs)),
+                            None => warn!("metadata extent at block {} can't be addressed", lbn),
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(vat) = self.vat.clone() {
+            let icb = self.read_icb(&vat.icb_loc)?;
+            ranges.push(sectors(self.lb_to_sector(&vat.icb_loc)?, lbs, bs));
+            for ad in icb.get_alloc_descs() {
+                if ad.ext_type() == 0 && !ad.is_empty() {
+                    let (offset, len) =
+                        self.alloc_desc_to_offset_len(&ad, vat.icb_loc.part_ref_nr)?;
+                    ranges.push(sectors((offset / bs) as LSN, len as u64, bs));
+                }
+            }
+        }
+
+        let fsd_ext = LongAD::parse_le(&self.logical_vol_desc.lv_contents_use)
+            .or(Err("error parsing FSD pointer."))?
+            .1;
+        ranges.push(sectors(
+            self.lb_to_sector(&fsd_ext.loc)?,
+            fsd_ext.len as u64,
+            bs,
+        ));
+        let snapshot = self.tree_snapshot()?;
+        for node in &snapshot.nodes {
+            let icb = self.read_icb(&node.icb)?;
+            ranges.push(sectors(self.lb_to_sector(&node.icb)?, lbs, bs));
+            // allocation descriptors continued in further extents
+            if !matches!(icb.icb_tag.flags.get_alloc_type()?, AllocType::EMBEDDED) {
+                for ad in icb.get_alloc_descs().iter().filter(|ad| ad.ext_type() == 3) {
+                    let (offset, len) = self.alloc_desc_to_offset_len(ad, node.icb.part_ref_nr)?;
+                    ranges.push(sectors((offset / bs) as LSN, len as u64, bs));
+                }
+            }
+            let max_type = if unrecorded { 1 } else { 0 };
+            for ext in node.extents.iter().filter(|e| e.ext_type <= max_type) {
+                ranges.push(sectors((ext.offset / bs) as LSN, ext.len as u64, bs));
+            }
+        }
+        Ok(merge_ranges(ranges))
+    }
+
+    /// number of logical blocks in the partition `pd`, whose length is recorded in sectors
+    fn part_blocks(&self, pd: &PD) -> u32 {
+        pd.part_len / self.block_sectors()
+    }
+
+    /// the blocks of the partition `pd` holding its unallocated space bitmap, or else its
+    /// unallocated space table, and whether it is a bitmap
+    fn space_desc(&self, pd: &PD) -> Result<Option<SpaceDesc>, Box<dyn Error>> {
+        if !pd.contents().is_nsr() {
+            return Ok(None);
+        }
+        let phd = PHD::parse(&pd.part_cont_use)
+            .or(Err("error parsing PHD"))?
+            .1;
+        let (ad, is_bitmap) = match (&phd.us_bmp, &phd.us_tbl) {
+            (bmp, _) if bmp.len > 0 => (bmp, true),
+            (_, tbl) if tbl.len > 0 => (tbl, false),
+            _ => return Ok(None),
+        };
+        let len = (ad.len as u64).div_ceil(self.block_size());
+        if ad.pos as u64 + len > self.part_blocks(pd) as u64 {
+            Err("space bitmap or table beyond the end of its partition")?
+        }
+        Ok(Some(SpaceDesc {
+            blocks: ad.pos..ad.pos + len as u32,
+            is_bitmap,
+        }))
+    }
+
+    /// reads the blocks of the space bitmap or table of the partition `pd`
+    fn read_space_desc(&mut self, pd: &PD, blocks: &Range<u32>) -> Result<Vec<u8>, Box<dyn Error>> {
+        let bs = self.block_size();
+        let pos = pd.part_start as u64 * self.sector_size() + blocks.start as u64 * bs;
+        // the recorded length isn't trusted to allocate more than the medium holds
+        if pos + blocks.len() as u64 * bs > self.medium_len()? {
+            Err("space bitmap or table beyond the end of the medium")?
+        }
+        let mut buf = vec![0; blocks.len() * bs as usize];
+        self.read_bytes(IoCategory::Metadata, pos, &mut buf)?;
+        Ok(buf)
+    }
+
+    /// the unallocated space bitmap of the partition `pd`, `None` if it records its free
+    /// space in a table or not at all
+    pub fn space_bitmap(&mut self, pd: &PD) -> Result<Option<SBD>, Box<dyn Error>> {
+        let Some(SpaceDesc {
+            blocks,
+            is_bitmap: true,
+        }) = self.space_desc(pd)?
+        else {
+            return Ok(None);
+        };
+        let buf = self.read_space_desc(pd, &blocks)?;
+        let sbd = SBD::parse(&buf)
+            .or(Err("space bitmap descriptor expected"))?
+            .1;
+        check_tag_crc(&self.options, "Space Bitmap Descriptor", &buf)?;
+        Ok(Some(sbd))
+    }
+
+    /// the blocks of the partition `pd` its unallocated space bitmap or table marks as
+    /// allocated, `None` if the partition records neither
+    pub fn allocated_blocks(&mut self, pd: &PD) -> Result<Option<Vec<Range<u32>>>, Box<dyn Error>> {
+        let bs = self.block_size();
+        let part_blocks = self.part_blocks(pd);
+        let mut free = Vec::new();
+        if let Some(sbd) = self.space_bitmap(pd)? {
+            free.extend(
+                sbd.extents()
+                    .filter(|extent| extent.free && extent.blocks.start < part_blocks)
+                    .map(|extent| extent.blocks.start..extent.blocks.end.min(part_blocks)),
+            );
+        } else {
+            let Some(SpaceDesc { blocks, .. }) = self.space_desc(pd)? else {
+                return Ok(None);
+            };
+            let buf = self.read_space_desc(pd, &blocks)?;
+            let tag_id = u16::from_le_bytes([buf[0], buf[1]]);
+            if tag_id != 263 {
+                Err("unallocated space entry expected")?
+            }
+            let ty = match buf[34] & 7 {
+                0 => AllocType::SHORT,
+                1 => AllocType::LONG,
+                _ => Err("unsupported allocation descriptors in unallocated space entry")?,
+            };
+            let len_ad = u32::from_le_bytes(buf[36..40].try_into().unwrap()) as usize;
+            let mut ads = buf
+                .get(40..40 + len_ad)
+                .ok_or("unallocated space entry too long")?;
+            while let Ok((rest, ad)) = AllocDesc::parse(ads, ty.clone()) {
+                let start = ad.lb_addr(0).lbn;
+                let end = start.saturating_add((ad.len() as u64).div_ceil(bs) as u32);
+                free.push(start..end.min(part_blocks));
+                ads = rest;
+            }
+        }
+        let whole = 0..part_blocks;
+        Ok(Some(subtract_ranges(&[whole], &merge_ranges(free))))
+    }
+
+    /// the partitions the partition maps refer to, each once
+    fn distinct_partitions(&self) -> Vec<PD> {
+        let mut partitions: Vec<PD> = Vec::new();
+        for map in &self.part_maps {
+            if let Some(pd) = &map.pd {
+                if !partitions.iter().any(|p| p.part_num == pd.part_num) {
+                    partitions.push(pd.clone());
+                }
+            }
+        }
+        partitions
+    }
+
+    /// the blocks of the partition `pd` holding any of the `referenced` sectors or its space
+    /// bitmap or table
+    fn blocks_in_use(
+        &self,
+        pd: &PD,
+        referenced: &[Range<LSN>],
+    ) -> Result<Vec<Range<u32>>, Box<dyn Error>> {
+        let block_sectors = self.block_sectors();
+        let part = pd.part_start..pd.part_start.saturating_add(pd.part_len);
+        // blocks with any of their sectors in use are in use
+        let mut in_use: Vec<Range<u32>> = referenced
+            .iter()
+            .filter(|r| r.start < part.end && r.end > part.start)
+            .map(|r| {
+                let start = r.start.max(part.start) - part.start;
+                let end = r.end.min(part.end) - part.start;
+                start / block_sectors..end.div_ceil(block_sectors)
+            })
+            .collect();
+        // the bitmap or table is allocated from the partition as well
+        if let Some(desc) = self.space_desc(pd)? {
+            in_use.push(desc.blocks);
+        }
+        Ok(merge_ranges(in_use))
+    }
+
+    /// the blocks of every partition that nothing refers to and that its space bitmap or
+    /// table marks as free, i.e. that hold nothing but remnants of deleted data. Partitions
+    /// without either are left out, as unreferenced blocks may well be in use, e.g. by the
+    /// ISO 9660 file system of a bridge disc.
+    #[cfg(feature = "writer")]
+    pub(crate) fn unused_blocks(&mut self) -> Result<Vec<UnusedBlocks>, Box<dyn Error>> {
+        let referenced = self.referenced_sectors(true)?;
+        let mut result = Vec::new();
+        for pd in self.distinct_partitions() {
+            let Some(allocated) = self.allocated_blocks(&pd)? else {
+                continue;
+            };
+            let in_use = merge_ranges([self.blocks_in_use(&pd, &referenced)?, allocated].concat());
+            let whole = 0..self.part_blocks(&pd);
+            let blocks = subtract_ranges(&[whole], &in_use);
+            result.push(UnusedBlocks {
+                part_start: pd.part_start,
+                blocks,
+            });
+        }
+        Ok(result)
+    }
+
+    /// compares the space bitmaps or tables of all partitions with the blocks referred to by
+    /// the volume structures and the file set. Partitions without either are left out.
+    pub fn orphan_blocks(&mut self) -> Result<Vec<OrphanBlocks>, Box<dyn Error>> {
+        let referenced = self.referenced_sectors(true)?;
+        let mut result = Vec::new();
+        for pd in self.distinct_partitions() {
+            let Some(allocated) = self.allocated_blocks(&pd)? else {
+                continue;
+            };
+            let in_use = self.blocks_in_use(&pd, &referenced)?;
+            result.push(OrphanBlocks {
+                part_num: pd.part_num,
+                unreferenced: subtract_ranges(&allocated, &in_use),
+                unallocated: subtract_ranges(&in_use, &allocated),
+            });
+        }
+        Ok(result)
+    }
+}
@@ -0,0 +1,514 @@
+/*
+    Backends a volume can be read from besides plain `Read + Seek` streams.
+
+    `ReadAt` backends read at explicit offsets (`pread` for files) and don't need exclusive
+    access, so one backend can be shared by several `PositionedReader`s, e.g. one per thread.
+    A `ReadScheduler` shares a backend between readers of different priority, issuing one
+    read at a time and serving waiting foreground reads before background ones.
+*/
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// a source of data that can be read at arbitrary offsets through a shared reference
+pub trait ReadAt {
+    /// reads up to `buf.len()` bytes starting at `pos`, returning the number of bytes read
+    fn read_at(&self, pos: u64, buf: &mut [u8]) -> io::Result<usize>;
+
+    /// total size of the data in bytes
+    fn size(&self) -> io::Result<u64>;
+
+    fn read_exact_at(&self, mut pos: u64, mut buf: &mut [u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.read_at(pos, buf) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => {
+                    pos += n as u64;
+                    buf = &mut buf[n..];
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ReadAt for File {
+    #[cfg(unix)]
+    fn read_at(&self, pos: u64, buf: &mut [u8]) -> io::Result<usize> {
+        std::os::unix::fs::FileExt::read_at(self, buf, pos)
+    }
+
+    #[cfg(windows)]
+    fn read_at(&self, pos: u64, buf: &mut [u8]) -> io::Result<usize> {
+        std::os::windows::fs::FileExt::seek_read(self, buf, pos)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+}
+
+impl ReadAt for [u8] {
+    fn read_at(&self, pos: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let start = pos.min(self.len() as u64) as usize;
+        let n = buf.len().min(self.len() - start);
+        buf[..n].copy_from_slice(&self[start..start + n]);
+        Ok(n)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.len() as u64)
+    }
+}
+
+impl ReadAt for Vec<u8> {
+    fn read_at(&self, pos: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.as_slice().read_at(pos, buf)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.len() as u64)
+    }
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for &T {
+    fn read_at(&self, pos: u64, buf: &mut [u8]) -> io::Result<usize> {
+        (**self).read_at(pos, buf)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        (**self).size()
+    }
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for Arc<T> {
+    fn read_at(&self, pos: u64, buf: &mut [u8]) -> io::Result<usize> {
+        (**self).read_at(pos, buf)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        (**self).size()
+    }
+}
+
+/// priority of the reads of a `ScheduledReader`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReadPriority {
+    /// e.g. streaming to a player, served as soon as the backend is free
+    Foreground,
+    /// e.g. hashing or verification, served only while no foreground read is waiting
+    Background,
+}
+
+#[derive(Debug, Default)]
+struct ScheduleState {
+    /// a read is being issued to the backend
+    busy: bool,
+    foreground_waiting: usize,
+}
+
+/// a `ReadAt` backend shared by readers of different priority. Reads are issued one at a
+/// time like through a single reader, but when the backend becomes free waiting foreground
+/// reads go first, so background work can't hold up streaming from the same device.
+#[derive(Debug)]
+pub struct ReadScheduler<R> {
+    inner: R,
+    state: Mutex<ScheduleState>,
+    free: Condvar,
+}
+
+impl<R: ReadAt> ReadScheduler<R> {
+    pub fn new(inner: R) -> Arc<Self> {
+        Arc::new(Self {
+            inner,
+            state: Mutex::new(ScheduleState::default()),
+            free: Condvar::new(),
+        })
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// a reader of the backend whose reads have `priority`, to be wrapped in a
+    /// `PositionedReader`
+    pub fn reader(self: &Arc<Self>, priority: ReadPriority) -> ScheduledReader<R> {
+        ScheduledReader {
+            scheduler: self.clone(),
+            priority,
+        }
+    }
+
+    fn read_at(&self, priority: ReadPriority, pos: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        if priority == ReadPriority::Foreground {
+            state.foreground_waiting += 1;
+            state = self.free.wait_while(state, |s| s.busy).unwrap();
+            state.foreground_waiting -= 1;
+        } else {
+            state = self
+                .free
+                .wait_while(state, |s| s.busy || s.foreground_waiting > 0)
+                .unwrap();
+        }
+        state.busy = true;
+        drop(state);
+        let result = self.inner.read_at(pos, buf);
+        self.state.lock().unwrap().busy = false;
+        self.free.notify_all();
+        result
+    }
+}
+
+/// a reader of a `ReadScheduler`, cloning it gives another reader of the same priority
+#[derive(Clone, Debug)]
+pub struct ScheduledReader<R> {
+    scheduler: Arc<ReadScheduler<R>>,
+    priority: ReadPriority,
+}
+
+impl<R: ReadAt> ScheduledReader<R> {
+    pub fn priority(&self) -> ReadPriority {
+        self.priority
+    }
+}
+
+impl<R: ReadAt> ReadAt for ScheduledReader<R> {
+    fn read_at(&self, pos: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.scheduler.read_at(self.priority, pos, buf)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        self.scheduler.inner.size()
+    }
+}
+
+/// `Read + Seek` view of a `ReadAt` backend with its own position. Cloning it is cheap when
+/// the backend is a reference or an `Arc`, and clones don't affect each other's position.
+#[derive(Clone, Debug)]
+pub struct PositionedReader<R> {
+    inner: R,
+    pos: u64,
+}
+
+impl<R: ReadAt> PositionedReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner, pos: 0 }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: ReadAt> Read for PositionedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read_at(self.pos, buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        self.inner.read_exact_at(self.pos, buf)?;
+        self.pos += buf.len() as u64;
+        Ok(())
+    }
+}
+
+impl<R: ReadAt> Seek for PositionedReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(pos) => {
+                self.pos = pos;
+                return Ok(pos);
+            }
+            SeekFrom::Current(offset) => (self.pos, offset),
+            SeekFrom::End(offset) => (self.inner.size()?, offset),
+        };
+        self.pos = base.checked_add_signed(offset).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        Ok(self.pos)
+    }
+}
+
+/// token bucket keeping reads below a number of bytes per second. Up to a second worth of
+/// reads may be issued at once after an idle period, longer bursts are slowed down.
+#[derive(Clone, Debug)]
+pub(crate) struct RateLimiter {
+    rate: u64,
+    /// bytes that may still be read without waiting, negative while reads are ahead of the
+    /// rate
+    allowance: f64,
+    last: Instant,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(rate: u64) -> Self {
+        Self {
+            rate: rate.max(1),
+            allowance: rate.max(1) as f64,
+            last: Instant::now(),
+        }
+    }
+
+    /// accounts for `bytes` read, waiting until they fit within the rate
+    fn consume(&mut self, bytes: usize) {
+        let now = Instant::now();
+        let rate = self.rate as f64;
+        let refill = now.duration_since(self.last).as_secs_f64() * rate;
+        self.allowance = (self.allowance + refill).min(rate) - bytes as f64;
+        self.last = now;
+        if self.allowance < 0.0 {
+            std::thread::sleep(Duration::from_secs_f64(-self.allowance / rate));
+        }
+    }
+}
+
+/// upper bound of aligned blocks read at once
+const MAX_ALIGNED_BLOCKS: u64 = 32;
+
+/// state of reading in aligned blocks: the position reads continue at and the blocks read
+/// last, which the following reads are served from
+#[derive(Clone, Debug)]
+struct AlignedReads {
+    size: u64,
+    pos: u64,
+    blocks_pos: u64,
+    blocks: Vec<u8>,
+}
+
+impl AlignedReads {
+    /// offset of the current position in `blocks`, if it was read already
+    fn cached(&self) -> Option<usize> {
+        self.pos
+            .checked_sub(self.blocks_pos)
+            .filter(|&off| off < self.blocks.len() as u64)
+            .map(|off| off as usize)
+    }
+
+    /// reads the aligned blocks covering `len` bytes from the current position, returning the
+    /// number of bytes read. A failed read ends the blocks early.
+    fn fill<R: Read + Seek>(&mut self, inner: &mut R, len: usize) -> io::Result<usize> {
+        let start = self.pos - self.pos % self.size;
+        let end = (self.pos + len as u64)
+            .div_ceil(self.size)
+            .min(start / self.size + MAX_ALIGNED_BLOCKS)
+            * self.size;
+        self.blocks.resize((end - start) as usize, 0);
+        self.blocks_pos = start;
+        let mut read = 0;
+        let result = inner.seek(SeekFrom::Start(start)).map(|_| ());
+        if result.is_ok() {
+            while read < self.blocks.len() {
+                match inner.read(&mut self.blocks[read..]) {
+                    Ok(0) => break,
+                    Ok(n) => read += n,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(_) => break,
+                }
+            }
+        }
+        self.blocks.truncate(read);
+        result.map(|_| read)
+    }
+}
+
+/// the part of a stream starting `start` bytes into it, for volumes recorded at an offset of
+/// the medium like a partition of a disk image
+#[derive(Clone, Debug)]
+pub(crate) struct Window<R> {
+    pub(crate) inner: R,
+    start: u64,
+    limiter: Option<RateLimiter>,
+    aligned: Option<AlignedReads>,
+    #[cfg(feature = "writer")]
+    journal: Option<Arc<Mutex<crate::undo::UndoJournal>>>,
+}
+
+impl<R> Window<R> {
+    pub(crate) fn new(inner: R, start: u64) -> Self {
+        Self {
+            inner,
+            start,
+            limiter: None,
+            aligned: None,
+            #[cfg(feature = "writer")]
+            journal: None,
+        }
+    }
+
+    /// limits reads to `rate` bytes per second, or lifts the limit if `None`
+    pub(crate) fn set_read_rate(&mut self, rate: Option<u64>) {
+        self.limiter = rate.map(RateLimiter::new);
+    }
+
+    /// aligns the reads of the underlying stream to blocks of `size` bytes and rounds their
+    /// length up to whole blocks, or reads just what is asked for if `None`
+    pub(crate) fn set_read_alignment(&mut self, size: Option<u32>) {
+        self.aligned = size.filter(|&size| size > 0).map(|size| AlignedReads {
+            size: size as u64,
+            pos: 0,
+            blocks_pos: 0,
+            blocks: Vec::new(),
+        });
+    }
+
+    /// records the original contents of blocks in `journal` before they are overwritten,
+    /// returning the journal used so far
+    #[cfg(feature = "writer")]
+    pub(crate) fn set_journal(
+        &mut self,
+        journal: Option<Arc<Mutex<crate::undo::UndoJournal>>>,
+    ) -> Option<Arc<Mutex<crate::undo::UndoJournal>>> {
+        std::mem::replace(&mut self.journal, journal)
+    }
+
+    /// drops the blocks kept from aligned reads, e.g. after the stream was written to
+    pub(crate) fn invalidate(&mut self) {
+        if let Some(aligned) = &mut self.aligned {
+            aligned.blocks.clear();
+        }
+    }
+
+    fn throttle(&mut self, bytes: usize) {
+        if let Some(limiter) = &mut self.limiter {
+            limiter.consume(bytes);
+        }
+    }
+}
+
+impl<R: Seek> Window<R> {
+    /// converts a position of the underlying stream to one of the window
+    fn relative(&self, pos: u64) -> io::Result<u64> {
+        pos.checked_sub(self.start).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek to before the start offset",
+            )
+        })
+    }
+}
+
+impl<R: Read + Seek> Read for Window<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(aligned) = &mut self.aligned else {
+            let n = self.inner.read(buf)?;
+            self.throttle(n);
+            return Ok(n);
+        };
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if aligned.cached().is_none() {
+            let read = aligned.fill(&mut self.inner, buf.len())?;
+            if let Some(limiter) = &mut self.limiter {
+                limiter.consume(read);
+            }
+            if aligned.cached().is_none() {
+                // the blocks ended before the position, at the end of the stream or at a bad
+                // sector that may well be outside of what is asked for
+                self.inner.seek(SeekFrom::Start(aligned.pos))?;
+                let n = self.inner.read(buf)?;
+                aligned.pos += n as u64;
+                self.throttle(n);
+                return Ok(n);
+            }
+        }
+        let off = aligned.cached().unwrap();
+        let n = buf.len().min(aligned.blocks.len() - off);
+        buf[..n].copy_from_slice(&aligned.blocks[off..off + n]);
+        aligned.pos += n as u64;
+        Ok(n)
+    }
+
+    fn read_exact(&mut self, mut buf: &mut [u8]) -> io::Result<()> {
+        if self.aligned.is_none() {
+            self.inner.read_exact(buf)?;
+            self.throttle(buf.len());
+            return Ok(());
+        }
+        while !buf.is_empty() {
+            match self.read(buf) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => buf = &mut buf[n..],
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Seek> Seek for Window<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match (pos, &mut self.aligned) {
+            (SeekFrom::Start(pos), aligned) => {
+                let pos = self.start.checked_add(pos).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "seek beyond the end")
+                })?;
+                match aligned {
+                    // the stream is positioned when blocks are read
+                    Some(aligned) => aligned.pos = pos,
+                    None => {
+                        self.inner.seek(SeekFrom::Start(pos))?;
+                    }
+                }
+                pos
+            }
+            (SeekFrom::Current(offset), Some(aligned)) => {
+                aligned.pos = aligned
+                    .pos
+                    .checked_add_signed(offset)
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek"))?;
+                aligned.pos
+            }
+            (pos, Some(aligned)) => {
+                aligned.pos = self.inner.seek(pos)?;
+                aligned.pos
+            }
+            (pos, None) => self.inner.seek(pos)?,
+        };
+        self.relative(pos)
+    }
+}
+
+impl<R: Read + Write + Seek> Write for Window<R> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        #[cfg(feature = "writer")]
+        if let Some(journal) = &self.journal {
+            let pos = match &self.aligned {
+                Some(aligned) => aligned.pos,
+                None => self.inner.stream_position()?,
+            };
+            journal
+                .lock()
+                .unwrap()
+                .save(&mut self.inner, pos, buf.len())?;
+            self.inner.seek(SeekFrom::Start(pos))?;
+        }
+        let Some(aligned) = &mut self.aligned else {
+            return self.inner.write(buf);
+        };
+        aligned.blocks.clear();
+        self.inner.seek(SeekFrom::Start(aligned.pos))?;
+        let n = self.inner.write(buf)?;
+        aligned.pos += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
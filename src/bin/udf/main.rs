@@ -0,0 +1,732 @@
+/*
+    Command line tool for inspecting and writing UDF images. Every command prints
+    human-readable text, or with `--json` a single JSON document on stdout for use in scripts.
+*/
+
+use std::error::Error;
+use std::fmt::Write;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::Mutex;
+
+use libudf_rs::catalogue::json_str;
+use libudf_rs::extract::{CollisionPolicy, ExtractOptions};
+use libudf_rs::format::human_size;
+use libudf_rs::partition::PartitionKind;
+use libudf_rs::raw::{IntegrityType, RegIDFlags, LVID};
+use libudf_rs::sessions::scan_session_starts;
+use libudf_rs::{UdfOptions, UDF};
+
+mod shell;
+mod verify;
+#[cfg(feature = "writer")]
+mod write;
+
+type Volume = UDF<BufReader<File>>;
+
+const USAGE: &str = "usage: udf <command> [--json] [--max-rate BYTES] <image> [args]
+
+commands:
+    info <image>           volume and partition information
+    ls <image> [path]      contents of a directory, the root directory by default
+    verify [--level=quick|normal|strict] <image>
+                           reads back the volume, see below
+    map <image>            sector ranges in use
+    sessions [--step N] <image>
+                           possible session starts, for images that lost their TOC
+    recover <image> <dir>  saves blocks allocated but unused, e.g. of deleted files, to dir
+    extract [--existing=error|skip|overwrite|rename] [--dry-run] [--state FILE]
+            <image> <path> <dest>
+                           copies a file or directory tree to dest, failing on existing
+                           files by default, or with --dry-run only lists what it would do.
+                           With --state the progress is recorded in FILE, and an
+                           interrupted extraction continues where it stopped when run again
+    graph <image>          structure of the volume in the DOT language of Graphviz, or a
+                           JSON tree with --json
+    shell <image>          interactive shell with cd, ls, stat, get and hash commands
+    create <image> --from <dir> [--revision 2.01] [--label NAME]
+                           writes a new image with the contents of a local directory
+    clone [--exclude PATH]... [--revision 2.01] [--label NAME] <image> <dest>
+                           writes a new image with the contents of the volume, leaving
+                           out every PATH with everything below it, e.g. AACS directories
+                           or junk files. The label and revision default to the volume's
+    add [--force] [--undo FILE | --swap] <image> <file> <path>
+                           adds a local file to an image at the given path, refusing
+                           images that look damaged unless --force is given. With --undo
+                           the original contents of overwritten blocks are saved to FILE,
+                           with --swap a copy of the image is modified and replaces it
+                           only once that succeeded
+    rollback <image> <file>
+                           restores an image from the undo file of an earlier add
+    scrub [--force] [--undo FILE | --swap] <image>
+                           zero-fills the slack of all files and all unallocated blocks,
+                           removing remnants of deleted data
+
+verify reads the directories at level quick, additionally all file data at level normal
+(the default) and at level strict it also treats every warning as an error. It exits with
+    0  if the volume is clean
+    1  if there were warnings only
+    2  if there were errors
+--max-rate limits the reads of any command to BYTES per second, e.g. to verify a disc without
+slowing down other readers of the same device.
+All commands exit with 3 if the command itself fails, e.g. the image can't be opened.";
+
+/// exit code of failed commands
+const EXIT_FAILURE: u8 = 3;
+
+/// collects the warnings the library logs, to report them along with the output
+struct WarningLog {
+    warnings: Mutex<Vec<String>>,
+}
+
+impl log::Log for WarningLog {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::Level::Warn
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            let mut warnings = self.warnings.lock().unwrap();
+            warnings.push(record.args().to_string());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static WARNINGS: WarningLog = WarningLog {
+    warnings: Mutex::new(Vec::new()),
+};
+
+/// the warnings logged since the last call
+fn take_warnings() -> Vec<String> {
+    std::mem::take(&mut WARNINGS.warnings.lock().unwrap())
+}
+
+/// command line arguments with the options taken out
+struct Args {
+    json: bool,
+    level: verify::Level,
+    existing: CollisionPolicy,
+    dry_run: bool,
+    force: bool,
+    swap: bool,
+    state: Option<String>,
+    max_rate: Option<u64>,
+    #[cfg(feature = "writer")]
+    from: Option<String>,
+    #[cfg(feature = "writer")]
+    revision: Option<u16>,
+    #[cfg(feature = "writer")]
+    label: Option<String>,
+    #[cfg(feature = "writer")]
+    exclude: Vec<String>,
+    #[cfg(feature = "writer")]
+    undo: Option<String>,
+    step: u32,
+    positional: Vec<String>,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, Box<dyn Error>> {
+        let mut result = Args {
+            json: false,
+            level: verify::Level::default(),
+            existing: CollisionPolicy::default(),
+            dry_run: false,
+            force: false,
+            swap: false,
+            state: None,
+            max_rate: None,
+            #[cfg(feature = "writer")]
+            from: None,
+            #[cfg(feature = "writer")]
+            revision: None,
+            #[cfg(feature = "writer")]
+            label: None,
+            #[cfg(feature = "writer")]
+            exclude: Vec::new(),
+            #[cfg(feature = "writer")]
+            undo: None,
+            step: 1,
+            positional: Vec::new(),
+        };
+        while let Some(arg) = args.next() {
+            if arg == "--json" {
+                result.json = true;
+                continue;
+            }
+            if arg == "--dry-run" {
+                result.dry_run = true;
+                continue;
+            }
+            if arg == "--force" {
+                result.force = true;
+                continue;
+            }
+            if arg == "--swap" {
+                result.swap = true;
+                continue;
+            }
+            let Some(opt) = arg.strip_prefix("--") else {
+                result.positional.push(arg);
+                continue;
+            };
+            // options take their value as `--opt value` or `--opt=value`
+            let (name, value) = match opt.split_once('=') {
+                Some((name, value)) => (name, value.to_string()),
+                None => match args.next() {
+                    Some(value) => (opt, value),
+                    None => Err(format!("option {} needs a value", arg))?,
+                },
+            };
+            match name {
+                "level" => result.level = value.parse()?,
+                "existing" => result.existing = value.parse()?,
+                "state" => result.state = Some(value),
+                "max-rate" => {
+                    result.max_rate = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("invalid rate {}", value))?,
+                    )
+                }
+                #[cfg(feature = "writer")]
+                "from" => result.from = Some(value),
+                #[cfg(feature = "writer")]
+                "revision" => result.revision = Some(write::parse_revision(&value)?),
+                #[cfg(feature = "writer")]
+                "label" => result.label = Some(value),
+                #[cfg(feature = "writer")]
+                "exclude" => result.exclude.push(value),
+                #[cfg(feature = "writer")]
+                "undo" => result.undo = Some(value),
+                "step" => {
+                    result.step = value
+                        .parse()
+                        .map_err(|_| format!("invalid step {}", value))?
+                }
+                _ => Err(format!("unknown option {}", arg))?,
+            }
+        }
+        Ok(result)
+    }
+}
+
+fn open(image: &str, options: UdfOptions) -> Result<Volume, Box<dyn Error>> {
+    let file = File::open(image).map_err(|e| format!("{}: {}", image, e))?;
+    UDF::new_with_options(BufReader::new(file), options)
+}
+
+fn kind_str(kind: &PartitionKind) -> &'static str {
+    match kind {
+        PartitionKind::Physical => "physical",
+        PartitionKind::Virtual => "virtual",
+        PartitionKind::Sparable(_) => "sparable",
+        PartitionKind::Metadata(_) => "metadata",
+        PartitionKind::Unknown(_) => "unknown",
+    }
+}
+
+fn integrity_state(lvid: &Option<LVID>) -> &'static str {
+    match lvid.as_ref().map(|lvid| lvid.integ_type) {
+        Some(IntegrityType::OPEN) => "open",
+        Some(IntegrityType::CLOSE) => "close",
+        None => "none",
+    }
+}
+
+/// free blocks of a partition according to the current integrity descriptor
+fn free_blocks(lvid: &Option<LVID>, part_ref: u16) -> Option<u32> {
+    lvid.as_ref()?.free_blocks(part_ref)
+}
+
+fn info(udf: &mut Volume, json: bool) -> Result<String, Box<dyn Error>> {
+    let volume = udf.volume_info();
+    let domain = udf.domain_flags();
+    let integrity = udf.integrity_sequence()?;
+    let vrs = udf.recognition_sequence()?;
+    let owner = udf
+        .lv_info()?
+        .map(|info| [info.owner_name, info.organization, info.contact_info].map(|s| s.to_string()));
+    let mut out = String::new();
+    if json {
+        out.push_str("{\"volume\":");
+        volume.write_json(&mut out);
+        out.push_str(",\"partitions\":[");
+        for (i, map) in udf.partition_maps().iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            write!(
+                out,
+                "{{\"part_ref\":{},\"kind\":\"{}\",\"part_num\":{}",
+                map.part_ref,
+                kind_str(&map.kind),
+                map.part_num
+            )
+            .unwrap();
+            match &map.pd {
+                Some(pd) => write!(out, ",\"start\":{},\"len\":{}", pd.part_start, pd.part_len),
+                None => write!(out, ",\"start\":null,\"len\":null"),
+            }
+            .unwrap();
+            match free_blocks(&integrity.current, map.part_ref) {
+                Some(free) => write!(out, ",\"free\":{}}}", free),
+                None => write!(out, ",\"free\":null}}"),
+            }
+            .unwrap();
+        }
+        out.push_str("],\"truncation\":");
+        match udf.truncation() {
+            Some(t) => write!(
+                out,
+                "{{\"medium_sectors\":{},\"expected_sectors\":{}}}",
+                t.medium_len / t.sector_size,
+                t.expected_sectors
+            )
+            .unwrap(),
+            None => out.push_str("null"),
+        }
+        write!(
+            out,
+            ",\"integrity\":{{\"state\":\"{}\",\"descriptors\":{},\"extents\":{}}}",
+            integrity_state(&integrity.current),
+            integrity.num_descs,
+            integrity.extents.len()
+        )
+        .unwrap();
+        match vrs.nsr_version() {
+            Some(version) => write!(out, ",\"recognition\":{{\"nsr\":{}", version),
+            None => write!(out, ",\"recognition\":{{\"nsr\":null"),
+        }
+        .unwrap();
+        write!(out, ",\"bridge\":{},\"boot\":[", vrs.is_bridge()).unwrap();
+        for (i, bd) in vrs.boot.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str("{\"arch\":");
+            json_str(&mut out, bd.arch.ident_str());
+            write!(
+                out,
+                ",\"loc\":{},\"len\":{}}}",
+                bd.boot_ext_loc, bd.boot_ext_len
+            )
+            .unwrap();
+        }
+        out.push_str("]}");
+        match &owner {
+            Some([name, organization, contact]) => {
+                out.push_str(",\"owner\":{\"name\":");
+                json_str(&mut out, name);
+                out.push_str(",\"organization\":");
+                json_str(&mut out, organization);
+                out.push_str(",\"contact\":");
+                json_str(&mut out, contact);
+                out.push('}');
+            }
+            None => out.push_str(",\"owner\":null"),
+        }
+        write!(
+            out,
+            ",\"domain\":{{\"dirty\":{},\"protected\":{}}}}}",
+            domain.contains(RegIDFlags::DIRTY),
+            domain.contains(RegIDFlags::PROTECTED)
+        )
+        .unwrap();
+        return Ok(out);
+    }
+    writeln!(out, "Volume:          {}", volume.vol_ident).unwrap();
+    writeln!(out, "Volume set:      {}", volume.vol_set_ident).unwrap();
+    writeln!(out, "Logical volume:  {}", volume.lv_ident).unwrap();
+    if let Some(owner) = &owner {
+        let owner: Vec<&str> = owner
+            .iter()
+            .map(String::as_str)
+            .filter(|s| !s.is_empty())
+            .collect();
+        if !owner.is_empty() {
+            writeln!(out, "Owner:           {}", owner.join(", ")).unwrap();
+        }
+    }
+    writeln!(
+        out,
+        "UDF revision:    {:x}.{:02x}",
+        volume.udf_revision >> 8,
+        volume.udf_revision & 0xff
+    )
+    .unwrap();
+    if !domain.is_empty() {
+        let mut flags = Vec::new();
+        if domain.contains(RegIDFlags::DIRTY) {
+            flags.push("dirty");
+        }
+        if domain.contains(RegIDFlags::PROTECTED) {
+            flags.push("protected");
+        }
+        writeln!(out, "Domain flags:    {}", flags.join(", ")).unwrap();
+    }
+    writeln!(out, "Sector size:     {} bytes", volume.sector_size).unwrap();
+    match vrs.nsr_version() {
+        Some(version) if vrs.is_bridge() => {
+            writeln!(out, "Recognition:     NSR0{}, ISO 9660 bridge", version)
+        }
+        Some(version) => writeln!(out, "Recognition:     NSR0{}", version),
+        None => writeln!(out, "Recognition:     no NSR descriptor"),
+    }
+    .unwrap();
+    for bd in &vrs.boot {
+        writeln!(
+            out,
+            "Boot:            {}, {} bytes at sector {}",
+            bd.arch.ident_str(),
+            bd.boot_ext_len,
+            bd.boot_ext_loc
+        )
+        .unwrap();
+    }
+    writeln!(out, "Recorded:        {}", volume.record_time).unwrap();
+    writeln!(
+        out,
+        "Integrity:       {}, {} descriptors in {} extents",
+        integrity_state(&integrity.current),
+        integrity.num_descs,
+        integrity.extents.len()
+    )
+    .unwrap();
+    if let Some(impl_use) = integrity
+        .current
+        .as_ref()
+        .and_then(|lvid| lvid.impl_use.as_ref())
+    {
+        writeln!(
+            out,
+            "Contents:        {} files, {} directories, written by {}",
+            impl_use.num_files,
+            impl_use.num_dirs,
+            impl_use.impl_ident.ident_str()
+        )
+        .unwrap();
+    }
+    for map in udf.partition_maps() {
+        write!(
+            out,
+            "Partition map {}: {} partition {}",
+            map.part_ref,
+            kind_str(&map.kind),
+            map.part_num
+        )
+        .unwrap();
+        match &map.pd {
+            Some(pd) => write!(
+                out,
+                ", sectors {}..{} ({})",
+                pd.part_start,
+                pd.part_start as u64 + pd.part_len as u64,
+                human_size(pd.part_len as u64 * volume.sector_size)
+            ),
+            None => write!(out, ", not recorded"),
+        }
+        .unwrap();
+        match free_blocks(&integrity.current, map.part_ref) {
+            Some(free) => writeln!(out, ", {} free", human_size(free as u64 * udf.block_size())),
+            None => writeln!(out),
+        }
+        .unwrap();
+    }
+    if let Some(t) = udf.truncation() {
+        writeln!(
+            out,
+            "Truncated:       {} of {} sectors recorded",
+            t.medium_len / t.sector_size,
+            t.expected_sectors
+        )
+        .unwrap();
+    }
+    Ok(out)
+}
+
+fn ls(udf: &mut Volume, path: &str, json: bool) -> Result<String, Box<dyn Error>> {
+    let dir = udf
+        .find_icb(Path::new(path))
+        .map_err(|e| format!("{}: {}", path, e))?;
+    let prefix = path.trim_end_matches('/');
+    let mut out = String::new();
+    if json {
+        out.push('[');
+    }
+    for (i, (entry, icb)) in udf.stat_children(&dir)?.into_iter().enumerate() {
+        let entry = udf.catalogue_entry(format!("{}/{}", prefix, entry.name), &icb)?;
+        if json {
+            if i > 0 {
+                out.push(',');
+            }
+            entry.write_json(&mut out);
+        } else {
+            let name = entry.path.rsplit('/').next().unwrap_or_default();
+            writeln!(
+                out,
+                "{:<7} {:>12} {} {}",
+                entry.kind, entry.size, entry.mtime, name
+            )
+            .unwrap();
+        }
+    }
+    if json {
+        out.push(']');
+    }
+    Ok(out)
+}
+
+fn map(udf: &mut Volume, json: bool) -> Result<String, Box<dyn Error>> {
+    let ranges = udf.allocated_sectors()?;
+    let total: u64 = ranges.iter().map(|r| (r.end - r.start) as u64).sum();
+    let mut out = String::new();
+    if json {
+        out.push_str("{\"ranges\":[");
+        for (i, r) in ranges.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            write!(out, "{{\"start\":{},\"end\":{}}}", r.start, r.end).unwrap();
+        }
+        write!(out, "],\"sectors\":{}}}", total).unwrap();
+        return Ok(out);
+    }
+    for r in &ranges {
+        writeln!(
+            out,
+            "{:>10}..{:<10} {:>10} sectors",
+            r.start,
+            r.end,
+            r.end - r.start
+        )
+        .unwrap();
+    }
+    writeln!(out, "{} sectors in use", total).unwrap();
+    Ok(out)
+}
+
+fn sessions(image: &str, step: u32, json: bool) -> Result<String, Box<dyn Error>> {
+    let file = File::open(image).map_err(|e| format!("{}: {}", image, e))?;
+    let candidates = scan_session_starts(&mut BufReader::new(file), step)?;
+    let mut out = String::new();
+    if json {
+        out.push('[');
+        for (i, c) in candidates.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            write!(
+                out,
+                "{{\"start\":{},\"vrs\":{},\"opens\":{},\"plausible\":{}}}",
+                c.start,
+                c.vrs,
+                c.opens,
+                c.is_plausible()
+            )
+            .unwrap();
+        }
+        out.push(']');
+        return Ok(out);
+    }
+    for c in &candidates {
+        let mut notes = Vec::new();
+        if c.vrs {
+            notes.push("recognition sequence");
+        }
+        if c.opens {
+            notes.push("volume opens");
+        }
+        writeln!(out, "{:>10}  {}", c.start, notes.join(", ")).unwrap();
+    }
+    match candidates.iter().rev().find(|c| c.is_plausible()) {
+        Some(c) => writeln!(out, "most likely start of the last session: {}", c.start),
+        None => writeln!(out, "no plausible session start found"),
+    }
+    .unwrap();
+    Ok(out)
+}
+
+fn recover(udf: &mut Volume, dest: &str, json: bool) -> Result<String, Box<dyn Error>> {
+    let runs = udf.export_orphans(Path::new(dest))?;
+    let mut out = String::new();
+    if json {
+        out.push('[');
+        for (i, run) in runs.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            write!(
+                out,
+                "{{\"partition\":{},\"start\":{},\"end\":{},\"type\":",
+                run.part_num, run.blocks.start, run.blocks.end
+            )
+            .unwrap();
+            match run.hint {
+                Some(hint) => json_str(&mut out, hint.name),
+                None => out.push_str("null"),
+            }
+            out.push_str(",\"path\":");
+            json_str(&mut out, &run.path.to_string_lossy());
+            out.push('}');
+        }
+        out.push(']');
+        return Ok(out);
+    }
+    for run in &runs {
+        writeln!(
+            out,
+            "{}  partition {} blocks {}..{}  {}",
+            run.path.display(),
+            run.part_num,
+            run.blocks.start,
+            run.blocks.end,
+            run.hint.map_or("unknown", |hint| hint.name)
+        )
+        .unwrap();
+    }
+    writeln!(out, "{} runs recovered", runs.len()).unwrap();
+    Ok(out)
+}
+
+fn extract(
+    udf: &mut Volume,
+    path: &str,
+    dest: &str,
+    args: &Args,
+) -> Result<String, Box<dyn Error>> {
+    let mut options = ExtractOptions::default()
+        .policy(args.existing)
+        .dry_run(args.dry_run);
+    if let Some(state) = &args.state {
+        options = options.state_file(state);
+    }
+    let entries = udf
+        .extract_to(Path::new(path), Path::new(dest), &options)
+        .map_err(|e| format!("{}: {}", path, e))?;
+    let mut out = String::new();
+    if args.json {
+        out.push('[');
+        for (i, entry) in entries.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str("{\"path\":");
+            json_str(&mut out, &entry.path);
+            out.push_str(",\"dest\":");
+            json_str(&mut out, &entry.dest.to_string_lossy());
+            write!(
+                out,
+                ",\"kind\":\"{}\",\"size\":{},\"action\":\"{}\"}}",
+                entry.kind,
+                entry.size,
+                entry.action.as_str()
+            )
+            .unwrap();
+        }
+        out.push(']');
+        return Ok(out);
+    }
+    for entry in &entries {
+        writeln!(
+            out,
+            "{:<11} {} -> {}",
+            entry.action.as_str(),
+            entry.path,
+            entry.dest.display()
+        )
+        .unwrap();
+    }
+    let size: u64 = entries.iter().map(|e| e.size).sum();
+    let verb = if args.dry_run { "would be" } else { "were" };
+    writeln!(
+        out,
+        "{} entries of {} {} extracted",
+        entries.len(),
+        human_size(size),
+        verb
+    )
+    .unwrap();
+    Ok(out)
+}
+
+fn run(args: Args) -> Result<ExitCode, Box<dyn Error>> {
+    let (command, image) = match &args.positional[..] {
+        [command, image, ..] => (command.as_str(), image.as_str()),
+        _ => Err(USAGE)?,
+    };
+    let rest = &args.positional[2..];
+    let options = || UdfOptions {
+        max_read_rate: args.max_rate,
+        ..Default::default()
+    };
+    let output = match (command, rest) {
+        ("info", []) => info(&mut open(image, options())?, args.json)?,
+        ("ls", []) => ls(&mut open(image, options())?, "/", args.json)?,
+        ("ls", [path]) => ls(&mut open(image, options())?, path, args.json)?,
+        ("verify", []) => {
+            let v = verify::verify(image, args.level, args.max_rate)?;
+            print!("{}", v.report(args.json));
+            if args.json {
+                println!();
+            }
+            return Ok(v.status().exit_code());
+        }
+        ("map", []) => map(&mut open(image, options())?, args.json)?,
+        ("sessions", []) => sessions(image, args.step, args.json)?,
+        ("recover", [dest]) => recover(&mut open(image, options())?, dest, args.json)?,
+        ("extract", [path, dest]) => extract(&mut open(image, options())?, path, dest, &args)?,
+        ("graph", []) => {
+            let graph = open(image, options())?.structure_graph()?;
+            if args.json {
+                graph.to_json()
+            } else {
+                graph.to_dot()
+            }
+        }
+        #[cfg(feature = "writer")]
+        ("create", []) => write::create(image, &args)?,
+        #[cfg(feature = "writer")]
+        ("clone", [dest]) => write::clone(&mut open(image, options())?, dest, &args)?,
+        #[cfg(feature = "writer")]
+        ("add", [local, path]) => write::add(image, local, path, &args)?,
+        #[cfg(feature = "writer")]
+        ("rollback", [journal]) => write::rollback(image, journal)?,
+        #[cfg(feature = "writer")]
+        ("scrub", []) => write::scrub(image, &args)?,
+        #[cfg(not(feature = "writer"))]
+        ("create" | "clone" | "add" | "rollback" | "scrub", _) => {
+            Err("udf was built without the writer feature")?
+        }
+        ("shell", []) => {
+            shell::run(open(image, options())?, std::io::stdin().lock())?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        _ => Err(USAGE)?,
+    };
+    print!("{}", output);
+    if args.json {
+        println!();
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn main() -> ExitCode {
+    log::set_logger(&WARNINGS).unwrap();
+    log::set_max_level(log::LevelFilter::Warn);
+    let result = Args::parse(std::env::args().skip(1)).and_then(run);
+    for warning in take_warnings() {
+        eprintln!("udf: warning: {}", warning);
+    }
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("udf: {}", e);
+            ExitCode::from(EXIT_FAILURE)
+        }
+    }
+}
@@ -0,0 +1,200 @@
+/*
+    Interactive shell on one opened volume. The volume stays open between commands, so
+    directories and ICBs read by one command are served from the cache for the next.
+*/
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+use libudf_rs::catalogue::ContentHasher;
+use libudf_rs::format::human_size;
+use libudf_rs::path::{PathComponent, UdfPath};
+use libudf_rs::raw::EntryKind;
+
+use crate::{ls, take_warnings, Volume};
+
+const HELP: &str = "commands:
+    cd [path]            change the current directory, the root directory by default
+    pwd                  print the current directory
+    ls [path]            contents of a directory
+    stat <path>          times, size and recorded extents of an entry
+    get <path> [file]    copy a file to the local file system
+    hash <path>          CRC-32 and size of a file
+    help                 this list
+    exit                 leave the shell";
+
+/// CRC-32 as used by zip and gzip
+#[derive(Default)]
+struct Crc32 {
+    crc: u32,
+    len: u64,
+}
+
+impl ContentHasher for Crc32 {
+    fn update(&mut self, data: &[u8]) {
+        let mut crc = !self.crc;
+        for &b in data {
+            crc ^= b as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 != 0 {
+                    (crc >> 1) ^ 0xedb88320
+                } else {
+                    crc >> 1
+                };
+            }
+        }
+        self.crc = !crc;
+        self.len += data.len() as u64;
+    }
+
+    fn finish(&mut self) -> String {
+        let result = format!("{:08x}  {} bytes", self.crc, self.len);
+        *self = Crc32::default();
+        result
+    }
+}
+
+struct Shell {
+    udf: Volume,
+    /// absolute path of the current directory in canonical form
+    cwd: String,
+}
+
+impl Shell {
+    /// `path` as an absolute path in canonical form, relative paths are taken relative to the
+    /// current directory. As in `cd`, `..` is followed through the parent FIDs and the path
+    /// of the directory holding the entry is reconstructed from the volume.
+    fn resolve(&mut self, path: &str) -> Result<String, Box<dyn Error>> {
+        let mut parsed = UdfPath::parse(&self.join(path))?;
+        if parsed.dir_only {
+            return self.dir_path(&parsed.to_string());
+        }
+        let Some(PathComponent::Name(name)) = parsed.components.pop() else {
+            return self.dir_path(&parsed.to_string());
+        };
+        parsed.dir_only = true;
+        let dir = self.dir_path(&parsed.to_string())?;
+        Ok(format!("{}/{}", dir.trim_end_matches('/'), name))
+    }
+
+    /// the absolute path of the directory at `path`, reconstructed from the volume
+    fn dir_path(&mut self, path: &str) -> Result<String, Box<dyn Error>> {
+        let icb = self.udf.find_icb(Path::new(path))?;
+        if icb.kind() != EntryKind::Dir {
+            Err(std::io::Error::from(std::io::ErrorKind::NotADirectory))?
+        }
+        self.udf.dir_path(&icb)
+    }
+
+    /// `path` as an absolute path, relative paths are taken relative to the current directory
+    fn join(&self, path: &str) -> String {
+        if path.starts_with('/') {
+            path.to_string()
+        } else {
+            format!("{}/{}", self.cwd, path)
+        }
+    }
+
+    fn cd(&mut self, path: &str) -> Result<String, Box<dyn Error>> {
+        self.cwd = self.dir_path(&self.join(path))?;
+        Ok(String::new())
+    }
+
+    fn stat(&mut self, path: &str) -> Result<String, Box<dyn Error>> {
+        let path = self.resolve(path)?;
+        let icb = self.udf.find_icb(Path::new(&path))?;
+        let sector = self.udf.lb_to_sector(&icb.loc)?;
+        let entry = self.udf.catalogue_entry(path, &icb)?;
+        let mut size = human_size(entry.size);
+        if entry.size >= 1024 {
+            size += &format!(" ({} bytes)", entry.size);
+        }
+        let mut out = format!(
+            "Path:     {}\nKind:     {}\nSize:     {}\nModified: {}\nAccessed: {}\nChanged:  {}\n",
+            entry.path, entry.kind, size, entry.mtime, entry.atime, entry.attrtime
+        );
+        out += &format!(
+            "ICB:      block {} of partition {}, sector {}\n",
+            icb.loc.lbn, icb.loc.part_ref_nr, sector
+        );
+        if entry.extents.is_empty() {
+            out += "Extents:  embedded in the ICB\n";
+        }
+        for ext in &entry.extents {
+            out += &format!("Extent:   {} bytes at offset {}\n", ext.len, ext.offset);
+        }
+        Ok(out)
+    }
+
+    fn get(&mut self, path: &str, dest: Option<&str>) -> Result<String, Box<dyn Error>> {
+        let path = self.resolve(path)?;
+        let mut source = self.udf.open(Path::new(&path))?;
+        let dest = match dest {
+            Some(dest) => dest,
+            None => path.rsplit('/').next().unwrap_or_default(),
+        };
+        let mut file = File::create(dest).map_err(|e| format!("{}: {}", dest, e))?;
+        let len = std::io::copy(&mut source, &mut file)?;
+        Ok(format!("{} bytes written to {}\n", len, dest))
+    }
+
+    fn hash(&mut self, path: &str) -> Result<String, Box<dyn Error>> {
+        let path = self.resolve(path)?;
+        let icb = self.udf.find_icb(Path::new(&path))?;
+        if icb.kind() == EntryKind::Dir {
+            Err(std::io::Error::from(std::io::ErrorKind::IsADirectory))?
+        }
+        let hash = self.udf.hash_contents(&icb, &mut Crc32::default())?;
+        Ok(format!("{}  {}\n", hash, path))
+    }
+
+    /// runs the command `line`, returning its output or `None` to leave the shell
+    fn execute(&mut self, line: &str) -> Option<Result<String, Box<dyn Error>>> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let result = match words[..] {
+            [] => Ok(String::new()),
+            ["exit"] | ["quit"] => return None,
+            ["help"] => Ok(format!("{}\n", HELP)),
+            ["pwd"] => Ok(format!("{}\n", self.cwd)),
+            ["cd"] => self.cd("/"),
+            ["cd", path] => self.cd(path),
+            ["ls"] => ls(&mut self.udf, &self.cwd.clone(), false),
+            ["ls", path] => self
+                .resolve(path)
+                .and_then(|path| ls(&mut self.udf, &path, false)),
+            ["stat", path] => self.stat(path),
+            ["get", path] => self.get(path, None),
+            ["get", path, dest] => self.get(path, Some(dest)),
+            ["hash", path] => self.hash(path),
+            [command, ..] => Err(format!("{}: unknown command or wrong arguments", command).into()),
+        };
+        Some(result)
+    }
+}
+
+/// reads commands from `input` until it ends or `exit` is entered
+pub fn run(udf: Volume, input: impl BufRead) -> Result<(), Box<dyn Error>> {
+    let mut shell = Shell {
+        udf,
+        cwd: "/".to_string(),
+    };
+    let mut lines = input.lines();
+    loop {
+        print!("{}> ", shell.cwd);
+        std::io::stdout().flush()?;
+        let Some(line) = lines.next() else {
+            println!();
+            return Ok(());
+        };
+        match shell.execute(&line?) {
+            None => return Ok(()),
+            Some(Ok(output)) => print!("{}", output),
+            Some(Err(e)) => eprintln!("{}", e),
+        }
+        for warning in take_warnings() {
+            eprintln!("warning: {}", warning);
+        }
+    }
+}
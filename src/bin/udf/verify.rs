@@ -0,0 +1,294 @@
+/*
+    Read-back verification of a volume, e.g. after burning it. The level decides how much is
+    read and how strictly inconsistencies are judged:
+
+    - quick: the volume descriptors, the integrity sequence and all directories, whose
+      numbers of files and directories have to match those the integrity sequence records and
+      whose extents must not overlap, and on bridge discs the ISO 9660 volume descriptor,
+      which has to describe the same volume
+    - normal: additionally the data of every file
+    - strict: like normal, but the volume is opened in strict mode and every warning counts
+      as an error
+*/
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt::Write;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::process::ExitCode;
+use std::str::FromStr;
+
+use libudf_rs::catalogue::{json_str, ContentHasher, CountCheck};
+use libudf_rs::format::human_size;
+use libudf_rs::raw::IntegrityType;
+use libudf_rs::{UdfOptions, UDF};
+
+use crate::take_warnings;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Level {
+    Quick,
+    #[default]
+    Normal,
+    Strict,
+}
+
+impl FromStr for Level {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "quick" => Ok(Level::Quick),
+            "normal" => Ok(Level::Normal),
+            "strict" => Ok(Level::Strict),
+            _ => Err(format!("unknown verification level {}", s).into()),
+        }
+    }
+}
+
+/// overall result of a verification, the exit code of `udf verify`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    /// exit code 0
+    Clean,
+    /// exit code 1, the volume is readable but inconsistencies were worked around
+    Warnings,
+    /// exit code 2, parts of the volume can't be read
+    Errors,
+}
+
+impl Status {
+    fn as_str(self) -> &'static str {
+        match self {
+            Status::Clean => "clean",
+            Status::Warnings => "warnings",
+            Status::Errors => "errors",
+        }
+    }
+
+    pub fn exit_code(self) -> ExitCode {
+        ExitCode::from(self as u8)
+    }
+}
+
+/// a hasher that only reads the data
+struct Discard;
+
+impl ContentHasher for Discard {
+    fn update(&mut self, _data: &[u8]) {}
+    fn finish(&mut self) -> String {
+        String::new()
+    }
+}
+
+/// outcome of reading back a volume
+pub struct Verification {
+    level: Level,
+    dirs: usize,
+    files: usize,
+    bytes: u64,
+    warnings: Vec<String>,
+    /// what went wrong, with the path it went wrong at unless it concerns the whole volume
+    errors: Vec<(Option<String>, String)>,
+}
+
+impl Verification {
+    pub fn status(&self) -> Status {
+        if !self.errors.is_empty() || (self.level == Level::Strict && !self.warnings.is_empty()) {
+            Status::Errors
+        } else if !self.warnings.is_empty() {
+            Status::Warnings
+        } else {
+            Status::Clean
+        }
+    }
+
+    pub fn report(&self, json: bool) -> String {
+        let mut out = String::new();
+        if json {
+            write!(
+                out,
+                "{{\"status\":\"{}\",\"dirs\":{},\"files\":{},\"bytes\":{},\"warnings\":[",
+                self.status().as_str(),
+                self.dirs,
+                self.files,
+                self.bytes
+            )
+            .unwrap();
+            for (i, warning) in self.warnings.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                json_str(&mut out, warning);
+            }
+            out.push_str("],\"errors\":[");
+            for (i, (path, error)) in self.errors.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str("{\"path\":");
+                match path {
+                    Some(path) => json_str(&mut out, path),
+                    None => out.push_str("null"),
+                }
+                out.push_str(",\"error\":");
+                json_str(&mut out, error);
+                out.push('}');
+            }
+            out.push_str("]}");
+            return out;
+        }
+        for warning in &self.warnings {
+            writeln!(out, "warning: {}", warning).unwrap();
+        }
+        for (path, error) in &self.errors {
+            match path {
+                Some(path) => writeln!(out, "error: {}: {}", path, error),
+                None => writeln!(out, "error: {}", error),
+            }
+            .unwrap();
+        }
+        writeln!(
+            out,
+            "{} directories, {} files, {} read, {} warnings, {} errors: {}",
+            self.dirs,
+            self.files,
+            human_size(self.bytes),
+            self.warnings.len(),
+            self.errors.len(),
+            self.status().as_str()
+        )
+        .unwrap();
+        out
+    }
+}
+
+/// verifies the volume in `image`. Fails only if the image can't be opened at all, problems
+/// of the volume are part of the result.
+pub fn verify(
+    image: &str,
+    level: Level,
+    max_rate: Option<u64>,
+) -> Result<Verification, Box<dyn Error>> {
+    let file = File::open(image).map_err(|e| format!("{}: {}", image, e))?;
+    let mut result = Verification {
+        level,
+        dirs: 0,
+        files: 0,
+        bytes: 0,
+        warnings: Vec::new(),
+        errors: Vec::new(),
+    };
+    take_warnings();
+    check(&mut result, file, max_rate);
+    result.warnings = take_warnings();
+    Ok(result)
+}
+
+fn check(result: &mut Verification, file: File, max_rate: Option<u64>) {
+    let options = UdfOptions {
+        strict: result.level == Level::Strict,
+        max_read_rate: max_rate,
+        ..Default::default()
+    };
+    let mut udf = match UDF::new_with_options(BufReader::new(file), options) {
+        Ok(udf) => udf,
+        Err(e) => return result.errors.push((None, e.to_string())),
+    };
+    let mut impl_use = None;
+    match udf.integrity_sequence() {
+        Ok(seq) => match seq.current {
+            Some(lvid) if lvid.integ_type == IntegrityType::OPEN => {
+                log::warn!("logical volume integrity descriptor is open")
+            }
+            Some(lvid) => impl_use = lvid.impl_use,
+            None => log::warn!("no logical volume integrity descriptor recorded"),
+        },
+        Err(e) => result.errors.push((None, e.to_string())),
+    }
+    // the ISO 9660 side of a bridge disc should show the same volume
+    match udf.bridge_mismatches() {
+        Ok(mismatches) => mismatches.iter().for_each(|m| log::warn!("{}", m)),
+        Err(e) => result.errors.push((None, e.to_string())),
+    }
+    let catalogue = match udf.catalogue() {
+        Ok(catalogue) => catalogue,
+        Err(e) => return result.errors.push((None, e.to_string())),
+    };
+    // the counts of an open integrity descriptor may well be outdated
+    if let Some(impl_use) = impl_use {
+        for discrepancy in CountCheck::new(&impl_use, &catalogue).discrepancies() {
+            log::warn!("{}", discrepancy);
+        }
+    }
+    for overlap in catalogue.extent_overlaps() {
+        let error = format!(
+            "sectors {}..{} also belong to {}",
+            overlap.sectors.start, overlap.sectors.end, overlap.second
+        );
+        result.errors.push((Some(overlap.first), error));
+    }
+    // blocks marked as allocated that are unused are lost space, blocks in use marked as
+    // free would be overwritten by the next writer
+    match udf.orphan_blocks() {
+        Ok(partitions) => {
+            for part in partitions {
+                for blocks in part.unreferenced {
+                    log::warn!(
+                        "blocks {}..{} of partition {} are allocated but unused",
+                        blocks.start,
+                        blocks.end,
+                        part.part_num
+                    );
+                }
+                for blocks in part.unallocated {
+                    let error = format!(
+                        "blocks {}..{} of partition {} are in use but marked as free",
+                        blocks.start, blocks.end, part.part_num
+                    );
+                    result.errors.push((None, error));
+                }
+            }
+        }
+        Err(e) => result.errors.push((None, e.to_string())),
+    }
+    let truncation = udf.truncation().cloned();
+    // the ICBs of the files checked so far, further links to them are skipped
+    let mut checked = HashSet::new();
+    for entry in catalogue.entries {
+        if entry.is_dir {
+            result.dirs += 1;
+            continue;
+        }
+        if entry.icb.is_some_and(|icb| !checked.insert(icb)) {
+            continue;
+        }
+        result.files += 1;
+        // data cut off by the end of the image is lost, whatever the level
+        if let Some(t) = &truncation {
+            if entry
+                .extents
+                .iter()
+                .any(|ext| t.cuts(ext.offset, ext.len as u64))
+            {
+                let error = "data lies beyond the end of the truncated image".to_string();
+                result.errors.push((Some(entry.path), error));
+                continue;
+            }
+        }
+        if result.level == Level::Quick {
+            continue;
+        }
+        let icb = match entry.icb {
+            Some(loc) => udf.read_icb(&loc),
+            None => udf.find_icb(Path::new(&entry.path)),
+        };
+        let read = icb.and_then(|icb| udf.hash_contents(&icb, &mut Discard));
+        match read {
+            Ok(_) => result.bytes += entry.size,
+            Err(e) => result.errors.push((Some(entry.path), e.to_string())),
+        }
+    }
+}
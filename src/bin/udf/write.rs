@@ -0,0 +1,133 @@
+/*
+    The commands writing images, only available with the `writer` feature.
+*/
+
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::BufWriter;
+use std::path::Path;
+
+use libudf_rs::raw::Timestamp;
+use libudf_rs::swap::modify_copy;
+use libudf_rs::undo::rollback_file;
+use libudf_rs::writer::{create_image, CreateOptions, WriteReport};
+use libudf_rs::{UdfOptions, UDF};
+
+use crate::{Args, Volume};
+
+/// parses a UDF revision like 2.01 into its BCD form
+pub fn parse_revision(s: &str) -> Result<u16, Box<dyn Error>> {
+    let invalid = || format!("invalid UDF revision {}", s);
+    let (major, minor) = s.split_once('.').ok_or_else(invalid)?;
+    if major.is_empty() || major.len() > 2 || minor.len() != 2 {
+        Err(invalid())?
+    }
+    let major = u16::from_str_radix(major, 16).map_err(|_| invalid())?;
+    let minor = u16::from_str_radix(minor, 16).map_err(|_| invalid())?;
+    Ok(major << 8 | minor)
+}
+
+/// `options` with the revision and label given on the command line
+fn create_options(mut options: CreateOptions, args: &Args) -> CreateOptions {
+    if let Some(revision) = args.revision {
+        options.revision = revision;
+    }
+    if let Some(label) = &args.label {
+        options.label = label.clone();
+    }
+    options
+}
+
+fn write_report(report: &WriteReport, json: bool) -> String {
+    if json {
+        format!(
+            "{{\"files\":{},\"dirs\":{},\"bytes\":{}}}",
+            report.files, report.directories, report.bytes
+        )
+    } else {
+        format!(
+            "{} directories, {} files, {} bytes written\n",
+            report.directories, report.files, report.bytes
+        )
+    }
+}
+
+pub fn create(image: &str, args: &Args) -> Result<String, Box<dyn Error>> {
+    let source = args
+        .from
+        .as_ref()
+        .ok_or("create needs a source directory, see --from")?;
+    let options = create_options(CreateOptions::default(), args);
+    options.validate()?;
+    let file = File::create(image).map_err(|e| format!("{}: {}", image, e))?;
+    let report = create_image(&mut BufWriter::new(file), Path::new(source), &options)?;
+    Ok(write_report(&report, args.json))
+}
+
+pub fn clone(udf: &mut Volume, dest: &str, args: &Args) -> Result<String, Box<dyn Error>> {
+    let options = create_options(udf.clone_options(), args);
+    options.validate()?;
+    let exclude: Vec<&str> = args.exclude.iter().map(String::as_str).collect();
+    let file = File::create(dest).map_err(|e| format!("{}: {}", dest, e))?;
+    let report = udf.clone_image(&mut BufWriter::new(file), &exclude, &options)?;
+    Ok(write_report(&report, args.json))
+}
+
+/// runs `modify` on the volume of `image`, in place or with --swap on a copy replacing it
+fn modify<T>(
+    image: &str,
+    args: &Args,
+    modify: impl FnOnce(&mut UDF<File>) -> Result<T, Box<dyn Error>>,
+) -> Result<T, Box<dyn Error>> {
+    if args.swap {
+        if args.undo.is_some() {
+            Err("--undo and --swap can't be combined")?
+        }
+        let (result, _) = modify_copy(Path::new(image), UdfOptions::default(), modify)?;
+        return Ok(result);
+    }
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(image)
+        .map_err(|e| format!("{}: {}", image, e))?;
+    let mut udf = UDF::new(file)?;
+    if let Some(undo) = &args.undo {
+        udf.start_undo_journal(Path::new(undo))?;
+    }
+    modify(&mut udf)
+}
+
+pub fn add(image: &str, local: &str, path: &str, args: &Args) -> Result<String, Box<dyn Error>> {
+    let mut data = File::open(local).map_err(|e| format!("{}: {}", local, e))?;
+    let meta = data.metadata()?;
+    if !meta.is_file() {
+        Err(format!("{}: not a regular file", local))?
+    }
+    let mtime = Timestamp::from_system_time(meta.modified()?);
+    let report = modify(image, args, |udf| {
+        udf.add_file(Path::new(path), &mut data, meta.len(), mtime, args.force)
+            .map_err(|e| format!("{}: {}", path, e).into())
+    })?;
+    Ok(write_report(&report, args.json))
+}
+
+pub fn rollback(image: &str, journal: &str) -> Result<String, Box<dyn Error>> {
+    rollback_file(Path::new(image), Path::new(journal))?;
+    Ok(String::new())
+}
+
+pub fn scrub(image: &str, args: &Args) -> Result<String, Box<dyn Error>> {
+    let report = modify(image, args, |udf| udf.scrub(args.force))?;
+    Ok(if args.json {
+        format!(
+            "{{\"slack_bytes\":{},\"unallocated_bytes\":{}}}",
+            report.slack_bytes, report.unallocated_bytes
+        )
+    } else {
+        format!(
+            "{} bytes of slack and {} unallocated bytes zero-filled\n",
+            report.slack_bytes, report.unallocated_bytes
+        )
+    })
+}
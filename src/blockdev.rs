@@ -0,0 +1,102 @@
+/*
+    Backend for reading volumes straight from Linux block devices such as /dev/sr0.
+
+    Reads are widened to whole device sectors, which O_DIRECT requires for both the offset and
+    the length of a read as well as the address of the buffer.
+*/
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Seek, SeekFrom};
+use std::os::unix::fs::{FileExt, FileTypeExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+use crate::backend::ReadAt;
+
+/// sector size assumed for files that aren't block devices
+const DEFAULT_SECTOR_SIZE: u32 = 512;
+
+#[derive(Debug)]
+pub struct BlockDevice {
+    file: File,
+    sector_size: u32,
+    capacity: u64,
+}
+
+impl BlockDevice {
+    /// opens the device at `path` for reading through the page cache
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::open_with_flags(path.as_ref(), 0)
+    }
+
+    /// opens the device at `path` for reading with O_DIRECT, bypassing the page cache
+    pub fn open_direct<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::open_with_flags(path.as_ref(), libc::O_DIRECT)
+    }
+
+    fn open_with_flags(path: &Path, flags: i32) -> io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .custom_flags(flags)
+            .open(path)?;
+        let sector_size = if file.metadata()?.file_type().is_block_device() {
+            let mut size: libc::c_int = 0;
+            // SAFETY: BLKSSZGET stores the logical sector size in the int pointed to
+            if unsafe { libc::ioctl(file.as_raw_fd(), libc::BLKSSZGET, &mut size) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            size as u32
+        } else {
+            DEFAULT_SECTOR_SIZE
+        };
+        // unlike the metadata length, seeking to the end also works for block devices
+        let capacity = file.seek(SeekFrom::End(0))?;
+        Ok(Self {
+            file,
+            sector_size,
+            capacity,
+        })
+    }
+
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    /// logical sector size reported by the device
+    pub fn sector_size(&self) -> u32 {
+        self.sector_size
+    }
+
+    /// capacity reported by the device in bytes, the end of the volume the anchors at N - 256
+    /// and N - 1 are relative to
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+}
+
+impl ReadAt for BlockDevice {
+    fn read_at(&self, pos: u64, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || pos >= self.capacity {
+            return Ok(0);
+        }
+        let sector_size = self.sector_size as u64;
+        let start = pos - pos % sector_size;
+        let end = (pos + buf.len() as u64)
+            .min(self.capacity)
+            .next_multiple_of(sector_size);
+        let len = (end - start) as usize;
+        // over-allocate so that an aligned window of `len` bytes fits
+        let mut scratch = vec![0; len + self.sector_size as usize];
+        let off = scratch.as_ptr().align_offset(self.sector_size as usize);
+        let aligned = &mut scratch[off..off + len];
+        let read = FileExt::read_at(&self.file, aligned, start)?;
+        let skip = (pos - start) as usize;
+        let n = read.saturating_sub(skip).min(buf.len());
+        buf[..n].copy_from_slice(&aligned[skip..skip + n]);
+        Ok(n)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.capacity)
+    }
+}
@@ -0,0 +1,174 @@
+/*
+    Verification of media: block integrity maps hold hashes of all allocated blocks of a
+    volume, stored next to an archived disc and compared against later reads to detect bit
+    rot. Burned discs can also be compared directly against their source image.
+*/
+
+use std::error::Error;
+use std::fmt::Write;
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::stats::IoCategory;
+use crate::volume::LSN;
+use crate::{MAX_MERGED_SECTORS, UDF};
+
+/// sectors per ECC block of DVD and BD media
+pub const ECC_BLOCK_SECTORS: u32 = 16;
+
+/// 64 bit FNV-1a hash
+fn block_hash(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockMap {
+    /// sectors per hashed block
+    pub granularity: u32,
+    /// first sector and hash of every block containing allocated sectors
+    pub blocks: Vec<(LSN, u64)>,
+}
+
+impl BlockMap {
+    /// serializes the map as text, one block per line
+    pub fn to_text(&self) -> String {
+        let mut out = format!("udf-blockmap 1 {}\n", self.granularity);
+        for (lsn, hash) in &self.blocks {
+            writeln!(out, "{} {:016x}", lsn, hash).unwrap();
+        }
+        out
+    }
+
+    pub fn from_text(s: &str) -> Result<Self, Box<dyn Error>> {
+        let mut lines = s.lines();
+        let granularity = match lines.next().map(|l| l.split(' ').collect::<Vec<_>>()) {
+            Some(header) if header.len() == 3 && header[..2] == ["udf-blockmap", "1"] => {
+                header[2].parse()?
+            }
+            _ => Err("not a block map")?,
+        };
+        if granularity == 0 {
+            Err("invalid block map granularity")?
+        }
+        let mut blocks = Vec::new();
+        for line in lines.filter(|l| !l.is_empty()) {
+            let (lsn, hash) = line.split_once(' ').ok_or("invalid block map entry")?;
+            blocks.push((lsn.parse()?, u64::from_str_radix(hash, 16)?));
+        }
+        Ok(Self {
+            granularity,
+            blocks,
+        })
+    }
+}
+
+/// compares the allocated sectors of the UDF image `source` with the same sectors of `copy`,
+/// e.g. a freshly burned disc, returning the sectors that differ or can't be read
+pub fn verify_against<A: Read + Seek, B: Read + Seek>(
+    source: A,
+    copy: &mut B,
+) -> Result<Vec<LSN>, Box<dyn Error>> {
+    UDF::new(source)?.verify_copy(copy)
+}
+
+impl<IO: Read + Seek> UDF<IO> {
+    /// compares the allocated sectors of this volume with the same sectors of `copy` in a
+    /// single pass, returning the sectors that differ or can't be read from `copy`
+    pub fn verify_copy<R: Read + Seek>(
+        &mut self,
+        copy: &mut R,
+    ) -> Result<Vec<LSN>, Box<dyn Error>> {
+        let bs = self.sector_size();
+        let mut mismatches = Vec::new();
+        for range in self.allocated_sectors()? {
+            let mut lsn = range.start;
+            while lsn < range.end {
+                let count = (range.end - lsn).min(MAX_MERGED_SECTORS as u32);
+                let expected = self.read_span(lsn, count)?;
+                let actual = read_span(copy, bs, lsn, count);
+                for (i, sector) in expected.chunks(bs as usize).enumerate() {
+                    let start = i * bs as usize;
+                    let same = match &actual {
+                        Ok(actual) => actual.get(start..start + sector.len()) == Some(sector),
+                        // find out which of the sectors can't be read
+                        Err(_) => {
+                            self.stats.retries += 1;
+                            read_span(copy, bs, lsn + i as LSN, 1).is_ok_and(|s| s == sector)
+                        }
+                    };
+                    if !same {
+                        mismatches.push(lsn + i as LSN);
+                    }
+                }
+                lsn += count;
+            }
+        }
+        Ok(mismatches)
+    }
+
+    /// hashes every block of `granularity` sectors containing allocated sectors, e.g.
+    /// `ECC_BLOCK_SECTORS` to match the error correction blocks of the medium
+    pub fn block_map(&mut self, granularity: u32) -> Result<BlockMap, Box<dyn Error>> {
+        if granularity == 0 {
+            Err("invalid block map granularity")?
+        }
+        let mut starts: Vec<LSN> = Vec::new();
+        for range in self.allocated_sectors()? {
+            let first = range.start / granularity;
+            let last = (range.end - 1) / granularity;
+            for block in first..=last {
+                // ranges are sorted, so only the previous range can share a block
+                if starts.last() != Some(&(block * granularity)) {
+                    starts.push(block * granularity);
+                }
+            }
+        }
+        let mut blocks = Vec::with_capacity(starts.len());
+        for lsn in starts {
+            let data = self.read_span(lsn, granularity)?;
+            blocks.push((lsn, block_hash(&data)));
+        }
+        Ok(BlockMap {
+            granularity,
+            blocks,
+        })
+    }
+
+    /// re-reads the blocks of `map` and returns the first sector of every block that can't
+    /// be read or whose contents changed
+    pub fn verify_block_map(&mut self, map: &BlockMap) -> Result<Vec<LSN>, Box<dyn Error>> {
+        let mut mismatches = Vec::new();
+        for &(lsn, hash) in &map.blocks {
+            match self.read_span(lsn, map.granularity) {
+                Ok(data) if block_hash(&data) == hash => {}
+                _ => mismatches.push(lsn),
+            }
+        }
+        Ok(mismatches)
+    }
+
+    fn read_span(&mut self, lsn: LSN, count: u32) -> std::io::Result<Vec<u8>> {
+        let bs = self.sector_size();
+        self.record_read(
+            IoCategory::Data,
+            lsn as u64 * bs,
+            count as usize * bs as usize,
+        );
+        read_span(&mut self.io, bs, lsn, count)
+    }
+}
+
+/// reads `count` sectors of `sector_size` bytes starting at `lsn`, stopping early at the end
+/// of the medium
+fn read_span<R: Read + Seek>(
+    io: &mut R,
+    sector_size: u64,
+    lsn: LSN,
+    count: u32,
+) -> std::io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    io.seek(SeekFrom::Start(lsn as u64 * sector_size))?;
+    io.take(count as u64 * sector_size).read_to_end(&mut data)?;
+    Ok(data)
+}
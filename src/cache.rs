@@ -0,0 +1,217 @@
+/*
+    Cache of metadata read from the volume: ICBs by their address and the entry lists of
+    directories by the address of the directory ICB.
+
+    A cache can be shared by several volumes, e.g. all volumes of a `Mounts` table, so that
+    they are bounded by a common capacity. Entries are kept apart by a per-volume id.
+
+    Anything changing recorded structures has to invalidate the addresses it touched, see
+    `UDF::invalidate`.
+*/
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::file::{DirEntry, LBAddr, ICB};
+
+/// source of the ids telling volumes sharing a cache apart
+static NEXT_VOLUME: AtomicU32 = AtomicU32::new(0);
+
+type Key = (u32, LBAddr);
+
+/// approximate memory held by a cached ICB
+fn icb_size(icb: &ICB) -> usize {
+    let body = icb
+        .file_entry()
+        .map_or(0, |fe| fe.ex_attrs().len() + fe.alloc_descs().len());
+    size_of::<ICB>() + body
+}
+
+/// approximate memory held by a cached directory listing
+fn dir_size(entries: &[DirEntry]) -> usize {
+    entries
+        .iter()
+        .map(|e| size_of::<DirEntry>() + e.name.len())
+        .sum()
+}
+
+struct Entries {
+    /// maximum number of ICBs and directories each, 0 disables caching
+    capacity: usize,
+    /// maximum memory held by all entries, see `icb_size` and `dir_size`
+    max_bytes: usize,
+    bytes: usize,
+    icbs: HashMap<Key, ICB>,
+    /// listings are shared with the lookups reading them instead of being copied
+    dirs: HashMap<Key, Arc<Vec<DirEntry>>>,
+    /// insertion order of the ICB and directory keys, oldest first
+    icb_order: VecDeque<Key>,
+    dir_order: VecDeque<Key>,
+}
+
+impl Entries {
+    fn remove_icb(&mut self, key: &Key) {
+        if let Some(icb) = self.icbs.remove(key) {
+            self.bytes -= icb_size(&icb);
+        }
+    }
+
+    fn remove_dir(&mut self, key: &Key) {
+        if let Some(entries) = self.dirs.remove(key) {
+            self.bytes -= dir_size(&entries);
+        }
+    }
+
+    /// evicts the oldest entries until the memory limit is kept, ICBs first as they are
+    /// cheaper to read again
+    fn shrink(&mut self) {
+        while self.bytes > self.max_bytes {
+            if let Some(key) = self.icb_order.pop_front() {
+                self.remove_icb(&key);
+            } else if let Some(key) = self.dir_order.pop_front() {
+                self.remove_dir(&key);
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+pub(crate) struct MetaCache {
+    entries: Arc<Mutex<Entries>>,
+    volume: u32,
+}
+
+impl MetaCache {
+    /// creates a cache of `capacity` ICBs and directories holding at most `max_bytes`
+    pub fn new(capacity: usize, max_bytes: Option<usize>) -> Self {
+        Self {
+            entries: Arc::new(Mutex::new(Entries {
+                capacity,
+                max_bytes: max_bytes.unwrap_or(usize::MAX),
+                bytes: 0,
+                icbs: HashMap::new(),
+                dirs: HashMap::new(),
+                icb_order: VecDeque::new(),
+                dir_order: VecDeque::new(),
+            })),
+            volume: NEXT_VOLUME.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    /// a handle for another volume sharing the entries and capacity of this cache
+    pub fn attach(&self) -> Self {
+        Self {
+            entries: self.entries.clone(),
+            volume: NEXT_VOLUME.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Entries> {
+        // entries are only inserted whole, so they are consistent even after a panic
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// approximate memory held by the cached entries of all volumes sharing this cache
+    pub fn bytes(&self) -> usize {
+        self.lock().bytes
+    }
+
+    pub fn icb(&self, loc: &LBAddr) -> Option<ICB> {
+        self.lock().icbs.get(&(self.volume, *loc)).cloned()
+    }
+
+    pub fn contains_icb(&self, loc: &LBAddr) -> bool {
+        self.lock().icbs.contains_key(&(self.volume, *loc))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.lock().capacity > 0
+    }
+
+    pub fn insert_icb(&self, icb: ICB) {
+        let mut e = self.lock();
+        let size = icb_size(&icb);
+        if e.capacity == 0 || size > e.max_bytes {
+            return;
+        }
+        let key = (self.volume, icb.loc);
+        let replaced = e.icbs.contains_key(&key);
+        e.remove_icb(&key);
+        e.bytes += size;
+        e.icbs.insert(key, icb);
+        if !replaced {
+            e.icb_order.push_back(key);
+            if e.icb_order.len() > e.capacity {
+                let oldest = e.icb_order.pop_front().unwrap();
+                e.remove_icb(&oldest);
+            }
+        }
+        e.shrink();
+    }
+
+    pub fn dir(&self, loc: &LBAddr) -> Option<Arc<Vec<DirEntry>>> {
+        self.lock().dirs.get(&(self.volume, *loc)).cloned()
+    }
+
+    pub fn insert_dir(&self, loc: LBAddr, entries: Arc<Vec<DirEntry>>) {
+        let mut e = self.lock();
+        let size = dir_size(&entries);
+        if e.capacity == 0 || size > e.max_bytes {
+            return;
+        }
+        let key = (self.volume, loc);
+        let replaced = e.dirs.contains_key(&key);
+        e.remove_dir(&key);
+        e.bytes += size;
+        e.dirs.insert(key, entries);
+        if !replaced {
+            e.dir_order.push_back(key);
+            if e.dir_order.len() > e.capacity {
+                let oldest = e.dir_order.pop_front().unwrap();
+                e.remove_dir(&oldest);
+            }
+        }
+        e.shrink();
+    }
+
+    /// drops the ICB at `loc` and the entries of the directory it describes
+    pub fn invalidate(&self, loc: &LBAddr) {
+        let mut e = self.lock();
+        let key = (self.volume, *loc);
+        if e.icbs.contains_key(&key) {
+            e.remove_icb(&key);
+            e.icb_order.retain(|k| *k != key);
+        }
+        if e.dirs.contains_key(&key) {
+            e.remove_dir(&key);
+            e.dir_order.retain(|k| *k != key);
+        }
+    }
+
+    /// drops all directory entry lists of this volume, e.g. after the way names are decoded
+    /// changed
+    pub fn invalidate_dirs(&self) {
+        let mut e = self.lock();
+        let volume = self.volume;
+        let keys: Vec<Key> = e.dirs.keys().filter(|k| k.0 == volume).copied().collect();
+        for key in &keys {
+            e.remove_dir(key);
+        }
+        e.dir_order.retain(|k| k.0 != volume);
+    }
+
+    /// drops all entries of this volume
+    pub fn clear(&self) {
+        let mut e = self.lock();
+        let volume = self.volume;
+        let keys: Vec<Key> = e.icbs.keys().filter(|k| k.0 == volume).copied().collect();
+        for key in &keys {
+            e.remove_icb(key);
+        }
+        e.icb_order.retain(|k| k.0 != volume);
+        drop(e);
+        self.invalidate_dirs();
+    }
+}
@@ -0,0 +1,829 @@
+/*
+    Complete machine-readable manifest of a volume: volume information and every file and
+    directory with its size, times and recorded extents, optionally with content hashes.
+*/
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::Write;
+use std::io::{Read, Seek};
+use std::ops::Range;
+use std::str::FromStr;
+
+use crate::file::{AllocType, DirEntry, EntryKind, FileType, LBAddr, ICB};
+use crate::volume::{LVIDImplUse, Timestamp, LSN};
+use crate::{DirectoryLinkError, BLOCKSIZE, UDF};
+
+/// size of the chunks file contents are fed to a `ContentHasher` in
+const HASH_CHUNK: usize = 64 * 1024;
+/// deepest nesting of arrays and objects accepted when reading back catalogues
+const MAX_JSON_DEPTH: usize = 128;
+
+/// computes the content hash stored in catalogue entries, e.g. a wrapper around a SHA-256
+/// implementation
+pub trait ContentHasher {
+    fn update(&mut self, data: &[u8]);
+    /// returns the hash of all data passed to `update` since the last call and resets
+    fn finish(&mut self) -> String;
+}
+
+/// The order the entries of a directory are walked in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WalkOrder {
+    /// the order the FIDs are recorded in
+    #[default]
+    OnDisc,
+    /// by name, comparing characters by their code points
+    Name,
+    /// by the position of the data on the medium, to read files with as few seeks as possible
+    Lba,
+}
+
+#[derive(Clone, Debug)]
+pub struct VolumeInfo {
+    pub vol_ident: String,
+    pub vol_set_ident: String,
+    pub lv_ident: String,
+    pub udf_revision: u16,
+    pub record_time: Timestamp,
+    /// size of the sectors of the medium in bytes
+    pub sector_size: u64,
+}
+
+/// a recorded extent of a file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CatalogueExtent {
+    /// byte offset on the medium
+    pub offset: u64,
+    pub len: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct CatalogueEntry {
+    /// absolute path, `/` for the root directory
+    pub path: String,
+    pub is_dir: bool,
+    pub kind: EntryKind,
+    pub size: u64,
+    pub mtime: Timestamp,
+    pub atime: Timestamp,
+    pub attrtime: Timestamp,
+    /// extents the data is recorded in, empty for data embedded in the ICB
+    pub extents: Vec<CatalogueExtent>,
+    pub hash: Option<String>,
+    /// location of the ICB, shared by all links of a hard-linked file. `None` in catalogues
+    /// written before it was recorded.
+    pub icb: Option<LBAddr>,
+}
+
+impl CatalogueEntry {
+    /// whether size, modification time and extents of both entries match, which is taken as
+    /// unchanged contents without comparing hashes
+    pub fn same_fingerprint(&self, other: &CatalogueEntry) -> bool {
+        self.kind == other.kind
+            && self.size == other.size
+            && self.mtime.to_string() == other.mtime.to_string()
+            && self.extents == other.extents
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Catalogue {
+    pub volume: VolumeInfo,
+    /// all files and directories, each directory followed by its contents
+    pub entries: Vec<CatalogueEntry>,
+}
+
+/// differences between a volume and an earlier catalogue of it
+#[derive(Clone, Debug)]
+pub struct CatalogueDiff {
+    pub added: Vec<CatalogueEntry>,
+    pub removed: Vec<CatalogueEntry>,
+    /// old and new entry of every modified path
+    pub modified: Vec<(CatalogueEntry, CatalogueEntry)>,
+    /// the catalogue of the volume as it is now
+    pub catalogue: Catalogue,
+}
+
+impl CatalogueDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+/// the numbers of files and directories the integrity descriptor records next to those found
+/// walking the file set
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CountCheck {
+    pub recorded_files: u32,
+    pub recorded_dirs: u32,
+    pub files: usize,
+    /// directories including the root directory
+    pub dirs: usize,
+}
+
+impl CountCheck {
+    /// counts the entries of `catalogue`, hard links to the same file once
+    pub fn new(impl_use: &LVIDImplUse, catalogue: &Catalogue) -> Self {
+        let dirs = catalogue.entries.iter().filter(|e| e.is_dir).count();
+        let mut seen = HashSet::new();
+        let files = catalogue
+            .entries
+            .iter()
+            .filter(|e| !e.is_dir && e.icb.is_none_or(|icb| seen.insert(icb)))
+            .count();
+        CountCheck {
+            recorded_files: impl_use.num_files,
+            recorded_dirs: impl_use.num_dirs,
+            files,
+            dirs,
+        }
+    }
+
+    pub fn is_consistent(&self) -> bool {
+        self.discrepancies().is_empty()
+    }
+
+    /// a description of every count that differs
+    pub fn discrepancies(&self) -> Vec<String> {
+        let mut result = Vec::new();
+        if self.recorded_files as usize != self.files {
+            result.push(format!(
+                "integrity descriptor records {} files, {} found",
+                self.recorded_files, self.files
+            ));
+        }
+        if self.recorded_dirs as usize != self.dirs {
+            result.push(format!(
+                "integrity descriptor records {} directories, {} found",
+                self.recorded_dirs, self.dirs
+            ));
+        }
+        result
+    }
+}
+
+/// sectors recorded as part of two different files or directories, i.e. cross-linked files
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExtentOverlap {
+    /// path of the entry whose extent starts first
+    pub first: String,
+    pub second: String,
+    pub sectors: Range<LSN>,
+}
+
+impl Catalogue {
+    /// finds sectors shared by the extents of different entries. Links to the same ICB share
+    /// their extents and aren't reported. Overlaps of the same two entries in adjacent
+    /// sectors are merged.
+    pub fn extent_overlaps(&self) -> Vec<ExtentOverlap> {
+        let mut extents: Vec<(Range<LSN>, usize)> = Vec::new();
+        for (i, entry) in self.entries.iter().enumerate() {
+            for ext in &entry.extents {
+                let start = (ext.offset / self.volume.sector_size) as LSN;
+                let end = (ext.offset + ext.len as u64).div_ceil(self.volume.sector_size) as LSN;
+                if start < end {
+                    extents.push((start..end, i));
+                }
+            }
+        }
+        extents.sort_by_key(|(r, _)| (r.start, r.end));
+        let mut overlaps: Vec<ExtentOverlap> = Vec::new();
+        // extents that may still overlap with those starting later
+        let mut active: Vec<(Range<LSN>, usize)> = Vec::new();
+        for (range, i) in extents {
+            active.retain(|(r, _)| r.end > range.start);
+            for (r, j) in &active {
+                let (a, b) = (&self.entries[*j], &self.entries[i]);
+                if *j == i || a.icb.is_some() && a.icb == b.icb {
+                    continue;
+                }
+                let sectors = range.start..r.end.min(range.end);
+                let (first, second) = (&a.path, &b.path);
+                let merged = overlaps.iter_mut().find(|o| {
+                    o.first == *first && o.second == *second && o.sectors.end >= sectors.start
+                });
+                match merged {
+                    Some(o) => o.sectors.end = o.sectors.end.max(sectors.end),
+                    None => overlaps.push(ExtentOverlap {
+                        first: first.clone(),
+                        second: second.clone(),
+                        sectors,
+                    }),
+                }
+            }
+            active.push((range, i));
+        }
+        overlaps
+    }
+}
+
+/// appends `s` to `out` as a JSON string
+pub fn json_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// JSON value as far as needed to read back catalogues
+enum Json {
+    Null,
+    Bool(bool),
+    /// numbers are kept as written to parse them into the integer type needed
+    Num(String),
+    Str(String),
+    Arr(Vec<Json>),
+    Obj(Vec<(String, Json)>),
+}
+
+struct JsonParser<'a> {
+    s: &'a str,
+    pos: usize,
+    depth: usize,
+}
+
+impl<'a> JsonParser<'a> {
+    fn skip_ws(&mut self) {
+        let rest = &self.s[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, c: char) -> Result<(), String> {
+        self.skip_ws();
+        if self.s[self.pos..].starts_with(c) {
+            self.pos += c.len_utf8();
+            Ok(())
+        } else {
+            Err(format!("expected '{}' at offset {}", c, self.pos))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        if self.depth == MAX_JSON_DEPTH {
+            return Err(format!("nesting too deep at offset {}", self.pos));
+        }
+        self.depth += 1;
+        let value = self.nested_value();
+        self.depth -= 1;
+        value
+    }
+
+    fn nested_value(&mut self) -> Result<Json, String> {
+        self.skip_ws();
+        let rest = &self.s[self.pos..];
+        match rest.chars().next() {
+            Some('{') => {
+                self.pos += 1;
+                let mut fields = Vec::new();
+                self.skip_ws();
+                if self.s[self.pos..].starts_with('}') {
+                    self.pos += 1;
+                    return Ok(Json::Obj(fields));
+                }
+                loop {
+                    self.skip_ws();
+                    let key = self.string()?;
+                    self.eat(':')?;
+                    fields.push((key, self.value()?));
+                    if self.eat(',').is_err() {
+                        self.eat('}')?;
+                        return Ok(Json::Obj(fields));
+                    }
+                }
+            }
+            Some('[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                self.skip_ws();
+                if self.s[self.pos..].starts_with(']') {
+                    self.pos += 1;
+                    return Ok(Json::Arr(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    if self.eat(',').is_err() {
+                        self.eat(']')?;
+                        return Ok(Json::Arr(items));
+                    }
+                }
+            }
+            Some('"') => Ok(Json::Str(self.string()?)),
+            _ if rest.starts_with("null") => {
+                self.pos += 4;
+                Ok(Json::Null)
+            }
+            _ if rest.starts_with("true") => {
+                self.pos += 4;
+                Ok(Json::Bool(true))
+            }
+            _ if rest.starts_with("false") => {
+                self.pos += 5;
+                Ok(Json::Bool(false))
+            }
+            _ => {
+                let len = rest
+                    .find(|c: char| !(c.is_ascii_digit() || "+-.eE".contains(c)))
+                    .unwrap_or(rest.len());
+                if len == 0 {
+                    return Err(format!("unexpected input at offset {}", self.pos));
+                }
+                self.pos += len;
+                Ok(Json::Num(rest[..len].to_string()))
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if !self.s[self.pos..].starts_with('"') {
+            return Err(format!("expected string at offset {}", self.pos));
+        }
+        self.pos += 1;
+        let mut out = String::new();
+        let mut chars = self.s[self.pos..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += i + 1;
+                    return Ok(out);
+                }
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('n') => out.push('\n'),
+                    Some('r') => out.push('\r'),
+                    Some('t') => out.push('\t'),
+                    Some('b') => out.push('\u{8}'),
+                    Some('f') => out.push('\u{c}'),
+                    Some('u') => {
+                        let first = hex_unit(&mut chars)?;
+                        let units = if (0xd800..0xdc00).contains(&first) {
+                            // the low surrogate follows as another \u escape
+                            match (chars.next(), chars.next()) {
+                                (Some((_, '\\')), Some((_, 'u'))) => {}
+                                _ => return Err("unpaired surrogate in string".to_string()),
+                            }
+                            vec![first, hex_unit(&mut chars)?]
+                        } else {
+                            vec![first]
+                        };
+                        out.push_str(&String::from_utf16(&units).map_err(|e| e.to_string())?);
+                    }
+                    Some(c) => out.push(c),
+                    None => break,
+                },
+                c => out.push(c),
+            }
+        }
+        Err("unterminated string".to_string())
+    }
+}
+
+fn hex_unit(chars: &mut std::str::CharIndices) -> Result<u16, String> {
+    let hex: String = chars.take(4).map(|(_, c)| c).collect();
+    u16::from_str_radix(&hex, 16).map_err(|e| e.to_string())
+}
+
+impl Json {
+    fn get(&self, key: &str) -> Result<&Json, String> {
+        match self {
+            Json::Obj(fields) => fields
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v)
+                .ok_or_else(|| format!("missing field {}", key)),
+            _ => Err(format!("expected object containing {}", key)),
+        }
+    }
+
+    fn str(&self) -> Result<&str, String> {
+        match self {
+            Json::Str(s) => Ok(s),
+            _ => Err("expected string".to_string()),
+        }
+    }
+
+    fn num<T: FromStr>(&self) -> Result<T, String> {
+        match self {
+            Json::Num(n) => n.parse().or(Err(format!("invalid number {}", n))),
+            _ => Err("expected number".to_string()),
+        }
+    }
+
+    fn time(&self) -> Result<Timestamp, String> {
+        Ok(self.str()?.parse()?)
+    }
+
+    fn arr(&self) -> Result<&[Json], String> {
+        match self {
+            Json::Arr(items) => Ok(items),
+            _ => Err("expected array".to_string()),
+        }
+    }
+}
+
+impl Catalogue {
+    /// reads back a catalogue written by `to_json`
+    pub fn from_json(s: &str) -> Result<Self, Box<dyn Error>> {
+        let root = JsonParser {
+            s,
+            pos: 0,
+            depth: 0,
+        }
+        .value()?;
+        let v = root.get("volume")?;
+        let (major, minor) = v
+            .get("udf_revision")?
+            .str()?
+            .split_once('.')
+            .ok_or("invalid UDF revision")?;
+        let volume = VolumeInfo {
+            vol_ident: v.get("vol_ident")?.str()?.to_string(),
+            vol_set_ident: v.get("vol_set_ident")?.str()?.to_string(),
+            lv_ident: v.get("lv_ident")?.str()?.to_string(),
+            udf_revision: u16::from_str_radix(major, 16)? << 8 | u16::from_str_radix(minor, 16)?,
+            record_time: v.get("record_time")?.time()?,
+            // catalogues written before the sector size was recorded are of 2048 byte sectors
+            sector_size: match v.get("sector_size") {
+                Ok(size) => size.num()?,
+                Err(_) => BLOCKSIZE,
+            },
+        };
+        let mut entries = Vec::new();
+        for e in root.get("entries")?.arr()? {
+            let mut extents = Vec::new();
+            for ext in e.get("extents")?.arr()? {
+                extents.push(CatalogueExtent {
+                    offset: ext.get("offset")?.num()?,
+                    len: ext.get("len")?.num()?,
+                });
+            }
+            let is_dir = matches!(e.get("is_dir")?, Json::Bool(true));
+            // catalogues written before kinds were recorded only tell directories apart
+            let kind = match e.get("kind") {
+                Ok(kind) => kind.str()?.parse()?,
+                Err(_) if is_dir => EntryKind::Dir,
+                Err(_) => EntryKind::File,
+            };
+            entries.push(CatalogueEntry {
+                path: e.get("path")?.str()?.to_string(),
+                is_dir,
+                kind,
+                size: e.get("size")?.num()?,
+                mtime: e.get("mtime")?.time()?,
+                atime: e.get("atime")?.time()?,
+                attrtime: e.get("attrtime")?.time()?,
+                extents,
+                hash: match e.get("hash")? {
+                    Json::Null => None,
+                    hash => Some(hash.str()?.to_string()),
+                },
+                icb: match e.get("icb") {
+                    Ok(Json::Null) | Err(_) => None,
+                    Ok(icb) => Some(LBAddr {
+                        lbn: icb.get("lbn")?.num()?,
+                        part_ref_nr: icb.get("part_ref_nr")?.num()?,
+                    }),
+                },
+            });
+        }
+        Ok(Catalogue { volume, entries })
+    }
+
+    /// serializes the catalogue as a JSON document
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        out.push_str("{\"volume\":");
+        self.volume.write_json(&mut out);
+        out.push_str(",\"entries\":[");
+        for (i, e) in self.entries.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            e.write_json(&mut out);
+        }
+        out.push_str("]}");
+        out
+    }
+}
+
+impl VolumeInfo {
+    /// appends the volume information to `out` as a JSON object
+    pub fn write_json(&self, out: &mut String) {
+        out.push_str("{\"vol_ident\":");
+        json_str(out, &self.vol_ident);
+        out.push_str(",\"vol_set_ident\":");
+        json_str(out, &self.vol_set_ident);
+        out.push_str(",\"lv_ident\":");
+        json_str(out, &self.lv_ident);
+        write!(
+            out,
+            ",\"udf_revision\":\"{:x}.{:02x}\",\"record_time\":",
+            self.udf_revision >> 8,
+            self.udf_revision & 0xff
+        )
+        .unwrap();
+        json_str(out, &self.record_time.to_string());
+        write!(out, ",\"sector_size\":{}}}", self.sector_size).unwrap();
+    }
+}
+
+impl CatalogueEntry {
+    /// appends the entry to `out` as a JSON object
+    pub fn write_json(&self, out: &mut String) {
+        out.push_str("{\"path\":");
+        json_str(out, &self.path);
+        write!(
+            out,
+            ",\"is_dir\":{},\"kind\":\"{}\",\"size\":{}",
+            self.is_dir, self.kind, self.size
+        )
+        .unwrap();
+        out.push_str(",\"mtime\":");
+        json_str(out, &self.mtime.to_string());
+        out.push_str(",\"atime\":");
+        json_str(out, &self.atime.to_string());
+        out.push_str(",\"attrtime\":");
+        json_str(out, &self.attrtime.to_string());
+        out.push_str(",\"extents\":[");
+        for (j, ext) in self.extents.iter().enumerate() {
+            if j > 0 {
+                out.push(',');
+            }
+            write!(out, "{{\"offset\":{},\"len\":{}}}", ext.offset, ext.len).unwrap();
+        }
+        out.push_str("],\"hash\":");
+        match &self.hash {
+            Some(hash) => json_str(out, hash),
+            None => out.push_str("null"),
+        }
+        match &self.icb {
+            Some(icb) => write!(
+                out,
+                ",\"icb\":{{\"part_ref_nr\":{},\"lbn\":{}}}}}",
+                icb.part_ref_nr, icb.lbn
+            )
+            .unwrap(),
+            None => out.push_str(",\"icb\":null}"),
+        }
+    }
+}
+
+impl<IO: Read + Seek> UDF<IO> {
+    pub fn volume_info(&self) -> VolumeInfo {
+        VolumeInfo {
+            vol_ident: self.primary_vol_desc.vol_ident.to_string(),
+            vol_set_ident: self.primary_vol_desc.vol_set_ident.to_string(),
+            lv_ident: self.logical_vol_desc.lvid.to_string(),
+            udf_revision: self.udf_revision(),
+            record_time: self.primary_vol_desc.record_time.clone(),
+            sector_size: self.sector_size(),
+        }
+    }
+
+    /// lists the volume information and every file and directory of the current file set
+    pub fn catalogue(&mut self) -> Result<Catalogue, Box<dyn Error>> {
+        Ok(self.build_catalogue(None, &HashMap::new())?.0)
+    }
+
+    /// like `catalogue`, additionally returning the ICB of every entry
+    pub(crate) fn catalogue_icbs(&mut self) -> Result<(Catalogue, Vec<ICB>), Box<dyn Error>> {
+        self.build_catalogue(None, &HashMap::new())
+    }
+
+    /// walks the file set and compares the numbers of files and directories found with those
+    /// recorded in the current integrity descriptor, `None` if it doesn't record any. All
+    /// links of a hard-linked file count as one file, named streams don't count.
+    pub fn check_file_counts(&mut self) -> Result<Option<CountCheck>, Box<dyn Error>> {
+        let seq = self.integrity_sequence()?;
+        let Some(impl_use) = seq.current.and_then(|lvid| lvid.impl_use) else {
+            return Ok(None);
+        };
+        let catalogue = self.catalogue()?;
+        Ok(Some(CountCheck::new(&impl_use, &catalogue)))
+    }
+
+    /// like `catalogue`, additionally hashing the contents of every file with `hasher`
+    pub fn catalogue_with_hasher(
+        &mut self,
+        hasher: &mut dyn ContentHasher,
+    ) -> Result<Catalogue, Box<dyn Error>> {
+        Ok(self.build_catalogue(Some(hasher), &HashMap::new())?.0)
+    }
+
+    /// re-scans the volume and compares it with the earlier catalogue `old`. Files whose
+    /// size, modification time and extents are unchanged keep their old hash instead of
+    /// being hashed again.
+    pub fn changes_since(
+        &mut self,
+        old: &Catalogue,
+        hasher: Option<&mut dyn ContentHasher>,
+    ) -> Result<CatalogueDiff, Box<dyn Error>> {
+        let known: HashMap<&str, &CatalogueEntry> =
+            old.entries.iter().map(|e| (e.path.as_str(), e)).collect();
+        let catalogue = self.build_catalogue(hasher, &known)?.0;
+        let mut added = Vec::new();
+        let mut modified = Vec::new();
+        for entry in &catalogue.entries {
+            match known.get(entry.path.as_str()) {
+                None => added.push(entry.clone()),
+                Some(old)
+                    if !old.same_fingerprint(entry)
+                        || (old.hash.is_some()
+                            && entry.hash.is_some()
+                            && old.hash != entry.hash) =>
+                {
+                    modified.push(((*old).clone(), entry.clone()))
+                }
+                Some(_) => {}
+            }
+        }
+        let current: HashSet<&str> = catalogue.entries.iter().map(|e| e.path.as_str()).collect();
+        let removed = old
+            .entries
+            .iter()
+            .filter(|e| !current.contains(e.path.as_str()))
+            .cloned()
+            .collect();
+        Ok(CatalogueDiff {
+            added,
+            removed,
+            modified,
+            catalogue,
+        })
+    }
+
+    /// walks the file set, hashing files with `hasher` unless `known` holds an entry with
+    /// the same fingerprint to take the hash from. Files with several links are listed
+    /// under every path, but hashed once. Returns the ICB of every entry along with it.
+    fn build_catalogue(
+        &mut self,
+        mut hasher: Option<&mut dyn ContentHasher>,
+        known: &HashMap<&str, &CatalogueEntry>,
+    ) -> Result<(Catalogue, Vec<ICB>), Box<dyn Error>> {
+        let volume = self.volume_info();
+        let root = self.get_root_dir()?;
+        let mut entries = Vec::new();
+        let mut icbs = Vec::new();
+        // directories visited, files may have several links, directories must not
+        let mut visited = HashSet::new();
+        let mut hashes: HashMap<LBAddr, String> = HashMap::new();
+        let max_depth = self.options.limits.max_depth.unwrap_or(usize::MAX);
+        let mut stack: Vec<(String, ICB, usize)> = vec![("/".to_string(), root, 0)];
+        // directories leading to the entry being visited
+        let mut ancestors = Vec::new();
+        while let Some((path, icb, depth)) = stack.pop() {
+            ancestors.truncate(depth);
+            if is_dir(&icb) && !visited.insert(icb.loc) {
+                return Err(DirectoryLinkError::CrossLink {
+                    path,
+                    target: icb.loc,
+                }
+                .into());
+            }
+            let mut entry = self.catalogue_entry(path.clone(), &icb)?;
+            if let Some(hasher) = hasher.as_deref_mut().filter(|_| !entry.is_dir) {
+                entry.hash = match (known.get(path.as_str()), hashes.get(&icb.loc)) {
+                    (Some(old), _) if old.same_fingerprint(&entry) && old.hash.is_some() => {
+                        old.hash.clone()
+                    }
+                    (_, Some(hash)) => Some(hash.clone()),
+                    _ => Some(self.hash_contents(&icb, hasher)?),
+                };
+                if let Some(hash) = &entry.hash {
+                    hashes.insert(icb.loc, hash.clone());
+                }
+            }
+            entries.push(entry);
+            if !is_dir(&icb) {
+                icbs.push(icb);
+                continue;
+            }
+            icbs.push(icb.clone());
+            let children: Vec<DirEntry> = icb
+                .read_entries(self)?
+                .into_iter()
+                .filter(|e| !e.is_deleted() && !e.is_parent())
+                .collect();
+            if !children.is_empty() && depth >= max_depth {
+                Err(format!("directory depth limit exceeded at {}", path))?
+            }
+            ancestors.push(icb.loc);
+            let mut children = children
+                .into_iter()
+                .map(|child| Ok((child.resolve(self)?, child)))
+                .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+            match self.options.walk_order {
+                WalkOrder::OnDisc => {}
+                WalkOrder::Name => children.sort_by(|a, b| a.1.name.cmp(&b.1.name)),
+                WalkOrder::Lba => {
+                    let mut keyed: Vec<_> = children
+                        .into_iter()
+                        .map(|(icb, child)| (self.first_offset(&icb), icb, child))
+                        .collect();
+                    keyed.sort_by_key(|(offset, _, _)| *offset);
+                    children = keyed
+                        .into_iter()
+                        .map(|(_, icb, child)| (icb, child))
+                        .collect();
+                }
+            }
+            // pushed in reverse so that entries come out in walk order
+            for (icb, child) in children.into_iter().rev() {
+                let prefix = if path == "/" { "" } else { path.as_str() };
+                let child_path = format!("{}/{}", prefix, child.name);
+                if is_dir(&icb) && ancestors.contains(&icb.loc) {
+                    return Err(DirectoryLinkError::Loop {
+                        path: child_path,
+                        target: icb.loc,
+                    }
+                    .into());
+                }
+                stack.push((child_path, icb, depth + 1));
+            }
+        }
+        Ok((Catalogue { volume, entries }, icbs))
+    }
+
+    /// byte offset of the first recorded extent of `icb`, or of the ICB itself for files
+    /// without one
+    fn first_offset(&mut self, icb: &ICB) -> u64 {
+        let embedded = matches!(icb.icb_tag.flags.get_alloc_type(), Ok(AllocType::EMBEDDED));
+        let extent = icb
+            .get_alloc_descs()
+            .into_iter()
+            .find(|ad| !embedded && ad.ext_type() == 0 && !ad.is_empty());
+        let offset = match extent {
+            Some(ad) => self
+                .alloc_desc_to_offset_len(&ad, icb.loc.part_ref_nr)
+                .map(|(offset, _)| offset),
+            None => self
+                .lb_to_sector(&icb.loc)
+                .map(|lsn| lsn as u64 * self.sector_size()),
+        };
+        offset.unwrap_or(u64::MAX)
+    }
+
+    /// feeds the contents of the file described by `icb` to `hasher`
+    pub fn hash_contents(
+        &mut self,
+        icb: &ICB,
+        hasher: &mut dyn ContentHasher,
+    ) -> Result<String, Box<dyn Error>> {
+        let mut buf = vec![0; HASH_CHUNK];
+        let mut pos = 0;
+        loop {
+            let n = icb.read_at(self, pos, &mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            pos += n as u64;
+        }
+        Ok(hasher.finish())
+    }
+
+    /// describes the file or directory `icb` recorded at `path`, without a hash
+    pub fn catalogue_entry(
+        &mut self,
+        path: String,
+        icb: &ICB,
+    ) -> Result<CatalogueEntry, Box<dyn Error>> {
+        let Some(fe) = icb.file_entry() else {
+            return Err(format!("{} has no file entry", path).into());
+        };
+        let is_dir = is_dir(icb);
+        let mut extents = Vec::new();
+        if !matches!(icb.icb_tag.flags.get_alloc_type()?, AllocType::EMBEDDED) {
+            for ad in icb.get_alloc_descs() {
+                if ad.ext_type() == 0 && !ad.is_empty() {
+                    let (offset, len) = self.alloc_desc_to_offset_len(&ad, icb.loc.part_ref_nr)?;
+                    extents.push(CatalogueExtent { offset, len });
+                }
+            }
+        }
+        Ok(CatalogueEntry {
+            path,
+            is_dir,
+            kind: icb.kind(),
+            size: fe.info_len(),
+            mtime: fe.mtime().clone(),
+            atime: fe.atime().clone(),
+            attrtime: fe.attrtime().clone(),
+            extents,
+            hash: None,
+            icb: Some(icb.loc),
+        })
+    }
+}
+
+fn is_dir(icb: &ICB) -> bool {
+    matches!(icb.icb_tag.file_type, FileType::DIR)
+}
@@ -0,0 +1,203 @@
+/*
+    Export of content hashes for deduplication stores: every file is split into
+    content-defined chunks with a gear hash (as in FastCDC) while streaming its data, so that
+    identical runs of data end up in identical chunks even when shifted between files or
+    volumes. Alternatively every file is hashed as a whole.
+*/
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::Write;
+use std::io::{Read, Seek};
+
+use crate::catalogue::ContentHasher;
+use crate::file::{LBAddr, ICB};
+use crate::UDF;
+
+/// size of the reads files are streamed with
+const READ_CHUNK: usize = 64 * 1024;
+
+/// pseudo-random values per byte value for the gear hash, generated with splitmix64
+const GEAR: [u64; 256] = {
+    let mut table = [0; 256];
+    let mut state: u64 = 0x5555_5555_5555_5555;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+};
+
+/// chunk size limits of content-defined chunking
+#[derive(Clone, Copy, Debug)]
+pub struct ChunkParams {
+    pub min: u64,
+    /// average chunk size, rounded up to a power of two
+    pub avg: u64,
+    pub max: u64,
+}
+
+impl Default for ChunkParams {
+    fn default() -> Self {
+        Self {
+            min: 2 * 1024,
+            avg: 8 * 1024,
+            max: 64 * 1024,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chunk {
+    /// offset within the file
+    pub offset: u64,
+    pub len: u64,
+    pub hash: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DedupRecord {
+    pub path: String,
+    pub size: u64,
+    /// consecutive chunks covering the whole file, empty for empty files
+    pub chunks: Vec<Chunk>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DedupIndex {
+    pub files: Vec<DedupRecord>,
+}
+
+impl DedupIndex {
+    /// serializes the index as text: a line `F <size> <path>` per file followed by a line
+    /// `C <offset> <length> <hash>` per chunk
+    pub fn to_text(&self) -> String {
+        let mut out = String::from("udf-dedup 1\n");
+        for file in &self.files {
+            writeln!(out, "F {} {}", file.size, file.path).unwrap();
+            for chunk in &file.chunks {
+                writeln!(out, "C {} {} {}", chunk.offset, chunk.len, chunk.hash).unwrap();
+            }
+        }
+        out
+    }
+
+    pub fn from_text(s: &str) -> Result<Self, Box<dyn Error>> {
+        let mut lines = s.lines();
+        if lines.next() != Some("udf-dedup 1") {
+            Err("not a dedup index")?
+        }
+        let mut index = DedupIndex::default();
+        for line in lines.filter(|l| !l.is_empty()) {
+            let mut fields = line.splitn(3, ' ');
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some("F"), Some(size), Some(path)) => index.files.push(DedupRecord {
+                    path: path.to_string(),
+                    size: size.parse()?,
+                    chunks: Vec::new(),
+                }),
+                (Some("C"), Some(offset), Some(rest)) => {
+                    let (len, hash) = rest.split_once(' ').ok_or("invalid chunk entry")?;
+                    let file = index.files.last_mut().ok_or("chunk without file")?;
+                    file.chunks.push(Chunk {
+                        offset: offset.parse()?,
+                        len: len.parse()?,
+                        hash: hash.to_string(),
+                    });
+                }
+                _ => Err(format!("invalid dedup index line: {}", line))?,
+            }
+        }
+        Ok(index)
+    }
+}
+
+impl<IO: Read + Seek> UDF<IO> {
+    /// hashes every file of the current file set with `hasher`, split into content-defined
+    /// chunks according to `params`, or as a whole if `params` is `None`. Files with several
+    /// links are recorded under every path, but read once.
+    pub fn dedup_index(
+        &mut self,
+        params: Option<ChunkParams>,
+        hasher: &mut dyn ContentHasher,
+    ) -> Result<DedupIndex, Box<dyn Error>> {
+        let mut index = DedupIndex::default();
+        let mut chunked: HashMap<LBAddr, Vec<Chunk>> = HashMap::new();
+        let (catalogue, icbs) = self.catalogue_icbs()?;
+        for (entry, icb) in catalogue.entries.into_iter().zip(icbs) {
+            if entry.is_dir {
+                continue;
+            }
+            let chunks = match chunked.get(&icb.loc) {
+                Some(chunks) => chunks.clone(),
+                None => self.chunk_file(&icb, params, hasher)?,
+            };
+            chunked.insert(icb.loc, chunks.clone());
+            index.files.push(DedupRecord {
+                path: entry.path,
+                size: entry.size,
+                chunks,
+            });
+        }
+        Ok(index)
+    }
+
+    fn chunk_file(
+        &mut self,
+        icb: &ICB,
+        params: Option<ChunkParams>,
+        hasher: &mut dyn ContentHasher,
+    ) -> Result<Vec<Chunk>, Box<dyn Error>> {
+        let (min, max, mask) = match params {
+            Some(p) => (
+                p.min.max(1),
+                p.max.max(p.min),
+                p.avg.next_power_of_two() - 1,
+            ),
+            None => (u64::MAX, u64::MAX, 0),
+        };
+        let mut chunks = Vec::new();
+        let mut buf = vec![0; READ_CHUNK];
+        let mut pos = 0;
+        let mut chunk_start = 0;
+        let mut gear: u64 = 0;
+        loop {
+            let n = icb.read_at(self, pos, &mut buf)?;
+            if n == 0 {
+                break;
+            }
+            let mut hashed = 0;
+            for (i, &b) in buf[..n].iter().enumerate() {
+                gear = (gear << 1).wrapping_add(GEAR[b as usize]);
+                let end = pos + i as u64 + 1;
+                let len = end - chunk_start;
+                if len >= min && (gear & mask == 0 || len >= max) {
+                    hasher.update(&buf[hashed..=i]);
+                    chunks.push(Chunk {
+                        offset: chunk_start,
+                        len,
+                        hash: hasher.finish(),
+                    });
+                    chunk_start = end;
+                    hashed = i + 1;
+                    gear = 0;
+                }
+            }
+            hasher.update(&buf[hashed..n]);
+            pos += n as u64;
+        }
+        if pos > chunk_start {
+            chunks.push(Chunk {
+                offset: chunk_start,
+                len: pos - chunk_start,
+                hash: hasher.finish(),
+            });
+        }
+        Ok(chunks)
+    }
+}
@@ -0,0 +1,98 @@
+/*
+    Detection of CSS scrambled DVD-Video titles.
+
+    Every sector of a VOB file is an MPEG-2 program stream pack. The PES header following the
+    14 byte pack header carries the PES scrambling control bits, which are set on sectors
+    encrypted with CSS. Nothing is decrypted here, the sectors are only sampled.
+*/
+
+use std::error::Error;
+use std::io::{ErrorKind, Read, Seek};
+use std::path::Path;
+
+use crate::{BLOCKSIZE, UDF};
+
+/// number of sectors sampled per VOB file, evenly spread over the file
+const SAMPLES_PER_FILE: u64 = 16;
+/// offset of the byte holding the PES scrambling control bits in a pack
+const PES_FLAGS_OFFSET: usize = 0x14;
+
+/// result of sampling one VOB file
+#[derive(Clone, Debug)]
+pub struct VobSample {
+    pub name: String,
+    /// number of sectors sampled
+    pub sampled: u32,
+    /// sampled sectors starting with a pack header
+    pub packs: u32,
+    /// packs with their PES scrambling control bits set
+    pub scrambled: u32,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CssReport {
+    /// VOB files found in the VIDEO_TS directory
+    pub files: Vec<VobSample>,
+}
+
+impl CssReport {
+    /// whether the volume has a VIDEO_TS directory with VOB files
+    pub fn is_dvd_video(&self) -> bool {
+        !self.files.is_empty()
+    }
+
+    /// whether any sampled sector appears to be scrambled
+    pub fn is_scrambled(&self) -> bool {
+        self.files.iter().any(|f| f.scrambled > 0)
+    }
+}
+
+/// whether `sector` is a program stream pack with its PES scrambling control bits set, or
+/// `None` if it isn't a pack at all
+fn pack_scrambled(sector: &[u8]) -> Option<bool> {
+    if sector.len() <= PES_FLAGS_OFFSET || sector[0..4] != [0, 0, 1, 0xba] {
+        return None;
+    }
+    Some(sector[PES_FLAGS_OFFSET] & 0x30 != 0)
+}
+
+impl<IO: Read + Seek> UDF<IO> {
+    /// samples the VOB files of a DVD-Video volume for CSS scrambled sectors
+    pub fn detect_css(&mut self) -> Result<CssReport, Box<dyn Error>> {
+        let mut report = CssReport::default();
+        let video_ts = match self.find_icb(Path::new("/VIDEO_TS")) {
+            Ok(icb) => icb,
+            Err(e) => match e.downcast_ref::<std::io::Error>() {
+                Some(e) if e.kind() == ErrorKind::NotFound => return Ok(report),
+                _ => return Err(e),
+            },
+        };
+        let mut sector = [0u8; BLOCKSIZE as usize];
+        for entry in video_ts.get_entries(self) {
+            if entry.is_dir() || !entry.name.to_ascii_uppercase().ends_with(".VOB") {
+                continue;
+            }
+            let icb = entry.resolve(self)?;
+            let num_sectors = icb.file_entry().map_or(0, |fe| fe.info_len() / BLOCKSIZE);
+            let mut sample = VobSample {
+                name: entry.name,
+                sampled: 0,
+                packs: 0,
+                scrambled: 0,
+            };
+            let step = num_sectors.div_ceil(SAMPLES_PER_FILE).max(1);
+            for n in (0..num_sectors).step_by(step as usize) {
+                if icb.read_at(self, n * BLOCKSIZE, &mut sector)? < sector.len() {
+                    break;
+                }
+                sample.sampled += 1;
+                if let Some(scrambled) = pack_scrambled(&sector) {
+                    sample.packs += 1;
+                    sample.scrambled += scrambled as u32;
+                }
+            }
+            report.files.push(sample);
+        }
+        Ok(report)
+    }
+}
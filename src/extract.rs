@@ -0,0 +1,453 @@
+/*
+    Extraction of files and directory trees from a volume to the host file system. What
+    happens to destination files that already exist is decided by a collision policy, and a
+    dry run reports what would be written without touching the host, e.g. to check a large
+    extraction before starting it. An extraction can record its progress in a state file, so
+    that an interrupted extraction from a slow drive continues where it stopped.
+*/
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use log::warn;
+
+use crate::file::{disambiguate, EntryKind, ICB};
+use crate::handle::UdfFile;
+use crate::UDF;
+
+/// what to do with a destination that already exists. Existing directories are always
+/// merged with the directories extracted to them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CollisionPolicy {
+    /// fail the extraction
+    #[default]
+    Error,
+    /// leave the destination as it is, and directories without extracting their contents
+    Skip,
+    /// replace the destination, unless it is a directory
+    Overwrite,
+    /// extract under a name made unique by `disambiguate`, e.g. `a~2.txt`
+    Rename,
+}
+
+impl CollisionPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CollisionPolicy::Error => "error",
+            CollisionPolicy::Skip => "skip",
+            CollisionPolicy::Overwrite => "overwrite",
+            CollisionPolicy::Rename => "rename",
+        }
+    }
+}
+
+impl std::str::FromStr for CollisionPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [Self::Error, Self::Skip, Self::Overwrite, Self::Rename]
+            .into_iter()
+            .find(|policy| policy.as_str() == s)
+            .ok_or_else(|| format!("invalid collision policy {}", s))
+    }
+}
+
+/// first field of the first line of a state file
+const STATE_MAGIC: &str = "libudf-rs extraction 1";
+
+/// largest read while copying a file
+const COPY_CHUNK: usize = 1 << 20;
+
+#[derive(Clone, Debug)]
+pub struct ExtractOptions {
+    pub policy: CollisionPolicy,
+    /// only report what would be written
+    pub dry_run: bool,
+    /// file recording the progress of the extraction, which is resumed from it if it exists
+    pub state_file: Option<PathBuf>,
+    /// bytes of a file written between the records of its progress in the state file
+    pub checkpoint_interval: u64,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        Self {
+            policy: CollisionPolicy::default(),
+            dry_run: false,
+            state_file: None,
+            checkpoint_interval: 64 << 20,
+        }
+    }
+}
+
+impl ExtractOptions {
+    pub fn policy(mut self, policy: CollisionPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn state_file(mut self, state_file: impl Into<PathBuf>) -> Self {
+        self.state_file = Some(state_file.into());
+        self
+    }
+
+    pub fn checkpoint_interval(mut self, bytes: u64) -> Self {
+        self.checkpoint_interval = bytes.max(1);
+        self
+    }
+}
+
+/// what was done, or would be done in a dry run, with an entry
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExtractAction {
+    Created,
+    /// an existing directory was reused
+    Merged,
+    Overwritten,
+    /// extracted under another name as the destination existed
+    Renamed,
+    Skipped,
+    /// extracted by an earlier run according to the state file
+    AlreadyExtracted,
+    /// partially extracted by an earlier run and continued where it stopped
+    Resumed,
+}
+
+impl ExtractAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExtractAction::Created => "created",
+            ExtractAction::Merged => "merged",
+            ExtractAction::Overwritten => "overwritten",
+            ExtractAction::Renamed => "renamed",
+            ExtractAction::Skipped => "skipped",
+            ExtractAction::AlreadyExtracted => "already extracted",
+            ExtractAction::Resumed => "resumed",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ExtractedEntry {
+    /// absolute path on the volume
+    pub path: String,
+    pub dest: PathBuf,
+    pub kind: EntryKind,
+    /// size of files, 0 for directories
+    pub size: u64,
+    pub action: ExtractAction,
+}
+
+/// the path to extract an entry of kind `kind` to instead of `dest` and what is done with
+/// it, according to `policy`
+fn destination(
+    dest: &Path,
+    kind: EntryKind,
+    policy: CollisionPolicy,
+) -> Result<(PathBuf, ExtractAction), io::Error> {
+    let Ok(meta) = dest.symlink_metadata() else {
+        return Ok((dest.to_path_buf(), ExtractAction::Created));
+    };
+    if kind == EntryKind::Dir && meta.is_dir() {
+        return Ok((dest.to_path_buf(), ExtractAction::Merged));
+    }
+    match policy {
+        CollisionPolicy::Error => Err(io::Error::new(
+            ErrorKind::AlreadyExists,
+            format!("{} already exists", dest.display()),
+        )),
+        CollisionPolicy::Skip => Ok((dest.to_path_buf(), ExtractAction::Skipped)),
+        CollisionPolicy::Overwrite if meta.is_dir() => Err(io::Error::new(
+            ErrorKind::IsADirectory,
+            format!("{} is a directory", dest.display()),
+        )),
+        CollisionPolicy::Overwrite => Ok((dest.to_path_buf(), ExtractAction::Overwritten)),
+        CollisionPolicy::Rename => {
+            let name = dest
+                .file_name()
+                .ok_or_else(|| io::Error::from(ErrorKind::InvalidInput))?
+                .to_string_lossy();
+            let mut n = 2;
+            let mut path = dest.with_file_name(disambiguate(&name, n));
+            while path.symlink_metadata().is_ok() {
+                n += 1;
+                path = dest.with_file_name(disambiguate(&name, n));
+            }
+            Ok((path, ExtractAction::Renamed))
+        }
+    }
+}
+
+/// how far an entry was extracted according to the state file
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Progress {
+    Done(PathBuf),
+    /// a file written up to an offset
+    Partial(u64, PathBuf),
+}
+
+/// the state file of an extraction: a header line identifying the volume followed by a line
+/// per entry extracted and per checkpoint of a large file, later lines taking precedence.
+/// Fields are separated by tabs.
+struct ExtractState {
+    /// the state file opened for appending, `None` in dry runs
+    file: Option<File>,
+    entries: HashMap<String, Progress>,
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some(c) => out.push(c),
+            None => {}
+        }
+    }
+    out
+}
+
+impl ExtractState {
+    /// reads the state file at `path` if it exists, failing if it was written for another
+    /// volume than the one identified by `volume`
+    fn open(path: &Path, volume: &str, dry_run: bool) -> Result<Self, Box<dyn Error>> {
+        let header = format!("{}\t{}", STATE_MAGIC, escape(volume));
+        let mut entries = HashMap::new();
+        let mut is_new = true;
+        if let Ok(file) = File::open(path) {
+            let mut lines = BufReader::new(file).lines();
+            if let Some(first) = lines.next() {
+                is_new = false;
+                if first? != header {
+                    Err(format!(
+                        "{} is not the state of an extraction from this volume",
+                        path.display()
+                    ))?
+                }
+            }
+            for line in lines {
+                let line = line?;
+                let fields: Vec<_> = line.split('\t').map(unescape).collect();
+                let (path, progress) = match &fields[..] {
+                    [kind, path, dest] if kind == "done" => (path, Progress::Done(dest.into())),
+                    [kind, offset, path, dest] if kind == "part" => match offset.parse() {
+                        Ok(offset) => (path, Progress::Partial(offset, dest.into())),
+                        Err(_) => continue,
+                    },
+                    // a line cut short when the extraction was interrupted
+                    _ => {
+                        warn!("ignoring malformed line in {}: {}", path.display(), line);
+                        continue;
+                    }
+                };
+                entries.insert(path.clone(), progress);
+            }
+        }
+        let mut file = None;
+        if !dry_run {
+            let mut state = OpenOptions::new().create(true).append(true).open(path)?;
+            if is_new {
+                writeln!(state, "{}", header)?;
+            }
+            file = Some(state);
+        }
+        Ok(Self { file, entries })
+    }
+
+    fn record(&mut self, path: &str, progress: Progress) -> io::Result<()> {
+        if let Some(file) = &mut self.file {
+            match &progress {
+                Progress::Done(dest) => writeln!(
+                    file,
+                    "done\t{}\t{}",
+                    escape(path),
+                    escape(&dest.to_string_lossy())
+                ),
+                Progress::Partial(offset, dest) => writeln!(
+                    file,
+                    "part\t{}\t{}\t{}",
+                    offset,
+                    escape(path),
+                    escape(&dest.to_string_lossy())
+                ),
+            }?;
+            file.sync_data()?;
+        }
+        self.entries.insert(path.to_string(), progress);
+        Ok(())
+    }
+}
+
+/// whether `name` can be used as a file name on the host without leaving its directory
+pub(crate) fn is_safe_name(name: &str) -> bool {
+    !matches!(name, "" | "." | "..") && !name.contains(['/', '\\', '\0'])
+}
+
+impl<IO: Read + Seek> UDF<IO> {
+    /// extracts the file or directory at `path` to `dest`, directories with all of their
+    /// regular files and subdirectories. Returns the entries in the order they were
+    /// extracted, each directory before its contents. Other kinds of entries and names that
+    /// can't be used on the host are logged and left out.
+    ///
+    /// With a state file, entries recorded in it as extracted are not extracted again and
+    /// files recorded as partially extracted are continued, without applying the collision
+    /// policy to their destinations. The state file is kept when the extraction completes.
+    pub fn extract_to(
+        &mut self,
+        path: &Path,
+        dest: &Path,
+        options: &ExtractOptions,
+    ) -> Result<Vec<ExtractedEntry>, Box<dyn Error>> {
+        let icb = self.find_icb(path)?;
+        let mut state = match &options.state_file {
+            Some(state_file) => {
+                let info = self.volume_info();
+                let volume = format!("{}\t{}", info.vol_ident, info.record_time);
+                Some(ExtractState::open(state_file, &volume, options.dry_run)?)
+            }
+            None => None,
+        };
+        let path = path.to_string_lossy().trim_end_matches('/').to_string();
+        let mut extracted = Vec::new();
+        let mut stack = vec![(path, icb, dest.to_path_buf())];
+        while let Some((path, icb, dest)) = stack.pop() {
+            let kind = icb.kind();
+            if !matches!(kind, EntryKind::File | EntryKind::Dir) {
+                warn!("not extracting {} of kind {}", path, kind);
+                continue;
+            }
+            let progress = state.as_ref().and_then(|s| s.entries.get(&path).cloned());
+            let (dest, action, offset) = match progress {
+                Some(Progress::Done(dest)) => (dest, ExtractAction::AlreadyExtracted, 0),
+                Some(Progress::Partial(offset, dest)) => (dest, ExtractAction::Resumed, offset),
+                None => {
+                    let (dest, action) = destination(&dest, kind, options.policy)?;
+                    (dest, action, 0)
+                }
+            };
+            let size = match kind {
+                EntryKind::File => icb.file_entry().map_or(0, |fe| fe.info_len()),
+                _ => 0,
+            };
+            extracted.push(ExtractedEntry {
+                path: if path.is_empty() {
+                    "/".into()
+                } else {
+                    path.clone()
+                },
+                dest: dest.clone(),
+                kind,
+                size,
+                action,
+            });
+            // directories extracted before are still entered, their contents may not be
+            let done = kind == EntryKind::File && action == ExtractAction::AlreadyExtracted;
+            if done || action == ExtractAction::Skipped {
+                continue;
+            }
+            if !options.dry_run {
+                if action == ExtractAction::Overwritten {
+                    // a symbolic link is replaced rather than written through
+                    fs::remove_file(&dest)?;
+                }
+                if kind == EntryKind::File {
+                    let interval = options.checkpoint_interval;
+                    self.extract_file(icb, &dest, offset, interval, |offset| match &mut state {
+                        Some(state) => state.record(&path, Progress::Partial(offset, dest.clone())),
+                        None => Ok(()),
+                    })?;
+                    if let Some(state) = &mut state {
+                        state.record(&path, Progress::Done(dest))?;
+                    }
+                    continue;
+                }
+                fs::create_dir_all(&dest)?;
+                if let Some(state) = &mut state {
+                    if action != ExtractAction::AlreadyExtracted {
+                        state.record(&path, Progress::Done(dest.clone()))?;
+                    }
+                }
+            }
+            if kind == EntryKind::Dir {
+                let mut children: Vec<_> = icb.get_children(self).into_iter().collect();
+                // popped from the stack in order of their names
+                children.sort_by(|a, b| b.0.cmp(&a.0));
+                for (name, child) in children {
+                    let child_path = format!("{}/{}", path, name);
+                    if !is_safe_name(&name) {
+                        warn!("not extracting {}, its name can't be used", child_path);
+                        continue;
+                    }
+                    stack.push((child_path, child, dest.join(&name)));
+                }
+            }
+        }
+        Ok(extracted)
+    }
+
+    /// writes the file recorded in `icb` to `dest` from `offset` on, calling `checkpoint`
+    /// with the bytes written every `interval` bytes once they are on the disk
+    fn extract_file(
+        &mut self,
+        icb: ICB,
+        dest: &Path,
+        mut offset: u64,
+        interval: u64,
+        mut checkpoint: impl FnMut(u64) -> io::Result<()>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut source = UdfFile::new(self, icb)?;
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(offset == 0)
+            .open(dest)?;
+        // the partial file may have been changed since
+        if file.metadata()?.len() < offset {
+            warn!(
+                "{} is shorter than recorded, extracting it again",
+                dest.display()
+            );
+            offset = 0;
+        }
+        file.set_len(offset)?;
+        file.seek(SeekFrom::Start(offset))?;
+        source.seek(SeekFrom::Start(offset))?;
+        let mut buf = vec![0; COPY_CHUNK.min(interval as usize)];
+        let mut next_checkpoint = offset + interval;
+        loop {
+            let n = source.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            file.write_all(&buf[..n])?;
+            offset += n as u64;
+            if offset >= next_checkpoint {
+                file.sync_data()?;
+                checkpoint(offset)?;
+                next_checkpoint = offset + interval;
+            }
+        }
+        file.sync_data()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+/*
+    Fault injection for tests: a backend wrapping another one that fails reads of bad
+    sectors, returns short reads and delays reads, all deterministically, so that handling
+    of damaged media can be tested without damaged media.
+*/
+
+use std::collections::BTreeSet;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::backend::ReadAt;
+use crate::volume::LSN;
+use crate::BLOCKSIZE;
+
+/// wraps a `Read + Seek` stream or a `ReadAt` backend, injecting faults into reads
+#[derive(Debug)]
+pub struct FaultyReader<R> {
+    inner: R,
+    bad_sectors: BTreeSet<LSN>,
+    max_read: Option<usize>,
+    latency: Duration,
+    pos: u64,
+    reads: AtomicU64,
+}
+
+impl<R> FaultyReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            bad_sectors: BTreeSet::new(),
+            max_read: None,
+            latency: Duration::ZERO,
+            pos: 0,
+            reads: AtomicU64::new(0),
+        }
+    }
+
+    /// makes reads touching sector `lsn` fail. Reads starting before it return the data up
+    /// to the bad sector, like a drive returning the sectors read before the error.
+    pub fn bad_sector(mut self, lsn: LSN) -> Self {
+        self.bad_sectors.insert(lsn);
+        self
+    }
+
+    pub fn bad_sectors(mut self, sectors: Range<LSN>) -> Self {
+        self.bad_sectors.extend(sectors);
+        self
+    }
+
+    /// returns at most `max` bytes per read
+    pub fn short_reads(mut self, max: usize) -> Self {
+        self.max_read = Some(max.max(1));
+        self
+    }
+
+    /// sleeps for `latency` before every read
+    pub fn latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// number of reads so far, including failed ones
+    pub fn reads(&self) -> u64 {
+        self.reads.load(Ordering::Relaxed)
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// applies the faults to a read of `len` bytes at `pos`, returning how many bytes may be
+    /// read or the injected error
+    fn admit(&self, pos: u64, len: usize) -> io::Result<usize> {
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        if !self.latency.is_zero() {
+            std::thread::sleep(self.latency);
+        }
+        let mut len = len.min(self.max_read.unwrap_or(usize::MAX));
+        if len == 0 {
+            return Ok(0);
+        }
+        let first = (pos / BLOCKSIZE) as LSN;
+        let last = ((pos + len as u64 - 1) / BLOCKSIZE) as LSN;
+        if let Some(&bad) = self.bad_sectors.range(first..=last).next() {
+            if bad == first {
+                return Err(io::Error::other(format!(
+                    "injected read error at sector {}",
+                    bad
+                )));
+            }
+            len = (bad as u64 * BLOCKSIZE - pos) as usize;
+        }
+        Ok(len)
+    }
+}
+
+impl<R: Read + Seek> Read for FaultyReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.admit(self.pos, buf.len())?;
+        self.inner.seek(SeekFrom::Start(self.pos))?;
+        let n = self.inner.read(&mut buf[..len])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek> Seek for FaultyReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = self.inner.seek(pos)?;
+        Ok(self.pos)
+    }
+}
+
+impl<R: ReadAt> ReadAt for FaultyReader<R> {
+    fn read_at(&self, pos: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.admit(pos, buf.len())?;
+        self.inner.read_at(pos, &mut buf[..len])
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        self.inner.size()
+    }
+}
@@ -1,21 +1,22 @@
 use std::collections::HashMap;
-use std::io::SeekFrom;
+use std::error::Error;
 use std::io::{Read, Seek};
+use std::ops::Range;
+use std::sync::Arc;
 
 use bitfield::BitRange;
-use log::error;
+use log::{error, warn};
 use nom::number::complete::*;
 use nom_derive::Nom;
 use nom_derive::Parse;
 
 use crate::volume::DString;
-use crate::volume::{parse_dynamic_dstring, CharSpec, RegID, Timestamp};
-use crate::BLOCKSIZE;
-use crate::UDF;
+use crate::volume::{decode_cs0, parse_dynamic_dstring, CharSpec, RegID, Timestamp};
+use crate::{check_tag_crc, check_tag_loc, UDF};
 
 pub type LBN = u32;
 
-#[derive(Nom, Debug, Clone)]
+#[derive(Nom, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
 #[nom(LittleEndian)]
 pub struct LBAddr {
     pub lbn: LBN,
@@ -29,7 +30,7 @@ pub struct ShortAD {
     pub ty: u8,
 }
 impl ShortAD {
-    pub fn parse_le<'nom>(i: &'nom [u8]) -> nom::IResult<&'nom [u8], Self> {
+    pub fn parse_le(i: &[u8]) -> nom::IResult<&[u8], Self> {
         let (i, len) = u32::parse_le(i)?;
         let (i, pos) = <_>::parse_le(i)?;
         let ty: u8 = len.bit_range(31, 30);
@@ -46,7 +47,7 @@ pub struct LongAD {
     pub ty: u8,
 }
 impl LongAD {
-    pub fn parse_le<'nom>(i: &'nom [u8]) -> nom::IResult<&'nom [u8], Self> {
+    pub fn parse_le(i: &[u8]) -> nom::IResult<&[u8], Self> {
         let (i, len) = le_u32(i)?;
         let (i, loc) = <_>::parse_le(i)?;
         let (i, impl_use) = <_>::parse_le(i)?;
@@ -76,7 +77,7 @@ pub struct ExtAD {
     pub rec_len_ty: u8,
 }
 impl ExtAD {
-    pub fn parse_le<'nom>(i: &'nom [u8]) -> nom::IResult<&'nom [u8], Self> {
+    pub fn parse_le(i: &[u8]) -> nom::IResult<&[u8], Self> {
         let (i, len) = le_u32(i)?;
         let (i, rec_len) = le_u32(i)?;
         let (i, info_len) = le_u32(i)?;
@@ -107,6 +108,7 @@ pub enum AllocType {
     SHORT = 0,
     LONG,
     EXTENDED,
+    EMBEDDED,
 }
 
 #[derive(Nom, Debug, Clone)]
@@ -119,6 +121,43 @@ pub enum AllocDesc {
     #[nom(Selector = "AllocType::EXTENDED")]
     EXTENDED(ExtAD),
 }
+impl AllocDesc {
+    /// length of the extent in bytes
+    pub fn len(&self) -> u32 {
+        match self {
+            AllocDesc::SHORT(ad) => ad.len,
+            AllocDesc::LONG(ad) => ad.len,
+            AllocDesc::EXTENDED(ad) => ad.len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// start of the extent. Short allocation descriptors lack a partition reference and
+    /// refer to `part_ref`, the partition of the ICB they were recorded in.
+    pub fn lb_addr(&self, part_ref: u16) -> LBAddr {
+        match self {
+            AllocDesc::SHORT(ad) => LBAddr {
+                lbn: ad.pos,
+                part_ref_nr: part_ref,
+            },
+            AllocDesc::LONG(ad) => ad.loc,
+            AllocDesc::EXTENDED(ad) => ad.ext_loc,
+        }
+    }
+
+    /// extent type: 0 = recorded and allocated, 1 = allocated but not recorded,
+    /// 2 = neither allocated nor recorded, 3 = next extent of allocation descriptors
+    pub fn ext_type(&self) -> u8 {
+        match self {
+            AllocDesc::SHORT(ad) => ad.ty,
+            AllocDesc::LONG(ad) => ad.ty,
+            AllocDesc::EXTENDED(ad) => ad.len_ty,
+        }
+    }
+}
 impl From<ShortAD> for AllocDesc {
     fn from(value: ShortAD) -> Self {
         AllocDesc::SHORT(value)
@@ -166,7 +205,7 @@ pub struct FileTag {
     pub tag_loc: LBN,
 }
 
-#[derive(Nom)]
+#[derive(Nom, Clone)]
 #[nom(LittleEndian)]
 pub struct FSD {
     #[nom(Verify = "tag.tag_id == FileTagID::FSD")]
@@ -209,6 +248,82 @@ pub struct PHD {
     _res: [u8; 88],
 }
 
+/// Space Bitmap Descriptor (ECMA-167 4/14.12), a set bit marks a free block
+#[derive(Nom, Debug, Clone)]
+#[nom(LittleEndian)]
+pub struct SBD {
+    #[nom(Verify = "tag.tag_id == FileTagID::SBD")]
+    pub tag: FileTag,
+    pub num_bits: u32,
+    pub num_bytes: u32,
+    #[nom(Count = "num_bytes")]
+    pub bitmap: Vec<u8>,
+}
+
+impl SBD {
+    /// number of blocks the bitmap records, at most as many as it has bits for
+    pub fn len(&self) -> u32 {
+        self.num_bits.min(self.bitmap.len() as u32 * 8)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// whether the block `lbn` is free, `None` beyond the end of the bitmap
+    pub fn is_free(&self, lbn: u32) -> Option<bool> {
+        (lbn < self.len()).then(|| self.bitmap[lbn as usize / 8] & (1 << (lbn % 8)) != 0)
+    }
+
+    /// the runs of free and of allocated blocks, in order
+    pub fn extents(&self) -> SpaceExtents<'_> {
+        SpaceExtents {
+            bitmap: self,
+            next: 0,
+        }
+    }
+}
+
+/// a run of blocks of the same allocation state
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpaceExtent {
+    pub blocks: Range<u32>,
+    pub free: bool,
+}
+
+/// iterator over the runs of free and of allocated blocks of a space bitmap
+pub struct SpaceExtents<'a> {
+    bitmap: &'a SBD,
+    next: u32,
+}
+
+impl Iterator for SpaceExtents<'_> {
+    type Item = SpaceExtent;
+
+    fn next(&mut self) -> Option<SpaceExtent> {
+        let start = self.next;
+        let free = self.bitmap.is_free(start)?;
+        let len = self.bitmap.len();
+        let mut end = start + 1;
+        while end < len {
+            let byte = self.bitmap.bitmap[end as usize / 8];
+            // skip whole bytes of the same state
+            if end.is_multiple_of(8) && end + 8 <= len && byte == if free { 0xFF } else { 0 } {
+                end += 8;
+            } else if self.bitmap.is_free(end) == Some(free) {
+                end += 1;
+            } else {
+                break;
+            }
+        }
+        self.next = end;
+        Some(SpaceExtent {
+            blocks: start..end,
+            free,
+        })
+    }
+}
+
 #[derive(Nom, Debug)]
 #[nom(LittleEndian)]
 pub struct FID {
@@ -222,11 +337,220 @@ pub struct FID {
     #[nom(Count = "impl_len")]
     pub impl_use: Vec<u8>,
     #[nom(Parse = "{ |i| parse_dynamic_dstring(i, fid_len) }")]
+    #[nom(AlignAfter(4))]
     pub fid: String,
-    #[nom(
-        Count = "4 * ((fid_len as usize+impl_len as usize+38+3)/4)-(fid_len as usize+impl_len as usize+38)"
-    )]
-    _padding: Vec<u8>,
+}
+impl FID {
+    /// length of the fixed part of a FID, up to the implementation use field
+    const HEADER_LEN: usize = 38;
+
+    /// the recorded file identifier of the FID in `raw`, as returned by `raw_len`
+    fn raw_name(raw: &[u8]) -> &[u8] {
+        let fid_len = raw[19] as usize;
+        let impl_len = u16::from_le_bytes([raw[36], raw[37]]) as usize;
+        &raw[Self::HEADER_LEN + impl_len..Self::HEADER_LEN + impl_len + fid_len]
+    }
+
+    /// length of the FID at the start of `raw` including its padding, taken from its header
+    pub(crate) fn raw_len(raw: &[u8]) -> Option<usize> {
+        if raw.len() < Self::HEADER_LEN {
+            return None;
+        }
+        let fid_len = raw[19] as usize;
+        let impl_len = u16::from_le_bytes([raw[36], raw[37]]) as usize;
+        let len = Self::HEADER_LEN + impl_len + fid_len;
+        (len <= raw.len()).then(|| len.next_multiple_of(4).min(raw.len()))
+    }
+}
+
+/// decodes the d-characters of file identifiers, see `UDF::set_name_decoder`
+pub trait NameDecoder: Send + Sync {
+    /// decodes a file identifier, `raw` starting with its compression ID
+    fn decode(&self, raw: &[u8]) -> String;
+}
+impl<F: Fn(&[u8]) -> String + Send + Sync> NameDecoder for F {
+    fn decode(&self, raw: &[u8]) -> String {
+        self(raw)
+    }
+}
+
+/// set of names file identifiers are looked up in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NameDomain {
+    /// the file identifier as recorded
+    #[default]
+    Udf,
+    /// the 8.3 name operating systems limited to DOS names show, following the translation
+    /// algorithm of UDF 2.01 6.7.1
+    Dos,
+}
+
+/// `name` with `~n` inserted before its extension, e.g. `a~2.txt`, to tell apart entries
+/// whose names clash
+pub fn disambiguate(name: &str, n: usize) -> String {
+    match name.rfind('.') {
+        Some(dot) if dot > 0 => format!("{}~{}{}", &name[..dot], n, &name[dot..]),
+        _ => format!("{}~{}", name, n),
+    }
+}
+
+/// characters DOS doesn't allow in file names
+fn dos_illegal(c: char) -> bool {
+    (c as u32) < 0x20 || "\\/:*?\"<>|;+,=[] ".contains(c)
+}
+
+/// translates `name` into an 8.3 DOS name. Names that aren't valid DOS names as they are get
+/// their base name cut to 4 characters followed by `#` and the last 3 hex digits of a CRC of
+/// the full name.
+pub fn dos_name(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    // the extension starts after the last period that is followed by anything but periods
+    // and spaces
+    let trimmed = chars.len()
+        - chars
+            .iter()
+            .rev()
+            .take_while(|c| **c == '.' || **c == ' ')
+            .count();
+    let ext_start = chars[..trimmed]
+        .iter()
+        .rposition(|c| *c == '.')
+        .filter(|i| *i > 0);
+    let (base, ext) = match ext_start {
+        Some(i) => (&chars[..i], &chars[i + 1..trimmed]),
+        None => (&chars[..], &chars[..0]),
+    };
+    let mut needs_crc = trimmed != chars.len();
+    let mut translate = |part: &[char], max: usize| -> String {
+        let mut out = String::new();
+        for &c in part {
+            if c == '.' || dos_illegal(c) || !c.is_ascii() {
+                needs_crc = true;
+                if c != '.' && c != ' ' && out.len() < max {
+                    out.push('_');
+                }
+            } else if out.len() < max {
+                out.push(c.to_ascii_uppercase());
+            } else {
+                needs_crc = true;
+            }
+        }
+        out
+    };
+    let mut dos = translate(base, 8);
+    let ext = translate(ext, 3);
+    if needs_crc || dos.is_empty() {
+        let utf16: Vec<u8> = name.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        let crc = crate::crc(&utf16);
+        dos.truncate(4);
+        dos.push_str(&format!("#{:03X}", crc & 0xfff));
+    }
+    if !ext.is_empty() {
+        dos.push('.');
+        dos.push_str(&ext);
+    }
+    dos
+}
+
+/// a directory entry as recorded in its FID, the ICB it points to is only read on `resolve()`
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub name: String,
+    /// file characteristics of the FID (ECMA-167 4/14.4.3)
+    pub file_bits: u8,
+    /// address of the entry's ICB
+    pub icb: LBAddr,
+}
+impl DirEntry {
+    /// the name of the entry in `domain`
+    pub fn name_in(&self, domain: NameDomain) -> String {
+        match domain {
+            NameDomain::Udf => self.name.clone(),
+            NameDomain::Dos => dos_name(&self.name),
+        }
+    }
+
+    /// the names of the entry in every domain
+    pub fn names(&self) -> Vec<(NameDomain, String)> {
+        [NameDomain::Udf, NameDomain::Dos]
+            .into_iter()
+            .map(|domain| (domain, self.name_in(domain)))
+            .collect()
+    }
+
+    /// whether `name` refers to this entry in `domain`. DOS names are case insensitive.
+    pub fn matches(&self, domain: NameDomain, name: &str) -> bool {
+        match domain {
+            NameDomain::Udf => self.name == name,
+            NameDomain::Dos => dos_name(&self.name).eq_ignore_ascii_case(name),
+        }
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.file_bits & 0x01 != 0
+    }
+
+    /// the kind of the entry as far as the FID tells, which only knows directories. The
+    /// exact kind, e.g. of symlinks and devices, is recorded in the ICB.
+    pub fn kind(&self) -> EntryKind {
+        if self.is_dir() {
+            EntryKind::Dir
+        } else {
+            EntryKind::File
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.file_bits & 0x02 != 0
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        self.file_bits & 0x04 != 0
+    }
+
+    pub fn is_parent(&self) -> bool {
+        self.file_bits & 0x08 != 0
+    }
+
+    /// decodes the entry from the raw bytes of a FID without parsing the whole descriptor
+    pub(crate) fn parse_raw(
+        raw: &[u8],
+        decoder: Option<&dyn NameDecoder>,
+    ) -> Result<Self, &'static str> {
+        let len = FID::raw_len(raw).ok_or("truncated FID")?;
+        if u16::from_le_bytes([raw[0], raw[1]]) != 257 {
+            return Err("not a FID");
+        }
+        // the ICB address of the long allocation descriptor at 20, read without parsing it
+        let icb = LBAddr {
+            lbn: u32::from_le_bytes(raw[24..28].try_into().unwrap()),
+            part_ref_nr: u16::from_le_bytes([raw[28], raw[29]]),
+        };
+        let name = FID::raw_name(&raw[..len]);
+        let name = match decoder {
+            Some(decoder) if !name.is_empty() => decoder.decode(name),
+            _ => decode_cs0(name),
+        };
+        Ok(Self {
+            name,
+            file_bits: raw[18],
+            icb,
+        })
+    }
+
+    /// reads the ICB of this entry
+    pub fn resolve<IO: Read + Seek>(&self, udf: &mut UDF<IO>) -> Result<ICB, Box<dyn Error>> {
+        udf.read_icb(&self.icb)
+    }
+}
+impl From<FID> for DirEntry {
+    fn from(fid: FID) -> Self {
+        Self {
+            name: fid.fid,
+            file_bits: fid.file_bits,
+            icb: fid.icb.loc,
+        }
+    }
 }
 
 #[derive(Nom)]
@@ -256,8 +580,87 @@ pub enum FileType {
     TE,
     SYMLINK,
     STREAMDIR,
+    VAT = 248,
     METAMAIN = 250,
     METAMIRROR,
+    METABITMAP,
+}
+
+/// What an entry is, simplified from the recorded `FileType`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EntryKind {
+    File,
+    Dir,
+    Symlink,
+    /// block or character device
+    Device,
+    Fifo,
+    Socket,
+    /// stream directory of a file with named streams
+    Stream,
+    /// structures of the file system itself, e.g. the VAT or the metadata file
+    Special,
+}
+impl EntryKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::File => "file",
+            Self::Dir => "dir",
+            Self::Symlink => "symlink",
+            Self::Device => "device",
+            Self::Fifo => "fifo",
+            Self::Socket => "socket",
+            Self::Stream => "stream",
+            Self::Special => "special",
+        }
+    }
+}
+impl From<FileType> for EntryKind {
+    fn from(file_type: FileType) -> Self {
+        match file_type {
+            // files of unspecified type are treated as regular files
+            FileType::UNK | FileType::BYTES => Self::File,
+            FileType::DIR => Self::Dir,
+            FileType::SYMLINK => Self::Symlink,
+            FileType::BLOCKDEV | FileType::CHARDEV => Self::Device,
+            FileType::FIFO => Self::Fifo,
+            FileType::SOCK => Self::Socket,
+            FileType::STREAMDIR => Self::Stream,
+            FileType::USE
+            | FileType::PIE
+            | FileType::IE
+            | FileType::EXTATTR
+            | FileType::TE
+            | FileType::VAT
+            | FileType::METAMAIN
+            | FileType::METAMIRROR
+            | FileType::METABITMAP => Self::Special,
+        }
+    }
+}
+impl std::str::FromStr for EntryKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            Self::File,
+            Self::Dir,
+            Self::Symlink,
+            Self::Device,
+            Self::Fifo,
+            Self::Socket,
+            Self::Stream,
+            Self::Special,
+        ]
+        .into_iter()
+        .find(|kind| kind.as_str() == s)
+        .ok_or_else(|| format!("invalid entry kind {}", s))
+    }
+}
+impl std::fmt::Display for EntryKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 #[derive(Nom, Clone)]
@@ -272,6 +675,7 @@ impl ICBFlags {
             0 => Ok(AllocType::SHORT),
             1 => Ok(AllocType::LONG),
             2 => Ok(AllocType::EXTENDED),
+            3 => Ok(AllocType::EMBEDDED),
             _ => Err("unknown alloc type."),
         }
     }
@@ -317,16 +721,154 @@ pub struct FileEntry {
     pub alloc_descs: Vec<u8>,
 }
 
+/// Extended File Entry (ECMA-167 4/14.17), used by UDF 2.00 and later. It adds a creation
+/// time, the size of the file including its streams and a stream directory to a File Entry.
+#[derive(Nom, Clone)]
+#[nom(LittleEndian)]
+pub struct ExtendedFileEntry {
+    pub uid: u32,
+    pub gid: u32,
+    pub permissions: u32,
+    pub file_link_count: u16,
+    pub record_format: u8,
+    pub record_disp_attrib: u8,
+    pub record_len: u32,
+    pub info_len: u64,
+    /// information length of the file and all of its streams
+    pub obj_size: u64,
+    pub num_lb_recorded: u64,
+    pub atime: Timestamp,
+    pub mtime: Timestamp,
+    pub ctime: Timestamp,
+    pub attrtime: Timestamp,
+    pub checkpoint: u32,
+    _res: u32,
+    pub ea_icb: LongAD,
+    pub stream_dir_icb: LongAD,
+    pub impl_ident: RegID,
+    pub unique_id: u64,
+    _ea_len: u32,
+    _ad_len: u32,
+    #[nom(Count = "_ea_len")]
+    pub ex_attrs: Vec<u8>,
+    #[nom(Count = "_ad_len")]
+    pub alloc_descs: Vec<u8>,
+}
+
+/// the fields of a File Entry or an Extended File Entry. The fields only recorded in Extended
+/// File Entries are `None` for File Entries.
+pub trait FileEntryFields {
+    fn uid(&self) -> u32;
+    fn gid(&self) -> u32;
+    fn permissions(&self) -> u32;
+    fn file_link_count(&self) -> u16;
+    fn info_len(&self) -> u64;
+    fn num_lb_recorded(&self) -> u64;
+    fn atime(&self) -> &Timestamp;
+    fn mtime(&self) -> &Timestamp;
+    fn attrtime(&self) -> &Timestamp;
+    fn checkpoint(&self) -> u32;
+    fn ea_icb(&self) -> &LongAD;
+    fn impl_ident(&self) -> &RegID;
+    fn unique_id(&self) -> u64;
+    fn ex_attrs(&self) -> &[u8];
+    /// the allocation descriptors, or the data of embedded files
+    fn alloc_descs(&self) -> &[u8];
+    fn ctime(&self) -> Option<&Timestamp> {
+        None
+    }
+    fn obj_size(&self) -> Option<u64> {
+        None
+    }
+    /// the stream directory ICB, if one is recorded
+    fn stream_dir_icb(&self) -> Option<&LongAD> {
+        None
+    }
+}
+
+macro_rules! impl_file_entry_fields {
+    ($ty:ty, $($extra:tt)*) => {
+        impl FileEntryFields for $ty {
+            fn uid(&self) -> u32 {
+                self.uid
+            }
+            fn gid(&self) -> u32 {
+                self.gid
+            }
+            fn permissions(&self) -> u32 {
+                self.permissions
+            }
+            fn file_link_count(&self) -> u16 {
+                self.file_link_count
+            }
+            fn info_len(&self) -> u64 {
+                self.info_len
+            }
+            fn num_lb_recorded(&self) -> u64 {
+                self.num_lb_recorded
+            }
+            fn atime(&self) -> &Timestamp {
+                &self.atime
+            }
+            fn mtime(&self) -> &Timestamp {
+                &self.mtime
+            }
+            fn attrtime(&self) -> &Timestamp {
+                &self.attrtime
+            }
+            fn checkpoint(&self) -> u32 {
+                self.checkpoint
+            }
+            fn ea_icb(&self) -> &LongAD {
+                &self.ea_icb
+            }
+            fn impl_ident(&self) -> &RegID {
+                &self.impl_ident
+            }
+            fn unique_id(&self) -> u64 {
+                self.unique_id
+            }
+            fn ex_attrs(&self) -> &[u8] {
+                &self.ex_attrs
+            }
+            fn alloc_descs(&self) -> &[u8] {
+                &self.alloc_descs
+            }
+            $($extra)*
+        }
+    };
+}
+
+impl_file_entry_fields! { FileEntry, }
+impl_file_entry_fields! {
+    ExtendedFileEntry,
+    fn ctime(&self) -> Option<&Timestamp> {
+        Some(&self.ctime)
+    }
+    fn obj_size(&self) -> Option<u64> {
+        Some(self.obj_size)
+    }
+    fn stream_dir_icb(&self) -> Option<&LongAD> {
+        Some(&self.stream_dir_icb).filter(|ad| ad.len != 0)
+    }
+}
+
 #[derive(Clone)]
 pub enum ICBBody {
     Indirect(LongAD),
     Terminal(),
     File(FileEntry),
+    ExtendedFile(ExtendedFileEntry),
 }
 impl ICBBody {
-    pub fn parse_le<'nom>(i: &'nom [u8], selector: FileType) -> nom::IResult<&'nom [u8], Self> {
+    /// parses the body of an ICB with the tag identifier `tag_id` and the file type `selector`
+    pub fn parse_le<'a>(
+        i: &'a [u8],
+        tag_id: &FileTagID,
+        selector: FileType,
+    ) -> nom::IResult<&'a [u8], Self> {
         match selector {
-            FileType::TE => return Ok((i, Self::Terminal())),
+            FileType::TE => Ok((i, Self::Terminal())),
             FileType::UNK
             | FileType::DIR
             | FileType::BYTES
@@ -335,14 +877,21 @@ impl ICBBody {
             | FileType::EXTATTR
             | FileType::FIFO
             | FileType::SOCK
+            | FileType::SYMLINK
+            | FileType::STREAMDIR
+            | FileType::VAT
             | FileType::METAMAIN
-            | FileType::METAMIRROR => return FileEntry::parse(i).map(|e| (e.0, Self::File(e.1))),
-            _ => {
-                return Err(nom::Err::Failure(nom::error::Error::new(
-                    i,
-                    nom::error::ErrorKind::Fail,
-                )))
-            }
+            | FileType::METAMIRROR
+            | FileType::METABITMAP => match tag_id {
+                FileTagID::EFE => {
+                    ExtendedFileEntry::parse(i).map(|e| (e.0, Self::ExtendedFile(e.1)))
+                }
+                _ => FileEntry::parse(i).map(|e| (e.0, Self::File(e.1))),
+            },
+            _ => Err(nom::Err::Failure(nom::error::Error::new(
+                i,
+                nom::error::ErrorKind::Fail,
+            ))),
         }
     }
 }
@@ -352,78 +901,540 @@ impl ICBBody {
 pub struct ICB {
     pub tag: FileTag,
     pub icb_tag: ICBTag,
-    #[nom(Parse = "{ |i| ICBBody::parse_le(i, icb_tag.file_type) }")]
+    #[nom(Parse = "{ |i| ICBBody::parse_le(i, &tag.tag_id, icb_tag.file_type) }")]
     pub body: ICBBody,
+    /// address this ICB was read from, short allocation descriptors refer to its partition
+    #[nom(Ignore)]
+    pub loc: LBAddr,
 }
 impl ICB {
+    pub fn kind(&self) -> EntryKind {
+        self.icb_tag.file_type.into()
+    }
+
+    /// the File Entry or Extended File Entry of this ICB, `None` for other ICBs
+    pub fn file_entry(&self) -> Option<&dyn FileEntryFields> {
+        match &self.body {
+            ICBBody::File(fe) => Some(fe),
+            ICBBody::ExtendedFile(efe) => Some(efe),
+            _ => None,
+        }
+    }
+
     pub fn get_alloc_descs(&self) -> Vec<AllocDesc> {
         let mut vec = Vec::new();
-        let ty = self.icb_tag.flags.get_alloc_type().unwrap();
-        if let ICBBody::File(file) = &self.body {
-            let mut desc = AllocDesc::parse(&file.alloc_descs, ty.clone());
-            loop {
-                if let Ok(res) = desc {
-                    vec.push(res.1);
-                    desc = AllocDesc::parse(&res.0, ty.clone());
-                } else {
-                    break;
-                }
+        let Ok(ty) = self.icb_tag.flags.get_alloc_type() else {
+            return vec;
+        };
+        if let AllocType::EMBEDDED = ty {
+            return vec;
+        }
+        if let Some(file) = self.file_entry() {
+            let mut desc = AllocDesc::parse(file.alloc_descs(), ty.clone());
+            while let Ok(res) = desc {
+                vec.push(res.1);
+                desc = AllocDesc::parse(res.0, ty.clone());
             }
         }
         vec
     }
 
-    /// gets all File Identifier Descriptors corresponding to this ICB
-    /// the first icb returned will be the FID belonging to the ICB itself
-    pub fn get_fids<IO: Read + Seek>(&self, udf: &mut UDF<IO>) -> Vec<FID> {
-        let body;
-        if let ICBBody::File(file) = &self.body {
-            body = file
-        } else {
-            return Vec::new();
+    /// reads the data described by this ICB, i.e. all of its extents (or the data embedded
+    /// in the ICB itself) concatenated and clamped to the information length
+    pub fn read_data<IO: Read + Seek>(&self, udf: &mut UDF<IO>) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut data = Vec::new();
+        self.read_data_into(udf, &mut data)?;
+        Ok(data)
+    }
+
+    /// reads up to `buf.len()` bytes of the data described by this ICB starting at `pos`,
+    /// returning the number of bytes read
+    pub fn read_at<IO: Read + Seek>(
+        &self,
+        udf: &mut UDF<IO>,
+        pos: u64,
+        buf: &mut [u8],
+    ) -> Result<usize, Box<dyn Error>> {
+        let Some(file) = self.file_entry() else {
+            return Ok(0);
+        };
+        let info_len = file.info_len();
+        if pos >= info_len {
+            return Ok(0);
         }
-        let ad = &self.get_alloc_descs()[0];
-        let (loc, _) = udf.alloc_desc_to_offset_len(&ad);
-        match self.icb_tag.strategy {
-            1 => {
-                todo!()
+        let len = buf.len().min((info_len - pos) as usize);
+        if let AllocType::EMBEDDED = self.icb_tag.flags.get_alloc_type()? {
+            let data = file.alloc_descs().get(pos as usize..).unwrap_or_default();
+            let n = len.min(data.len());
+            buf[..n].copy_from_slice(&data[..n]);
+            return Ok(n);
+        }
+        let mut done = 0;
+        let mut ext_start = 0;
+        for ad in self.get_alloc_descs() {
+            let ext_end = ext_start + ad.len() as u64;
+            // more than one piece if the extent crosses packets relocated by a sparing table
+            while done < len && pos + (done as u64) < ext_end {
+                let cur = pos + done as u64;
+                let mut n = (len - done).min((ext_end - cur) as usize);
+                match ad.ext_type() {
+                    0 => {
+                        let part_ref = self.loc.part_ref_nr;
+                        let (loc, contiguous) = udf.extent_pos(&ad, part_ref, cur - ext_start)?;
+                        n = n.min(contiguous as usize);
+                        udf.read_bytes(self.io_category(), loc, &mut buf[done..done + n])?;
+                    }
+                    1 | 2 => buf[done..done + n].fill(0),
+                    _ => Err("allocation extent descriptors are not supported yet")?,
+                }
+                done += n;
+            }
+            if done == len {
+                break;
+            }
+            ext_start = ext_end;
+        }
+        Ok(done)
+    }
+
+    /// like `read_data`, but reads into `data` to reuse its allocation
+    pub fn read_data_into<IO: Read + Seek>(
+        &self,
+        udf: &mut UDF<IO>,
+        data: &mut Vec<u8>,
+    ) -> Result<(), Box<dyn Error>> {
+        data.clear();
+        let Some(file) = self.file_entry() else {
+            return Ok(());
+        };
+        let info_len = file.info_len();
+        if let AllocType::EMBEDDED = self.icb_tag.flags.get_alloc_type()? {
+            let len = file.alloc_descs().len().min(info_len as usize);
+            data.extend_from_slice(&file.alloc_descs()[..len]);
+            return Ok(());
+        }
+        // neither the information length nor the extent lengths are trusted to allocate
+        // more than the medium holds
+        let medium_len = udf.medium_len()?;
+        data.reserve(info_len.min(medium_len) as usize);
+        for ad in self.get_alloc_descs() {
+            if data.len() as u64 >= info_len {
+                break;
+            }
+            match ad.ext_type() {
+                // extents are usually padded to whole blocks, the padding isn't read
+                0 => {
+                    let max = info_len - data.len() as u64;
+                    udf.read_extent(self.io_category(), &ad, self.loc.part_ref_nr, max, data)?
+                }
+                1 | 2 => {
+                    let len = (data.len() as u64 + ad.len() as u64).min(info_len);
+                    if len > medium_len {
+                        Err("unrecorded extents exceed the size of the medium, use read_at")?
+                    }
+                    data.resize(len as usize, 0)
+                }
+                _ => Err("allocation extent descriptors are not supported yet")?,
+            }
+        }
+        data.truncate(info_len as usize);
+        Ok(())
+    }
+
+    /// reads the slack of the file: the bytes from the end of its data to the end of the
+    /// last block allocated to it, which often hold remnants of deleted data. Includes
+    /// extents allocated beyond the end of the data, and is empty for data embedded in the
+    /// ICB.
+    pub fn read_slack<IO: Read + Seek>(
+        &self,
+        udf: &mut UDF<IO>,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut slack = Vec::new();
+        for range in self.slack_ranges(udf)? {
+            let len = slack.len();
+            slack.resize(len + (range.end - range.start) as usize, 0);
+            udf.read_bytes(self.io_category(), range.start, &mut slack[len..])?;
+        }
+        Ok(slack)
+    }
+
+    /// the byte ranges on the medium holding the slack of the file, see `read_slack`
+    pub(crate) fn slack_ranges<IO: Read + Seek>(
+        &self,
+        udf: &mut UDF<IO>,
+    ) -> Result<Vec<Range<u64>>, Box<dyn Error>> {
+        let mut ranges = Vec::new();
+        let Some(file) = self.file_entry() else {
+            return Ok(ranges);
+        };
+        if let AllocType::EMBEDDED = self.icb_tag.flags.get_alloc_type()? {
+            return Ok(ranges);
+        }
+        let info_len = file.info_len();
+        let bs = udf.block_size();
+        let medium_len = udf.medium_len()?;
+        let mut total = 0;
+        let mut ext_start = 0;
+        for ad in self.get_alloc_descs() {
+            // the slack of an extent runs from the end of the data to the end of its last
+            // block, extents that end short of the data have none
+            let from = info_len.saturating_sub(ext_start);
+            let to = (ad.len() as u64).div_ceil(bs) * bs;
+            ext_start += ad.len() as u64;
+            // unallocated extents hold no slack
+            if !matches!(ad.ext_type(), 0 | 1) || from >= to {
+                continue;
             }
-            2 => {
-                todo!()
+            total += to - from;
+            if total > medium_len {
+                Err("slack exceeds the size of the medium")?
             }
-            3 => {
-                todo!()
+            let start = ad.lb_addr(self.loc.part_ref_nr);
+            let mut pos = from;
+            while pos < to {
+                let loc = LBAddr {
+                    lbn: start.lbn.saturating_add((pos / bs) as u32),
+                    part_ref_nr: start.part_ref_nr,
+                };
+                let block_pos = udf.lb_to_sector(&loc)? as u64 * udf.sector_size();
+                let range = block_pos + pos % bs..block_pos + bs;
+                pos += bs - pos % bs;
+                match ranges.last_mut() {
+                    Some(last) if last.end == range.start => last.end = range.end,
+                    _ => ranges.push(range),
+                }
             }
-            4 => {
-                let mut buf = [0 as u8; BLOCKSIZE as _];
-                udf.io.seek(SeekFrom::Start(loc as _)).unwrap();
-                udf.io.read(&mut buf).unwrap();
-                let mut res =
-                    nom::multi::count(FID::parse_le, body.file_link_count as usize + 1)(&buf)
-                        .unwrap()
-                        .1;
-                res
+        }
+        Ok(ranges)
+    }
+
+    /// calls `f` with the raw bytes of every FID recorded in this directory, in order.
+    /// FIDs recorded at the wrong location are logged and skipped. Returns false if the
+    /// directory couldn't be read completely, and an error if it exceeds the volume's limits.
+    pub(crate) fn for_each_raw_fid<IO: Read + Seek>(
+        &self,
+        udf: &mut UDF<IO>,
+        mut f: impl FnMut(&[u8]),
+    ) -> Result<bool, Box<dyn Error>> {
+        self.for_each_located_fid(udf, |raw, _, _| f(raw))
+    }
+
+    /// like `for_each_raw_fid`, also passing the logical block every FID was read from and
+    /// its byte offset in that block
+    pub(crate) fn for_each_located_fid<IO: Read + Seek>(
+        &self,
+        udf: &mut UDF<IO>,
+        mut f: impl FnMut(&[u8], LBAddr, u64),
+    ) -> Result<bool, Box<dyn Error>> {
+        match self.icb_tag.strategy {
+            // strategy 4096 is only defined by ECMA-167 3rd edition, its direct entry is read like
+            // a strategy 4 ICB
+            4 | 4096 => {
+                if self.icb_tag.strategy == 4096 && self.tag.version < 3 {
+                    warn!(
+                        "ICB strategy 4096 used by a version {} descriptor",
+                        self.tag.version
+                    );
+                }
+                if let (Some(file), Ok(AllocType::SHORT | AllocType::LONG)) =
+                    (self.file_entry(), self.icb_tag.flags.get_alloc_type())
+                {
+                    udf.count_metadata(file.info_len())?;
+                    if udf
+                        .options
+                        .max_dir_bytes()
+                        .is_some_and(|max| file.info_len() > max as u64)
+                    {
+                        Err("directory exceeds the memory budget")?
+                    }
+                }
+                let mut data = udf.take_buf();
+                if let Err(e) = self.read_data_into(udf, &mut data) {
+                    error!("Error reading directory: {}", e);
+                    udf.recycle_buf(data);
+                    return Ok(false);
+                }
+                // start offset in the directory data and first block of every extent
+                let mut extents = vec![(0, self.loc.lbn)];
+                let mut offset = 0;
+                for ad in self.get_alloc_descs() {
+                    extents.push((offset, ad.lb_addr(self.loc.part_ref_nr).lbn));
+                    offset += ad.len() as usize;
+                }
+                // the FIDs of embedded directories follow the file entry in its block
+                let embedded_at = match (&self.body, extents.len()) {
+                    (ICBBody::File(fe), 1) => 176 + fe.ex_attrs().len() as u64,
+                    (ICBBody::ExtendedFile(efe), 1) => 216 + efe.ex_attrs().len() as u64,
+                    _ => 0,
+                };
+                let max_entries = udf.options.limits.max_dir_entries.unwrap_or(usize::MAX);
+                let mut offset = 0;
+                let mut count = 0;
+                let mut complete = true;
+                while offset < data.len() {
+                    count += 1;
+                    if count > max_entries {
+                        udf.recycle_buf(data);
+                        return Err("directory entry limit exceeded".into());
+                    }
+                    let raw = &data[offset..];
+                    let Some(len) = FID::raw_len(raw) else {
+                        error!("Error parsing FID at offset {}", offset);
+                        complete = false;
+                        break;
+                    };
+                    let (start, lbn) = extents
+                        .iter()
+                        .rev()
+                        .find(|(start, _)| *start <= offset)
+                        .unwrap();
+                    let pos = embedded_at + (offset - start) as u64;
+                    let block = pos / udf.block_size();
+                    let in_block = pos % udf.block_size();
+                    let expected = lbn.wrapping_add(block as u32);
+                    let tag_loc = u32::from_le_bytes(raw[12..16].try_into().unwrap());
+                    // the checks only fail in strict mode, which never lists a damaged directory
+                    if let Err(e) = check_tag_crc(&udf.options, "FID", raw)
+                        .and_then(|_| check_tag_loc(udf.options.strict, "FID", tag_loc, expected))
+                    {
+                        udf.recycle_buf(data);
+                        return Err(format!("{} at offset {}", e, offset).into());
+                    }
+                    let loc = LBAddr {
+                        lbn: expected,
+                        part_ref_nr: self.loc.part_ref_nr,
+                    };
+                    f(&raw[..len], loc, in_block);
+                    offset += len;
+                }
+                udf.recycle_buf(data);
+                Ok(complete)
             }
-            _ => {
-                error!("Unknown ICB strategy!");
-                Vec::new()
+            strategy => {
+                error!("Unsupported ICB strategy {}", strategy);
+                Ok(false)
             }
         }
     }
 
-    pub fn get_children<IO: Read + Seek>(&self, udf: &mut UDF<IO>) -> HashMap<String, ICB> {
-        self.get_fids(udf)
+    /// gets all File Identifier Descriptors corresponding to this ICB
+    /// the first icb returned will be the FID belonging to the ICB itself
+    pub fn get_fids<IO: Read + Seek>(&self, udf: &mut UDF<IO>) -> Vec<FID> {
+        self.located_fids(udf)
             .into_iter()
-            .skip(1) // Skip the FID belonging to ourselves
-            .map(|f| {
-                let buf = udf.read_into_buf(&f.icb.into()).unwrap();
-                (f.fid, ICB::parse_le(&buf).unwrap().1)
-            })
+            .map(|(fid, _, _)| fid)
             .collect()
     }
 
+    /// like `get_fids`, with the logical block every FID was read from and its byte offset
+    /// in that block
+    pub(crate) fn located_fids<IO: Read + Seek>(
+        &self,
+        udf: &mut UDF<IO>,
+    ) -> Vec<(FID, LBAddr, u64)> {
+        let mut fids = Vec::new();
+        let decoder = udf.name_decoder.clone();
+        let res = self.for_each_located_fid(udf, |raw, loc, in_block| match FID::parse_le(raw) {
+            Ok((_, mut fid)) => {
+                if let Some(decoder) = &decoder {
+                    if fid.fid_len > 0 {
+                        fid.fid = decoder.decode(FID::raw_name(raw));
+                    }
+                }
+                fids.push((fid, loc, in_block))
+            }
+            Err(_) => error!("Error parsing FID"),
+        });
+        if let Err(e) = res {
+            error!("Error reading directory: {}", e);
+        }
+        fids
+    }
+
+    /// lists the entries of this directory without reading their ICBs. Only the fields needed
+    /// for the entry are decoded, which makes this cheaper than `get_fids` on large directories.
+    /// The parent directory entry is only listed with `UdfOptions::parent_entry`.
+    pub fn get_entries<IO: Read + Seek>(&self, udf: &mut UDF<IO>) -> Vec<DirEntry> {
+        self.read_entries(udf).unwrap_or_else(|e| {
+            error!("Error reading directory: {}", e);
+            Vec::new()
+        })
+    }
+
+    /// like `get_entries`, but fails if the directory exceeds the limits of the volume
+    pub fn read_entries<IO: Read + Seek>(
+        &self,
+        udf: &mut UDF<IO>,
+    ) -> Result<Vec<DirEntry>, Box<dyn Error>> {
+        let mut entries = self.read_all_entries(udf)?;
+        if !udf.options.parent_entry {
+            entries.retain(|e| !e.is_parent());
+        }
+        Ok(entries)
+    }
+
+    /// every entry recorded in this directory, including the parent directory entry. Other
+    /// entries without a name can't be told apart or looked up, they are left out with a
+    /// warning unless they are deleted.
+    pub(crate) fn read_all_entries<IO: Read + Seek>(
+        &self,
+        udf: &mut UDF<IO>,
+    ) -> Result<Vec<DirEntry>, Box<dyn Error>> {
+        Ok(self.shared_entries(udf)?.as_ref().clone())
+    }
+
+    /// like `read_all_entries`, but shares the listing with the cache instead of copying it
+    pub(crate) fn shared_entries<IO: Read + Seek>(
+        &self,
+        udf: &mut UDF<IO>,
+    ) -> Result<Arc<Vec<DirEntry>>, Box<dyn Error>> {
+        let cached = udf.cache.dir(&self.loc);
+        udf.record_cache(cached.is_some());
+        if let Some(entries) = cached {
+            return Ok(entries);
+        }
+        let mut entries = Vec::new();
+        let decoder = udf.name_decoder.clone();
+        let complete = self.for_each_raw_fid(udf, |raw| {
+            match DirEntry::parse_raw(raw, decoder.as_deref()) {
+                Ok(entry) if entry.name.is_empty() && !entry.is_parent() => {
+                    if !entry.is_deleted() {
+                        warn!("Skipping entry without a name for ICB at {:?}", entry.icb);
+                    }
+                }
+                Ok(entry) => entries.push(entry),
+                Err(e) => error!("{}", e),
+            }
+        })?;
+        let entries = Arc::new(entries);
+        if complete {
+            udf.cache.insert_dir(self.loc, entries.clone());
+        }
+        if udf.options.prefetch_children {
+            if let Err(e) = udf.prefetch_icbs(&entries) {
+                warn!("Error prefetching ICBs: {}", e);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// reads the ICB of the directory the parent FID of this directory points to, which is
+    /// the root directory itself for the root directory
+    pub fn parent<IO: Read + Seek>(&self, udf: &mut UDF<IO>) -> Result<ICB, Box<dyn Error>> {
+        let parent = self
+            .shared_entries(udf)?
+            .iter()
+            .find(|e| e.is_parent())
+            .cloned()
+            .ok_or("directory has no parent FID")?;
+        parent.resolve(udf)
+    }
+
+    /// reads the ICBs of the entries of this directory by name, never including the parent
+    /// directory. Names differing only in case are distinct in UDF and kept as they are,
+    /// entries recorded more than once under the same name are told apart by `disambiguate`
+    /// in the order they are recorded.
+    pub fn get_children<IO: Read + Seek>(&self, udf: &mut UDF<IO>) -> HashMap<String, ICB> {
+        let mut children = HashMap::new();
+        for e in self.get_entries(udf) {
+            if e.is_parent() {
+                continue;
+            }
+            let icb = match e.resolve(udf) {
+                Ok(icb) => icb,
+                Err(err) => {
+                    error!("Error reading ICB of {}: {}", e.name, err);
+                    continue;
+                }
+            };
+            let mut name = e.name.clone();
+            let mut n = 1;
+            while children.contains_key(&name) {
+                n += 1;
+                name = disambiguate(&e.name, n);
+            }
+            if n > 1 {
+                warn!(
+                    "Entry {} is recorded more than once, listing it as {}",
+                    e.name, name
+                );
+            }
+            children.insert(name, icb);
+        }
+        children
+    }
+
     pub fn get_content<IO: Read + Seek>(&self, udf: &mut UDF<IO>) -> Vec<u8> {
-        udf.read_into_buf(&self.get_alloc_descs()[0])
-            .unwrap_or(Vec::new())
+        udf.read_into_buf(&self.get_alloc_descs()[0], self.loc.part_ref_nr)
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Nom, Debug, Clone)]
+#[nom(LittleEndian)]
+pub struct VATHeader {
+    pub len_header: u16,
+    pub len_impl_use: u16,
+    pub lv_ident: DString<128>,
+    pub prev_vat_icb: LBN,
+    pub num_files: u32,
+    pub num_dirs: u32,
+    pub min_udf_read: u16,
+    pub min_udf_write: u16,
+    pub max_udf_write: u16,
+    _res: u16,
+    #[nom(Count = "len_impl_use")]
+    pub impl_use: Vec<u8>,
+}
+
+/// Virtual Allocation Table of a virtual partition, mapping virtual block numbers to blocks of
+/// the underlying physical partition (UDF 2.2.11)
+#[derive(Debug, Clone)]
+pub struct VAT {
+    /// address of the VAT ICB in the physical partition
+    pub icb_loc: LBAddr,
+    /// header of the UDF 2.00+ layout, `None` for UDF 1.50 VATs
+    pub header: Option<VATHeader>,
+    /// location of the previous VAT ICB in the physical partition
+    pub prev_vat_icb: Option<LBN>,
+    pub entries: Vec<LBN>,
+}
+impl VAT {
+    /// marks an unused entry of the table
+    pub const UNUSED: LBN = 0xFFFFFFFF;
+
+    /// parses the contents of a VAT file, `file_type` being the type of the VAT ICB
+    pub fn parse(data: &[u8], file_type: FileType, icb_loc: LBAddr) -> Result<Self, &'static str> {
+        let (header, prev, entries) = match file_type {
+            FileType::VAT => {
+                let (_, header) = VATHeader::parse(data).or(Err("error parsing VAT header"))?;
+                let entries = data
+                    .get(header.len_header as usize..)
+                    .ok_or("VAT header exceeds VAT")?;
+                let prev = header.prev_vat_icb;
+                (Some(header), prev, entries)
+            }
+            FileType::UNK => {
+                // UDF 1.50: entries followed by the "*UDF Virtual Alloc Tbl" RegID and the previous VAT ICB
+                let tail = data.len().checked_sub(36).ok_or("VAT too short")?;
+                let (_, regid) = RegID::parse(&data[tail..]).or(Err("error parsing VAT RegID"))?;
+                if regid.ident_str() != "*UDF Virtual Alloc Tbl" {
+                    return Err("missing VAT identifier");
+                }
+                let prev = u32::from_le_bytes(data[tail + 32..].try_into().unwrap());
+                (None, prev, &data[..tail])
+            }
+            _ => return Err("not a VAT ICB"),
+        };
+        let entries = entries
+            .chunks_exact(4)
+            .map(|c| u32::from_le_bytes(c.try_into().unwrap()))
+            .collect();
+        Ok(Self {
+            icb_loc,
+            header,
+            prev_vat_icb: (prev != Self::UNUSED).then_some(prev),
+            entries,
+        })
     }
 }
@@ -0,0 +1,57 @@
+/*
+    Formatting of sizes and timestamps shared by the `Display` implementations and the command
+    line tool, so that every frontend presents the same values. The output doesn't depend on
+    the locale: sizes are given in binary units with a decimal point, timestamps in ISO 8601.
+*/
+
+use std::fmt::{self, Write};
+
+use crate::volume::Timestamp;
+
+const UNITS: [&str; 7] = ["bytes", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+/// `bytes` in the largest binary unit it amounts to at least one of, rounded to one decimal,
+/// e.g. `1.5 MiB`. Sizes below 1 KiB are given in bytes, e.g. `512 bytes`.
+pub fn human_size(bytes: u64) -> String {
+    let mut unit = 0;
+    while unit + 1 < UNITS.len() && bytes >> (10 * (unit + 1)) > 0 {
+        unit += 1;
+    }
+    if unit == 0 {
+        return format!("{} {}", bytes, UNITS[0]);
+    }
+    let tenths = |unit: usize| (bytes as u128 * 10 + (1 << (10 * unit - 1))) >> (10 * unit);
+    // rounding may reach the next unit, e.g. 1023.99 KiB
+    if tenths(unit) >= 10240 && unit + 1 < UNITS.len() {
+        unit += 1;
+    }
+    let tenths = tenths(unit);
+    format!("{}.{} {}", tenths / 10, tenths % 10, UNITS[unit])
+}
+
+/// writes `t` according to ISO 8601, e.g. `2023-04-01T12:30:00.250000+02:00`. Years outside
+/// of 0 to 9999 are written with a sign, sub-second fields beyond their range of 0 to 99 are
+/// clamped and the offset from UTC is left out if the timestamp doesn't specify one.
+pub fn write_timestamp<W: Write>(out: &mut W, t: &Timestamp) -> fmt::Result {
+    if (0..=9999).contains(&t.year) {
+        write!(out, "{:04}", t.year)?;
+    } else {
+        write!(out, "{:+05}", t.year)?;
+    }
+    let micros = t.centisecond.min(99) as u32 * 10000
+        + t.centims.min(99) as u32 * 100
+        + t.microsecond.min(99) as u32;
+    write!(
+        out,
+        "-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}",
+        t.month, t.day, t.hour, t.minute, t.second, micros
+    )?;
+    match t.tz_offset() {
+        Some(0) => write!(out, "Z"),
+        Some(off) => {
+            let sign = if off < 0 { '-' } else { '+' };
+            write!(out, "{}{:02}:{:02}", sign, off.abs() / 60, off.abs() % 60)
+        }
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,87 @@
+/*
+    Entry points for fuzzing. They take untrusted input from memory, do no other I/O and
+    only allocate in proportion to their input, so that arbitrary input can be fed to them
+    in a loop. Any panic or unbounded allocation on any input is a bug.
+*/
+
+use std::error::Error;
+use std::io::Cursor;
+
+use nom_derive::Parse;
+
+use crate::file::{DirEntry, FileTag, FID, FSD, ICB};
+use crate::volume::{AVD, LVD, LVID, PD, PVD};
+use crate::{check_tag_crc, UdfOptions, UDF};
+
+/// a descriptor parsed by `parse_descriptor`
+pub enum Descriptor {
+    Anchor(AVD),
+    Primary(PVD),
+    Partition(PD),
+    LogicalVolume(LVD),
+    Integrity(LVID),
+    FileSet(FSD),
+    FileIdentifier(FID),
+    Icb(ICB),
+    /// a descriptor with a valid tag that isn't parsed any further
+    Other(u16),
+}
+
+/// options the entry points open volumes with: lenient, so that as much of the input as
+/// possible is looked at, and without a cache
+fn fuzz_options() -> UdfOptions {
+    UdfOptions {
+        strict: false,
+        cache_size: 0,
+        ..Default::default()
+    }
+}
+
+/// parses the descriptor at the start of `data` after verifying its tag checksum and CRC
+pub fn parse_descriptor(data: &[u8]) -> Result<Descriptor, Box<dyn Error>> {
+    if data.len() < 16 {
+        Err("descriptor shorter than its tag")?
+    }
+    let options = UdfOptions::default();
+    check_tag_crc(&options, "descriptor", data)?;
+    let id = u16::from_le_bytes([data[0], data[1]]);
+    let err = |_| "error parsing descriptor";
+    Ok(match id {
+        1 => Descriptor::Primary(PVD::parse(data).map_err(err)?.1),
+        2 => Descriptor::Anchor(AVD::parse(data).map_err(err)?.1),
+        5 => Descriptor::Partition(PD::parse(data).map_err(err)?.1),
+        6 => Descriptor::LogicalVolume(LVD::parse(data).map_err(err)?.1),
+        9 => Descriptor::Integrity(LVID::parse(data).map_err(err)?.1),
+        256 => Descriptor::FileSet(FSD::parse(data).map_err(err)?.1),
+        257 => Descriptor::FileIdentifier(FID::parse_le(data).map_err(err)?.1),
+        261 => Descriptor::Icb(ICB::parse(data).map_err(err)?.1),
+        _ => {
+            FileTag::parse(data).map_err(err)?;
+            Descriptor::Other(id)
+        }
+    })
+}
+
+/// parses `data` as the contents of a directory, i.e. a sequence of FIDs, failing on the
+/// first malformed one. Tag locations aren't checked as the location of `data` is unknown.
+pub fn parse_directory(data: &[u8]) -> Result<Vec<DirEntry>, Box<dyn Error>> {
+    let options = UdfOptions::default();
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let raw = &data[offset..];
+        let len = FID::raw_len(raw).ok_or("truncated FID")?;
+        check_tag_crc(&options, "FID", raw)?;
+        entries.push(DirEntry::parse_raw(&raw[..len], None)?);
+        offset += len;
+    }
+    Ok(entries)
+}
+
+/// opens the UDF image in `data` and lists its whole directory tree once, so that all
+/// metadata reachable from the file set is parsed
+pub fn open_image(data: &[u8]) -> Result<UDF<Cursor<&[u8]>>, Box<dyn Error>> {
+    let mut udf = UDF::new_with_options(Cursor::new(data), fuzz_options())?;
+    udf.catalogue()?;
+    Ok(udf)
+}
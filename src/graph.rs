@@ -0,0 +1,290 @@
+/*
+    Export of the structure of a volume as a graph: the integrity chain, the metadata files,
+    the VAT generations, the file set descriptors and the ICBs of all directories and files
+    reachable from the root directory. The graph can be written in the DOT language of
+    Graphviz or as a JSON tree, e.g. for teaching material or to look at strange discs.
+*/
+
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt::Write;
+use std::io::{Read, Seek};
+
+use log::error;
+
+use crate::allocation::part_sector;
+use crate::catalogue::json_str;
+use crate::file::{EntryKind, LBAddr, LongAD, ICB};
+use crate::partition::PartitionKind;
+use crate::volume::{IntegrityType, LSN};
+use crate::UDF;
+
+/// what a node of the structure graph stands for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeKind {
+    /// the logical volume, root of the graph
+    Volume,
+    Integrity,
+    /// the metadata file, its mirror or the metadata bitmap file
+    MetadataFile,
+    Vat,
+    FileSet,
+    /// the ICB of a directory, file or other entry
+    Entry(EntryKind),
+}
+
+impl NodeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NodeKind::Volume => "volume",
+            NodeKind::Integrity => "integrity",
+            NodeKind::MetadataFile => "metadata",
+            NodeKind::Vat => "vat",
+            NodeKind::FileSet => "file set",
+            NodeKind::Entry(kind) => kind.as_str(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct GraphNode {
+    pub kind: NodeKind,
+    pub label: String,
+    /// sector the descriptor is recorded in, if it could be located
+    pub lsn: Option<LSN>,
+}
+
+/// an edge between the nodes with the given indices
+#[derive(Clone, Debug)]
+pub struct GraphEdge {
+    pub from: usize,
+    pub to: usize,
+    pub label: String,
+}
+
+/// the structure of a volume, node 0 being the volume itself. Every ICB is a single node, so
+/// entries linked from several directories have more than one incoming edge.
+#[derive(Clone, Debug)]
+pub struct StructureGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+impl StructureGraph {
+    fn add_node(&mut self, kind: NodeKind, label: String, lsn: Option<LSN>) -> usize {
+        self.nodes.push(GraphNode { kind, label, lsn });
+        self.nodes.len() - 1
+    }
+
+    fn add_edge(&mut self, from: usize, to: usize, label: &str) {
+        self.edges.push(GraphEdge {
+            from,
+            to,
+            label: label.to_string(),
+        });
+    }
+
+    /// the graph in the DOT language of Graphviz
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph udf {\n    node [shape=box];\n");
+        for (i, node) in self.nodes.iter().enumerate() {
+            let mut label = format!("{}\\n{}", node.kind.as_str(), dot_escape(&node.label));
+            if let Some(lsn) = node.lsn {
+                write!(label, "\\nsector {}", lsn).unwrap();
+            }
+            writeln!(out, "    n{} [label=\"{}\"];", i, label).unwrap();
+        }
+        for edge in &self.edges {
+            write!(out, "    n{} -> n{}", edge.from, edge.to).unwrap();
+            if !edge.label.is_empty() {
+                write!(out, " [label=\"{}\"]", dot_escape(&edge.label)).unwrap();
+            }
+            out.push_str(";\n");
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// the graph as a JSON tree of nested nodes starting at the volume. A node reached again,
+    /// like a hard linked file, is written as `{"ref":<id>}` after its first appearance.
+    pub fn to_json(&self) -> String {
+        let mut children = vec![Vec::new(); self.nodes.len()];
+        for edge in &self.edges {
+            children[edge.from].push(edge);
+        }
+        let mut written = vec![false; self.nodes.len()];
+        let mut out = String::new();
+        self.write_json_node(&mut out, 0, &children, &mut written);
+        out
+    }
+
+    fn write_json_node(
+        &self,
+        out: &mut String,
+        id: usize,
+        children: &[Vec<&GraphEdge>],
+        written: &mut [bool],
+    ) {
+        if written[id] {
+            write!(out, "{{\"ref\":{}}}", id).unwrap();
+            return;
+        }
+        written[id] = true;
+        let node = &self.nodes[id];
+        write!(
+            out,
+            "{{\"id\":{},\"kind\":\"{}\",\"label\":",
+            id,
+            node.kind.as_str()
+        )
+        .unwrap();
+        json_str(out, &node.label);
+        out.push_str(",\"lsn\":");
+        match node.lsn {
+            Some(lsn) => write!(out, "{}", lsn).unwrap(),
+            None => out.push_str("null"),
+        }
+        out.push_str(",\"children\":[");
+        for (i, edge) in children[id].iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str("{\"edge\":");
+            json_str(out, &edge.label);
+            out.push_str(",\"node\":");
+            self.write_json_node(out, edge.to, children, written);
+            out.push('}');
+        }
+        out.push_str("]}");
+    }
+}
+
+/// escapes `s` for a quoted DOT string
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl<IO: Read + Seek> UDF<IO> {
+    /// builds the structure graph of the volume. Directories and ICBs that can't be read are
+    /// logged and left out.
+    pub fn structure_graph(&mut self) -> Result<StructureGraph, Box<dyn Error>> {
+        let mut graph = StructureGraph {
+            nodes: Vec::new(),
+            edges: Vec::new(),
+        };
+        let lv_ident = self.volume_info().lv_ident;
+        let volume = graph.add_node(NodeKind::Volume, lv_ident, None);
+
+        let mut integrity = Vec::new();
+        self.walk_integrity_seq(|lsn, lvid| integrity.push((lsn, lvid)))?;
+        let mut prev = (volume, "integrity");
+        for (lsn, lvid) in integrity {
+            let state = match lvid.integ_type {
+                IntegrityType::OPEN => "open",
+                IntegrityType::CLOSE => "closed",
+            };
+            let label = format!("{}, {}", state, lvid.rec_time);
+            let node = graph.add_node(NodeKind::Integrity, label, Some(lsn));
+            graph.add_edge(prev.0, node, prev.1);
+            prev = (node, "next");
+        }
+
+        self.add_metadata_files(&mut graph, volume);
+
+        match self.vat_history() {
+            Ok(history) => {
+                let mut prev = (volume, "vat");
+                for vat in history {
+                    let lsn = self.lb_to_sector(&vat.icb_loc).ok();
+                    let label = format!("{} entries", vat.entries.len());
+                    let node = graph.add_node(NodeKind::Vat, label, lsn);
+                    graph.add_edge(prev.0, node, prev.1);
+                    prev = (node, "previous");
+                }
+            }
+            Err(e) => error!("Error reading VAT history: {}", e),
+        }
+
+        let fsd_part = LongAD::parse_le(&self.logical_vol_desc.lv_contents_use)
+            .or(Err("error parsing FSD pointer"))?
+            .1
+            .loc
+            .part_ref_nr;
+        // reading the root directory selects the file set it belongs to
+        let root = self.get_root_dir()?;
+        let current = self.file_set_desc.as_ref().map(|fsd| fsd.tag.tag_loc);
+        let mut tree_parent = volume;
+        for (i, fsd) in self.file_sets()?.into_iter().enumerate() {
+            let loc = LBAddr {
+                lbn: fsd.tag.tag_loc,
+                part_ref_nr: fsd_part,
+            };
+            let label = fsd.fs_id.to_string();
+            let node = graph.add_node(NodeKind::FileSet, label, self.lb_to_sector(&loc).ok());
+            graph.add_edge(volume, node, &format!("file set {}", i));
+            if Some(fsd.tag.tag_loc) == current {
+                tree_parent = node;
+            }
+        }
+        self.add_tree(&mut graph, tree_parent, root);
+        Ok(graph)
+    }
+
+    /// adds the metadata files of every metadata partition as children of `volume`
+    fn add_metadata_files(&mut self, graph: &mut StructureGraph, volume: usize) {
+        let maps = self.partition_maps().to_vec();
+        for map in maps {
+            let (PartitionKind::Metadata(meta), Some(pd)) = (&map.kind, &map.pd) else {
+                continue;
+            };
+            let files = [
+                ("metadata file", meta.meta_file_loc),
+                ("metadata mirror file", meta.meta_mirror_loc),
+                ("metadata bitmap file", meta.meta_bmp_loc),
+            ];
+            for (name, loc) in files {
+                // the bitmap file is optional
+                if loc == 0xFFFFFFFF {
+                    continue;
+                }
+                let lsn = part_sector(pd.part_start, loc, self.block_sectors());
+                let node = graph.add_node(NodeKind::MetadataFile, name.to_string(), lsn);
+                graph.add_edge(volume, node, &format!("partition {}", map.part_ref));
+            }
+        }
+    }
+
+    /// adds the directory tree starting at `root` below `parent`, breadth first
+    fn add_tree(&mut self, graph: &mut StructureGraph, parent: usize, root: ICB) {
+        let lsn = self.lb_to_sector(&root.loc).ok();
+        let root_node = graph.add_node(NodeKind::Entry(root.kind()), "/".to_string(), lsn);
+        graph.add_edge(parent, root_node, "root");
+        let mut nodes = HashMap::from([(root.loc, root_node)]);
+        let mut queue = VecDeque::from([(root, root_node)]);
+        while let Some((dir, dir_node)) = queue.pop_front() {
+            for entry in dir.get_entries(self) {
+                if entry.is_deleted() || entry.is_parent() {
+                    continue;
+                }
+                if let Some(&node) = nodes.get(&entry.icb) {
+                    graph.add_edge(dir_node, node, &entry.name);
+                    continue;
+                }
+                let icb = match self.read_icb(&entry.icb) {
+                    Ok(icb) => icb,
+                    Err(e) => {
+                        error!("Error reading ICB at {:?}: {}", entry.icb, e);
+                        continue;
+                    }
+                };
+                let lsn = self.lb_to_sector(&entry.icb).ok();
+                let node = graph.add_node(NodeKind::Entry(icb.kind()), entry.name.clone(), lsn);
+                graph.add_edge(dir_node, node, &entry.name);
+                nodes.insert(entry.icb, node);
+                if icb.kind() == EntryKind::Dir {
+                    queue.push_back((icb, node));
+                }
+            }
+        }
+    }
+}
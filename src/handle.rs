@@ -0,0 +1,195 @@
+/*
+    File handles reading the data of a file on demand, for files too large to be read into
+    memory as a whole, and directory listings describing every entry of a directory.
+*/
+
+use std::error::Error;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::catalogue::CatalogueEntry;
+use crate::file::{AllocDesc, AllocType, EntryKind, ICB};
+use crate::UDF;
+
+/// An open file of a volume, reading from the extents of the file as it is read. Borrows the
+/// volume for as long as it is open.
+pub struct UdfFile<'a, IO: Read + Seek> {
+    udf: &'a mut UDF<IO>,
+    icb: ICB,
+    len: u64,
+    pos: u64,
+    /// offset in the file and allocation descriptor of every extent, empty for data embedded
+    /// in the ICB
+    extents: Vec<(u64, AllocDesc)>,
+}
+
+fn to_io_error(e: Box<dyn Error>) -> io::Error {
+    match e.downcast::<io::Error>() {
+        Ok(e) => *e,
+        Err(e) => io::Error::other(e.to_string()),
+    }
+}
+
+impl<'a, IO: Read + Seek> UdfFile<'a, IO> {
+    /// opens the file recorded in `icb`
+    pub fn new(udf: &'a mut UDF<IO>, icb: ICB) -> Result<Self, Box<dyn Error>> {
+        let Some(fe) = icb.file_entry() else {
+            Err("ICB has no file entry")?
+        };
+        let len = fe.info_len();
+        let mut extents = Vec::new();
+        let mut offset = 0;
+        for ad in icb.get_alloc_descs() {
+            let ext_len = ad.len() as u64;
+            extents.push((offset, ad));
+            offset += ext_len;
+        }
+        Ok(UdfFile {
+            udf,
+            icb,
+            len,
+            pos: 0,
+            extents,
+        })
+    }
+
+    /// the ICB of the file
+    pub fn icb(&self) -> &ICB {
+        &self.icb
+    }
+
+    /// size of the file in bytes
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// reads from the extent containing the current position, at most up to its end
+    fn read_extent(&mut self, buf: &mut [u8]) -> Result<usize, Box<dyn Error>> {
+        let i = self
+            .extents
+            .partition_point(|(offset, _)| *offset <= self.pos);
+        let Some((offset, ad)) = i.checked_sub(1).map(|i| &self.extents[i]) else {
+            return Ok(0);
+        };
+        let in_ext = self.pos - offset;
+        if in_ext >= ad.len() as u64 {
+            // the extents end before the information length
+            return Ok(0);
+        }
+        let mut n = buf.len().min((ad.len() as u64 - in_ext) as usize);
+        match ad.ext_type() {
+            0 => {
+                let part_ref = self.icb.loc.part_ref_nr;
+                let (loc, contiguous) = self.udf.extent_pos(ad, part_ref, in_ext)?;
+                n = n.min(contiguous as usize);
+                let category = self.icb.io_category();
+                self.udf.read_bytes(category, loc, &mut buf[..n])?;
+            }
+            1 | 2 => buf[..n].fill(0),
+            _ => Err("allocation extent descriptors are not supported yet")?,
+        }
+        Ok(n)
+    }
+}
+
+impl<IO: Read + Seek> Read for UdfFile<'_, IO> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let max = buf.len().min((self.len - self.pos) as usize);
+        let buf = &mut buf[..max];
+        let n = match self.icb.icb_tag.flags.get_alloc_type() {
+            Ok(AllocType::EMBEDDED) => self
+                .icb
+                .read_at(self.udf, self.pos, buf)
+                .map_err(to_io_error)?,
+            Ok(_) => self.read_extent(buf).map_err(to_io_error)?,
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<IO: Read + Seek> Seek for UdfFile<'_, IO> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::End(n) => self.len.checked_add_signed(n),
+            SeekFrom::Current(n) => self.pos.checked_add_signed(n),
+        };
+        self.pos = new.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative position")
+        })?;
+        Ok(self.pos)
+    }
+}
+
+/// the entries of a directory, excluding the parent directory and deleted entries
+pub struct UdfDir {
+    entries: std::vec::IntoIter<CatalogueEntry>,
+}
+
+impl Iterator for UdfDir {
+    type Item = CatalogueEntry;
+
+    fn next(&mut self) -> Option<CatalogueEntry> {
+        self.entries.next()
+    }
+}
+
+/// `path` as an absolute path with `/` as separator
+fn absolute(path: &Path) -> String {
+    let mut abs = String::new();
+    for component in path.iter().filter(|c| *c != "/") {
+        abs.push('/');
+        abs.push_str(&component.to_string_lossy());
+    }
+    if abs.is_empty() {
+        abs.push('/');
+    }
+    abs
+}
+
+impl<IO: Read + Seek> UDF<IO> {
+    /// opens the file at `path` for reading
+    pub fn open(&mut self, path: &Path) -> Result<UdfFile<'_, IO>, Box<dyn Error>> {
+        let icb = self.find_icb(path)?;
+        if icb.kind() == EntryKind::Dir {
+            Err(io::Error::from(io::ErrorKind::IsADirectory))?
+        }
+        UdfFile::new(self, icb)
+    }
+
+    /// describes the file or directory at `path`
+    pub fn metadata(&mut self, path: &Path) -> Result<CatalogueEntry, Box<dyn Error>> {
+        let icb = self.find_icb(path)?;
+        self.catalogue_entry(absolute(path), &icb)
+    }
+
+    /// lists the directory at `path`
+    pub fn read_dir(&mut self, path: &Path) -> Result<UdfDir, Box<dyn Error>> {
+        let dir = self.find_icb(path)?;
+        if dir.kind() != EntryKind::Dir {
+            Err(io::Error::from(io::ErrorKind::NotADirectory))?
+        }
+        let prefix = absolute(path);
+        let prefix = prefix.trim_end_matches('/');
+        let mut entries = Vec::new();
+        for (entry, icb) in self.stat_children(&dir)? {
+            if entry.is_parent() || entry.is_deleted() {
+                continue;
+            }
+            let path = format!("{}/{}", prefix, entry.name);
+            entries.push(self.catalogue_entry(path, &icb)?);
+        }
+        Ok(UdfDir {
+            entries: entries.into_iter(),
+        })
+    }
+}
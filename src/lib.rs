@@ -1,252 +1,1775 @@
+pub mod allocation;
+pub mod backend;
+#[cfg(all(target_os = "linux", feature = "blockdev"))]
+pub mod blockdev;
+pub mod blockmap;
+mod cache;
+pub mod catalogue;
+pub mod dedup;
+pub mod dvd;
+pub mod extract;
+pub mod faults;
+#[doc(hidden)]
 pub mod file;
+pub mod format;
+pub mod fuzz;
+pub mod graph;
+pub mod handle;
+#[cfg(all(target_os = "linux", feature = "mmc"))]
+pub mod mmc;
+pub mod mounts;
+#[cfg(feature = "writer")]
+pub mod overlay;
 pub mod parser;
+pub mod partition;
+pub mod path;
+pub mod prelude;
+pub mod raw;
+#[cfg(feature = "writer")]
+pub mod rebuild;
+pub mod recovery;
+pub mod report;
+pub mod scan;
+pub mod sessions;
+pub mod stats;
+pub mod streams;
+#[cfg(feature = "writer")]
+pub mod swap;
+#[cfg(test)]
+mod testimage;
+pub mod tree;
+#[cfg(feature = "writer")]
+pub mod undo;
+pub mod volset;
+#[doc(hidden)]
 pub mod volume;
+pub mod vrs;
+#[cfg(feature = "writer")]
+pub mod writer;
 
-use log::{info, warn};
+use log::{error, info, warn};
 use nom_derive::Parse;
 use std::{
+    collections::HashSet,
     error::Error,
     io::{ErrorKind, Read, Seek, SeekFrom},
-    path::{Component, Path},
+    path::Path,
+    sync::Arc,
 };
 
 use file::*;
+use path::{PathComponent, UdfPath};
+use stats::IoCategory;
 use volume::*;
 
 pub const BLOCKSIZE: u64 = 2048;
+/// size of the ECC blocks of DVDs and BDs, which drives read as a whole
+pub const ECC_BLOCK_SIZE: u32 = 16 * BLOCKSIZE as u32;
+/// upper bound of sectors read at once when merging reads of adjacent blocks
+const MAX_MERGED_SECTORS: usize = 64;
+/// number of scratch buffers kept around for reuse
+const MAX_POOLED_BUFS: usize = 4;
+/// upper bound of integrity descriptors read, far more than even heavily rewritten media
+/// record
+const MAX_INTEGRITY_DESCS: usize = 1 << 16;
+/// extents of a volume descriptor sequence followed at most
+const MAX_VDS_EXTENTS: usize = 64;
 
-pub struct UDF<IO: Read + Seek> {
-    io: Box<IO>,
-    pub primary_vol_desc: PVD,
-    pub part_desc: PD,
-    pub logical_vol_desc: LVD,
-    meta_file_offset: Option<u32>,
-    root_icb: Option<ICB>,
+/// sector sizes tried when looking for the anchor, in order
+pub const SECTOR_SIZES: [u32; 4] = [2048, 512, 1024, 4096];
+
+fn read_sector<IO: Read + Seek>(
+    io: &mut IO,
+    sector_size: u64,
+    lsn: LSN,
+    buf: &mut [u8],
+) -> std::io::Result<()> {
+    io.seek(SeekFrom::Start(lsn as u64 * sector_size))?;
+    io.read_exact(buf)
 }
 
-impl<IO: Read + Seek> UDF<IO> {
-    pub fn new(mut io: IO) -> Result<Self, Box<dyn Error>> {
-        let mut buf: [u8; BLOCKSIZE as usize] = [0; BLOCKSIZE as usize];
+/// whether `buf` holds an anchor volume descriptor pointer recorded at sector `lsn`
+fn is_anchor(buf: &[u8], lsn: LSN) -> bool {
+    u16::from_le_bytes([buf[0], buf[1]]) == 2
+        && tag_checksum(buf) == buf[4]
+        && u32::from_le_bytes(buf[12..16].try_into().unwrap()) == lsn
+}
+
+/// finds the sector size of the medium by looking for an anchor at sector 256 of the session
+/// starting at `session_start`, or else at the last sector, for each of `SECTOR_SIZES`.
+/// Returns `None` if there is no anchor at either place.
+pub fn detect_sector_size<IO: Read + Seek>(
+    io: &mut IO,
+    session_start: LSN,
+) -> std::io::Result<Option<u32>> {
+    let medium_len = io.seek(SeekFrom::End(0))?;
+    let mut buf = [0; 512];
+    for last in [false, true] {
+        for size in SECTOR_SIZES {
+            let lsn = match last {
+                false => session_start.saturating_add(256),
+                true => match (medium_len / size as u64).checked_sub(1) {
+                    Some(lsn) => lsn as LSN,
+                    None => continue,
+                },
+            };
+            if (lsn as u64 + 1) * size as u64 > medium_len {
+                continue;
+            }
+            read_sector(io, size as u64, lsn, &mut buf)?;
+            if is_anchor(&buf, lsn) {
+                return Ok(Some(size));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// compares the location recorded in a descriptor tag with the location the descriptor was
+/// read from, failing in strict mode and logging a warning otherwise
+pub(crate) fn check_tag_loc(
+    strict: bool,
+    desc: &str,
+    tag_loc: u32,
+    expected: u32,
+) -> Result<(), Box<dyn Error>> {
+    if tag_loc == expected {
+        return Ok(());
+    }
+    let msg = format!(
+        "{} recorded at {} claims to be located at {}",
+        desc, expected, tag_loc
+    );
+    if strict {
+        return Err(msg.into());
+    }
+    warn!("{}", msg);
+    Ok(())
+}
+
+/// CRC-ITU-T of every byte value, the CRC is computed a byte at a time
+const CRC_TABLE: [u16; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u16) << 8;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// CRC-ITU-T as used by descriptor tags (ECMA-167 1/7.2.6)
+pub(crate) fn crc(data: &[u8]) -> u16 {
+    data.iter().fold(0, |crc, &b| {
+        (crc << 8) ^ CRC_TABLE[((crc >> 8) as u8 ^ b) as usize]
+    })
+}
+
+/// sum of the descriptor tag bytes except the checksum itself (ECMA-167 3/7.2.3)
+pub(crate) fn tag_checksum(tag: &[u8]) -> u8 {
+    tag[0..4]
+        .iter()
+        .chain(&tag[5..16])
+        .fold(0u8, |acc, b| acc.wrapping_add(*b))
+}
+
+/// fills in the descriptor tag of the `len` bytes long descriptor at the start of `desc`
+#[cfg(any(test, feature = "writer"))]
+pub(crate) fn write_tag(desc: &mut [u8], id: u16, version: u16, loc: u32, len: usize) {
+    desc[0..2].copy_from_slice(&id.to_le_bytes());
+    desc[2..4].copy_from_slice(&version.to_le_bytes());
+    desc[10..12].copy_from_slice(&((len - 16) as u16).to_le_bytes());
+    desc[12..16].copy_from_slice(&loc.to_le_bytes());
+    retag(desc);
+}
+
+/// recomputes the CRC and tag checksum of a descriptor after it was modified
+#[cfg(any(test, feature = "writer"))]
+pub(crate) fn retag(desc: &mut [u8]) {
+    let len = (16 + u16::from_le_bytes([desc[10], desc[11]]) as usize).min(desc.len());
+    let crc = crc(&desc[16..len]);
+    desc[8..10].copy_from_slice(&crc.to_le_bytes());
+    desc[4] = tag_checksum(desc);
+}
 
-        io.seek(SeekFrom::Start(256 * BLOCKSIZE))?;
-        io.read(&mut buf)?;
+/// verifies the tag checksum and the CRC of the descriptor at the start of `buf`, failing in
+/// strict mode and logging a warning otherwise
+pub(crate) fn check_tag_crc(
+    options: &UdfOptions,
+    desc: &str,
+    buf: &[u8],
+) -> Result<(), Box<dyn Error>> {
+    if !options.verify_checksums {
+        return Ok(());
+    }
+    let crc_len = u16::from_le_bytes([buf[10], buf[11]]) as usize;
+    let msg = if tag_checksum(buf) != buf[4] {
+        format!("{} has an invalid tag checksum", desc)
+    } else if 16 + crc_len > buf.len() {
+        format!(
+            "{} has a CRC length of {} exceeding the descriptor",
+            desc, crc_len
+        )
+    } else if crc(&buf[16..16 + crc_len]) != u16::from_le_bytes([buf[8], buf[9]]) {
+        format!("{} has an invalid CRC", desc)
+    } else {
+        return Ok(());
+    };
+    if options.strict {
+        return Err(msg.into());
+    }
+    warn!("{}", msg);
+    Ok(())
+}
+
+fn read_avd_at<IO: Read + Seek>(
+    io: &mut IO,
+    lsn: LSN,
+    options: &UdfOptions,
+) -> Result<AVD, Box<dyn Error>> {
+    let mut buf = vec![0; options.sector_bytes() as usize];
+    read_sector(io, options.sector_bytes(), lsn, &mut buf)?;
+    let avd = AVD::parse(&buf).or(Err("error parsing AVD"))?.1;
+    check_tag_crc(options, "AVD", &buf)?;
+    check_tag_loc(options.strict, "AVD", avd.tag.tag_loc, lsn)?;
+    Ok(avd)
+}
 
-        let avd = AVD::parse(&buf).or(Err("error parsing AVD"))?.1;
-        io.seek(SeekFrom::Start(avd.main_vds.loc as u64 * BLOCKSIZE))?;
+/// reads the Anchor Volume Descriptor Pointer at sector 256 of the last session, falling back
+/// to the anchors at N - 256 and N - 1, N being the size of the volume in sectors
+fn read_avd<IO: Read + Seek>(io: &mut IO, options: &UdfOptions) -> Result<AVD, Box<dyn Error>> {
+    let first = options.session_start + 256;
+    let err = match read_avd_at(io, first, options) {
+        Ok(avd) => return Ok(avd),
+        Err(e) => e,
+    };
+    let num_sectors = (io.seek(SeekFrom::End(0))? / options.sector_bytes()) as LSN;
+    let mut fallbacks = vec![num_sectors.checked_sub(256), num_sectors.checked_sub(1)];
+    if options.session_start != 0 {
+        // the anchor of the first session only describes the volume as it was back then
+        fallbacks.push(Some(256));
+    }
+    for lsn in fallbacks.into_iter().flatten() {
+        if lsn == first || (lsn < first && lsn != 256) {
+            continue;
+        }
+        match read_avd_at(io, lsn, options) {
+            Ok(avd) => {
+                warn!(
+                    "No usable anchor at sector {} ({}), using the one at {}",
+                    first, err, lsn
+                );
+                return Ok(avd);
+            }
+            Err(e) => info!("No anchor at sector {}: {}", lsn, e),
+        }
+    }
+    Err(err)
+}
 
-        io.read(&mut buf)?;
+/// The prevailing descriptors of one Volume Descriptor Sequence
+#[derive(Clone)]
+pub struct VolDescSeq {
+    /// location of the first sector of the sequence
+    pub loc: LSN,
+    /// the extents of the sequence in the order they were followed, starting with the one at
+    /// `loc` and continued by volume descriptor pointers
+    pub extents: Vec<ExtentAD>,
+    pub pvd: Option<PVD>,
+    pub partitions: Vec<PD>,
+    pub lvd: Option<LVD>,
+    pub iuvds: Vec<IUVD>,
+}
 
-        let mut o_pvd: Option<PVD> = None;
-        let mut o_pd: Option<PD> = None;
-        let mut o_lvd: Option<LVD> = None;
+/// reads the Volume Descriptor Sequence recorded in the extent at `loc` with a length of `len` bytes,
+/// following volume descriptor pointers into further extents
+fn read_vds<IO: Read + Seek>(
+    io: &mut IO,
+    loc: LSN,
+    len: u32,
+    options: &UdfOptions,
+) -> Result<VolDescSeq, Box<dyn Error>> {
+    let sector_size = options.sector_bytes();
+    let mut buf = vec![0; sector_size as usize];
+    let mut vds = VolDescSeq {
+        loc,
+        extents: Vec::new(),
+        pvd: None,
+        partitions: Vec::new(),
+        lvd: None,
+        iuvds: Vec::new(),
+    };
 
-        let vds_start: LSN = avd.main_vds.loc;
-        let vds_end: LSN = vds_start + avd.main_vds.len;
+    let mut visited = HashSet::new();
+    let mut ext = Some(ExtentAD { len, loc });
 
-        for n in vds_start..vds_end {
-            io.seek(SeekFrom::Start(n as u64 * BLOCKSIZE)).unwrap();
-            io.read(&mut buf).unwrap();
+    while let Some(cur) = ext.take() {
+        if cur.len == 0 {
+            break;
+        }
+        if !visited.insert(cur.loc) {
+            let msg = format!("volume descriptor pointer loops back to sector {}", cur.loc);
+            if options.strict {
+                return Err(msg.into());
+            }
+            warn!("{}, ignoring it", msg);
+            break;
+        }
+        if vds.extents.len() == MAX_VDS_EXTENTS {
+            let msg = format!(
+                "volume descriptor sequence has more than {} extents",
+                MAX_VDS_EXTENTS
+            );
+            if options.strict {
+                return Err(msg.into());
+            }
+            warn!("{}, ignoring the rest", msg);
+            break;
+        }
+        vds.extents.push(cur.clone());
+        let num_sectors = (cur.len as u64).div_ceil(sector_size) as u32;
+        for n in cur.loc..cur.loc.saturating_add(num_sectors) {
+            read_sector(io, sector_size, n, &mut buf)?;
             let tag = Tag::parse(&buf).or(Err("error parsing VDS tag"))?.1;
 
             if tag.tag_id != TagID::UNK {
                 info!("Found descriptor of type: {:?}", tag.tag_id);
+                check_tag_crc(options, "volume descriptor", &buf)?;
+                check_tag_loc(options.strict, "volume descriptor", tag.tag_loc, n)?;
             }
             match tag.tag_id {
                 TagID::TD => {
                     break;
                 }
                 TagID::VD => {
+                    let vd = VD::parse(&buf).or(Err("error parsing VD."))?.1;
+                    info!(
+                        "Volume descriptor sequence continues at sector {}",
+                        vd.next_vds.loc
+                    );
+                    ext = Some(vd.next_vds);
                     break;
                 }
                 TagID::PVD => {
-                    let pvd = PVD::parse(&buf).unwrap().1;
+                    let pvd = PVD::parse(&buf).or(Err("error parsing PVD."))?.1;
                     info!("Volume Identifier: {}", pvd.vol_ident);
-                    o_pvd = Some(pvd);
+                    vds.pvd = Some(pvd);
                 }
                 TagID::PD => {
                     let pd = PD::parse(&buf).or(Err("error parsing PD."))?.1;
-                    let ident = std::str::from_utf8(&pd.part_cont.ident)
-                        .unwrap()
-                        .trim_matches('\0');
-                    info!("Found partition {} of type {}", pd.part_num, ident);
-                    match ident {
-                        "+NSR02" | "+NSR03" => {
+                    let contents = pd.contents();
+                    info!("Found partition {} of type {:?}", pd.part_num, contents);
+                    match contents {
+                        PartContents::NSR02 | PartContents::NSR03 => {
                             //let phd = PHD::parse(&pd.impl_use).unwrap().1;
                         }
-                        _ => {
+                        PartContents::UNK(ident) => {
                             warn!("Unknown partition type: {}", ident);
                         }
+                        _ => {
+                            info!("Skipping non-UDF partition {}", pd.part_num);
+                        }
+                    }
+                    // a partition is described by the descriptor with the highest sequence number
+                    match vds
+                        .partitions
+                        .iter_mut()
+                        .find(|p| p.part_num == pd.part_num)
+                    {
+                        Some(prev) if prev.vds_num <= pd.vds_num => *prev = pd,
+                        Some(_) => {}
+                        None => vds.partitions.push(pd),
                     }
-                    o_pd = Some(pd);
                 }
                 TagID::LVD => {
-                    let lvd = LVD::parse(&buf).unwrap().1;
+                    let lvd = LVD::parse(&buf).or(Err("error parsing LVD."))?.1;
                     info!("Found logical volume: {}", lvd.lvid);
-                    o_lvd = Some(lvd);
+                    vds.lvd = Some(lvd);
+                }
+                TagID::IUVD => {
+                    let iuvd = IUVD::parse(&buf).or(Err("error parsing IUVD."))?.1;
+                    info!(
+                        "Found implementation use descriptor of {}",
+                        iuvd.impl_id.ident_str()
+                    );
+                    vds.iuvds.push(iuvd);
                 }
                 _ => {}
             }
         }
+    }
+    Ok(vds)
+}
 
-        let pvd = o_pvd.ok_or("no primary volume descriptor found")?;
-        let pd = o_pd.ok_or("no partition descriptor found")?;
-        let lvd = o_lvd.ok_or("no local volume descriptor found")?;
+/// Summary of the Logical Volume Integrity Sequence
+#[derive(Clone)]
+pub struct IntegritySequence {
+    /// the extents of the sequence in the order they were followed, starting with the one
+    /// recorded in the logical volume descriptor
+    pub extents: Vec<ExtentAD>,
+    /// number of integrity descriptors recorded in all extents
+    pub num_descs: usize,
+    /// the last descriptor recorded, describing the current state of the volume
+    pub current: Option<LVID>,
+}
 
-        // Search for metadata offset of FSD
-        let mut metadata_offset: Option<u32> = None;
-        {
-            let mut meta_file_loc: Option<u32> = None;
-            for part_map in &lvd.part_maps {
-                match &part_map.part_map {
-                    PartMapType::Type2(part) => {
-                        info!("Found metadata partition");
-                        meta_file_loc = Some(part.meta_file_loc);
-                    }
-                    _ => {}
+/// the volume structures read when opening a volume
+struct VolumeStructures {
+    pvd: PVD,
+    pd: PD,
+    partitions: Vec<PD>,
+    lvd: LVD,
+    part_maps: Vec<partition::PartitionMap>,
+    meta_file_offset: Option<u32>,
+    sector_size: u32,
+}
+
+/// the descriptors of a volume descriptor sequence needed to open a volume
+struct UsableVds {
+    pvd: PVD,
+    partitions: Vec<PD>,
+    /// the partition selected by the options
+    pd: PD,
+    lvd: LVD,
+}
+
+/// reads the `copy` of the volume descriptor sequence `avd` points to, failing if it lacks a
+/// descriptor needed to open the volume
+fn read_usable_vds<IO: Read + Seek>(
+    io: &mut IO,
+    avd: &AVD,
+    copy: StructureCopy,
+    options: &UdfOptions,
+) -> Result<UsableVds, Box<dyn Error>> {
+    let ext = match copy {
+        StructureCopy::Primary => &avd.main_vds,
+        StructureCopy::Backup => &avd.reserve_vds,
+    };
+    let vds = read_vds(io, ext.loc, ext.len, options)?;
+    let partitions = vds.partitions;
+    let pvd = vds.pvd.ok_or("no primary volume descriptor found")?;
+    let pd = match options.partition {
+        Some(num) => partitions
+            .iter()
+            .find(|pd| pd.part_num == num)
+            .ok_or_else(|| format!("no partition descriptor for partition {}", num))?,
+        None => partitions
+            .iter()
+            .find(|pd| pd.contents().is_nsr())
+            .ok_or("no UDF partition descriptor found")?,
+    }
+    .clone();
+    let lvd = vds.lvd.ok_or("no local volume descriptor found")?;
+    Ok(UsableVds {
+        pvd,
+        partitions,
+        pd,
+        lvd,
+    })
+}
+
+/// reads the anchor, the volume descriptor sequence it points to and the location of the
+/// metadata file, if any
+fn read_volume<IO: Read + Seek>(
+    io: &mut IO,
+    options: &UdfOptions,
+) -> Result<VolumeStructures, Box<dyn Error>> {
+    let sector_size = match options.sector_size {
+        Some(size) if !SECTOR_SIZES.contains(&size) => {
+            return Err(format!("unsupported sector size {}", size).into())
+        }
+        Some(size) => size,
+        None => detect_sector_size(io, options.session_start)?.unwrap_or(BLOCKSIZE as u32),
+    };
+    let options = &UdfOptions {
+        sector_size: Some(sector_size),
+        ..options.clone()
+    };
+    let avd = read_avd(io, options)?;
+
+    // a damaged main volume descriptor sequence is replaced by the reserve one, unless a
+    // copy was asked for explicitly
+    let copy = options.vds_copy.unwrap_or_default();
+    let UsableVds {
+        pvd,
+        partitions,
+        pd,
+        lvd,
+    } = match read_usable_vds(io, &avd, copy, options) {
+        Ok(vds) => vds,
+        Err(e) if options.vds_copy.is_some() => return Err(e),
+        Err(e) => match read_usable_vds(io, &avd, StructureCopy::Backup, options) {
+            Ok(vds) => {
+                warn!("The main VDS is unusable ({}), using the reserve VDS", e);
+                vds
+            }
+            Err(_) => return Err(e),
+        },
+    };
+    // ECMA-167 allows logical blocks of several sectors, UDF requires them to be one sector
+    if lvd.lbs == 0 || lvd.lbs % sector_size != 0 {
+        return Err(format!(
+            "logical block size {} is not a multiple of the sector size {}",
+            lvd.lbs, sector_size
+        )
+        .into());
+    }
+    if lvd.lbs != sector_size {
+        let msg = format!(
+            "logical block size {} differs from the sector size {}",
+            lvd.lbs, sector_size
+        );
+        if options.strict {
+            return Err(msg.into());
+        }
+        warn!("{}", msg);
+    }
+    let block_sectors = lvd.lbs / sector_size;
+
+    // Search for metadata offset of FSD
+    let mut metadata_offset: Option<u32> = None;
+    {
+        let mut meta_part: Option<(u16, MetaPartMap)> = None;
+        for part_map in &lvd.part_maps {
+            if let PartMapType::Type2(part) = &part_map.part_map {
+                if let Some(meta) = part.metadata() {
+                    info!("Found metadata partition");
+                    meta_part = Some((part.part_num, meta));
                 }
             }
-            if let Some(meta_file_loc) = meta_file_loc {
-                io.seek(SeekFrom::Start(
-                    (pd.part_start + meta_file_loc) as u64 * BLOCKSIZE,
-                ))?;
-                io.read(&mut buf)?;
-                let meta_file = ICB::parse(&buf).unwrap().1;
-                let alloc_descs = meta_file.get_alloc_descs();
-                if let Some(desc) = alloc_descs.get(0) {
-                    match desc {
-                        AllocDesc::SHORT(ad) => metadata_offset = Some(ad.pos),
-                        AllocDesc::LONG(ad) => metadata_offset = Some(ad.loc.lbn),
-                        AllocDesc::EXTENDED(ad) => metadata_offset = Some(ad.ext_loc.lbn),
+        }
+        if let Some((part_num, meta)) = meta_part {
+            // the metadata file is recorded in the partition the metadata partition maps onto
+            let part_start = partitions
+                .iter()
+                .find(|pd| pd.part_num == part_num)
+                .ok_or("no partition descriptor for metadata partition")?
+                .part_start;
+            // a damaged metadata file is replaced by its mirror, unless a copy was asked for
+            // explicitly
+            let loc = match options.metadata_copy.unwrap_or_default() {
+                StructureCopy::Primary => meta.meta_file_loc,
+                StructureCopy::Backup => meta.meta_mirror_loc,
+            };
+            let read =
+                |io: &mut IO, loc| read_metadata_file(io, part_start, loc, block_sectors, options);
+            metadata_offset = Some(match read(io, loc) {
+                Ok(offset) => offset,
+                Err(e) if options.metadata_copy.is_some() => return Err(e),
+                Err(e) => match read(io, meta.meta_mirror_loc) {
+                    Ok(offset) => {
+                        warn!(
+                            "The metadata file is unusable ({}), using the metadata mirror file",
+                            e
+                        );
+                        offset
                     }
+                    Err(_) => return Err(e),
+                },
+            });
+        }
+    }
+
+    let desc_version = pd.contents().desc_version();
+    for (desc, version) in [
+        ("PVD", pvd.tag.version),
+        ("PD", pd.tag.version),
+        ("LVD", lvd.tag.version),
+    ] {
+        if version != desc_version {
+            warn!(
+                "{} has descriptor version {} on a volume with {:?} contents",
+                desc,
+                version,
+                pd.contents()
+            );
+        }
+    }
+
+    let mut part_maps = partition::resolve_partition_maps(&lvd, &partitions);
+    let map_lens: u32 = lvd.part_maps.iter().map(|m| m.map_len()).sum();
+    if map_lens != lvd.map_table_len {
+        warn!(
+            "Partition maps take {} bytes, the LVD claims {}",
+            map_lens, lvd.map_table_len
+        );
+    }
+    if !part_maps.iter().any(partition::PartitionMap::is_usable) {
+        if options.strict || !part_maps.is_empty() {
+            Err("no usable partition: the logical volume has no partition map of a recorded partition")?
+        }
+        // assume the volume lives in the UDF partition, as with a single Type 1 map
+        warn!(
+            "Logical volume has no partition maps, assuming partition {}",
+            pd.part_num
+        );
+        part_maps = vec![partition::PartitionMap {
+            part_ref: 0,
+            kind: partition::PartitionKind::Physical,
+            vol_seq_num: 1,
+            part_num: pd.part_num,
+            pd: Some(pd.clone()),
+            sparing_table: None,
+        }];
+    }
+    for map in &mut part_maps {
+        if let partition::PartitionKind::Sparable(sparable) = &map.kind {
+            map.sparing_table =
+                partition::read_sparing_table(io, sector_size as u64, sparable, options);
+            if map.sparing_table.is_none() {
+                if options.strict {
+                    Err("no usable sparing table")?
                 }
+                warn!(
+                    "No usable sparing table for partition {}, relocated packets are read from their original location",
+                    map.part_num
+                );
+            }
+        }
+    }
+    Ok(VolumeStructures {
+        pvd,
+        pd,
+        partitions,
+        lvd,
+        part_maps,
+        meta_file_offset: metadata_offset,
+        sector_size,
+    })
+}
+
+/// reads the ICB of the metadata file or its mirror at block `loc` of the partition starting
+/// at sector `part_start` and returns the block its data starts at. A damaged ICB or an
+/// unreadable first block of data fails even outside of strict mode, so that the other copy
+/// is used instead.
+fn read_metadata_file<IO: Read + Seek>(
+    io: &mut IO,
+    part_start: LSN,
+    loc: u32,
+    block_sectors: u32,
+    options: &UdfOptions,
+) -> Result<u32, Box<dyn Error>> {
+    let sector_size = options.sector_bytes();
+    let strict = UdfOptions {
+        strict: true,
+        ..options.clone()
+    };
+    let mut buf = vec![0; block_sectors as usize * sector_size as usize];
+    let sector = |lbn: u32| {
+        allocation::part_sector(part_start, lbn, block_sectors)
+            .ok_or("metadata file beyond the partition")
+    };
+    read_sector(io, sector_size, sector(loc)?, &mut buf)?;
+    let icb = ICB::parse(&buf)
+        .or(Err("error parsing metadata file ICB"))?
+        .1;
+    check_tag_crc(&strict, "metadata file ICB", &buf)?;
+    check_tag_loc(true, "metadata file ICB", icb.tag.tag_loc, loc)?;
+    if !matches!(
+        icb.icb_tag.file_type,
+        FileType::METAMAIN | FileType::METAMIRROR
+    ) {
+        Err("not a metadata file ICB")?
+    }
+    let start = match icb.get_alloc_descs().first() {
+        Some(AllocDesc::SHORT(ad)) => ad.pos,
+        Some(AllocDesc::LONG(ad)) => ad.loc.lbn,
+        Some(AllocDesc::EXTENDED(ad)) => ad.ext_loc.lbn,
+        None => Err("metadata file has no extent")?,
+    };
+    read_sector(io, sector_size, sector(start)?, &mut buf)?;
+    Ok(start)
+}
+
+/// One of the two copies of structures recorded redundantly
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StructureCopy {
+    /// the main volume descriptor sequence or the metadata file
+    #[default]
+    Primary,
+    /// the reserve volume descriptor sequence or the metadata mirror file
+    Backup,
+}
+
+/// Criterion for choosing among multiple file sets of a logical volume
+#[derive(Clone, Debug)]
+pub enum FileSetSelector {
+    /// the first recorded file set descriptor
+    First,
+    /// the file set descriptor with the given `fsd_num`
+    FsdNum(u32),
+    /// the file set with the given file set identifier (`fs_id`)
+    Ident(String),
+    /// the first file set whose file set character set has the given type
+    Charset(u8),
+}
+
+/// Settings controlling how a volume is opened and read
+#[derive(Clone, Debug)]
+pub struct UdfOptions {
+    /// fail on inconsistent structures instead of logging a warning and working around them
+    pub strict: bool,
+    /// verify descriptor tag checksums and CRCs, can be turned off for images that were
+    /// already validated
+    pub verify_checksums: bool,
+    /// start of the last session of a multisession disc, the anchor is looked for 256 sectors
+    /// after it
+    pub session_start: LSN,
+    /// the names path components are matched against
+    pub name_domain: NameDomain,
+    /// number of ICBs and directory listings kept in memory, 0 disables the cache
+    pub cache_size: usize,
+    /// bounds on the structures walked, to process untrusted images safely
+    pub limits: Limits,
+    /// read the ICBs of all entries of a directory into the cache when the directory is
+    /// listed, in as few reads as possible. Speeds up listing with details on slow media.
+    pub prefetch_children: bool,
+    /// approximate upper bound in bytes of the memory held by the metadata cache, directory
+    /// listings and scratch buffers. Caches evict entries and buffers are shrunk to stay
+    /// within it, directories too large to be read within it fail to be listed.
+    pub memory_budget: Option<usize>,
+    /// the order the entries of directories are visited in when walking the file set
+    pub walk_order: catalogue::WalkOrder,
+    /// the volume descriptor sequence the volume is opened through. Opening a volume through
+    /// each copy and comparing the results reveals copies that were tampered with. If
+    /// `None`, the main sequence is used and the reserve sequence if the main one is
+    /// unusable. A copy given explicitly that is unusable fails opening the volume.
+    pub vds_copy: Option<StructureCopy>,
+    /// the copy of the metadata partition ICBs and directories are read from. If `None`,
+    /// the metadata file is used and its mirror if its ICB is damaged or its data can't be
+    /// read. A copy given explicitly that is unusable fails opening the volume.
+    pub metadata_copy: Option<StructureCopy>,
+    /// size of the sectors of the medium in bytes, one of `SECTOR_SIZES`, detected from the
+    /// location of the anchor if `None`
+    pub sector_size: Option<u32>,
+    /// number of the partition descriptor describing the volume, the first UDF partition if
+    /// `None`
+    pub partition: Option<u16>,
+    /// byte offset of the volume in the medium, e.g. of a partition of a disk image. Sector
+    /// numbers count from there.
+    pub start_offset: u64,
+    /// list the parent directory entry, which has an empty name, along with the entries of
+    /// directories. Resolving `..` in paths doesn't depend on it.
+    pub parent_entry: bool,
+    /// upper bound in bytes per second of the reads issued to the medium, e.g. to keep a
+    /// background verification from starving other readers of the same device. Unlimited if
+    /// `None`.
+    pub max_read_rate: Option<u64>,
+    /// size in bytes of the blocks reads of the medium are aligned to and rounded up to, e.g.
+    /// `ECC_BLOCK_SIZE` for optical drives. The blocks read last are kept and reads of the
+    /// sectors following are served from them. Reads are issued as requested if `None`.
+    pub read_alignment: Option<u32>,
+}
+impl UdfOptions {
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    pub fn partition(mut self, part_num: u16) -> Self {
+        self.partition = Some(part_num);
+        self
+    }
+
+    pub fn start_offset(mut self, offset: u64) -> Self {
+        self.start_offset = offset;
+        self
+    }
+
+    pub fn sector_size(mut self, size: u32) -> Self {
+        self.sector_size = Some(size);
+        self
+    }
+
+    pub fn parent_entry(mut self, parent_entry: bool) -> Self {
+        self.parent_entry = parent_entry;
+        self
+    }
+
+    pub fn max_read_rate(mut self, bytes_per_sec: u64) -> Self {
+        self.max_read_rate = Some(bytes_per_sec);
+        self
+    }
+
+    pub fn read_alignment(mut self, size: u32) -> Self {
+        self.read_alignment = Some(size);
+        self
+    }
+
+    /// opens the volume in `io` with these options
+    pub fn open<IO: Read + Seek>(self, io: IO) -> Result<UDF<IO>, Box<dyn Error>> {
+        UDF::new_with_options(io, self)
+    }
+
+    /// the sector size in bytes, 2048 unless set
+    pub(crate) fn sector_bytes(&self) -> u64 {
+        self.sector_size.map_or(BLOCKSIZE, u64::from)
+    }
+
+    /// share of the memory budget the metadata cache may hold
+    pub(crate) fn cache_bytes(&self) -> Option<usize> {
+        self.memory_budget.map(|budget| budget / 2)
+    }
+
+    /// largest directory that is read into memory as a whole
+    pub(crate) fn max_dir_bytes(&self) -> Option<usize> {
+        self.memory_budget.map(|budget| budget / 4)
+    }
+
+    /// largest scratch buffer kept for reuse
+    pub(crate) fn max_pooled_buf(&self) -> Option<usize> {
+        self.memory_budget
+            .map(|budget| budget / (4 * MAX_POOLED_BUFS))
+    }
+}
+impl Default for UdfOptions {
+    fn default() -> Self {
+        Self {
+            strict: true,
+            verify_checksums: true,
+            session_start: 0,
+            name_domain: NameDomain::Udf,
+            cache_size: 1024,
+            limits: Limits::default(),
+            prefetch_children: false,
+            memory_budget: None,
+            walk_order: catalogue::WalkOrder::OnDisc,
+            vds_copy: None,
+            metadata_copy: None,
+            sector_size: None,
+            partition: None,
+            start_offset: 0,
+            parent_entry: false,
+            max_read_rate: None,
+            read_alignment: None,
+        }
+    }
+}
+
+/// Limits enforced while walking directories and resolving paths, `None` means unlimited
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// maximum number of components of a path below the root directory
+    pub max_depth: Option<usize>,
+    /// maximum number of FIDs recorded in a single directory
+    pub max_dir_entries: Option<usize>,
+    /// maximum number of bytes of ICBs and directories read over the lifetime of the volume
+    pub max_metadata_bytes: Option<u64>,
+}
+
+/// A directory linked into the hierarchy more than once, found while walking the file set or
+/// resolving a path. Returned as the error so that callers can tell it from I/O errors.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DirectoryLinkError {
+    /// the directory at `path` is recorded at `target`, which is one of its own ancestors
+    Loop { path: String, target: LBAddr },
+    /// the directory at `path` is recorded at `target`, which is linked elsewhere already
+    CrossLink { path: String, target: LBAddr },
+}
+
+impl std::fmt::Display for DirectoryLinkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Loop { path, target } => {
+                write!(f, "directory loop at {}: links back to {:?}", path, target)
+            }
+            Self::CrossLink { path, target } => {
+                write!(f, "directory {} cross-linked with {:?}", path, target)
             }
         }
+    }
+}
+
+impl Error for DirectoryLinkError {}
+
+/// An image ending before the partitions recorded on it, e.g. a rip of an overburned disc or
+/// an interrupted download. Data beyond the end of the image can't be read, everything else
+/// can.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Truncation {
+    /// size of the image in bytes
+    pub medium_len: u64,
+    /// number of sectors the partitions extend to
+    pub expected_sectors: u64,
+    /// size of the sectors in bytes
+    pub sector_size: u64,
+}
+
+impl Truncation {
+    /// whether `len` bytes at byte offset `pos` extend beyond the end of the image
+    pub fn cuts(&self, pos: u64, len: u64) -> bool {
+        pos.saturating_add(len) > self.medium_len
+    }
+
+    /// the error reads of `len` bytes at byte offset `pos` fail with
+    fn error(&self, pos: u64, len: u64) -> std::io::Error {
+        std::io::Error::new(
+            ErrorKind::UnexpectedEof,
+            format!(
+                "sectors {}..{} lie beyond the end of the truncated image ({} sectors)",
+                pos / self.sector_size,
+                pos.saturating_add(len).div_ceil(self.sector_size),
+                self.medium_len / self.sector_size
+            ),
+        )
+    }
+}
+
+/// compares the size of the medium with the extent of the partitions `partitions`
+fn detect_truncation<IO: Read + Seek>(
+    io: &mut IO,
+    partitions: &[PD],
+    sector_size: u64,
+) -> std::io::Result<Option<Truncation>> {
+    let medium_len = io.seek(SeekFrom::End(0))?;
+    let expected_sectors = partitions
+        .iter()
+        .map(|pd| pd.part_start as u64 + pd.part_len as u64)
+        .max()
+        .unwrap_or(0);
+    if medium_len >= expected_sectors * sector_size {
+        return Ok(None);
+    }
+    warn!(
+        "Image is truncated: {} of {} sectors recorded, data beyond sector {} can't be read",
+        medium_len / sector_size,
+        expected_sectors,
+        medium_len / sector_size
+    );
+    Ok(Some(Truncation {
+        medium_len,
+        expected_sectors,
+        sector_size,
+    }))
+}
+
+pub struct UDF<IO: Read + Seek> {
+    io: Box<backend::Window<IO>>,
+    pub options: UdfOptions,
+    pub primary_vol_desc: PVD,
+    pub part_desc: PD,
+    pub partitions: Vec<PD>,
+    pub logical_vol_desc: LVD,
+    pub file_set_desc: Option<FSD>,
+    part_maps: Vec<partition::PartitionMap>,
+    meta_file_offset: Option<u32>,
+    vat: Option<VAT>,
+    root_icb: Option<ICB>,
+    /// scratch buffers handed back after directory scans, reused by later reads
+    buf_pool: Vec<Vec<u8>>,
+    name_decoder: Option<Arc<dyn NameDecoder>>,
+    pub(crate) cache: cache::MetaCache,
+    /// bytes of ICBs and directories read so far, checked against `Limits::max_metadata_bytes`
+    metadata_read: u64,
+    stats: stats::IoStats,
+    /// where the last read ended, to tell seeks from sequential reads
+    io_pos: Option<u64>,
+    truncation: Option<Truncation>,
+}
+
+/// a clone shares nothing with the original volume but what cloning `IO` shares, e.g. a
+/// `PositionedReader` over an `Arc<File>` gives every clone its own position in the same file
+impl<IO: Read + Seek + Clone> Clone for UDF<IO> {
+    fn clone(&self) -> Self {
+        Self {
+            io: self.io.clone(),
+            options: self.options.clone(),
+            primary_vol_desc: self.primary_vol_desc.clone(),
+            part_desc: self.part_desc.clone(),
+            partitions: self.partitions.clone(),
+            logical_vol_desc: self.logical_vol_desc.clone(),
+            file_set_desc: self.file_set_desc.clone(),
+            part_maps: self.part_maps.clone(),
+            meta_file_offset: self.meta_file_offset,
+            vat: self.vat.clone(),
+            root_icb: self.root_icb.clone(),
+            buf_pool: Vec::new(),
+            name_decoder: self.name_decoder.clone(),
+            cache: cache::MetaCache::new(self.options.cache_size, self.options.cache_bytes()),
+            metadata_read: self.metadata_read,
+            stats: stats::IoStats::default(),
+            io_pos: None,
+            truncation: self.truncation.clone(),
+        }
+    }
+}
+
+impl UDF<std::fs::File> {
+    /// the default options, to be adjusted and then opened with `UdfOptions::open`, like
+    /// `UDF::options().strict(false).open(io)`. The options open any reader, they are only
+    /// defined for files so that the call needs no type annotations.
+    pub fn options() -> UdfOptions {
+        UdfOptions::default()
+    }
+}
+
+impl<IO: Read + Seek> UDF<IO> {
+    pub fn new(io: IO) -> Result<Self, Box<dyn Error>> {
+        Self::new_with_options(io, UdfOptions::default())
+    }
 
-        let result = Self {
+    pub fn new_with_options(io: IO, mut options: UdfOptions) -> Result<Self, Box<dyn Error>> {
+        let mut io = backend::Window::new(io, options.start_offset);
+        io.set_read_rate(options.max_read_rate);
+        io.set_read_alignment(options.read_alignment);
+        let vol = read_volume(&mut io, &options)?;
+        options.sector_size = Some(vol.sector_size);
+        let truncation = detect_truncation(&mut io, &vol.partitions, options.sector_bytes())?;
+        let mut result = Self {
             io: Box::new(io),
-            primary_vol_desc: pvd,
-            part_desc: pd,
-            logical_vol_desc: lvd,
-            meta_file_offset: metadata_offset,
+            primary_vol_desc: vol.pvd,
+            part_desc: vol.pd,
+            partitions: vol.partitions,
+            logical_vol_desc: vol.lvd,
+            part_maps: vol.part_maps,
+            meta_file_offset: vol.meta_file_offset,
+            vat: None,
+            file_set_desc: None,
             root_icb: None,
+            buf_pool: Vec::new(),
+            name_decoder: None,
+            cache: cache::MetaCache::new(options.cache_size, options.cache_bytes()),
+            metadata_read: 0,
+            stats: stats::IoStats::default(),
+            io_pos: None,
+            truncation,
+            options,
         };
+        result.vat = result.find_vat()?;
         Ok(result)
     }
 
-    pub fn get_root_dir(&mut self) -> Result<ICB, Box<dyn Error>> {
-        if let Some(root_icb) = self.root_icb.clone() {
-            return Ok(root_icb);
+    /// re-reads the anchor, the volume descriptors, the VAT and the file set and drops all
+    /// cached metadata, e.g. to follow an image that is still being written by another
+    /// process. A file set selected with `open_file_set` stays selected if it still exists.
+    /// Returns the current Logical Volume Integrity Descriptor.
+    pub fn refresh(&mut self) -> Result<Option<LVID>, Box<dyn Error>> {
+        self.io.invalidate();
+        let vol = read_volume(&mut *self.io, &self.options)?;
+        self.primary_vol_desc = vol.pvd;
+        self.part_desc = vol.pd;
+        self.partitions = vol.partitions;
+        self.logical_vol_desc = vol.lvd;
+        self.part_maps = vol.part_maps;
+        self.meta_file_offset = vol.meta_file_offset;
+        let sector_size = self.sector_size();
+        self.truncation = detect_truncation(&mut *self.io, &self.partitions, sector_size)?;
+        self.io_pos = None;
+        self.vat = None;
+        self.vat = self.find_vat()?;
+        self.invalidate_all();
+        self.root_icb = None;
+        if let Some(fsd) = self.file_set_desc.take() {
+            if self
+                .open_file_set(FileSetSelector::FsdNum(fsd.fsd_num))
+                .is_err()
+            {
+                warn!("File set {} disappeared, using the first one", fsd.fsd_num);
+                self.file_set_desc = None;
+            }
+        }
+        Ok(self.integrity_sequence()?.current)
+    }
+
+    /// decodes file identifiers with `decoder` instead of as OSTA CS0, in directory listings
+    /// and path lookups alike
+    pub fn set_name_decoder(&mut self, decoder: impl NameDecoder + 'static) {
+        self.name_decoder = Some(Arc::new(decoder));
+        self.cache.invalidate_dirs();
+    }
+
+    /// goes back to decoding file identifiers as OSTA CS0
+    pub fn clear_name_decoder(&mut self) {
+        self.name_decoder = None;
+        self.cache.invalidate_dirs();
+    }
+
+    /// forgets the cached ICB recorded at `loc` and, if it is a directory, its entries. Needed
+    /// after the structures recorded there changed, e.g. after writing through `get_mut`.
+    pub fn invalidate(&mut self, loc: &LBAddr) {
+        self.cache.invalidate(loc);
+    }
+
+    /// forgets all cached ICBs and directory entries
+    pub fn invalidate_all(&mut self) {
+        self.cache.clear();
+    }
+
+    pub fn get_ref(&self) -> &IO {
+        &self.io.inner
+    }
+
+    /// the underlying medium. Cached metadata isn't updated when writing through it, see
+    /// `invalidate`.
+    pub fn get_mut(&mut self) -> &mut IO {
+        self.io_pos = None;
+        self.io.invalidate();
+        &mut self.io.inner
+    }
+
+    /// approximate memory held by cached metadata and scratch buffers, see
+    /// `UdfOptions::memory_budget`. A cache shared with other volumes counts in whole.
+    pub fn memory_used(&self) -> usize {
+        self.cache.bytes() + self.buf_pool.iter().map(Vec::capacity).sum::<usize>()
+    }
+
+    /// bytes of ICBs and directories read so far
+    pub fn metadata_read(&self) -> u64 {
+        self.metadata_read
+    }
+
+    /// starts counting metadata bytes against `Limits::max_metadata_bytes` from zero again
+    pub fn reset_metadata_read(&mut self) {
+        self.metadata_read = 0;
+    }
+
+    /// accounts for `len` bytes of metadata about to be read
+    pub(crate) fn count_metadata(&mut self, len: u64) -> Result<(), Box<dyn Error>> {
+        self.metadata_read = self.metadata_read.saturating_add(len);
+        match self.options.limits.max_metadata_bytes {
+            Some(max) if self.metadata_read > max => Err("metadata read limit exceeded")?,
+            _ => Ok(()),
+        }
+    }
+
+    /// changes the upper bound in bytes per second of the reads issued to the medium, e.g. to
+    /// slow a background job down while other readers are busy, `None` lifts it
+    pub fn set_max_read_rate(&mut self, bytes_per_sec: Option<u64>) {
+        self.options.max_read_rate = bytes_per_sec;
+        self.io.set_read_rate(bytes_per_sec);
+    }
+
+    /// size of the sectors of the medium in bytes
+    pub fn sector_size(&self) -> u64 {
+        self.options.sector_bytes()
+    }
+
+    /// size of the logical blocks of the volume in bytes, a multiple of the sector size
+    pub fn block_size(&self) -> u64 {
+        self.logical_vol_desc.lbs as u64
+    }
+
+    /// number of sectors in a logical block
+    pub(crate) fn block_sectors(&self) -> u32 {
+        (self.block_size() / self.sector_size()) as u32
+    }
+
+    fn read_block(&mut self, loc: &LBAddr, buf: &mut [u8]) -> Result<(), Box<dyn Error>> {
+        let lsn = self.lb_to_sector(loc)?;
+        let pos = lsn as u64 * self.sector_size();
+        self.check_recorded(pos, buf.len() as u64)?;
+        self.record_read(IoCategory::Metadata, pos, buf.len());
+        let sector_size = self.sector_size();
+        Ok(read_sector(&mut self.io, sector_size, lsn, buf)?)
+    }
+
+    /// how the image falls short of the partitions recorded on it, if it does
+    pub fn truncation(&self) -> Option<&Truncation> {
+        self.truncation.as_ref()
+    }
+
+    /// fails if `len` bytes at byte offset `pos` lie beyond the end of a truncated image
+    fn check_recorded(&self, pos: u64, len: u64) -> std::io::Result<()> {
+        match &self.truncation {
+            Some(t) if t.cuts(pos, len) => Err(t.error(pos, len)),
+            _ => Ok(()),
+        }
+    }
+
+    /// reads the ICB recorded at the logical block address `loc`
+    pub fn read_icb(&mut self, loc: &LBAddr) -> Result<ICB, Box<dyn Error>> {
+        let cached = self.cache.icb(loc);
+        self.record_cache(cached.is_some());
+        if let Some(icb) = cached {
+            return Ok(icb);
         }
+        let mut buf = vec![0; self.block_size() as usize];
+        self.count_metadata(self.block_size())?;
+        self.read_block(loc, &mut buf)?;
+        let icb = self.parse_icb(&buf, loc)?;
+        self.cache.insert_icb(icb.clone());
+        Ok(icb)
+    }
+
+    fn parse_icb(&self, buf: &[u8], loc: &LBAddr) -> Result<ICB, Box<dyn Error>> {
+        let mut icb = ICB::parse(buf).or(Err("error parsing ICB"))?.1;
+        check_tag_crc(&self.options, "ICB", buf)?;
+        check_tag_loc(self.options.strict, "ICB", icb.tag.tag_loc, loc.lbn)?;
+        if icb.tag.version != self.desc_version() {
+            warn!(
+                "ICB at {:?} has descriptor version {}, expected {}",
+                loc,
+                icb.tag.version,
+                self.desc_version()
+            );
+        }
+        icb.loc = *loc;
+        Ok(icb)
+    }
+
+    /// reads all File Set Descriptors of the file set descriptor sequence, following
+    /// `next_extent` continuations
+    pub fn file_sets(&mut self) -> Result<Vec<FSD>, Box<dyn Error>> {
+        let file_sets = self.located_file_sets()?;
+        Ok(file_sets.into_iter().map(|(_, fsd)| fsd).collect())
+    }
+
+    /// like `file_sets`, with the logical block every descriptor was read from
+    pub(crate) fn located_file_sets(&mut self) -> Result<Vec<(LBAddr, FSD)>, Box<dyn Error>> {
         let fsd_ext = LongAD::parse_le(&self.logical_vol_desc.lv_contents_use)
             .or(Err("error parsing FSD pointer."))?
             .1;
-        let mut fsd_loc = self.part_desc.part_start + fsd_ext.loc.lbn;
-        if let Some(meta_offset) = self.meta_file_offset {
-            fsd_loc += meta_offset;
-        }
-        let mut buf: [u8; BLOCKSIZE as usize] = [0; BLOCKSIZE as usize];
-        self.io.seek(SeekFrom::Start(fsd_loc as u64 * BLOCKSIZE))?;
-        self.io.read(&mut buf)?;
-        let fsd = FSD::parse(&buf).or(Err("error parsing FSD"))?.1;
-        let mut icb_loc = self.part_desc.part_start + fsd.root_dir_icb.loc.lbn;
-        if let Some(meta_offset) = self.meta_file_offset {
-            icb_loc += meta_offset;
-        }
-        self.io.seek(SeekFrom::Start(icb_loc as u64 * BLOCKSIZE))?;
-        self.io.read(&mut buf)?;
-        let root_entry = ICB::parse(&buf).or(Err("error parsing root ICB"))?.1;
-
-        let root_ad = root_entry.get_alloc_descs();
-        if root_ad.len() > 1 {
-            Err("multiple allocation descriptors for one ICB not supported yet")?;
+        let mut file_sets = Vec::new();
+        let mut buf = vec![0; self.block_size() as usize];
+        let mut visited = HashSet::new();
+        let mut ext = Some(fsd_ext);
+
+        while let Some(cur) = ext.take() {
+            if cur.len == 0 || !visited.insert(cur.loc) {
+                break;
+            }
+            let num_blocks = (cur.len as u64).div_ceil(self.block_size()) as u32;
+            for lbn in cur.loc.lbn..cur.loc.lbn.saturating_add(num_blocks) {
+                let loc = LBAddr { lbn, ..cur.loc };
+                self.read_block(&loc, &mut buf)?;
+                match FileTag::parse(&buf) {
+                    Ok((_, tag)) if tag.tag_id == FileTagID::FSD => {}
+                    _ => break,
+                }
+                let fsd = FSD::parse(&buf).or(Err("error parsing FSD"))?.1;
+                check_tag_crc(&self.options, "FSD", &buf)?;
+                check_tag_loc(self.options.strict, "FSD", fsd.tag.tag_loc, lbn)?;
+                info!("Found file set {} ({})", fsd.fs_num, fsd.fs_id);
+                let next = fsd.next_extent.clone();
+                file_sets.push((loc, fsd));
+                if next.len > 0 {
+                    ext = Some(next);
+                    break;
+                }
+            }
+        }
+        Ok(file_sets)
+    }
+
+    /// selects the file set used for all following path lookups. Without an explicit selection
+    /// the first recorded file set is used.
+    pub fn open_file_set(&mut self, selector: FileSetSelector) -> Result<&FSD, Box<dyn Error>> {
+        let fsd = self
+            .file_sets()?
+            .into_iter()
+            .find(|fsd| match &selector {
+                FileSetSelector::First => true,
+                FileSetSelector::FsdNum(n) => fsd.fsd_num == *n,
+                FileSetSelector::Ident(ident) => fsd.fs_id.to_string() == *ident,
+                FileSetSelector::Charset(cs_type) => fsd.fs_charset.cs_type == *cs_type,
+            })
+            .ok_or("no matching file set descriptor found")?;
+        self.root_icb = None;
+        Ok(self.file_set_desc.insert(fsd))
+    }
+
+    pub fn get_root_dir(&mut self) -> Result<ICB, Box<dyn Error>> {
+        if let Some(root_icb) = self.root_icb.clone() {
+            return Ok(root_icb);
+        }
+        if self.file_set_desc.is_none() {
+            self.open_file_set(FileSetSelector::First)?;
         }
+        let root_loc = self.file_set_desc.as_ref().unwrap().root_dir_icb.loc;
+        let root_entry = self.read_icb(&root_loc)?;
+
         self.root_icb = Some(root_entry);
         Ok(self.root_icb.clone().unwrap())
     }
 
-    pub fn alloc_desc_to_offset_len(&self, ad: &AllocDesc) -> (u32, u32) {
-        let mut loc: u32;
-        let len: u32;
-        match ad {
-            AllocDesc::SHORT(x) => {
-                loc = x.pos;
-                len = x.len;
-            }
-            AllocDesc::LONG(x) => {
-                loc = x.loc.lbn;
-                len = x.len;
-            }
-            AllocDesc::EXTENDED(x) => {
-                loc = x.ext_loc.lbn;
-                len = x.len;
-            }
+    /// follows the predecessor volume descriptor sequence pointers of the primary volume
+    /// descriptor, returning the superseded sequences from newest to oldest
+    pub fn predecessor_vds(&mut self) -> Result<Vec<VolDescSeq>, Box<dyn Error>> {
+        let mut seqs: Vec<VolDescSeq> = Vec::new();
+        let mut visited = HashSet::new();
+        let mut loc = self.primary_vol_desc.predec_vds;
+        while loc != 0 && visited.insert(loc) {
+            // the predecessor location carries no length, so scan up to the minimum VDS extent length
+            let len = 16 * self.sector_size() as u32;
+            let vds = read_vds(&mut self.io, loc, len, &self.options)?;
+            loc = vds.pvd.as_ref().map(|pvd| pvd.predec_vds).unwrap_or(0);
+            seqs.push(vds);
         }
-        loc += self.part_desc.part_start;
-        if let Some(meta_offset) = self.meta_file_offset {
-            loc += meta_offset;
-        }
-        (loc * BLOCKSIZE as u32, len)
+        Ok(seqs)
+    }
+
+    /// the implementation use volume descriptors of the volume descriptor sequence the
+    /// options select
+    pub fn implementation_use_descs(&mut self) -> Result<Vec<IUVD>, Box<dyn Error>> {
+        let avd = read_avd(&mut self.io, &self.options)?;
+        let ext = match self.options.vds_copy.unwrap_or_default() {
+            StructureCopy::Primary => &avd.main_vds,
+            StructureCopy::Backup => &avd.reserve_vds,
+        };
+        Ok(read_vds(&mut self.io, ext.loc, ext.len, &self.options)?.iuvds)
     }
 
-    pub fn read_into_buf(&mut self, ad: &AllocDesc) -> Result<Vec<u8>, Box<dyn Error>> {
-        let (loc, len) = self.alloc_desc_to_offset_len(ad);
-        let mut buf = vec![0; len as _];
-        self.io.seek(SeekFrom::Start(loc as _))?;
-        self.io.read_exact(&mut buf)?;
-        return Ok(buf);
+    /// the owner of the logical volume as recorded in an IUVD, `None` if the volume doesn't
+    /// record one
+    pub fn lv_info(&mut self) -> Result<Option<LVInfo>, Box<dyn Error>> {
+        let iuvds = self.implementation_use_descs()?;
+        Ok(iuvds.iter().find_map(IUVD::lv_info))
     }
 
-    pub fn find_icb(&mut self, path: &Path) -> Result<ICB, Box<dyn Error>> {
-        if !path.is_absolute() {
-            return Err(Box::new(std::io::Error::new(
-                ErrorKind::InvalidInput,
-                "path must be absolute",
-            )));
+    /// the descriptor tag version used on this volume: 2 for ECMA-167 2nd edition (NSR02)
+    /// and 3 for ECMA-167 3rd edition (NSR03) structures
+    pub fn desc_version(&self) -> u16 {
+        self.part_desc.contents().desc_version()
+    }
+
+    /// the UDF revision the logical volume claims to comply with, e.g. 0x0102 for UDF 1.02
+    pub fn udf_revision(&self) -> u16 {
+        self.logical_vol_desc.domain_id.udf_revision()
+    }
+
+    /// the flags of the domain identifiers of the logical volume and the open file set
+    /// combined
+    pub fn domain_flags(&self) -> RegIDFlags {
+        let mut flags = self.logical_vol_desc.domain_id.reg_flags();
+        if let Some(fsd) = &self.file_set_desc {
+            flags |= fsd.domain_id.reg_flags();
         }
-        let mut cur_icb: ICB = self.get_root_dir()?;
-        let mut prev_icb: Vec<ICB> = Vec::with_capacity(path.components().count());
-        for p in path.components() {
-            // TODO: Handle ParentDir with symbolic links
-            match p {
-                Component::Prefix(_) => {
-                    return Err(Box::new(std::io::Error::new(
-                        ErrorKind::InvalidInput,
-                        "path must not contain prefix",
-                    )))
-                }
-                Component::CurDir => {
-                    continue;
+        flags
+    }
+
+    /// reads all Logical Volume Integrity Descriptors of the integrity sequence in recording order,
+    /// following `next_integ_ext` continuation extents. The last entry describes the current state.
+    pub fn integrity_history(&mut self) -> Result<Vec<LVID>, Box<dyn Error>> {
+        let mut history = Vec::new();
+        self.walk_integrity_seq(|_, lvid| history.push(lvid))?;
+        Ok(history)
+    }
+
+    /// follows the integrity sequence like `integrity_history`, keeping only the current
+    /// descriptor
+    pub fn integrity_sequence(&mut self) -> Result<IntegritySequence, Box<dyn Error>> {
+        let mut num_descs = 0;
+        let mut current = None;
+        let extents = self.walk_integrity_seq(|_, lvid| {
+            num_descs += 1;
+            current = Some(lvid);
+        })?;
+        Ok(IntegritySequence {
+            extents,
+            num_descs,
+            current,
+        })
+    }
+
+    /// passes the integrity descriptors and their sectors to `f` in recording order, returning
+    /// the extents followed. Stops at the first extent recorded twice and after
+    /// `MAX_INTEGRITY_DESCS` descriptors. Continuation extents that can't be read end the
+    /// sequence, in strict mode they are an error.
+    fn walk_integrity_seq(
+        &mut self,
+        mut f: impl FnMut(LSN, LVID),
+    ) -> Result<Vec<ExtentAD>, Box<dyn Error>> {
+        let sector_size = self.sector_size();
+        let mut buf = vec![0; sector_size as usize];
+        let mut visited = HashSet::new();
+        let mut extents: Vec<ExtentAD> = Vec::new();
+        let mut num_descs = 0;
+        let mut ext = Some(self.logical_vol_desc.integr_seq_ext.clone());
+
+        while let Some(cur) = ext.take() {
+            if cur.len == 0 || !visited.insert(cur.loc) {
+                break;
+            }
+            extents.push(cur.clone());
+            let num_sectors = (cur.len as u64).div_ceil(sector_size) as u32;
+            for n in cur.loc..cur.loc.saturating_add(num_sectors) {
+                if num_descs == MAX_INTEGRITY_DESCS {
+                    let msg = format!(
+                        "integrity sequence has more than {} descriptors",
+                        MAX_INTEGRITY_DESCS
+                    );
+                    if self.options.strict {
+                        return Err(msg.into());
+                    }
+                    warn!("{}, ignoring the rest", msg);
+                    return Ok(extents);
                 }
-                Component::RootDir => {
-                    cur_icb = self.get_root_dir()?;
-                    prev_icb.clear();
+                self.record_read(IoCategory::Metadata, n as u64 * sector_size, buf.len());
+                if let Err(e) = read_sector(&mut self.io, sector_size, n, &mut buf) {
+                    if extents.len() == 1 || self.options.strict {
+                        return Err(e.into());
+                    }
+                    warn!(
+                        "Integrity sequence continues at sector {}, which can't be read: {}",
+                        n, e
+                    );
+                    return Ok(extents);
                 }
-                Component::ParentDir => {
-                    cur_icb = prev_icb.pop().ok_or_else(|| {
-                        Box::new(std::io::Error::new(
-                            ErrorKind::InvalidInput,
-                            "path tried to go above root directory",
-                        ))
-                    })?;
+                match Tag::parse(&buf) {
+                    Ok((_, tag)) if tag.tag_id == TagID::LVID => {}
+                    _ => break,
                 }
-                Component::Normal(p) => {
-                    let c = cur_icb.get_children(self).remove(p.to_str().unwrap());
-                    if let Some(c) = c {
-                        prev_icb.push(cur_icb);
-                        cur_icb = c;
-                    } else {
-                        return Err(Box::new(std::io::Error::from(ErrorKind::NotFound)));
-                    }
+                let lvid = LVID::parse(&buf).or(Err("error parsing LVID"))?.1;
+                check_tag_crc(&self.options, "LVID", &buf)?;
+                check_tag_loc(self.options.strict, "LVID", lvid.tag.tag_loc, n)?;
+                info!(
+                    "Found integrity descriptor of type {:?} at {}",
+                    lvid.integ_type, n
+                );
+                let next = lvid.next_integ_ext.clone();
+                num_descs += 1;
+                f(n, lvid);
+                if next.len > 0 {
+                    ext = Some(next);
+                    break;
                 }
             }
         }
-        Ok(cur_icb)
+        Ok(extents)
+    }
+
+    /// byte offset and length of the extent described by `ad`. Short allocation descriptors
+    /// refer to the partition `part_ref` of the ICB they were recorded in. Packets of sparable
+    /// partitions relocated by the sparing table are recorded elsewhere, so an extent
+    /// crossing one isn't contiguous, see `extent_pos`.
+    pub fn alloc_desc_to_offset_len(
+        &self,
+        ad: &AllocDesc,
+        part_ref: u16,
+    ) -> Result<(u64, u32), Box<dyn Error>> {
+        let lsn = self.lb_to_sector(&ad.lb_addr(part_ref))?;
+        Ok((lsn as u64 * self.sector_size(), ad.len()))
+    }
+
+    /// byte offset on the medium of the byte `pos` of the extent described by `ad`, and the
+    /// number of bytes of the extent recorded contiguously from there
+    pub(crate) fn extent_pos(
+        &self,
+        ad: &AllocDesc,
+        part_ref: u16,
+        pos: u64,
+    ) -> Result<(u64, u64), Box<dyn Error>> {
+        let mut loc = ad.lb_addr(part_ref);
+        let block_size = self.block_size();
+        loc.lbn = u32::try_from(pos / block_size)
+            .ok()
+            .and_then(|blocks| loc.lbn.checked_add(blocks))
+            .ok_or("block address beyond the partition")?;
+        let in_block = pos % block_size;
+        let lsn = self.lb_to_sector(&loc)?;
+        let remaining = (ad.len() as u64).saturating_sub(pos - in_block);
+        let contiguous = self.contiguous_len(&loc, remaining) - in_block.min(remaining);
+        if contiguous == 0 {
+            Err("position beyond the extent")?
+        }
+        Ok((lsn as u64 * self.sector_size() + in_block, contiguous))
+    }
+
+    pub fn read_into_buf(
+        &mut self,
+        ad: &AllocDesc,
+        part_ref: u16,
+    ) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut buf = Vec::new();
+        self.read_extent_into(ad, part_ref, &mut buf)?;
+        Ok(buf)
+    }
+
+    /// appends the contents of the extent described by `ad` to `buf`
+    pub fn read_extent_into(
+        &mut self,
+        ad: &AllocDesc,
+        part_ref: u16,
+        buf: &mut Vec<u8>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.read_extent(IoCategory::Data, ad, part_ref, u64::MAX, buf)
+    }
+
+    /// like `read_extent_into`, counting the read as `category` and reading at most `max`
+    /// bytes of the extent
+    pub(crate) fn read_extent(
+        &mut self,
+        category: IoCategory,
+        ad: &AllocDesc,
+        part_ref: u16,
+        max: u64,
+        buf: &mut Vec<u8>,
+    ) -> Result<(), Box<dyn Error>> {
+        let len = (ad.len() as u64).min(max);
+        let start = buf.len();
+        let mut done = 0;
+        // a single piece unless the extent crosses packets relocated by a sparing table
+        while done < len {
+            let (loc, contiguous) = self.extent_pos(ad, part_ref, done)?;
+            let n = contiguous.min(len - done);
+            if let Err(e) = self.read_extent_piece(category, loc, n, buf) {
+                buf.truncate(start);
+                return Err(e);
+            }
+            done += n;
+        }
+        Ok(())
+    }
+
+    /// appends the `len` bytes at byte offset `loc` to `buf`
+    fn read_extent_piece(
+        &mut self,
+        category: IoCategory,
+        loc: u64,
+        len: u64,
+        buf: &mut Vec<u8>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.check_recorded(loc, len)?;
+        let start = buf.len();
+        self.record_read(category, loc, len as usize);
+        self.io.seek(SeekFrom::Start(loc))?;
+        // grows with the data actually read, a bogus length can't allocate more than the
+        // medium holds
+        let res = (&mut self.io).take(len).read_to_end(buf);
+        if res.is_err() || buf.len() - start != len as usize {
+            return Err(res.err().unwrap_or(ErrorKind::UnexpectedEof.into()).into());
+        }
+        Ok(())
+    }
+
+    /// size of the medium in bytes
+    pub(crate) fn medium_len(&mut self) -> std::io::Result<u64> {
+        self.io_pos = None;
+        self.io.seek(SeekFrom::End(0))
+    }
+
+    /// reads `buf.len()` bytes at byte offset `pos` of the medium
+    pub(crate) fn read_bytes(
+        &mut self,
+        category: IoCategory,
+        pos: u64,
+        buf: &mut [u8],
+    ) -> std::io::Result<()> {
+        self.check_recorded(pos, buf.len() as u64)?;
+        self.record_read(category, pos, buf.len());
+        self.io.seek(SeekFrom::Start(pos))?;
+        self.io.read_exact(buf)
+    }
+
+    /// takes an empty scratch buffer from the pool, allocating one if the pool is empty
+    pub(crate) fn take_buf(&mut self) -> Vec<u8> {
+        self.buf_pool.pop().unwrap_or_default()
+    }
+
+    /// returns a scratch buffer to the pool
+    pub(crate) fn recycle_buf(&mut self, mut buf: Vec<u8>) {
+        if self.buf_pool.len() < MAX_POOLED_BUFS {
+            buf.clear();
+            if let Some(max) = self.options.max_pooled_buf() {
+                buf.shrink_to(max);
+            }
+            self.buf_pool.push(buf);
+        }
+    }
+
+    /// reads the ICBs of all entries of the directory `dir` in the order they are recorded,
+    /// merging the reads of ICBs in adjacent sectors. Entries whose ICB can't be read are
+    /// logged and left out.
+    pub fn stat_children(&mut self, dir: &ICB) -> Result<Vec<(DirEntry, ICB)>, Box<dyn Error>> {
+        let entries = dir.read_entries(self)?;
+        self.read_icbs(entries)
+    }
+
+    /// reads the ICBs of `entries` into the cache, merging the reads of ICBs in adjacent
+    /// sectors. ICBs already cached aren't read again.
+    pub fn prefetch_icbs(&mut self, entries: &[DirEntry]) -> Result<(), Box<dyn Error>> {
+        if !self.cache.is_enabled() {
+            return Ok(());
+        }
+        let missing = entries
+            .iter()
+            .filter(|e| !e.is_parent() && !self.cache.contains_icb(&e.icb))
+            .cloned()
+            .collect();
+        for (_, icb) in self.read_icbs(missing)? {
+            self.cache.insert_icb(icb);
+        }
+        Ok(())
+    }
+
+    /// reads the ICBs of `entries` in the order they are recorded on the medium, merging the
+    /// reads of ICBs in adjacent sectors
+    fn read_icbs(
+        &mut self,
+        entries: Vec<DirEntry>,
+    ) -> Result<Vec<(DirEntry, ICB)>, Box<dyn Error>> {
+        let mut sorted = Vec::with_capacity(entries.len());
+        for e in entries {
+            match self.lb_to_sector(&e.icb) {
+                Ok(lsn) => sorted.push((lsn, e)),
+                Err(err) => error!("Error reading ICB of {}: {}", e.name, err),
+            }
+        }
+        sorted.sort_by_key(|(lsn, _)| *lsn);
+
+        let mut result = Vec::with_capacity(sorted.len());
+        let mut buf = self.take_buf();
+        let sector_size = self.sector_size();
+        let block_sectors = self.block_sectors();
+        let bs = self.block_size() as usize;
+        for run in sorted.chunk_by(|a, b| b.0 <= a.0.saturating_add(block_sectors)) {
+            for run in run.chunks(MAX_MERGED_SECTORS) {
+                let start = run[0].0;
+                let count = run[run.len() - 1].0 - start + block_sectors;
+                buf.resize(count as usize * sector_size as usize, 0);
+                self.count_metadata(buf.len() as u64)?;
+                self.record_read(IoCategory::Metadata, start as u64 * sector_size, buf.len());
+                read_sector(&mut self.io, sector_size, start, &mut buf)?;
+                for (lsn, e) in run {
+                    let off = (lsn - start) as usize * sector_size as usize;
+                    match self.parse_icb(&buf[off..off + bs], &e.icb) {
+                        Ok(icb) => result.push((e.clone(), icb)),
+                        Err(err) => error!("Error reading ICB of {}: {}", e.name, err),
+                    }
+                }
+            }
+        }
+        self.recycle_buf(buf);
+        Ok(result)
+    }
+
+    /// looks up the ICB at `path`, see the `path` module for the accepted syntax
+    pub fn find_icb(&mut self, path: &Path) -> Result<ICB, Box<dyn Error>> {
+        self.lookup(&UdfPath::from_path(path)?)
+    }
+
+    /// looks up the ICB at the parsed `path`, or the ICB of its named stream
+    pub fn lookup(&mut self, path: &UdfPath) -> Result<ICB, Box<dyn Error>> {
+        let not_a_dir = || std::io::Error::from(ErrorKind::NotADirectory);
+        let mut cur_icb: ICB = self.get_root_dir()?;
+        // directories leading to `cur_icb`
+        let mut ancestors: Vec<LBAddr> = Vec::new();
+        for (i, c) in path.components.iter().enumerate() {
+            if !matches!(cur_icb.icb_tag.file_type, FileType::DIR) {
+                Err(not_a_dir())?
+            }
+            match c {
+                PathComponent::Parent => {
+                    ancestors.pop().ok_or_else(|| {
+                        std::io::Error::new(
+                            ErrorKind::InvalidInput,
+                            "path tried to go above root directory",
+                        )
+                    })?;
+                    cur_icb = cur_icb.parent(self)?;
+                }
+                PathComponent::Name(name) => {
+                    if self
+                        .options
+                        .limits
+                        .max_depth
+                        .is_some_and(|max| ancestors.len() >= max)
+                    {
+                        Err("directory depth limit exceeded")?
+                    }
+                    let entry = cur_icb
+                        .shared_entries(self)?
+                        .iter()
+                        .find(|e| !e.is_parent() && e.matches(self.options.name_domain, name))
+                        .cloned()
+                        .ok_or_else(|| std::io::Error::from(ErrorKind::NotFound))?;
+                    if let (Some(stream), true) = (&path.stream, i == path.components.len() - 1) {
+                        // the ICB of a file with streams is only read for its stream directory
+                        if path.dir_only && !entry.is_dir() {
+                            Err(not_a_dir())?
+                        }
+                        return self.stream_icb(&entry.icb, stream);
+                    }
+                    let c = entry.resolve(self)?;
+                    if matches!(c.icb_tag.file_type, FileType::DIR)
+                        && (c.loc == cur_icb.loc || ancestors.contains(&c.loc))
+                    {
+                        Err(DirectoryLinkError::Loop {
+                            path: path.to_string(),
+                            target: c.loc,
+                        })?
+                    }
+                    ancestors.push(cur_icb.loc);
+                    cur_icb = c;
+                }
+            }
+        }
+        if path.dir_only && !matches!(cur_icb.icb_tag.file_type, FileType::DIR) {
+            Err(not_a_dir())?
+        }
+        match &path.stream {
+            Some(stream) => self.stream_icb(&cur_icb.loc, stream),
+            None => Ok(cur_icb),
+        }
+    }
+
+    /// the absolute path of the directory `dir`, reconstructed by following the parent FIDs
+    /// up to the root directory and looking up the name of each directory in its parent
+    pub fn dir_path(&mut self, dir: &ICB) -> Result<String, Box<dyn Error>> {
+        let root = self.get_root_dir()?.loc;
+        let mut names = Vec::new();
+        let mut cur = dir.clone();
+        let mut visited = HashSet::from([cur.loc]);
+        while cur.loc != root {
+            let parent = cur.parent(self)?;
+            if !visited.insert(parent.loc) {
+                Err("directory is not connected to the root directory")?
+            }
+            let entry = parent
+                .shared_entries(self)?
+                .iter()
+                .find(|e| !e.is_deleted() && !e.is_parent() && e.icb == cur.loc)
+                .map(|e| e.name.clone())
+                .ok_or("directory is missing from its parent directory")?;
+            names.push(entry);
+            cur = parent;
+        }
+        names.reverse();
+        Ok(format!("/{}", names.join("/")))
+    }
+
+    /// returns the first `n` bytes of the file at `path`, fewer if the file is shorter. Only
+    /// the extents holding these bytes are read, e.g. to detect file types by magic numbers.
+    pub fn peek(&mut self, path: &Path, n: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+        let icb = self.find_icb(path)?;
+        let mut buf = vec![0; n];
+        let len = icb.read_at(self, 0, &mut buf)?;
+        buf.truncate(len);
+        Ok(buf)
+    }
+}
+
+#[cfg(feature = "writer")]
+impl<IO: Read + Seek + std::io::Write> UDF<IO> {
+    /// writes `data` to the medium starting at sector `lsn` and drops all cached metadata, as
+    /// any of it may have been overwritten
+    pub fn write_sectors(&mut self, lsn: LSN, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        use std::io::Write;
+        self.io_pos = None;
+        self.io
+            .seek(SeekFrom::Start(lsn as u64 * self.sector_size()))?;
+        self.io.write_all(data)?;
+        self.invalidate_all();
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::{fs::File, io::BufReader};
+    use std::{
+        fs::File,
+        io::{BufReader, Cursor},
+    };
 
     use super::*;
 
@@ -260,6 +1783,50 @@ mod tests {
             .try_init();
     }
 
+    /// hashes contents to a cheap digest of them, good enough to tell the test files apart
+    #[derive(Default)]
+    struct TestHasher(Vec<u8>);
+
+    impl catalogue::ContentHasher for TestHasher {
+        fn update(&mut self, data: &[u8]) {
+            self.0.extend(data);
+        }
+        fn finish(&mut self) -> String {
+            let data = std::mem::take(&mut self.0);
+            format!(
+                "{:016x}",
+                data.iter()
+                    .fold(data.len() as u64, |h, &b| h.rotate_left(5) ^ b as u64)
+            )
+        }
+    }
+
+    /// rewrites the File Entry in `fe` as an Extended File Entry with the stream directory
+    /// `streams`, using the modification time as creation time
+    fn to_efe(fe: &mut [u8], streams: Option<LBAddr>) {
+        let old = fe.to_vec();
+        let tail = u32::from_le_bytes(old[168..172].try_into().unwrap()) as usize
+            + u32::from_le_bytes(old[172..176].try_into().unwrap()) as usize;
+        fe.fill(0);
+        fe[..64].copy_from_slice(&old[..64]);
+        fe[0..2].copy_from_slice(&266u16.to_le_bytes());
+        let crc_len = u16::from_le_bytes([old[10], old[11]]) + 40;
+        fe[10..12].copy_from_slice(&crc_len.to_le_bytes());
+        // object size, the information length of a file without streams
+        fe[64..72].copy_from_slice(&old[56..64]);
+        fe[72..104].copy_from_slice(&old[64..96]);
+        fe[104..116].copy_from_slice(&old[84..96]);
+        fe[116..132].copy_from_slice(&old[96..112]);
+        fe[136..152].copy_from_slice(&old[112..128]);
+        if let Some(streams) = streams {
+            fe[152..156].copy_from_slice(&2048u32.to_le_bytes());
+            fe[156..160].copy_from_slice(&streams.lbn.to_le_bytes());
+            fe[160..162].copy_from_slice(&streams.part_ref_nr.to_le_bytes());
+        }
+        fe[168..216 + tail].copy_from_slice(&old[128..176 + tail]);
+        retag(fe);
+    }
+
     #[test]
     fn get_file_contents() -> Result<(), Box<dyn Error>> {
         init_logger();
@@ -274,12 +1841,3380 @@ mod tests {
     }
 
     #[test]
-    fn find_file_icb() -> Result<(), Box<dyn Error>> {
+    fn read_from_threads() -> Result<(), Box<dyn Error>> {
+        use backend::PositionedReader;
+        use std::sync::Arc;
         init_logger();
-        let file = File::open("./tests/test.iso").unwrap();
-        let mut file = BufReader::new(file);
-        let mut udf = UDF::new(&mut file)?;
-        let _file_icb = udf.find_icb(&Path::new("/LICENSE.md"))?;
+        let file = Arc::new(File::open("./tests/test.iso")?);
+        let udf = UDF::new(PositionedReader::new(file))?;
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let mut udf = udf.clone();
+                std::thread::spawn(move || {
+                    let icb = udf.find_icb(Path::new("/LICENSE.md")).unwrap();
+                    icb.read_data(&mut udf).unwrap()
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), include_bytes!("../LICENSE.md"));
+        }
+        let mut udf = UDF::new(PositionedReader::new(std::fs::read("./tests/test.iso")?))?;
+        udf.find_icb(Path::new("/LICENSE.md"))?;
+        Ok(())
+    }
+
+    #[cfg(all(target_os = "linux", feature = "blockdev"))]
+    #[test]
+    fn read_block_device() -> Result<(), Box<dyn Error>> {
+        use backend::PositionedReader;
+        init_logger();
+        let dev = blockdev::BlockDevice::open("./tests/test.iso")?;
+        assert_eq!(dev.capacity(), 420 * 2048);
+        let mut udf = UDF::new(PositionedReader::new(dev))?;
+        let icb = udf.find_icb(Path::new("/LICENSE.md"))?;
+        assert_eq!(icb.read_data(&mut udf)?, include_bytes!("../LICENSE.md"));
+        Ok(())
+    }
+
+    #[test]
+    fn open_at_session_start() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let img = std::fs::read("./tests/test.iso")?;
+        // test.iso repeats its anchor in sectors 269 and up
+        let options = UdfOptions {
+            session_start: 13,
+            ..Default::default()
+        };
+        UDF::new_with_options(Cursor::new(img.clone()), options)?;
+        // no anchor at 256 + 200, the one at N - 1 is used instead
+        let options = UdfOptions {
+            session_start: 200,
+            ..Default::default()
+        };
+        UDF::new_with_options(Cursor::new(img), options)?;
+        Ok(())
+    }
+
+    #[cfg(all(target_os = "linux", feature = "mmc"))]
+    #[test]
+    fn parse_mmc_responses() -> Result<(), Box<dyn Error>> {
+        let mut disc = [0u8; 34];
+        disc[4] = 2; // sessions
+        disc[6] = 3; // last track in last session
+        assert_eq!(mmc::parse_disc_info(&disc)?, (2, 3));
+        let mut track = [0u8; 34];
+        track[3] = 2;
+        track[8..12].copy_from_slice(&11_700u32.to_be_bytes());
+        let session = mmc::parse_track_info(&track)?;
+        assert_eq!((session.number, session.start), (2, 11_700));
+        assert!(mmc::parse_track_info(&track[..20]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn detect_scrambled_vobs() -> Result<(), Box<dyn Error>> {
+        use testimage::Node;
+        init_logger();
+        let vob = |scrambled: bool| {
+            let mut data = vec![0; 4 * BLOCKSIZE as usize];
+            for pack in data.chunks_mut(BLOCKSIZE as usize) {
+                pack[0..4].copy_from_slice(&[0, 0, 1, 0xba]);
+                pack[14..18].copy_from_slice(&[0, 0, 1, 0xe0]);
+                pack[0x14] = if scrambled { 0x90 } else { 0x80 };
+            }
+            Node::File(data)
+        };
+        let root = Node::dir(vec![(
+            "VIDEO_TS",
+            Node::dir(vec![
+                ("VIDEO_TS.VOB", vob(false)),
+                ("VTS_01_1.VOB", vob(true)),
+                ("VTS_01_0.IFO", Node::file(b"DVDVIDEO-VTS")),
+            ]),
+        )]);
+        let mut udf = UDF::new(Cursor::new(testimage::build(&root)))?;
+        let report = udf.detect_css()?;
+        assert!(report.is_dvd_video() && report.is_scrambled());
+        assert_eq!(report.files.len(), 2);
+        assert_eq!(report.files[0].scrambled, 0);
+        assert_eq!(report.files[1].packs, 4);
+        assert_eq!(report.files[1].scrambled, 4);
+
+        let mut udf = UDF::new(File::open("./tests/test.iso")?)?;
+        assert!(!udf.detect_css()?.is_dvd_video());
+        Ok(())
+    }
+
+    #[test]
+    fn build_catalogue() -> Result<(), Box<dyn Error>> {
+        use catalogue::ContentHasher;
+        use testimage::Node;
+        init_logger();
+        struct Sum(u64);
+        impl ContentHasher for Sum {
+            fn update(&mut self, data: &[u8]) {
+                self.0 += data.iter().map(|b| *b as u64).sum::<u64>();
+            }
+            fn finish(&mut self) -> String {
+                std::mem::take(&mut self.0).to_string()
+            }
+        }
+        let root = Node::dir(vec![
+            ("a \"quoted\" name", Node::file(&[1; 3000])),
+            ("dir", Node::dir(vec![("b", Node::file(&[2; 10]))])),
+        ]);
+        let mut udf = UDF::new(Cursor::new(testimage::build(&root)))?;
+        let cat = udf.catalogue_with_hasher(&mut Sum(0))?;
+        let paths: Vec<_> = cat.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["/", "/a \"quoted\" name", "/dir", "/dir/b"]);
+        assert_eq!(cat.entries[1].size, 3000);
+        assert_eq!(cat.entries[1].extents.len(), 1);
+        assert_eq!(cat.entries[1].hash.as_deref(), Some("3000"));
+        assert_eq!(cat.entries[3].hash.as_deref(), Some("20"));
+        assert!(cat.entries[2].is_dir && cat.entries[2].hash.is_none());
+        let json = cat.to_json();
+        assert!(json
+            .contains(r#"{"path":"/a \"quoted\" name","is_dir":false,"kind":"file","size":3000,"#));
+        assert_eq!(cat.entries[2].kind, EntryKind::Dir);
+        let legacy = json.replace(r#""kind":"dir","#, "");
+        let read = catalogue::Catalogue::from_json(&legacy)?;
+        assert_eq!(read.entries[2].kind, EntryKind::Dir);
+        assert_eq!(read.entries[3].kind, EntryKind::File);
+        assert_eq!(EntryKind::from(FileType::SYMLINK), EntryKind::Symlink);
+        assert_eq!("device".parse::<EntryKind>()?, EntryKind::Device);
+
+        let mut udf = UDF::new(File::open("./tests/test.iso")?)?;
+        let cat = udf.catalogue()?;
+        assert_eq!(cat.volume.udf_revision, 0x0102);
+        assert_eq!(cat.entries[1].path, "/LICENSE.md");
+        assert_eq!(cat.entries[1].extents[0].offset, 268 * 2048);
+        Ok(())
+    }
+
+    #[test]
+    fn catalogue_changes() -> Result<(), Box<dyn Error>> {
+        use catalogue::{Catalogue, ContentHasher};
+        use testimage::Node;
+        init_logger();
+        /// counts the files hashed, hashing to the file length
+        struct Counter(u32, usize);
+        impl ContentHasher for Counter {
+            fn update(&mut self, data: &[u8]) {
+                self.1 += data.len();
+            }
+            fn finish(&mut self) -> String {
+                self.0 += 1;
+                std::mem::take(&mut self.1).to_string()
+            }
+        }
+        let v1 = Node::dir(vec![
+            ("a", Node::file(&[1; 100])),
+            ("b", Node::file(&[2; 200])),
+            ("c", Node::file(&[3; 300])),
+        ]);
+        let v2 = Node::dir(vec![
+            ("a", Node::file(&[1; 100])),
+            ("b", Node::file(&[2; 201])),
+            ("d", Node::file(&[4; 400])),
+        ]);
+        let mut hasher = Counter(0, 0);
+        let mut udf = UDF::new(Cursor::new(testimage::build(&v1)))?;
+        let json = udf.catalogue_with_hasher(&mut hasher)?.to_json();
+        let old = Catalogue::from_json(&json)?;
+        assert_eq!(old.to_json(), json);
+        assert_eq!(hasher.0, 3);
+
+        let mut udf = UDF::new(Cursor::new(testimage::build(&v2)))?;
+        let diff = udf.changes_since(&old, Some(&mut hasher))?;
+        // only the changed and the added file are hashed
+        assert_eq!(hasher.0, 5);
+        let paths = |entries: &[catalogue::CatalogueEntry]| -> Vec<String> {
+            entries.iter().map(|e| e.path.clone()).collect()
+        };
+        assert_eq!(paths(&diff.added), ["/d"]);
+        assert_eq!(paths(&diff.removed), ["/c"]);
+        assert_eq!(diff.modified.len(), 1);
+        assert_eq!(diff.modified[0].1.hash.as_deref(), Some("201"));
+        assert!(udf.changes_since(&diff.catalogue, None)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn custom_name_decoder() -> Result<(), Box<dyn Error>> {
+        use testimage::Node;
+        init_logger();
+        // the generator records names as UTF-8 bytes behind compression ID 8, like mastering
+        // tools ignoring CS0 do
+        let root = Node::dir(vec![("日本.txt", Node::file(b"utf-8"))]);
+        let mut udf = UDF::new(Cursor::new(testimage::build(&root)))?;
+        let root = udf.get_root_dir()?;
+        assert_eq!(root.get_entries(&mut udf)[0].name, "æ\u{97}¥æ\u{9c}¬.txt");
+        assert!(udf.find_icb(Path::new("/日本.txt")).is_err());
+
+        udf.set_name_decoder(|raw: &[u8]| match std::str::from_utf8(&raw[1..]) {
+            Ok(name) if raw[0] == 8 => name.to_string(),
+            _ => volume::decode_cs0(raw),
+        });
+        assert_eq!(root.get_entries(&mut udf)[0].name, "日本.txt");
+        assert_eq!(root.get_fids(&mut udf)[1].fid, "日本.txt");
+        let icb = udf.find_icb(Path::new("/日本.txt"))?;
+        assert_eq!(icb.read_data(&mut udf)?, b"utf-8");
+        Ok(())
+    }
+
+    #[test]
+    fn dos_name_domain() -> Result<(), Box<dyn Error>> {
+        use file::{dos_name, NameDomain};
+        use testimage::Node;
+        init_logger();
+        assert_eq!(dos_name("readme.txt"), "README.TXT");
+        assert_eq!(dos_name("LICENSE.md"), "LICENSE.MD");
+        let long = dos_name("long file name.html");
+        assert!(long.starts_with("LONG#") && long.ends_with(".HTM"));
+        assert_eq!(long.len(), 12);
+        assert_ne!(dos_name("long file name.htm"), long);
+
+        let root = Node::dir(vec![(
+            "Program Files",
+            Node::dir(vec![("a.b", Node::file(b"x"))]),
+        )]);
+        let mut udf = UDF::new(Cursor::new(testimage::build(&root)))?;
+        let entries = udf.get_root_dir()?.get_entries(&mut udf);
+        let dos = entries[0].name_in(NameDomain::Dos);
+        assert_eq!(
+            entries[0].names()[0],
+            (NameDomain::Udf, "Program Files".to_string())
+        );
+        assert_eq!(entries[0].names()[1], (NameDomain::Dos, dos.clone()));
+        assert!(udf.find_icb(Path::new(&format!("/{}/A.B", dos))).is_err());
+        udf.options.name_domain = NameDomain::Dos;
+        let path = format!("/{}/A.B", dos.to_lowercase());
+        assert_eq!(udf.find_icb(Path::new(&path))?.read_data(&mut udf)?, b"x");
+        Ok(())
+    }
+
+    #[test]
+    fn invalidate_cached_icbs() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let img = std::fs::read("./tests/test.iso")?;
+        let mut udf = UDF::new(Cursor::new(img))?;
+        let icb = udf.find_icb(Path::new("/LICENSE.md"))?;
+        // shrink the information length of the LICENSE.md file entry
+        let fe = &mut udf.get_mut().get_mut()[261 * 2048..262 * 2048];
+        fe[56] -= 1;
+        retag(fe);
+        let info_len = |icb: &ICB| match &icb.body {
+            ICBBody::File(fe) => fe.info_len,
+            _ => 0,
+        };
+        let cached = udf.find_icb(Path::new("/LICENSE.md"))?;
+        assert_eq!(info_len(&cached), info_len(&icb));
+        udf.invalidate(&icb.loc);
+        let fresh = udf.find_icb(Path::new("/LICENSE.md"))?;
+        assert_eq!(info_len(&fresh), info_len(&icb) - 1);
+
+        let root = udf.get_root_dir()?;
+        assert_eq!(root.get_entries(&mut udf).len(), 1);
+        // the LICENSE.md FID becomes deleted
+        udf.get_mut().get_mut()[260 * 2048 + 40 + 18] |= 0x04;
+        retag(&mut udf.get_mut().get_mut()[260 * 2048 + 40..]);
+        assert!(!root.get_entries(&mut udf)[0].is_deleted());
+        udf.invalidate_all();
+        assert!(root.get_entries(&mut udf)[0].is_deleted());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "writer")]
+    fn overlay_writes() -> Result<(), Box<dyn Error>> {
+        use overlay::Overlay;
+        init_logger();
+        let base = std::fs::read("./tests/test.iso")?;
+        let mut udf = UDF::new(Overlay::new(Cursor::new(base.clone()))?)?;
+        let icb = udf.find_icb(Path::new("/LICENSE.md"))?;
+        let (offset, _) = udf.alloc_desc_to_offset_len(&icb.get_alloc_descs()[0], 0)?;
+        let mut sector = base[offset as usize..offset as usize + 2048].to_vec();
+        sector[..4].copy_from_slice(b"COPY");
+        udf.write_sectors((offset / BLOCKSIZE) as LSN, &sector)?;
+        assert_eq!(&icb.read_data(&mut udf)?[..4], b"COPY");
+        assert_eq!(udf.get_ref().modified_sectors().collect::<Vec<_>>(), [268]);
+
+        let mut delta = Vec::new();
+        udf.get_ref().save_delta(&mut delta)?;
+        let mut overlay = Overlay::new(Cursor::new(base.clone()))?;
+        overlay.load_delta(&mut Cursor::new(delta))?;
+        let mut image = Vec::new();
+        overlay.export(&mut image)?;
+        assert_eq!(image.len(), base.len());
+        assert_eq!(&image[offset as usize..offset as usize + 4], b"COPY");
+        assert_eq!(image[..offset as usize], base[..offset as usize]);
+
+        udf.get_mut().discard();
+        udf.invalidate_all();
+        assert_eq!(icb.read_data(&mut udf)?, include_bytes!("../LICENSE.md"));
+        Ok(())
+    }
+
+    #[test]
+    fn restore_tree_snapshot() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let mut image = std::fs::read("./tests/test.iso")?;
+        let mut udf = UDF::new(Cursor::new(image.clone()))?;
+        let snapshot = udf.tree_snapshot()?;
+        let bytes = snapshot.to_bytes();
+        let snapshot = tree::TreeSnapshot::from_bytes(&bytes)?;
+        assert_eq!(snapshot.to_bytes(), bytes);
+        let node = snapshot
+            .nodes
+            .iter()
+            .find(|n| n.path == "/LICENSE.md")
+            .unwrap();
+        assert_eq!(node.info_len, include_bytes!("../LICENSE.md").len() as u64);
+
+        // wipe the file entry of LICENSE.md
+        image[261 * 2048..262 * 2048].fill(0);
+        let mut udf = UDF::new(Cursor::new(image))?;
+        assert!(udf.find_icb(Path::new("/LICENSE.md")).is_err());
+        let dest = std::env::temp_dir().join(format!("libudf-restore-{}", std::process::id()));
+        udf.restore_tree(&snapshot, &dest)?;
+        let restored = std::fs::read(dest.join("LICENSE.md"));
+        std::fs::remove_dir_all(&dest)?;
+        assert_eq!(restored?, include_bytes!("../LICENSE.md"));
+
+        // extent lengths are only trusted up to the size of the medium
+        let mut huge = snapshot.clone();
+        let node = huge
+            .nodes
+            .iter_mut()
+            .find(|n| n.path == "/LICENSE.md")
+            .unwrap();
+        node.info_len = u64::MAX;
+        let unrecorded = tree::TreeExtent {
+            len: (1 << 30) - 2048,
+            ext_type: 1,
+            ..node.extents[0].clone()
+        };
+        node.extents.extend(std::iter::repeat_n(unrecorded, 8));
+        assert!(udf.restore_tree(&huge, &dest).is_err());
+        std::fs::remove_dir_all(&dest)?;
+        Ok(())
+    }
+
+    #[test]
+    fn snapshot_unusual_names() -> Result<(), Box<dyn Error>> {
+        use testimage::Node;
+        init_logger();
+        let root = Node::dir(vec![
+            ("..", Node::file(b"dots")),
+            ("a/b", Node::file(b"slash")),
+            ("d", Node::dir(vec![("f", Node::file(b"f"))])),
+        ]);
+        let mut udf = UDF::new(Cursor::new(testimage::build(&root)))?;
+        let snapshot = udf.tree_snapshot()?;
+        let paths: Vec<_> = snapshot.nodes.iter().map(|n| n.path.as_str()).collect();
+        assert_eq!(paths, ["/", "/..", "/a/b", "/d", "/d/f"]);
+        let sizes: Vec<_> = snapshot.nodes.iter().map(|n| n.info_len).collect();
+        assert_eq!((sizes[1], sizes[2], sizes[4]), (4, 5, 1));
+        Ok(())
+    }
+
+    #[test]
+    fn restore_tree_unsafe_names() -> Result<(), Box<dyn Error>> {
+        use testimage::Node;
+        init_logger();
+        let root = Node::dir(vec![
+            ("..", Node::file(b"dots")),
+            ("a/b", Node::file(b"slash")),
+            ("d", Node::dir(vec![("f", Node::file(b"f"))])),
+        ]);
+        let mut udf = UDF::new(Cursor::new(testimage::build(&root)))?;
+        let mut snapshot = udf.tree_snapshot()?;
+        // a tampered snapshot recording a directory above the root and an entry inside it
+        let mut up = snapshot.nodes[3].clone();
+        up.path = "/..".to_string();
+        let mut escaped = snapshot.nodes[4].clone();
+        escaped.path = "/../escaped".to_string();
+        let mut current = snapshot.nodes[4].clone();
+        current.path = "/d/.".to_string();
+        snapshot.nodes.extend([up, escaped, current]);
+
+        let parent = std::env::temp_dir().join(format!("libudf-unsafe-{}", std::process::id()));
+        let dest = parent.join("dest");
+        let result = udf.restore_tree(&snapshot, &dest);
+        let list = |dir: &Path| -> Result<Vec<String>, std::io::Error> {
+            let mut names = std::fs::read_dir(dir)?
+                .map(|e| e.map(|e| e.file_name().to_string_lossy().into_owned()))
+                .collect::<Result<Vec<_>, _>>()?;
+            names.sort();
+            Ok(names)
+        };
+        let listed = (list(&parent), list(&dest), list(&dest.join("d")));
+        std::fs::remove_dir_all(&parent)?;
+        result?;
+        assert_eq!(listed.0?, ["dest"]);
+        assert_eq!(listed.1?, ["d"]);
+        assert_eq!(listed.2?, ["f"]);
+        Ok(())
+    }
+
+    #[test]
+    fn resource_limits() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let image = testimage::build(&testimage::Node::dir(vec![
+            (
+                "a",
+                testimage::Node::dir(vec![(
+                    "b",
+                    testimage::Node::dir(vec![("c.txt", testimage::Node::file(b"c"))]),
+                )]),
+            ),
+            (
+                "many",
+                testimage::Node::dir(vec![
+                    ("1", testimage::Node::file(b"1")),
+                    ("2", testimage::Node::file(b"2")),
+                    ("3", testimage::Node::file(b"3")),
+                ]),
+            ),
+        ]));
+        let open = |limits: Limits| {
+            let options = UdfOptions {
+                limits,
+                ..Default::default()
+            };
+            UDF::new_with_options(Cursor::new(image.clone()), options)
+        };
+
+        let mut udf = open(Limits {
+            max_depth: Some(2),
+            ..Default::default()
+        })?;
+        assert!(udf.find_icb(Path::new("/a/b")).is_ok());
+        assert!(udf.find_icb(Path::new("/a/b/c.txt")).is_err());
+        assert!(udf.catalogue().is_err());
+
+        let mut udf = open(Limits {
+            max_dir_entries: Some(3),
+            ..Default::default()
+        })?;
+        assert!(udf.find_icb(Path::new("/a/b/c.txt")).is_ok());
+        assert!(udf.find_icb(Path::new("/many/1")).is_err());
+
+        let mut udf = open(Limits::default())?;
+        assert_eq!(udf.catalogue()?.entries.len(), 8);
+        let total = udf.metadata_read();
+        let mut udf = open(Limits {
+            max_metadata_bytes: Some(total - 1),
+            ..Default::default()
+        })?;
+        assert!(udf.catalogue().is_err());
+        assert!(udf.metadata_read() >= total);
+        udf.reset_metadata_read();
+        assert!(udf.find_icb(Path::new("/a/b/c.txt")).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn directory_loops() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let image = testimage::build(&testimage::Node::dir(vec![
+            (
+                "a",
+                testimage::Node::dir(vec![("b", testimage::Node::dir(vec![]))]),
+            ),
+            ("c", testimage::Node::dir(vec![])),
+        ]));
+        let mut udf = UDF::new(Cursor::new(image.clone()))?;
+        let root = udf.get_root_dir()?.loc;
+        let a = udf.find_icb(Path::new("/a"))?;
+        let b = udf.find_icb(Path::new("/a/b"))?.loc;
+        let dir_sector = udf.lb_to_sector(&a.get_alloc_descs()[0].lb_addr(a.loc.part_ref_nr))?;
+
+        // points the FID of b at `target`
+        let relink = |target: LBAddr| {
+            let mut image = image.clone();
+            let sector = &mut image[dir_sector as usize * 2048..][..2048];
+            let fid = (0..2048 - 40)
+                .step_by(4)
+                .find(|&o| sector[o..o + 2] == [1, 1] && sector[o + 38..o + 40] == [8, b'b'])
+                .unwrap();
+            sector[fid + 24..fid + 28].copy_from_slice(&target.lbn.to_le_bytes());
+            retag(&mut sector[fid..]);
+            UDF::new(Cursor::new(image))
+        };
+
+        let mut udf = relink(root)?;
+        let err = udf.catalogue().err().unwrap();
+        assert_eq!(
+            err.downcast_ref::<DirectoryLinkError>(),
+            Some(&DirectoryLinkError::Loop {
+                path: "/a/b".to_string(),
+                target: root,
+            })
+        );
+        let err = udf.find_icb(Path::new("/a/b/a")).err().unwrap();
+        assert!(err.downcast_ref::<DirectoryLinkError>().is_some());
+
+        let c = udf.find_icb(Path::new("/c"))?.loc;
+        let mut udf = relink(c)?;
+        let err = udf.catalogue().err().unwrap();
+        assert!(matches!(
+            err.downcast_ref::<DirectoryLinkError>(),
+            Some(DirectoryLinkError::CrossLink { .. })
+        ));
+        assert_eq!(udf.find_icb(Path::new("/a/b"))?.loc, c);
+        assert_ne!(b, c);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "writer")]
+    fn rebuild_metadata_from_snapshot() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let image = testimage::build(&testimage::Node::dir(vec![
+            ("a.txt", testimage::Node::file(b"first file")),
+            (
+                "sub",
+                testimage::Node::dir(vec![("b.bin", testimage::Node::file(&[7; 5000]))]),
+            ),
+        ]));
+        let mut udf = UDF::new(Cursor::new(image.clone()))?;
+        let snapshot = udf.tree_snapshot()?;
+
+        // wipe all metadata blocks, keeping only the file data
+        let mut damaged = image.clone();
+        let data: Vec<u64> = snapshot
+            .nodes
+            .iter()
+            .filter(|n| !n.is_dir())
+            .flat_map(|n| n.extents.iter())
+            .flat_map(|e| e.offset / BLOCKSIZE..(e.offset + e.len as u64).div_ceil(BLOCKSIZE))
+            .collect();
+        for lsn in testimage::PART_START as u64..(image.len() as u64 / BLOCKSIZE) {
+            if !data.contains(&lsn) && lsn != 256 && lsn < image.len() as u64 / BLOCKSIZE - 1 {
+                damaged[lsn as usize * 2048..(lsn as usize + 1) * 2048].fill(0);
+            }
+        }
+        let mut udf = UDF::new(Cursor::new(damaged))?;
+        assert!(udf.get_root_dir().is_err());
+
+        let report = udf.rebuild_metadata(&snapshot)?;
+        assert_eq!((report.file_entries, report.directories), (4, 2));
+        let icb = udf.find_icb(Path::new("/sub/b.bin"))?;
+        assert_eq!(icb.read_data(&mut udf)?, [7; 5000]);
+        let icb = udf.find_icb(Path::new("/a.txt"))?;
+        assert_eq!(icb.read_data(&mut udf)?, b"first file");
+        assert_eq!(udf.tree_snapshot()?.nodes.len(), snapshot.nodes.len());
+        Ok(())
+    }
+
+    #[test]
+    fn block_integrity_map() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let mut image = std::fs::read("./tests/test.iso")?;
+        let mut udf = UDF::new(Cursor::new(image.clone()))?;
+        let allocated = udf.allocated_sectors()?;
+        assert!(allocated.iter().any(|r| r.contains(&268)));
+        assert!(!allocated.iter().any(|r| r.contains(&100)));
+
+        let map = udf.block_map(blockmap::ECC_BLOCK_SECTORS)?;
+        assert!(map.blocks.iter().all(|(lsn, _)| lsn % 16 == 0));
+        let map = blockmap::BlockMap::from_text(&map.to_text())?;
+        assert!(udf.verify_block_map(&map)?.is_empty());
+
+        // flip a bit in the contents of LICENSE.md and in unallocated space
+        image[268 * 2048 + 100] ^= 1;
+        image[100 * 2048] ^= 1;
+        let mut udf = UDF::new(Cursor::new(image))?;
+        assert_eq!(udf.verify_block_map(&map)?, [256]);
+        Ok(())
+    }
+
+    #[test]
+    fn verify_burned_copy() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let image = std::fs::read("./tests/test.iso")?;
+        let mut copy = image.clone();
+        copy[100 * 2048] ^= 1;
+        assert!(blockmap::verify_against(
+            File::open("./tests/test.iso")?,
+            &mut Cursor::new(&copy)
+        )?
+        .is_empty());
+
+        copy[268 * 2048 + 5] ^= 1;
+        copy.truncate(410 * 2048);
+        let mismatches = blockmap::verify_against(Cursor::new(image), &mut Cursor::new(copy))?;
+        assert_eq!(mismatches[0], 268);
+        assert!(mismatches[1..].iter().all(|&lsn| lsn >= 410));
+        Ok(())
+    }
+
+    #[test]
+    fn size_report() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let image = testimage::build(&testimage::Node::dir(vec![
+            ("a.TXT", testimage::Node::file(&[1; 300])),
+            ("b.txt", testimage::Node::file(&[2; 100])),
+            (
+                "dir.d",
+                testimage::Node::dir(vec![
+                    ("c.bin", testimage::Node::file(&[3; 5000])),
+                    (".hidden", testimage::Node::file(&[4; 10])),
+                ]),
+            ),
+        ]));
+        let mut udf = UDF::new(Cursor::new(image))?;
+        let report = udf.size_report(2)?;
+        let largest: Vec<&str> = report.largest.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(largest, ["/dir.d/c.bin", "/a.TXT"]);
+        assert_eq!((report.total_files, report.total_bytes), (4, 5410));
+        let ext = |e: &str, files, bytes| report::ExtensionStats {
+            extension: e.to_string(),
+            files,
+            bytes,
+        };
+        assert_eq!(
+            report.extensions,
+            [ext("bin", 1, 5000), ext("txt", 2, 400), ext("", 1, 10)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn peek_file_start() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let file = File::open("./tests/test.iso")?;
+        let mut udf = UDF::new(file)?;
+        let license = include_bytes!("../LICENSE.md");
+        assert_eq!(udf.peek(Path::new("/LICENSE.md"), 16)?, license[..16]);
+        assert_eq!(udf.peek(Path::new("/LICENSE.md"), 1 << 20)?, license);
+        assert!(udf.peek(Path::new("/missing"), 16).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn find_duplicates() -> Result<(), Box<dyn Error>> {
+        use testimage::Node;
+        init_logger();
+        let root = Node::dir(vec![
+            ("a", Node::file(&[1; 3000])),
+            ("b", Node::file(&[2; 3000])),
+            ("c", Node::file(b"xy")),
+            ("d", Node::dir(vec![("a", Node::file(&[1; 3000]))])),
+            ("e", Node::file(b"xy")),
+            ("f", Node::file(b"z")),
+        ]);
+        let mut udf = UDF::new(Cursor::new(testimage::build(&root)))?;
+        let groups = udf.duplicates(&mut TestHasher::default())?;
+        let paths: Vec<_> = groups.iter().map(|g| g.paths.clone()).collect();
+        assert_eq!(paths, [["/a", "/d/a"], ["/c", "/e"]]);
+        assert_eq!(groups[0].redundant_bytes(), 3000);
+        Ok(())
+    }
+
+    #[test]
+    fn dedup_chunks() -> Result<(), Box<dyn Error>> {
+        use testimage::Node;
+        init_logger();
+        // pseudo-random data, the second file sharing its tail with the first
+        let data: Vec<u8> = (0u32..100_000)
+            .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+            .collect();
+        let mut shifted = b"some prefix".to_vec();
+        shifted.extend(&data[50_000..]);
+        let root = Node::dir(vec![("a", Node::file(&data)), ("b", Node::file(&shifted))]);
+        let mut udf = UDF::new(Cursor::new(testimage::build(&root)))?;
+
+        let params = dedup::ChunkParams {
+            min: 512,
+            avg: 2048,
+            max: 8192,
+        };
+        let index = udf.dedup_index(Some(params), &mut TestHasher::default())?;
+        assert_eq!(dedup::DedupIndex::from_text(&index.to_text())?, index);
+        let (a, b) = (&index.files[0], &index.files[1]);
+        assert_eq!(a.chunks.iter().map(|c| c.len).sum::<u64>(), 100_000);
+        assert!(a.chunks.iter().all(|c| c.len <= 8192));
+        // chunk boundaries resynchronize after the differing prefix
+        let shared = b
+            .chunks
+            .iter()
+            .filter(|c| a.chunks.iter().any(|d| d.hash == c.hash))
+            .count();
+        assert!(shared + 2 >= b.chunks.len());
+
+        let index = udf.dedup_index(None, &mut TestHasher::default())?;
+        assert_eq!(index.files[0].chunks.len(), 1);
+        assert_eq!(index.files[0].chunks[0].len, 100_000);
+        Ok(())
+    }
+
+    #[test]
+    fn mount_table() -> Result<(), Box<dyn Error>> {
+        use testimage::Node;
+        init_logger();
+        let mut mounts = mounts::Mounts::new(16);
+        assert_eq!(
+            mounts.mount(Cursor::new(std::fs::read("./tests/test.iso")?))?,
+            "TestISO"
+        );
+        let generated = testimage::build(&Node::dir(vec![("a", Node::file(b"abc"))]));
+        assert_eq!(mounts.mount(Cursor::new(generated.clone()))?, "TESTVOL");
+        assert_eq!(mounts.mount(Cursor::new(generated))?, "TESTVOL_2");
+
+        assert_eq!(mounts.list_dir(Path::new("/"))?, ["discs"]);
+        assert_eq!(
+            mounts.list_dir(Path::new("/discs"))?,
+            ["TESTVOL", "TESTVOL_2", "TestISO"]
+        );
+        assert_eq!(mounts.list_dir(Path::new("/discs/TESTVOL_2"))?, ["a"]);
+        assert_eq!(mounts.read_file(Path::new("/discs/TESTVOL/a"))?, b"abc");
+        assert_eq!(
+            mounts.read_file(Path::new("/discs/TestISO/LICENSE.md"))?,
+            include_bytes!("../LICENSE.md")
+        );
+        assert!(mounts.read_file(Path::new("/discs/OTHER/a")).is_err());
+
+        let udf = mounts.unmount("TESTVOL").unwrap();
+        assert_eq!(udf.primary_vol_desc.vol_ident.to_string(), "TESTVOL");
+        assert!(mounts.read_file(Path::new("/discs/TESTVOL/a")).is_err());
+        assert_eq!(mounts.read_file(Path::new("/discs/TESTVOL_2/a"))?, b"abc");
+        Ok(())
+    }
+
+    #[test]
+    fn injected_faults() -> Result<(), Box<dyn Error>> {
+        use backend::{PositionedReader, ReadAt};
+        use faults::FaultyReader;
+        init_logger();
+        let image = std::fs::read("./tests/test.iso")?;
+        let license = include_bytes!("../LICENSE.md");
+
+        let mut udf = UDF::new(FaultyReader::new(Cursor::new(image.clone())).short_reads(100))?;
+        let icb = udf.find_icb(Path::new("/LICENSE.md"))?;
+        assert_eq!(icb.read_data(&mut udf)?, license);
+        assert!(udf.get_ref().reads() > 2 * license.len() as u64 / 100);
+
+        let mut udf = UDF::new(FaultyReader::new(Cursor::new(image.clone())).bad_sector(268))?;
+        let icb = udf.find_icb(Path::new("/LICENSE.md"))?;
+        assert!(icb.read_data(&mut udf).is_err());
+        let mut copy = FaultyReader::new(Cursor::new(image.clone())).bad_sectors(268..270);
+        let mut source = UDF::new(Cursor::new(image.clone()))?;
+        assert_eq!(source.verify_copy(&mut copy)?, [268]);
+
+        let faulty = FaultyReader::new(image).bad_sector(261);
+        let mut buf = vec![0; 3 * 2048];
+        assert_eq!(faulty.read_at(259 * 2048, &mut buf)?, 2 * 2048);
+        assert!(faulty.read_at(261 * 2048, &mut buf).is_err());
+        let mut udf = UDF::new(PositionedReader::new(&faulty))?;
+        assert!(udf.find_icb(Path::new("/LICENSE.md")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn fuzz_entry_points() -> Result<(), Box<dyn Error>> {
+        let iterations: usize = std::env::var("FUZZ_ITERATIONS")
+            .ok()
+            .and_then(|n| n.parse().ok())
+            .unwrap_or(200);
+        let images = [
+            std::fs::read("./tests/test.iso")?,
+            testimage::build_vat(&[testimage::Node::dir(vec![(
+                "a",
+                testimage::Node::dir(vec![("b", testimage::Node::file(b"data"))]),
+            )])]),
+        ];
+        for image in &images {
+            assert!(fuzz::open_image(image).is_ok());
+        }
+        // xorshift, deterministic mutations of the metadata of the images
+        let mut state: u64 = 0x2545f4914f6cdd1d;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for i in 0..iterations {
+            let mut image = images[i % images.len()].clone();
+            let sectors: Vec<usize> = (16..20).chain(32..70).chain(256..300).collect();
+            for _ in 0..1 + next() % 8 {
+                let sector = sectors[next() as usize % sectors.len()].min(image.len() / 2048 - 1);
+                let pos = sector * 2048 + next() as usize % 2048;
+                image[pos] = next() as u8;
+                // keep the tag valid so that mutations get past the CRC check
+                if next() % 2 == 0 {
+                    retag(&mut image[sector * 2048..(sector + 1) * 2048]);
+                }
+            }
+            if let Ok(mut udf) = fuzz::open_image(&image) {
+                let _ = udf.integrity_history();
+                let _ = udf.allocated_sectors();
+            }
+            for sector in image.chunks(2048).skip(16).take(256) {
+                let _ = fuzz::parse_descriptor(sector);
+                let _ = fuzz::parse_directory(&sector[..(next() as usize % 2048)]);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn find_file_icb() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let file = File::open("./tests/test.iso").unwrap();
+        let mut file = BufReader::new(file);
+        let mut udf = UDF::new(&mut file)?;
+        let _file_icb = udf.find_icb(Path::new("/LICENSE.md"))?;
+        Ok(())
+    }
+
+    #[test]
+    fn open_udf102_volume() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let file = File::open("./tests/test.iso").unwrap();
+        let mut file = BufReader::new(file);
+        let mut udf = UDF::new(&mut file)?;
+        assert_eq!(udf.udf_revision(), 0x0102);
+        assert_eq!(udf.desc_version(), 2);
+        assert_eq!(udf.part_desc.contents(), PartContents::NSR02);
+        let root_fids = udf.get_root_dir()?.get_fids(&mut udf);
+        assert_eq!(root_fids.len(), 2);
+        assert_eq!(root_fids[1].fid, "LICENSE.md");
+        Ok(())
+    }
+
+    #[test]
+    fn select_file_set() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let file = File::open("./tests/test.iso").unwrap();
+        let mut file = BufReader::new(file);
+        let mut udf = UDF::new(&mut file)?;
+        assert_eq!(udf.file_sets()?.len(), 1);
+        assert!(udf
+            .open_file_set(FileSetSelector::Ident("missing".to_string()))
+            .is_err());
+        assert_eq!(udf.open_file_set(FileSetSelector::FsdNum(0))?.fsd_num, 0);
+        udf.find_icb(Path::new("/LICENSE.md"))?;
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_partition_reference() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let mut img = std::fs::read("./tests/test.iso")?;
+        // partition reference of the LICENSE.md FID in the root directory
+        img[260 * 2048 + 68] = 5;
+        retag(&mut img[260 * 2048 + 40..]);
+        let mut udf = UDF::new(Cursor::new(img.clone()))?;
+        assert!(udf.find_icb(Path::new("/LICENSE.md")).is_err());
+        let options = UdfOptions {
+            strict: false,
+            ..Default::default()
+        };
+        let mut udf = UDF::new_with_options(Cursor::new(img), options)?;
+        udf.find_icb(Path::new("/LICENSE.md"))?;
+        Ok(())
+    }
+
+    #[test]
+    fn tag_location_mismatch() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let mut img = std::fs::read("./tests/test.iso")?;
+        // tag location of the LICENSE.md file entry
+        img[261 * 2048 + 12] = 9;
+        retag(&mut img[261 * 2048..]);
+        let mut udf = UDF::new(Cursor::new(img.clone()))?;
+        assert!(udf.find_icb(Path::new("/LICENSE.md")).is_err());
+        let options = UdfOptions {
+            strict: false,
+            ..Default::default()
+        };
+        let mut udf = UDF::new_with_options(Cursor::new(img), options)?;
+        udf.find_icb(Path::new("/LICENSE.md"))?;
+        Ok(())
+    }
+
+    #[test]
+    fn descriptor_checksums() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let mut img = std::fs::read("./tests/test.iso")?;
+        // modification time of the LICENSE.md file entry, covered by its CRC
+        img[261 * 2048 + 84] ^= 1;
+        let mut udf = UDF::new(Cursor::new(img.clone()))?;
+        assert!(udf.find_icb(Path::new("/LICENSE.md")).is_err());
+        let options = UdfOptions {
+            verify_checksums: false,
+            ..Default::default()
+        };
+        let mut udf = UDF::new_with_options(Cursor::new(img.clone()), options)?;
+        udf.find_icb(Path::new("/LICENSE.md"))?;
+        // tag checksum of the AVD, the volume is still found through the anchor at N - 1
+        img[256 * 2048 + 4] ^= 1;
+        UDF::new(Cursor::new(img.clone()))?;
+        img[419 * 2048 + 4] ^= 1;
+        assert!(UDF::new(Cursor::new(img)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn list_partition_maps() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let file = File::open("./tests/test.iso").unwrap();
+        let mut file = BufReader::new(file);
+        let mut udf = UDF::new(&mut file)?;
+        let maps = udf.partition_maps().to_vec();
+        assert_eq!(maps.len(), 1);
+        assert!(matches!(maps[0].kind, partition::PartitionKind::Physical));
+        assert_eq!(maps[0].pd.as_ref().map(|pd| pd.part_start), Some(257));
+        assert!(udf.vat_history()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn no_partition_maps() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let mut img = std::fs::read("./tests/test.iso")?;
+        // map table length, number of maps and the only map of the LVD
+        let lvd = 35 * 2048;
+        img[lvd + 264..lvd + 272].fill(0);
+        img[lvd + 440..lvd + 446].fill(0);
+        retag(&mut img[lvd..]);
+        let err = UDF::new(Cursor::new(img.clone())).err().unwrap();
+        assert!(err.to_string().contains("no usable partition"));
+        let options = UdfOptions {
+            strict: false,
+            ..Default::default()
+        };
+        let mut udf = UDF::new_with_options(Cursor::new(img.clone()), options)?;
+        assert_eq!(udf.partition_maps().len(), 1);
+        let icb = udf.find_icb(Path::new("/LICENSE.md"))?;
+        assert_eq!(icb.read_data(&mut udf)?, include_bytes!("../LICENSE.md"));
+        // a map of an unknown type doesn't make the partition usable either
+        img[lvd + 264] = 6;
+        img[lvd + 268] = 1;
+        img[lvd + 440..lvd + 442].copy_from_slice(&[0, 6]);
+        retag(&mut img[lvd..]);
+        let options = UdfOptions {
+            strict: false,
+            ..Default::default()
+        };
+        assert!(UDF::new_with_options(Cursor::new(img), options).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn multiple_partitions() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let mut img = std::fs::read("./tests/test.iso")?;
+        // move the terminating descriptor to make room for two descriptors of partition 1
+        img.copy_within(37 * 2048..38 * 2048, 38 * 2048);
+        img[38 * 2048 + 12] = 38;
+        retag(&mut img[38 * 2048..39 * 2048]);
+        for (lsn, vds_num, part_start) in [(37, 5u32, 0u32), (36, 4, 100)] {
+            let at = lsn * 2048;
+            img.copy_within(34 * 2048..35 * 2048, at);
+            img[at + 12] = lsn as u8;
+            img[at + 16..at + 20].copy_from_slice(&vds_num.to_le_bytes());
+            img[at + 22] = 1;
+            img[at + 188..at + 192].copy_from_slice(&part_start.to_le_bytes());
+            retag(&mut img[at..at + 2048]);
+        }
+        // second Type 1 map, onto partition 1
+        let lvd = 35 * 2048;
+        img[lvd + 10] += 6;
+        img[lvd + 264] = 12;
+        img[lvd + 268] = 2;
+        img[lvd + 446..lvd + 452].copy_from_slice(&[1, 6, 1, 0, 1, 0]);
+        retag(&mut img[lvd..lvd + 2048]);
+
+        let mut udf = UDF::new(Cursor::new(img.clone()))?;
+        let maps = udf.partition_maps().to_vec();
+        assert_eq!(maps.len(), 2);
+        assert_eq!(maps[0].pd.as_ref().map(|pd| pd.part_start), Some(257));
+        assert_eq!(maps[1].pd.as_ref().map(|pd| pd.part_start), Some(0));
+        // the data of LICENSE.md through the partition starting at sector 0
+        let loc = LBAddr {
+            lbn: 268,
+            part_ref_nr: 1,
+        };
+        assert_eq!(udf.lb_to_sector(&loc)?, 268);
+        let mut buf = [0; BLOCKSIZE as usize];
+        udf.read_block(&loc, &mut buf)?;
+        assert_eq!(buf[..], img[268 * 2048..269 * 2048]);
+        let icb = udf.find_icb(Path::new("/LICENSE.md"))?;
+        assert_eq!(icb.read_data(&mut udf)?, include_bytes!("../LICENSE.md"));
+        Ok(())
+    }
+
+    #[test]
+    fn volume_sets() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let img = std::fs::read("./tests/test.iso")?;
+        let mut second = img.clone();
+        // second volume of a set of three
+        second[32 * 2048 + 56] = 2;
+        second[32 * 2048 + 58] = 3;
+        retag(&mut second[32 * 2048..33 * 2048]);
+        let volumes = [
+            UDF::new(Cursor::new(second))?,
+            UDF::new(Cursor::new(testimage::build(&testimage::Node::dir(vec![]))))?,
+            UDF::new(Cursor::new(img))?,
+            UDF::new(Cursor::new(testimage::build(&testimage::Node::dir(vec![]))))?,
+        ];
+        let sets = volset::group_volume_sets(&volumes);
+        assert_eq!(sets.len(), 2);
+        assert_eq!(sets[0].ident, "6380C6FF000298E9");
+        assert_eq!(sets[0].members, vec![(1, 2), (2, 0)]);
+        assert_eq!(sets[0].missing(), vec![3]);
+        assert!(!sets[0].is_complete());
+        // two copies of the same generated volume
+        assert_eq!(sets[1].ident, "TESTSET");
+        assert_eq!(sets[1].members, vec![(1, 1), (1, 3)]);
+        assert_eq!(sets[1].duplicates(), vec![1]);
+        assert!(sets[1].is_complete());
+        Ok(())
+    }
+
+    #[test]
+    fn read_generated_image() -> Result<(), Box<dyn Error>> {
+        use testimage::Node;
+        init_logger();
+        let root = Node::dir(vec![
+            ("empty", Node::file(b"")),
+            (
+                "dir",
+                Node::dir(vec![("nested.txt", Node::file(&[7; 5000]))]),
+            ),
+        ]);
+        let mut udf = UDF::new(Cursor::new(testimage::build(&root)))?;
+        assert_eq!(udf.udf_revision(), 0x0201);
+        assert_eq!(udf.desc_version(), 3);
+        let nested = udf.find_icb(Path::new("/dir/../dir/nested.txt"))?;
+        assert_eq!(nested.read_data(&mut udf)?, vec![7; 5000]);
+        let empty = udf.find_icb(Path::new("/empty"))?;
+        assert!(empty.read_data(&mut udf)?.is_empty());
+        let mut buf = b"stale".to_vec();
+        nested.read_data_into(&mut udf, &mut buf)?;
+        assert_eq!(buf, vec![7; 5000]);
+        empty.read_data_into(&mut udf, &mut buf)?;
+        assert!(buf.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn path_normalization() -> Result<(), Box<dyn Error>> {
+        use path::PathComponent::*;
+        use testimage::Node;
+        init_logger();
+        let p = UdfPath::parse("//a/./b//../c/")?;
+        assert_eq!(
+            p.components,
+            [Name("a".into()), Name("b".into()), Parent, Name("c".into())]
+        );
+        assert!(p.dir_only);
+        assert_eq!(p.to_string(), "/a/b/../c/");
+        assert_eq!(p.lexical()?, ["a", "c"]);
+        assert!(!UdfPath::parse("/a/b")?.dir_only);
+        assert!(UdfPath::parse("/a/..")?.dir_only);
+        assert_eq!(UdfPath::parse("/")?, UdfPath::root());
+        assert!(UdfPath::parse("a/b").is_err());
+        assert!(UdfPath::parse("/a\0b").is_err());
+        assert!(UdfPath::parse(&format!("/{}", "x".repeat(256))).is_err());
+        assert!(UdfPath::parse("/..")?.lexical().is_err());
+
+        let root = Node::dir(vec![
+            ("dir", Node::dir(vec![("f", Node::file(b"f"))])),
+            ("g", Node::file(b"g")),
+        ]);
+        let mut udf = UDF::new(Cursor::new(testimage::build(&root)))?;
+        let root = udf.get_root_dir()?.loc;
+        let dir = udf.find_icb(Path::new("/dir"))?.loc;
+        assert_eq!(udf.find_icb(Path::new("/dir/"))?.loc, dir);
+        assert_eq!(udf.find_icb(Path::new("/dir/../dir/./"))?.loc, dir);
+        assert_eq!(udf.find_icb(Path::new("/dir/.."))?.loc, root);
+        let kind = |r: Result<ICB, Box<dyn Error>>| {
+            r.err()
+                .and_then(|e| e.downcast_ref::<std::io::Error>().map(|e| e.kind()))
+        };
+        assert_eq!(
+            kind(udf.find_icb(Path::new("/g/"))),
+            Some(ErrorKind::NotADirectory)
+        );
+        assert_eq!(
+            kind(udf.find_icb(Path::new("/g/x"))),
+            Some(ErrorKind::NotADirectory)
+        );
+        assert_eq!(
+            kind(udf.find_icb(Path::new("/dir/f/.."))),
+            Some(ErrorKind::NotADirectory)
+        );
+        assert_eq!(
+            kind(udf.find_icb(Path::new("/h"))),
+            Some(ErrorKind::NotFound)
+        );
+        assert_eq!(
+            kind(udf.find_icb(Path::new("/.."))),
+            Some(ErrorKind::InvalidInput)
+        );
+        assert_eq!(
+            kind(udf.find_icb(Path::new("dir"))),
+            Some(ErrorKind::InvalidInput)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn named_streams() -> Result<(), Box<dyn Error>> {
+        use testimage::Node;
+        init_logger();
+        let p = UdfPath::parse_with_stream("/d/f.dat:meta:$DATA")?;
+        assert_eq!(p.stream.as_deref(), Some("meta"));
+        assert_eq!(p.to_string(), "/d/f.dat:meta");
+        assert_eq!(UdfPath::parse_with_stream("/a:b/c")?.stream, None);
+        assert!(UdfPath::parse_with_stream("/f:").is_err());
+        assert!(UdfPath::parse_with_stream("/f:a:b").is_err());
+
+        let root = Node::dir(vec![
+            ("f.dat", Node::file(b"main")),
+            ("streams", Node::dir(vec![("meta", Node::file(b"stream"))])),
+        ]);
+        let mut image = testimage::build(&root);
+        let mut udf = UDF::new(Cursor::new(image.clone()))?;
+        assert!(udf.streams(Path::new("/f.dat"))?.is_empty());
+        assert!(udf.open_path("/f.dat:meta").is_err());
+        let stream_dir = udf.find_icb(Path::new("/streams"))?.loc;
+        let fe = udf.find_icb(Path::new("/f.dat"))?.loc;
+        let fe = udf.lb_to_sector(&fe)? as usize;
+
+        // turn the file entry of f.dat into an extended file entry with a stream directory
+        to_efe(&mut image[fe * 2048..(fe + 1) * 2048], Some(stream_dir));
+        let mut udf = UDF::new(Cursor::new(image))?;
+        let icb = udf.open_path("/f.dat:meta")?;
+        assert_eq!(icb.read_data(&mut udf)?, b"stream");
+        let icb = udf.find_stream(Path::new("/f.dat"), "meta")?;
+        assert_eq!(icb.read_data(&mut udf)?, b"stream");
+        assert_eq!(udf.streams(Path::new("/f.dat"))?, ["meta"]);
+        assert!(udf.open_path("/f.dat:other").is_err());
+        assert!(udf.open_path("/f.dat/:meta").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn prefetch_child_icbs() -> Result<(), Box<dyn Error>> {
+        use faults::FaultyReader;
+        use testimage::Node;
+        init_logger();
+        let names = ["a", "b", "c", "d", "e"];
+        let root = Node::dir(names.iter().map(|n| (*n, Node::file(b"x"))).collect());
+        let image = testimage::build(&root);
+        let reads_for_lookups = |prefetch_children| -> Result<u64, Box<dyn Error>> {
+            let options = UdfOptions {
+                prefetch_children,
+                ..Default::default()
+            };
+            let mut udf =
+                UDF::new_with_options(FaultyReader::new(Cursor::new(image.clone())), options)?;
+            let root = udf.get_root_dir()?;
+            assert_eq!(root.read_entries(&mut udf)?.len(), names.len());
+            let reads = udf.get_ref().reads();
+            for name in names {
+                udf.find_icb(Path::new(&format!("/{}", name)))?;
+            }
+            Ok(udf.get_ref().reads() - reads)
+        };
+        assert_eq!(reads_for_lookups(true)?, 0);
+        assert!(reads_for_lookups(false)? >= names.len() as u64);
+        Ok(())
+    }
+
+    #[test]
+    fn memory_budget() -> Result<(), Box<dyn Error>> {
+        use testimage::Node;
+        init_logger();
+        let names: Vec<String> = (0..200).map(|i| format!("file{:03}", i)).collect();
+        let root = Node::dir(vec![
+            (
+                "big",
+                Node::dir(
+                    names
+                        .iter()
+                        .map(|n| (n.as_str(), Node::file(b"x")))
+                        .collect(),
+                ),
+            ),
+            ("small", Node::dir(vec![("a", Node::file(b"a"))])),
+        ]);
+        let image = testimage::build(&root);
+        let open = |memory_budget| {
+            let options = UdfOptions {
+                memory_budget,
+                ..Default::default()
+            };
+            UDF::new_with_options(Cursor::new(image.clone()), options)
+        };
+
+        let mut udf = open(None)?;
+        assert_eq!(udf.catalogue()?.entries.len(), 204);
+        let unbounded = udf.memory_used();
+
+        // the big directory can't be listed in 16 KiB, everything else still works
+        let budget = 16 * 1024;
+        let mut udf = open(Some(budget))?;
+        assert!(udf.catalogue().is_err());
+        assert!(udf.find_icb(Path::new("/big/file007")).is_err());
+        assert_eq!(
+            udf.find_icb(Path::new("/small/a"))?.read_data(&mut udf)?,
+            b"a"
+        );
+        assert!(udf.memory_used() <= budget);
+
+        let budget = 64 * 1024;
+        let mut udf = open(Some(budget))?;
+        assert_eq!(udf.catalogue()?.entries.len(), 204);
+        assert!(udf.memory_used() <= budget);
+        assert!(udf.memory_used() < unbounded);
+        Ok(())
+    }
+
+    #[test]
+    fn io_statistics() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let license = include_bytes!("../LICENSE.md");
+        let mut udf = UDF::new(File::open("./tests/test.iso")?)?;
+        assert_eq!(udf.io_stats(), stats::IoStats::default());
+        let icb = udf.find_icb(Path::new("/LICENSE.md"))?;
+        let stats = udf.io_stats();
+        assert!(stats.metadata.reads >= 3 && stats.cache_misses >= 2);
+        assert_eq!(stats.data, stats::IoCounters::default());
+
+        assert_eq!(icb.read_data(&mut udf)?, license);
+        let stats = udf.io_stats();
+        assert_eq!(stats.data.reads, 1);
+        assert_eq!(stats.data.seeks, 1);
+        assert_eq!(stats.data.bytes, license.len() as u64);
+        assert_eq!(stats.data.blocks, (license.len() as u64).div_ceil(2048));
+
+        udf.find_icb(Path::new("/LICENSE.md"))?;
+        assert_eq!(udf.io_stats().metadata, stats.metadata);
+        assert!(udf.io_stats().cache_hits > stats.cache_hits);
+        udf.reset_io_stats();
+        assert_eq!(udf.io_stats().total(), stats::IoCounters::default());
+        Ok(())
+    }
+
+    #[test]
+    fn walk_orders() -> Result<(), Box<dyn Error>> {
+        use catalogue::WalkOrder;
+        use testimage::Node;
+        init_logger();
+        let root = Node::dir(vec![
+            ("b", Node::file(b"b")),
+            ("a", Node::file(b"a")),
+            ("c", Node::file(b"c")),
+        ]);
+        let mut image = testimage::build(&root);
+        let mut udf = UDF::new(Cursor::new(image.clone()))?;
+        let root = udf.get_root_dir()?;
+        let dir = udf.lb_to_sector(&root.get_alloc_descs()[0].lb_addr(0))? as usize;
+
+        // swap the FIDs of b and c, which have the same length, so that c is recorded
+        // first but its data is recorded last
+        let sector = &mut image[dir * 2048..(dir + 1) * 2048];
+        let fid = |name: u8| {
+            (0..2000)
+                .step_by(4)
+                .find(|&o| sector[o + 38..o + 40] == [8, name])
+        };
+        let (b, c) = (fid(b'b').unwrap(), fid(b'c').unwrap());
+        let fid_b = sector[b..b + 40].to_vec();
+        sector.copy_within(c..c + 40, b);
+        sector[c..c + 40].copy_from_slice(&fid_b);
+
+        let paths = |walk_order| -> Result<Vec<String>, Box<dyn Error>> {
+            let options = UdfOptions {
+                walk_order,
+                ..Default::default()
+            };
+            let mut udf = UDF::new_with_options(Cursor::new(image.clone()), options)?;
+            Ok(udf
+                .catalogue()?
+                .entries
+                .into_iter()
+                .map(|e| e.path)
+                .collect())
+        };
+        assert_eq!(paths(WalkOrder::OnDisc)?, ["/", "/c", "/a", "/b"]);
+        assert_eq!(paths(WalkOrder::Name)?, ["/", "/a", "/b", "/c"]);
+        assert_eq!(paths(WalkOrder::Lba)?, ["/", "/b", "/a", "/c"]);
+        Ok(())
+    }
+
+    #[test]
+    fn stat_dir_children() -> Result<(), Box<dyn Error>> {
+        use testimage::Node;
+        init_logger();
+        let root = Node::Dir(
+            (0..100)
+                .map(|i| (format!("f{}", i), Node::File(vec![1; i])))
+                .collect(),
+        );
+        let mut udf = UDF::new(Cursor::new(testimage::build(&root)))?;
+        let dir = udf.get_root_dir()?;
+        let children = udf.stat_children(&dir)?;
+        assert_eq!(children.len(), 100);
+        for (entry, icb) in children {
+            let size: usize = entry.name[1..].parse()?;
+            match icb.body {
+                ICBBody::File(fe) => assert_eq!(fe.info_len, size as u64),
+                _ => panic!("{} is not a file", entry.name),
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn lazy_dir_entries() -> Result<(), Box<dyn Error>> {
+        use testimage::Node;
+        init_logger();
+        let root = Node::dir(vec![
+            ("a.txt", Node::file(b"a")),
+            ("sub", Node::dir(vec![])),
+        ]);
+        let mut udf = UDF::new(Cursor::new(testimage::build(&root)))?;
+        let entries = udf.get_root_dir()?.get_entries(&mut udf);
+        let names: Vec<_> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["a.txt", "sub"]);
+        assert!(!entries[0].is_dir() && entries[1].is_dir());
+        let fids = udf.get_root_dir()?.get_fids(&mut udf);
+        for (fid, entry) in fids[1..].iter().zip(&entries) {
+            assert_eq!(fid.fid, entry.name);
+            assert_eq!(fid.file_bits, entry.file_bits);
+            assert_eq!(fid.icb.loc, entry.icb);
+        }
+        let sub = entries[1].resolve(&mut udf)?;
+        assert_eq!(sub.loc, entries[1].icb);
+        assert!(sub.get_entries(&mut udf).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn open_vat_generations() -> Result<(), Box<dyn Error>> {
+        use testimage::Node;
+        init_logger();
+        let gen0 = Node::dir(vec![("a.txt", Node::file(b"first"))]);
+        let gen1 = Node::dir(vec![
+            ("a.txt", Node::file(b"second")),
+            ("b.txt", Node::file(b"new")),
+        ]);
+        let img = testimage::build_vat(&[gen0, gen1]);
+        let mut udf = UDF::new(Cursor::new(img))?;
+        let a = udf.find_icb(Path::new("/a.txt"))?;
+        assert_eq!(a.read_data(&mut udf)?, b"second");
+
+        let snapshots = udf.snapshots()?;
+        assert_eq!(snapshots.len(), 2);
+        {
+            let mut old = udf.snapshot(&snapshots[1]);
+            let a = old.find_icb(Path::new("/a.txt"))?;
+            assert_eq!(a.read_data(&mut old)?, b"first");
+            assert!(old.find_icb(Path::new("/b.txt")).is_err());
+        }
+        udf.find_icb(Path::new("/b.txt"))?;
+
+        let history = udf.vat_history()?;
+        assert_eq!(history.len(), 2);
+        udf.use_vat(history[1].clone());
+        let a = udf.find_icb(Path::new("/a.txt"))?;
+        assert_eq!(a.read_data(&mut udf)?, b"first");
+        assert!(udf.find_icb(Path::new("/b.txt")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn refresh_growing_image() -> Result<(), Box<dyn Error>> {
+        use testimage::Node;
+        init_logger();
+        let gen0 = || Node::dir(vec![("a.txt", Node::file(b"first"))]);
+        let gen1 = Node::dir(vec![
+            ("a.txt", Node::file(b"second")),
+            ("b.txt", Node::file(b"new")),
+        ]);
+        let mut udf = UDF::new(Cursor::new(testimage::build_vat(&[gen0()])))?;
+        let a = udf.find_icb(Path::new("/a.txt"))?;
+        assert_eq!(a.read_data(&mut udf)?, b"first");
+        assert!(udf.find_icb(Path::new("/b.txt")).is_err());
+
+        // another session gets appended behind our back
+        *udf.get_mut() = Cursor::new(testimage::build_vat(&[gen0(), gen1]));
+        assert!(udf.find_icb(Path::new("/b.txt")).is_err());
+        assert!(udf.refresh()?.is_some());
+        let a = udf.find_icb(Path::new("/a.txt"))?;
+        assert_eq!(a.read_data(&mut udf)?, b"second");
+        udf.find_icb(Path::new("/b.txt"))?;
+        Ok(())
+    }
+
+    #[test]
+    fn read_integrity_history() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let file = File::open("./tests/test.iso").unwrap();
+        let mut file = BufReader::new(file);
+        let mut udf = UDF::new(&mut file)?;
+        assert!(udf.predecessor_vds()?.is_empty());
+        let history = udf.integrity_history()?;
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].integ_type, IntegrityType::CLOSE);
+        assert_eq!(history[0].size_tbl, vec![13]);
+        let impl_use = history[0].impl_use.as_ref().unwrap();
+        assert_eq!(impl_use.impl_ident.ident_str(), "*mkisofs");
+        assert_eq!((impl_use.num_files, impl_use.num_dirs), (1, 1));
+        assert_eq!(impl_use.min_udf_read_rev, 0x0102);
+        assert_eq!(impl_use.max_udf_write_rev, 0x0102);
+        assert!(impl_use.impl_data.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "writer")]
+    fn create_options_validation() {
+        let valid = writer::CreateOptions::default();
+        assert!(valid.validate().is_ok());
+        let revision = writer::CreateOptions {
+            revision: 0x0250,
+            ..valid.clone()
+        };
+        assert!(revision.validate().is_err());
+        let label = writer::CreateOptions {
+            label: "L".repeat(31),
+            ..valid
+        };
+        assert!(label.validate().is_err());
+        // nothing is written for options that fail validation
+        let mut image = Cursor::new(Vec::new());
+        assert!(writer::create_image(&mut image, Path::new("."), &revision).is_err());
+        assert!(image.get_ref().is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "writer")]
+    fn create_image() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let source = std::env::temp_dir().join(format!("libudf-create-{}", std::process::id()));
+        std::fs::create_dir_all(source.join("sub/deeper"))?;
+        std::fs::write(source.join("a.txt"), b"hello")?;
+        std::fs::write(source.join("sub/big.bin"), vec![3; 5000])?;
+        let mut image = Cursor::new(Vec::new());
+        let options = writer::CreateOptions {
+            revision: 0x0150,
+            label: "CREATED".to_string(),
+        };
+        let report = writer::create_image(&mut image, &source, &options);
+        std::fs::remove_dir_all(&source)?;
+        let report = report?;
+        assert_eq!(
+            (report.files, report.directories, report.bytes),
+            (2, 3, 5005)
+        );
+
+        let options = UdfOptions {
+            strict: true,
+            ..Default::default()
+        };
+        let mut udf = UDF::new_with_options(Cursor::new(image.into_inner()), options)?;
+        assert_eq!(udf.udf_revision(), 0x0150);
+        assert_eq!(udf.desc_version(), 2);
+        assert_eq!(udf.volume_info().vol_ident, "CREATED");
+        let paths: Vec<String> = udf
+            .catalogue()?
+            .entries
+            .into_iter()
+            .map(|e| e.path)
+            .collect();
+        assert_eq!(
+            paths,
+            ["/", "/a.txt", "/sub", "/sub/big.bin", "/sub/deeper"]
+        );
+        let big = udf.find_icb(Path::new("/sub/big.bin"))?;
+        assert_eq!(big.read_data(&mut udf)?, vec![3; 5000]);
+        assert_eq!(udf.integrity_history()?[0].integ_type, IntegrityType::CLOSE);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "writer")]
+    fn add_file() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let mut image = Cursor::new(std::fs::read("./tests/test.iso")?);
+        let mut udf = UDF::new(&mut image)?;
+        let mtime = udf.volume_info().record_time;
+        let data = vec![9; 3000];
+        udf.add_file(
+            Path::new("/added.bin"),
+            &mut &data[..],
+            3000,
+            mtime.clone(),
+            false,
+        )?;
+        assert!(udf
+            .add_file(
+                Path::new("/added.bin"),
+                &mut &data[..],
+                3000,
+                mtime.clone(),
+                false
+            )
+            .is_err());
+        assert!(udf
+            .add_file(Path::new("/missing/x"), &mut &data[..], 3000, mtime, false)
+            .is_err());
+        let added = udf.find_icb(Path::new("/added.bin"))?;
+        assert_eq!(added.read_data(&mut udf)?, data);
+
+        let options = UdfOptions {
+            strict: true,
+            ..Default::default()
+        };
+        let mut udf = UDF::new_with_options(Cursor::new(image.into_inner()), options)?;
+        let license = udf.find_icb(Path::new("/LICENSE.md"))?;
+        assert_eq!(
+            license.read_data(&mut udf)?,
+            include_bytes!("../LICENSE.md")
+        );
+        let added = udf.find_icb(Path::new("/added.bin"))?;
+        assert_eq!(added.read_data(&mut udf)?, data);
+        let lvid = udf.integrity_history()?.pop().unwrap();
+        assert_eq!(lvid.size_tbl, vec![13 + 2 + 1 + 1]);
+        assert_eq!(udf.partition_maps()[0].pd.as_ref().unwrap().part_len, 17);
+        Ok(())
+    }
+
+    #[test]
+    fn truncated_image() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let mut image = std::fs::read("./tests/test.iso")?;
+        let udf = UDF::new(Cursor::new(image.clone()))?;
+        assert!(udf.truncation().is_none());
+
+        // cut the image off in the middle of the partition, before the data of LICENSE.md
+        image.truncate(266 * 2048);
+        let mut udf = UDF::new(Cursor::new(image))?;
+        let truncation = udf.truncation().unwrap().clone();
+        assert_eq!(truncation.medium_len, 266 * 2048);
+        assert_eq!(truncation.expected_sectors, 270);
+        let entry = udf.catalogue()?.entries.pop().unwrap();
+        assert_eq!(entry.path, "/LICENSE.md");
+        assert!(truncation.cuts(entry.extents[0].offset, entry.extents[0].len as u64));
+        let license = udf.find_icb(Path::new("/LICENSE.md"))?;
+        let err = license.read_data(&mut udf).unwrap_err();
+        let err = err.downcast::<std::io::Error>().unwrap();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        assert!(err.to_string().contains("truncated"));
+        Ok(())
+    }
+
+    #[test]
+    fn scan_session_starts() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let mut image = Cursor::new(std::fs::read("./tests/test.iso")?);
+        // test.iso repeats its anchor in sectors 269 to 419
+        let candidates = sessions::scan_session_starts(&mut image, 1)?;
+        assert_eq!(candidates.len(), 1 + 151);
+        let plausible: Vec<LSN> = candidates
+            .iter()
+            .filter(|c| c.is_plausible())
+            .map(|c| c.start)
+            .collect();
+        assert_eq!(plausible, [0]);
+        assert!(candidates[1].opens && !candidates[1].vrs);
+        let candidates = sessions::scan_session_starts(&mut image, 16)?;
+        assert_eq!(candidates.len(), 11);
+        assert!(candidates.iter().all(|c| c.start % 16 == 0));
+        assert!(sessions::scan_session_starts(&mut image, 0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn integrity_continuation() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let mut image = std::fs::read("./tests/test.iso")?;
+        // continue the integrity sequence at 64 with a copy at 70, which in turn continues
+        // beyond the end of the image
+        let lvid = image[64 * 2048..65 * 2048].to_vec();
+        for (lsn, next) in [(64usize, 70u32), (70, 1000)] {
+            let desc = &mut image[lsn * 2048..(lsn + 1) * 2048];
+            desc.copy_from_slice(&lvid);
+            desc[12..16].copy_from_slice(&(lsn as u32).to_le_bytes());
+            desc[32..36].copy_from_slice(&2048u32.to_le_bytes());
+            desc[36..40].copy_from_slice(&next.to_le_bytes());
+            retag(desc);
+        }
+        let options = UdfOptions {
+            strict: false,
+            ..Default::default()
+        };
+        let mut udf = UDF::new_with_options(Cursor::new(image.clone()), options)?;
+        let seq = udf.integrity_sequence()?;
+        assert_eq!(seq.num_descs, 2);
+        let extents: Vec<LSN> = seq.extents.iter().map(|e| e.loc).collect();
+        assert_eq!(extents, [64, 70, 1000]);
+        assert_eq!(seq.current.unwrap().tag.tag_loc, 70);
+        assert_eq!(udf.integrity_history()?.len(), 2);
+
+        let mut udf = UDF::new(Cursor::new(image))?;
+        assert!(udf.integrity_sequence().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn check_file_counts() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let mut image = std::fs::read("./tests/test.iso")?;
+        let mut udf = UDF::new(Cursor::new(image.clone()))?;
+        let check = udf.check_file_counts()?.unwrap();
+        assert!(check.is_consistent());
+        assert_eq!((check.files, check.dirs), (1, 1));
+
+        // the LVID of test.iso records its number of files at 120
+        let lvid = &mut image[64 * 2048..65 * 2048];
+        lvid[120..124].copy_from_slice(&5u32.to_le_bytes());
+        retag(lvid);
+        let mut udf = UDF::new(Cursor::new(image))?;
+        let check = udf.check_file_counts()?.unwrap();
+        assert_eq!(check.recorded_files, 5);
+        assert_eq!(
+            check.discrepancies(),
+            ["integrity descriptor records 5 files, 1 found"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn open_reserve_vds() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let mut image = std::fs::read("./tests/test.iso")?;
+        // rename the volume in the PVD of the reserve sequence only
+        let pvd = &mut image[48 * 2048..49 * 2048];
+        pvd[24..56].fill(0);
+        pvd[24..32].copy_from_slice(b"\x08Altered");
+        pvd[55] = 8;
+        retag(pvd);
+
+        let mut main = UDF::new(Cursor::new(image.clone()))?;
+        let options = UdfOptions {
+            vds_copy: Some(StructureCopy::Backup),
+            ..Default::default()
+        };
+        let mut reserve = UDF::new_with_options(Cursor::new(image), options)?;
+        assert_eq!(main.volume_info().vol_ident, "TestISO");
+        assert_eq!(reserve.volume_info().vol_ident, "Altered");
+        let catalogue = reserve.catalogue()?;
+        assert!(main.changes_since(&catalogue, None)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn damaged_vds_fallback() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let image = std::fs::read("./tests/test.iso")?;
+        // wipe the PD of one sequence and the LVD of the other
+        for (lsn, copy) in [(34, StructureCopy::Primary), (51, StructureCopy::Backup)] {
+            let mut image = image.clone();
+            image[lsn * 2048..(lsn + 1) * 2048].fill(0);
+            let mut udf = UDF::new(Cursor::new(image.clone()))?;
+            assert_eq!(udf.volume_info().vol_ident, "TestISO");
+            assert_eq!(udf.catalogue()?.entries.len(), 2);
+            // a copy asked for explicitly isn't replaced by the other one
+            let options = UdfOptions {
+                vds_copy: Some(copy),
+                ..Default::default()
+            };
+            assert!(UDF::new_with_options(Cursor::new(image), options).is_err());
+        }
+
+        // with both copies damaged the error of the chosen one is returned
+        let mut image = image;
+        image[34 * 2048..35 * 2048].fill(0);
+        image[51 * 2048..52 * 2048].fill(0);
+        let err = UDF::new(Cursor::new(image)).err().unwrap();
+        assert_eq!(err.to_string(), "no UDF partition descriptor found");
+        Ok(())
+    }
+
+    #[test]
+    fn extent_overlaps() -> Result<(), Box<dyn Error>> {
+        use testimage::Node;
+        init_logger();
+        let root = Node::dir(vec![
+            ("a", Node::file(&[1; 5000])),
+            ("b", Node::file(&[2; 3000])),
+            ("c", Node::file(&[3; 100])),
+        ]);
+        let mut udf = UDF::new(Cursor::new(testimage::build(&root)))?;
+        let mut catalogue = udf.catalogue()?;
+        assert!(catalogue.extent_overlaps().is_empty());
+
+        // cross-link the second half of a with b
+        let a = catalogue.entries[1].extents[0].clone();
+        let b = &mut catalogue.entries[2];
+        b.extents[0].offset = a.offset + 2048;
+        let start = (a.offset / 2048) as LSN;
+        let overlaps = catalogue.extent_overlaps();
+        assert_eq!(overlaps.len(), 1);
+        assert_eq!(overlaps[0].first, "/a");
+        assert_eq!(overlaps[0].second, "/b");
+        assert_eq!(overlaps[0].sectors, start + 1..start + 3);
+        Ok(())
+    }
+
+    /// records a space bitmap at block 5 of the partition of test.iso marking 7 to 12 as
+    /// free, which leaves 6 allocated though unused and marks 11, the data of LICENSE.md, as
+    /// free
+    fn add_space_bitmap(image: &mut [u8]) {
+        let bitmap = &mut image[262 * 2048..263 * 2048];
+        bitmap[16..20].copy_from_slice(&13u32.to_le_bytes());
+        bitmap[20..24].copy_from_slice(&2u32.to_le_bytes());
+        bitmap[24..26].copy_from_slice(&[0x80, 0x1f]);
+        write_tag(bitmap, 264, 2, 5, 26);
+        let pd = &mut image[34 * 2048..35 * 2048];
+        pd[64..68].copy_from_slice(&26u32.to_le_bytes());
+        pd[68..72].copy_from_slice(&5u32.to_le_bytes());
+        retag(pd);
+    }
+
+    #[test]
+    fn orphan_blocks() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let mut image = std::fs::read("./tests/test.iso")?;
+        let udf = UDF::new(Cursor::new(image.clone()))?;
+        assert!(udf.clone().orphan_blocks()?.is_empty());
+
+        add_space_bitmap(&mut image);
+        let mut udf = UDF::new(Cursor::new(image))?;
+        let orphans = udf.orphan_blocks()?;
+        assert_eq!(orphans.len(), 1);
+        assert_eq!(orphans[0].unreferenced, vec![6..7]);
+        assert_eq!(orphans[0].unallocated, vec![11..12]);
+        Ok(())
+    }
+
+    #[test]
+    fn allocation_out_of_range() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        // a metadata mirror far beyond what sectors can address, which opening falls back from
+        let root = testimage::Node::dir(vec![("a.txt", testimage::Node::file(b"a"))]);
+        let mut image = testimage::build_metadata(&root);
+        for lsn in [34, 50] {
+            let lvd = &mut image[lsn * 2048..(lsn + 1) * 2048];
+            lvd[490..494].copy_from_slice(&0xFFFF_FFF0u32.to_le_bytes());
+            retag(lvd);
+        }
+        let mut udf = UDF::new(Cursor::new(image))?;
+        assert!(!udf.allocated_sectors()?.is_empty());
+        udf.orphan_blocks()?;
+
+        // a space bitmap recorded as 1 GiB long in a partition as long as it
+        let mut image = std::fs::read("./tests/test.iso")?;
+        add_space_bitmap(&mut image);
+        let pd = &mut image[34 * 2048..35 * 2048];
+        pd[64..68].copy_from_slice(&((1u32 << 30) - 2048).to_le_bytes());
+        pd[192..196].copy_from_slice(&(1u32 << 20).to_le_bytes());
+        retag(pd);
+        let mut udf = UDF::new(Cursor::new(image))?;
+        let pd = udf.part_desc.clone();
+        assert!(udf.allocated_blocks(&pd).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn space_bitmap_extents() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let mut image = std::fs::read("./tests/test.iso")?;
+        let mut udf = UDF::new(Cursor::new(image.clone()))?;
+        let pd = udf.part_desc.clone();
+        assert!(udf.space_bitmap(&pd)?.is_none());
+
+        add_space_bitmap(&mut image);
+        let mut udf = UDF::new(Cursor::new(image))?;
+        let pd = udf.part_desc.clone();
+        let sbd = udf.space_bitmap(&pd)?.unwrap();
+        assert_eq!(sbd.len(), 13);
+        assert_eq!(sbd.is_free(6), Some(false));
+        assert_eq!(sbd.is_free(7), Some(true));
+        assert_eq!(sbd.is_free(13), None);
+        let extents: Vec<_> = sbd.extents().map(|e| (e.blocks, e.free)).collect();
+        assert_eq!(extents, vec![(0..7, false), (7..13, true)]);
+
+        let mut buf = vec![0; 27];
+        buf[0..2].copy_from_slice(&264u16.to_le_bytes());
+        buf[16..20].copy_from_slice(&20u32.to_le_bytes());
+        buf[20..24].copy_from_slice(&3u32.to_le_bytes());
+        buf[24..27].copy_from_slice(&[0xFF, 0x00, 0x0F]);
+        let sbd = raw::SBD::parse(&buf).unwrap().1;
+        let extents: Vec<_> = sbd.extents().map(|e| (e.blocks, e.free)).collect();
+        assert_eq!(extents, vec![(0..8, true), (8..16, false), (16..20, true)]);
+        Ok(())
+    }
+
+    #[test]
+    fn export_orphans() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let mut image = std::fs::read("./tests/test.iso")?;
+        add_space_bitmap(&mut image);
+        image[263 * 2048..263 * 2048 + 8].copy_from_slice(b"%PDF-1.4");
+        let mut udf = UDF::new(Cursor::new(image.clone()))?;
+        let dest = std::env::temp_dir().join(format!("libudf-orphans-{}", std::process::id()));
+        let runs = udf.export_orphans(&dest);
+        let pdf = std::fs::read(dest.join("0001.pdf"));
+        std::fs::remove_dir_all(&dest)?;
+        let runs = runs?;
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].blocks, 6..7);
+        assert_eq!(runs[0].hint.unwrap().name, "PDF document");
+        assert_eq!(pdf?, &image[263 * 2048..264 * 2048]);
+
+        assert_eq!(
+            recovery::type_hint(&image[261 * 2048..]).unwrap().extension,
+            "desc"
+        );
+        assert_eq!(
+            recovery::type_hint(include_bytes!("../LICENSE.md"))
+                .unwrap()
+                .name,
+            "text"
+        );
+        assert_eq!(recovery::type_hint(&[0xfe; 64]), None);
+
+        // two byte signatures alone don't label data
+        let mut exe = [0xfe; 0x84];
+        exe[..2].copy_from_slice(b"MZ");
+        assert_eq!(recovery::type_hint(&exe), None);
+        exe[0x3c..0x40].copy_from_slice(&0x80u32.to_le_bytes());
+        exe[0x80..].copy_from_slice(b"PE\0\0");
+        assert_eq!(recovery::type_hint(&exe).unwrap().extension, "exe");
+        assert_eq!(recovery::type_hint(b"\x1f\x8b\xfe\xfe"), None);
+        assert_eq!(
+            recovery::type_hint(b"\x1f\x8b\x08\x00").unwrap().extension,
+            "gz"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn read_clamped_to_info_len() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let license = include_bytes!("../LICENSE.md");
+        let mut image = std::fs::read("./tests/test.iso")?;
+        // pad the extent of LICENSE to a whole block of garbage
+        image[268 * 2048 + license.len()..269 * 2048].fill(0xaa);
+        let fe = &mut image[261 * 2048..262 * 2048];
+        fe[428..432].copy_from_slice(&2048u32.to_le_bytes());
+        retag(fe);
+        let mut udf = UDF::new(Cursor::new(image.clone()))?;
+        let icb = udf.find_icb(Path::new("/LICENSE.md"))?;
+        assert_eq!(icb.read_data(&mut udf)?, license);
+        let mut buf = vec![0; 4096];
+        assert_eq!(icb.read_at(&mut udf, 0, &mut buf)?, license.len());
+        let mut data = Vec::new();
+        udf.open(Path::new("/LICENSE.md"))?.read_to_end(&mut data)?;
+        assert_eq!(data, license);
+
+        // embedded data longer than the information length
+        let fe = &mut image[261 * 2048..262 * 2048];
+        fe[34] = (fe[34] & !7) | 3;
+        fe[56..64].copy_from_slice(&5u64.to_le_bytes());
+        fe[428..436].copy_from_slice(b"abcdefgh");
+        retag(fe);
+        let mut udf = UDF::new(Cursor::new(image))?;
+        let icb = udf.find_icb(Path::new("/LICENSE.md"))?;
+        assert_eq!(icb.read_data(&mut udf)?, b"abcde");
+        Ok(())
+    }
+
+    #[test]
+    fn domain_flags() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let mut image = std::fs::read("./tests/test.iso")?;
+        let udf = UDF::new(Cursor::new(image.clone()))?;
+        assert!(udf.domain_flags().is_empty());
+
+        let lvd = &mut image[35 * 2048..36 * 2048];
+        lvd[216] = RegIDFlags::PROTECTED.bits();
+        retag(lvd);
+        let udf = UDF::new(Cursor::new(image))?;
+        assert!(udf.logical_vol_desc.domain_id.is_protected());
+        assert!(!udf.logical_vol_desc.domain_id.is_dirty());
+        assert_eq!(udf.domain_flags(), RegIDFlags::PROTECTED);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "writer")]
+    fn protected_domain_writes() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let mut image = std::fs::read("./tests/test.iso")?;
+        for sector in [35, 51] {
+            let lvd = &mut image[sector * 2048..(sector + 1) * 2048];
+            lvd[216] = RegIDFlags::PROTECTED.bits();
+            retag(lvd);
+        }
+        // the protection is only warned about, by every entry point that modifies the volume
+        let mut udf = UDF::new(Cursor::new(image))?;
+        let mtime = udf.volume_info().record_time;
+        let data = vec![9; 3000];
+        udf.add_file(Path::new("/added.bin"), &mut &data[..], 3000, mtime, false)?;
+        udf.scrub(false)?;
+        let added = udf.find_icb(Path::new("/added.bin"))?;
+        assert_eq!(added.read_data(&mut udf)?, data);
+        assert_eq!(udf.domain_flags(), RegIDFlags::PROTECTED);
+        Ok(())
+    }
+
+    #[test]
+    fn prelude_api() -> prelude::Result<()> {
+        use prelude::*;
+        init_logger();
+        let mut volume = Volume::new(std::fs::File::open("./tests/test.iso")?)?;
+        let entries: Vec<Metadata> = volume.read_dir(Path::new("/"))?.collect();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].path, "/LICENSE.md");
+        assert_eq!(entries[0].kind, EntryKind::File);
+        let meta = volume.metadata(Path::new("/LICENSE.md"))?;
+        assert_eq!(meta.path, "/LICENSE.md");
+        assert_eq!(meta.size, include_bytes!("../LICENSE.md").len() as u64);
+        assert!(volume.read_dir(Path::new("/LICENSE.md")).is_err());
+        assert!(volume.metadata(Path::new("/"))?.is_dir);
+        Ok(())
+    }
+
+    #[test]
+    fn open_file_handle() -> Result<(), Box<dyn Error>> {
+        use testimage::Node;
+        init_logger();
+        let data: Vec<u8> = (0..5000u32).map(|i| i as u8).collect();
+        let root = Node::dir(vec![
+            ("big", Node::file(&data)),
+            ("small", Node::file(b"tiny")),
+        ]);
+        let mut udf = UDF::new(Cursor::new(testimage::build(&root)))?;
+        let mut file = udf.open(Path::new("/big"))?;
+        assert_eq!(file.len(), 5000);
+        let mut all = Vec::new();
+        file.read_to_end(&mut all)?;
+        assert_eq!(all, data);
+
+        let mut buf = [0; 100];
+        file.seek(SeekFrom::Start(2000))?;
+        file.read_exact(&mut buf)?;
+        assert_eq!(buf[..], data[2000..2100]);
+        file.seek(SeekFrom::Current(-50))?;
+        file.read_exact(&mut buf[..10])?;
+        assert_eq!(buf[..10], data[2050..2060]);
+        assert_eq!(file.seek(SeekFrom::End(-5))?, 4995);
+        assert_eq!(file.read(&mut buf)?, 5);
+        assert_eq!(file.read(&mut buf)?, 0);
+        assert!(file.seek(SeekFrom::Current(-6000)).is_err());
+
+        let mut small = String::new();
+        udf.open(Path::new("/small"))?.read_to_string(&mut small)?;
+        assert_eq!(small, "tiny");
+        assert!(udf.open(Path::new("/")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn sector_size_detection() -> Result<(), Box<dyn Error>> {
+        use testimage::Node;
+        init_logger();
+        let data: Vec<u8> = (0..5000u32).map(|i| (i % 251) as u8).collect();
+        let root = Node::dir(vec![
+            ("sub", Node::dir(vec![("big", Node::file(&data))])),
+            ("small", Node::file(b"tiny")),
+        ]);
+        for bs in [512, 1024, 2048, 4096] {
+            let image = testimage::build_with_sector_size(&root, bs);
+            assert_eq!(
+                detect_sector_size(&mut Cursor::new(&image), 0)?,
+                Some(bs as u32)
+            );
+            let mut udf = UDF::new(Cursor::new(image.clone()))?;
+            assert_eq!(udf.sector_size(), bs as u64);
+            assert_eq!(udf.volume_info().sector_size, bs as u64);
+            let mut all = Vec::new();
+            udf.open(Path::new("/sub/big"))?.read_to_end(&mut all)?;
+            assert_eq!(all, data);
+            assert_eq!(udf.catalogue()?.entries.len(), 4);
+            assert!(udf.integrity_sequence()?.current.is_some());
+
+            // a sector size given explicitly is not second-guessed
+            let wrong = if bs == 2048 { 512 } else { 2048 };
+            let options = UdfOptions {
+                sector_size: Some(wrong),
+                ..Default::default()
+            };
+            assert!(UDF::new_with_options(Cursor::new(image), options).is_err());
+        }
+        for size in [0, 100, 2047, 8192] {
+            let image = Cursor::new(std::fs::read("./tests/test.iso")?);
+            let err = UDF::options().sector_size(size).open(image).err().unwrap();
+            assert_eq!(err.to_string(), format!("unsupported sector size {}", size));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn multi_sector_blocks() -> Result<(), Box<dyn Error>> {
+        use testimage::Node;
+        init_logger();
+        let data: Vec<u8> = (0..5000u32).map(|i| (i % 251) as u8).collect();
+        let root = Node::dir(vec![
+            ("sub", Node::dir(vec![("big", Node::file(&data))])),
+            ("small", Node::file(b"tiny")),
+        ]);
+        let image = testimage::build_with_block_size(&root, 512, 2048);
+        // UDF requires logical blocks of one sector
+        assert!(UDF::new(Cursor::new(image.clone())).is_err());
+        let options = UdfOptions {
+            strict: false,
+            ..Default::default()
+        };
+        let mut udf = UDF::new_with_options(Cursor::new(image), options)?;
+        assert_eq!((udf.sector_size(), udf.block_size()), (512, 2048));
+        let mut all = Vec::new();
+        udf.open(Path::new("/sub/big"))?.read_to_end(&mut all)?;
+        assert_eq!(all, data);
+        let cat = udf.catalogue()?;
+        assert_eq!(cat.entries.len(), 4);
+        assert!(cat.extent_overlaps().is_empty());
+        // the data follows the FSD and the ICBs of the root, `sub` and `big`
+        let big = cat.entries.iter().find(|e| e.path == "/sub/big").unwrap();
+        assert_eq!(big.extents[0].offset, 257 * 512 + 4 * 2048);
+        let allocated = udf.allocated_sectors()?;
+        assert!(allocated
+            .iter()
+            .any(|r| r.contains(&(257 + 4 * 4)) && r.contains(&(257 + 4 * 4 + 9))));
+        Ok(())
+    }
+
+    #[test]
+    fn scan_events() -> Result<(), Box<dyn Error>> {
+        use scan::Descriptor;
+        init_logger();
+        let mut udf = UDF::new(File::open("./tests/test.iso")?)?;
+        let mut events = Vec::new();
+        udf.scan(|event| events.push((event.lsn, event.descriptor)))?;
+        let names: Vec<_> = events.iter().map(|(lsn, d)| (*lsn, d.name())).collect();
+        assert_eq!(names[..3], [(16, "VSD"), (17, "VSD"), (18, "VSD")]);
+        assert!(names.contains(&(256, "AVD")));
+        assert!(names.contains(&(32, "PVD")));
+        assert!(names.contains(&(34, "PD")));
+        assert!(names.contains(&(35, "LVD")));
+        assert!(names.contains(&(64, "LVID")));
+        assert_eq!(names.iter().filter(|(_, name)| *name == "TD").count(), 2);
+        assert_eq!(names.iter().filter(|(_, name)| *name == "FSD").count(), 1);
+        assert!(names.contains(&(261, "ICB")));
+        let fids: Vec<_> = events
+            .iter()
+            .filter_map(|(_, d)| match d {
+                Descriptor::FileIdentifier(fid) => Some(fid.fid.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(fids, ["", "LICENSE.md"]);
+        // the file set comes last, starting with the root directory
+        let first_icb = names.iter().position(|(_, name)| *name == "ICB").unwrap();
+        assert!(names[first_icb..]
+            .iter()
+            .all(|(_, n)| *n == "ICB" || *n == "FID"));
+
+        // events carry the sector read, not the location the FID of LICENSE.md claims
+        let mut image = std::fs::read("./tests/test.iso")?;
+        let fid = &mut image[260 * 2048 + 40..260 * 2048 + 80];
+        fid[12..16].copy_from_slice(&99u32.to_le_bytes());
+        retag(fid);
+        let mut udf =
+            UDF::new_with_options(Cursor::new(image), UdfOptions::default().strict(false))?;
+        let mut fids = Vec::new();
+        udf.scan(|event| {
+            if let Descriptor::FileIdentifier(fid) = event.descriptor {
+                fids.push((event.lsn, fid.tag.tag_loc));
+            }
+        })?;
+        assert_eq!(fids, [(260, 3), (260, 99)]);
+
+        // FIDs in the later sectors of blocks of several sectors
+        let names: Vec<String> = (0..40).map(|i| format!("file{:02}", i)).collect();
+        let root = testimage::Node::dir(
+            names
+                .iter()
+                .map(|name| (name.as_str(), testimage::Node::file(b"")))
+                .collect(),
+        );
+        let image = testimage::build_with_block_size(&root, 512, 2048);
+        let mut udf =
+            UDF::new_with_options(Cursor::new(image), UdfOptions::default().strict(false))?;
+        let root = udf.get_root_dir()?.loc;
+        let root_lsn = udf.lb_to_sector(&root)?;
+        let mut fids = Vec::new();
+        udf.scan(|event| {
+            if let Descriptor::FileIdentifier(_) = event.descriptor {
+                fids.push(event.lsn);
+            }
+        })?;
+        assert_eq!(fids.len(), 41);
+        assert!(fids.windows(2).all(|w| w[0] <= w[1]));
+        assert!(fids[0] > root_lsn && fids[40] > fids[0]);
+        Ok(())
+    }
+
+    #[test]
+    fn options_builder() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let iso = std::fs::read("./tests/test.iso")?;
+        let mut disk = vec![0xaa; 1 << 20];
+        disk.extend(&iso);
+        let mut udf = UDF::options()
+            .strict(false)
+            .partition(0)
+            .start_offset(1 << 20)
+            .open(Cursor::new(disk.clone()))?;
+        assert!(!udf.options.strict);
+        let icb = udf.find_icb(Path::new("/LICENSE.md"))?;
+        assert_eq!(icb.read_data(&mut udf)?, include_bytes!("../LICENSE.md"));
+        assert_eq!(udf.catalogue()?.entries.len(), 2);
+        assert!(udf.truncation().is_none());
+        assert_eq!(udf.get_ref().get_ref().len(), disk.len());
+
+        assert!(UDF::options().open(Cursor::new(disk.clone())).is_err());
+        let missing = UDF::options().start_offset(1 << 20).partition(5);
+        assert!(missing.open(Cursor::new(disk.clone())).is_err());
+        let sectors = UDF::options().start_offset(1 << 20).sector_size(512);
+        assert!(sectors.open(Cursor::new(disk)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn structure_graph() -> Result<(), Box<dyn Error>> {
+        use graph::NodeKind;
+        use testimage::Node;
+        init_logger();
+        let mut udf = UDF::new(File::open("./tests/test.iso")?)?;
+        let graph = udf.structure_graph()?;
+        let nodes: Vec<_> = graph.nodes.iter().map(|n| (n.kind, n.lsn)).collect();
+        assert_eq!(nodes[0], (NodeKind::Volume, None));
+        assert!(nodes.contains(&(NodeKind::Integrity, Some(64))));
+        assert!(nodes.contains(&(NodeKind::Entry(EntryKind::File), Some(261))));
+        assert_eq!(graph.edges.len(), graph.nodes.len() - 1);
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph udf {") && dot.contains("LICENSE.md"));
+        let json = graph.to_json();
+        assert!(json.starts_with("{\"id\":0,\"kind\":\"volume\""));
+        assert!(json.contains("{\"edge\":\"LICENSE.md\",\"node\":{\"id\":"));
+
+        let gen0 = Node::dir(vec![("a.txt", Node::file(b"first"))]);
+        let gen1 = Node::dir(vec![("b.txt", Node::file(b"second"))]);
+        let mut udf = UDF::new(Cursor::new(testimage::build_vat(&[gen0, gen1])))?;
+        let graph = udf.structure_graph()?;
+        let vats: Vec<_> = (0..graph.nodes.len())
+            .filter(|&i| graph.nodes[i].kind == NodeKind::Vat)
+            .collect();
+        assert_eq!(vats.len(), 2);
+        assert!(graph
+            .edges
+            .iter()
+            .any(|e| (e.from, e.to, e.label.as_str()) == (vats[0], vats[1], "previous")));
+
+        // a metadata mirror recorded at a block no sector can be addressed for
+        let mut image = testimage::build_metadata(&Node::dir(vec![("a", Node::file(b"a"))]));
+        for lsn in [34, 50] {
+            let lvd = &mut image[lsn * 2048..(lsn + 1) * 2048];
+            lvd[490..494].copy_from_slice(&0xFFFF_FFF0u32.to_le_bytes());
+            retag(lvd);
+        }
+        let mut udf = UDF::new(Cursor::new(image))?;
+        let graph = udf.structure_graph()?;
+        let meta: Vec<_> = graph
+            .nodes
+            .iter()
+            .filter(|n| n.kind == NodeKind::MetadataFile)
+            .map(|n| (n.label.as_str(), n.lsn))
+            .collect();
+        assert_eq!(
+            meta,
+            [
+                ("metadata file", Some(testimage::PART_START)),
+                ("metadata mirror file", None)
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn formatting() -> Result<(), Box<dyn Error>> {
+        use format::human_size;
+        use std::time::{Duration, UNIX_EPOCH};
+        assert_eq!(human_size(0), "0 bytes");
+        assert_eq!(human_size(1023), "1023 bytes");
+        assert_eq!(human_size(1536), "1.5 KiB");
+        assert_eq!(human_size((1 << 20) - 1), "1.0 MiB");
+        assert_eq!(human_size(u64::MAX), "16.0 EiB");
+
+        let t: Timestamp = "2023-04-01T12:30:00.250000+02:00".parse()?;
+        assert_eq!(t.to_string(), "2023-04-01T12:30:00.250000+02:00");
+        let secs = 1680345000;
+        let expected = UNIX_EPOCH + Duration::from_millis(secs * 1000 + 250);
+        assert_eq!(t.to_system_time(), Some(expected));
+        assert_eq!(
+            Timestamp::from_system_time(expected).to_string(),
+            "2023-04-01T10:30:00.250000Z"
+        );
+
+        let mut t: Timestamp = "-0044-03-15T12:00:00.000000".parse()?;
+        assert_eq!(t.year, -44);
+        assert_eq!(t.to_string(), "-0044-03-15T12:00:00.000000");
+        let time = t.to_system_time().unwrap();
+        assert_eq!(
+            Timestamp::from_system_time(time).to_string(),
+            "-0044-03-15T12:00:00.000000Z"
+        );
+        // coordinated universal time, whatever the offset bits say
+        t.type_tz = 0x0078;
+        t.centisecond = 255;
+        assert_eq!(t.to_string(), "-0044-03-15T12:00:00.990000Z");
+        assert_eq!(t.to_system_time(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn parent_entries() -> Result<(), Box<dyn Error>> {
+        use testimage::Node;
+        init_logger();
+        let root = Node::dir(vec![(
+            "a",
+            Node::dir(vec![("b", Node::dir(vec![("f", Node::file(b"f"))]))]),
+        )]);
+        let image = testimage::build(&root);
+        let mut udf = UDF::new(Cursor::new(image.clone()))?;
+        let root = udf.get_root_dir()?;
+        let b = udf.find_icb(Path::new("/a/b"))?;
+        assert!(root.get_entries(&mut udf).iter().all(|e| !e.is_parent()));
+        assert_eq!(udf.dir_path(&b)?, "/a/b");
+        assert_eq!(udf.find_icb(Path::new("/a/b/../.."))?.loc, root.loc);
+
+        // the parent FID of the root directory points to the root directory itself
+        assert_eq!(root.parent(&mut udf)?.loc, root.loc);
+        assert_eq!(udf.dir_path(&root)?, "/");
+        let mut udf = UDF::options().parent_entry(true).open(Cursor::new(image))?;
+        let entries = root.get_entries(&mut udf);
+        assert_eq!(entries.len(), 2);
+        assert!(entries[0].is_parent() && entries[0].name.is_empty());
+        assert_eq!(entries[0].icb, root.loc);
+        let entries = b.get_entries(&mut udf);
+        assert_eq!(entries[0].icb, udf.find_icb(Path::new("/a"))?.loc);
+        // lookups and walks skip it
+        assert_eq!(udf.find_icb(Path::new("/a/b/.."))?.loc, entries[0].icb);
+        assert_eq!(udf.catalogue()?.entries.len(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn recognition_sequence() -> Result<(), Box<dyn Error>> {
+        use testimage::Node;
+        use vrs::VolumeStructure::*;
+        init_logger();
+        let mut udf = UDF::new(File::open("./tests/test.iso")?)?;
+        let vrs = udf.recognition_sequence()?;
+        assert_eq!(
+            vrs.descriptors,
+            [
+                (16, Iso9660(1)),
+                (17, Iso9660(255)),
+                (18, ExtendedAreaStart),
+                (19, Nsr(2)),
+                (20, ExtendedAreaEnd)
+            ]
+        );
+        assert_eq!(vrs.nsr_version(), Some(2));
+        assert!(vrs.is_bridge());
+
+        let root = Node::dir(vec![("a", Node::file(b"a"))]);
+        let mut image = testimage::build_with_sector_size(&root, 4096);
+        let vrs = vrs::read_vrs(&mut Cursor::new(&image), 0, 4096)?;
+        let sectors: Vec<_> = vrs.descriptors.iter().map(|(lsn, _)| *lsn).collect();
+        assert_eq!(sectors, [8, 9, 10]);
+        assert_eq!(vrs.nsr_version(), Some(3));
+        assert!(!vrs.is_bridge());
+
+        // an NSR descriptor outside of an extended area doesn't count
+        image[8 * 4096..9 * 4096].fill(0);
+        assert!(vrs::read_vrs(&mut Cursor::new(&image), 0, 4096)?
+            .descriptors
+            .is_empty());
+        image[8 * 4096 + 1..8 * 4096 + 6].copy_from_slice(b"CD001");
+        let vrs = vrs::read_vrs(&mut Cursor::new(&image), 0, 4096)?;
+        assert_eq!(vrs.descriptors.len(), 3);
+        assert!(!vrs.is_udf() && !vrs.is_bridge());
+        Ok(())
+    }
+
+    #[test]
+    fn unnamed_entries() -> Result<(), Box<dyn Error>> {
+        use testimage::Node;
+        init_logger();
+        let root = Node::dir(vec![
+            ("", Node::file(b"unnamed")),
+            ("a", Node::file(b"a")),
+            ("", Node::dir(vec![])),
+        ]);
+        let mut udf = UDF::options()
+            .parent_entry(true)
+            .open(Cursor::new(testimage::build(&root)))?;
+        let root = udf.get_root_dir()?;
+        let entries = root.get_entries(&mut udf);
+        let names: Vec<_> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["", "a"]);
+        assert!(entries[0].is_parent());
+        assert_eq!(root.get_fids(&mut udf).len(), 4);
+        let children = root.get_children(&mut udf);
+        assert_eq!(children.keys().collect::<Vec<_>>(), ["a"]);
+        assert_eq!(udf.catalogue()?.entries.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn bridge_detection() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let mut image = std::fs::read("./tests/test.iso")?;
+        let mut udf = UDF::new(Cursor::new(image.clone()))?;
+        let iso = udf.recognition_sequence()?.iso9660.unwrap();
+        assert_eq!((iso.lsn, iso.vol_ident.as_str()), (16, "TestISO"));
+        assert_eq!(iso.system_ident, "LINUX");
+        assert_eq!(iso.len(), image.len() as u64);
+        assert!(udf.bridge_mismatches()?.is_empty());
+
+        // an ISO 9660 volume of another disc, ending before the UDF partition
+        let pvd = &mut image[16 * 2048..17 * 2048];
+        pvd[40..47].copy_from_slice(b"OTHER  ");
+        pvd[80..84].copy_from_slice(&200u32.to_le_bytes());
+        let mut udf = UDF::new(Cursor::new(image.clone()))?;
+        assert_eq!(udf.bridge_mismatches()?.len(), 2);
+
+        // UDF identifiers are recorded as d-characters on the ISO 9660 side
+        let mut bridge = image.clone();
+        bridge[16 * 2048 + 80..16 * 2048 + 84]
+            .copy_from_slice(&(image.len() as u32 / 2048).to_le_bytes());
+        for lsn in [32, 48] {
+            let pvd = &mut bridge[lsn * 2048..(lsn + 1) * 2048];
+            pvd[24..56].fill(0);
+            pvd[24] = 8;
+            pvd[25..33].copy_from_slice(b"My Movie");
+            pvd[55] = 9;
+            retag(pvd);
+        }
+        for (iso_ident, mismatches) in [("MY_MOVIE", 0), ("my_movie", 0), ("MY_MOVIES", 1)] {
+            let pvd = &mut bridge[16 * 2048..17 * 2048];
+            pvd[40..72].fill(b' ');
+            pvd[40..40 + iso_ident.len()].copy_from_slice(iso_ident.as_bytes());
+            let mut udf = UDF::new(Cursor::new(bridge.clone()))?;
+            assert_eq!(udf.primary_vol_desc.vol_ident.to_string(), "My Movie");
+            assert_eq!(udf.bridge_mismatches()?.len(), mismatches, "{}", iso_ident);
+        }
+
+        // without the primary volume descriptor it is no bridge disc anymore
+        image[16 * 2048] = 2;
+        let mut udf = UDF::new(Cursor::new(image))?;
+        assert!(!udf.recognition_sequence()?.is_bridge());
+        assert!(udf.bridge_mismatches()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn name_collisions() -> Result<(), Box<dyn Error>> {
+        use testimage::Node;
+        init_logger();
+        assert_eq!(file::disambiguate("a.txt", 2), "a~2.txt");
+        assert_eq!(file::disambiguate(".profile", 3), ".profile~3");
+        let root = Node::dir(vec![
+            ("a.txt", Node::file(b"lower")),
+            ("A.txt", Node::file(b"upper")),
+            ("a.txt", Node::file(b"again")),
+            ("d", Node::dir(vec![("f", Node::file(b"f"))])),
+        ]);
+        let mut udf = UDF::new(Cursor::new(testimage::build(&root)))?;
+        let children = udf.get_root_dir()?.get_children(&mut udf);
+        let mut names: Vec<_> = children.keys().map(String::as_str).collect();
+        names.sort();
+        assert_eq!(names, ["A.txt", "a.txt", "a~2.txt", "d"]);
+        assert_eq!(children["a~2.txt"].read_data(&mut udf)?, b"again");
+
+        let snapshot = udf.tree_snapshot()?;
+        let dest = std::env::temp_dir().join(format!("libudf-collide-{}", std::process::id()));
+        udf.restore_tree(&snapshot, &dest)?;
+        let mut restored: Vec<_> = std::fs::read_dir(&dest)?
+            .map(|e| e.map(|e| e.file_name().to_string_lossy().into_owned()))
+            .collect::<Result<_, _>>()?;
+        let nested = std::fs::read(dest.join("d/f"));
+        std::fs::remove_dir_all(&dest)?;
+        restored.sort();
+        // on hosts ignoring case A.txt is restored as A~2.txt or the like instead
+        assert_eq!(restored.len(), 4);
+        assert!(
+            restored.contains(&"a~2.txt".to_string()) || restored.contains(&"a~3.txt".to_string())
+        );
+        assert_eq!(nested?, b"f");
+        Ok(())
+    }
+
+    #[test]
+    fn extended_file_entries() -> Result<(), Box<dyn Error>> {
+        use testimage::Node;
+        init_logger();
+        let content = b"recorded in an extended file entry";
+        let root = Node::dir(vec![
+            ("d", Node::dir(vec![("f.txt", Node::file(content))])),
+            ("g.txt", Node::file(b"plain")),
+        ]);
+        let mut image = testimage::build(&root);
+        let mut udf = UDF::new(Cursor::new(image.clone()))?;
+        for path in ["/d", "/d/f.txt"] {
+            let loc = udf.find_icb(Path::new(path))?.loc;
+            let lsn = udf.lb_to_sector(&loc)? as usize;
+            to_efe(&mut image[lsn * 2048..(lsn + 1) * 2048], None);
+        }
+
+        let mut udf = UDF::options().strict(true).open(Cursor::new(image))?;
+        let dir = udf.find_icb(Path::new("/d"))?;
+        assert!(matches!(dir.body, ICBBody::ExtendedFile(_)));
+        let icb = udf.find_icb(Path::new("/d/f.txt"))?;
+        let efe = icb.file_entry().unwrap();
+        assert_eq!(efe.info_len(), content.len() as u64);
+        assert_eq!(efe.obj_size(), Some(content.len() as u64));
+        assert_eq!(
+            efe.ctime().map(|t| t.to_string()),
+            Some(efe.mtime().to_string())
+        );
+        assert_eq!(efe.file_link_count(), 1);
+        assert!(efe.stream_dir_icb().is_none());
+        assert_eq!(icb.read_data(&mut udf)?, content);
+        assert!(udf.streams(Path::new("/d/f.txt"))?.is_empty());
+
+        let icb = udf.find_icb(Path::new("/g.txt"))?;
+        assert!(matches!(icb.body, ICBBody::File(_)));
+        let fe = icb.file_entry().unwrap();
+        assert!(fe.ctime().is_none() && fe.obj_size().is_none());
+        assert_eq!(icb.read_data(&mut udf)?, b"plain");
+        Ok(())
+    }
+
+    #[test]
+    fn extraction_policies() -> Result<(), Box<dyn Error>> {
+        use extract::{CollisionPolicy, ExtractAction, ExtractOptions};
+        use testimage::Node;
+        init_logger();
+        let root = Node::dir(vec![(
+            "d",
+            Node::dir(vec![
+                ("a.txt", Node::file(b"a")),
+                ("sub", Node::dir(vec![("b.txt", Node::file(b"b"))])),
+            ]),
+        )]);
+        let mut udf = UDF::new(Cursor::new(testimage::build(&root)))?;
+        let dest = std::env::temp_dir().join(format!("libudf-extract-{}", std::process::id()));
+        let extract = |udf: &mut UDF<_>, options: ExtractOptions| {
+            udf.extract_to(Path::new("/d"), &dest, &options)
+                .map(|entries| entries.into_iter().map(|e| (e.path, e.action)).collect())
+        };
+        let actions = |action| {
+            vec![
+                ("/d".to_string(), ExtractAction::Merged),
+                ("/d/a.txt".to_string(), action),
+                ("/d/sub".to_string(), ExtractAction::Merged),
+                ("/d/sub/b.txt".to_string(), action),
+            ]
+        };
+
+        let dry_run: Vec<_> = extract(&mut udf, ExtractOptions::default().dry_run(true))?;
+        assert_eq!(dry_run[1], ("/d/a.txt".to_string(), ExtractAction::Created));
+        assert!(!dest.exists());
+        let created: Vec<_> = extract(&mut udf, ExtractOptions::default())?;
+        assert_eq!(created, dry_run);
+        assert_eq!(std::fs::read(dest.join("sub/b.txt"))?, b"b");
+
+        let result = extract(&mut udf, ExtractOptions::default());
+        let result = result.map_err(|e| e.downcast::<std::io::Error>().unwrap().kind());
+        assert_eq!(result, Err(std::io::ErrorKind::AlreadyExists));
+        let skip = ExtractOptions::default().policy(CollisionPolicy::Skip);
+        assert_eq!(extract(&mut udf, skip)?, actions(ExtractAction::Skipped));
+        std::fs::write(dest.join("a.txt"), b"changed")?;
+        let overwrite = ExtractOptions::default().policy(CollisionPolicy::Overwrite);
+        assert_eq!(
+            extract(&mut udf, overwrite)?,
+            actions(ExtractAction::Overwritten)
+        );
+        assert_eq!(std::fs::read(dest.join("a.txt"))?, b"a");
+        let rename = ExtractOptions::default().policy(CollisionPolicy::Rename);
+        let dry_run = rename.clone().dry_run(true);
+        assert_eq!(extract(&mut udf, dry_run)?, actions(ExtractAction::Renamed));
+        assert!(!dest.join("a~2.txt").exists());
+        assert_eq!(extract(&mut udf, rename)?, actions(ExtractAction::Renamed));
+        let renamed = std::fs::read(dest.join("a~2.txt"));
+        std::fs::remove_dir_all(&dest)?;
+        assert_eq!(renamed?, b"a");
+        Ok(())
+    }
+
+    #[test]
+    fn resumable_extraction() -> Result<(), Box<dyn Error>> {
+        use extract::{ExtractAction, ExtractOptions};
+        use faults::FaultyReader;
+        use testimage::Node;
+        init_logger();
+        let big: Vec<u8> = (0..3 * 2048).map(|i| (i / 7) as u8).collect();
+        let root = Node::dir(vec![
+            ("a.txt", Node::file(b"a")),
+            ("big.bin", Node::file(&big)),
+            ("c.txt", Node::file(b"c")),
+        ]);
+        let image = testimage::build(&root);
+        let mut udf = UDF::new(Cursor::new(image.clone()))?;
+        let icb = udf.find_icb(Path::new("/big.bin"))?;
+        let ad = &icb.get_alloc_descs()[0];
+        let data = udf.lb_to_sector(&ad.lb_addr(icb.loc.part_ref_nr))?;
+
+        let dir = std::env::temp_dir().join(format!("libudf-resume-{}", std::process::id()));
+        let dest = dir.join("out");
+        let options = ExtractOptions::default()
+            .state_file(dir.join("state"))
+            .checkpoint_interval(2048);
+        std::fs::create_dir_all(&dir)?;
+        // the third block of big.bin can't be read the first time
+        let faulty = FaultyReader::new(Cursor::new(image.clone())).bad_sector(data + 2);
+        let mut udf = UDF::new(faulty)?;
+        assert!(udf.extract_to(Path::new("/"), &dest, &options).is_err());
+        let state = std::fs::read_to_string(dir.join("state"))?;
+        assert!(state
+            .lines()
+            .any(|l| l.starts_with("part\t4096\t/big.bin\t")));
+
+        let mut udf = UDF::new(Cursor::new(image))?;
+        let dry_run = options.clone().dry_run(true);
+        let actions: Vec<_> = udf
+            .extract_to(Path::new("/"), &dest, &dry_run)?
+            .into_iter()
+            .map(|e| (e.path, e.action))
+            .collect();
+        let expected = [
+            ("/", ExtractAction::AlreadyExtracted),
+            ("/a.txt", ExtractAction::AlreadyExtracted),
+            ("/big.bin", ExtractAction::Resumed),
+            ("/c.txt", ExtractAction::Created),
+        ];
+        let expected: Vec<_> = expected.map(|(p, a)| (p.to_string(), a)).into();
+        assert_eq!(actions, expected);
+        let entries = udf.extract_to(Path::new("/"), &dest, &options)?;
+        assert_eq!(entries[2].action, ExtractAction::Resumed);
+        let extracted = std::fs::read(dest.join("big.bin"));
+        let again = udf.extract_to(Path::new("/"), &dest, &options)?;
+        std::fs::remove_dir_all(&dir)?;
+        assert_eq!(extracted?, big);
+        assert!(again
+            .iter()
+            .all(|e| e.action == ExtractAction::AlreadyExtracted));
+        Ok(())
+    }
+
+    #[test]
+    fn sparable_partition() -> Result<(), Box<dyn Error>> {
+        use testimage::Node;
+        init_logger();
+        let content: Vec<u8> = (0..6 * 2048).map(|i| (i / 2048 + i % 13) as u8).collect();
+        let root = Node::dir(vec![("f.bin", Node::file(&content))]);
+        let mut udf = UDF::new(Cursor::new(testimage::build(&root)))?;
+        let icb = udf.find_icb(Path::new("/f.bin"))?;
+        let lbn = icb.get_alloc_descs()[0].lb_addr(0).lbn;
+        // a packet of two blocks in the middle of the file
+        let packet = (lbn + 2) & !1;
+
+        let image = testimage::build_sparable(&root, 2, &[packet]);
+        let mut udf = UDF::new(Cursor::new(image.clone()))?;
+        let map = &udf.partition_maps()[0];
+        assert!(matches!(map.kind, partition::PartitionKind::Sparable(_)));
+        let table = map.sparing_table.as_ref().unwrap();
+        assert_eq!(table.entries.len(), 1);
+        let spare = table.entries[0].mapped;
+        let loc = LBAddr {
+            lbn: packet + 1,
+            part_ref_nr: 0,
+        };
+        assert_eq!(udf.lb_to_sector(&loc)?, spare + 1);
+        let icb = udf.find_icb(Path::new("/f.bin"))?;
+        assert_eq!(icb.read_data(&mut udf)?, content);
+        let mut buf = vec![0; 3000];
+        let pos = (packet - lbn) as u64 * 2048 - 1000;
+        assert_eq!(icb.read_at(&mut udf, pos, &mut buf)?, buf.len());
+        assert_eq!(buf, content[pos as usize..pos as usize + buf.len()]);
+        let mut read = Vec::new();
+        udf.open(Path::new("/f.bin"))?.read_to_end(&mut read)?;
+        assert_eq!(read, content);
+
+        // without a sparing table the relocated packet reads as zeros
+        let mut image = image;
+        image[80 * 2048..81 * 2048].fill(0);
+        image[96 * 2048..97 * 2048].fill(0);
+        assert!(UDF::new(Cursor::new(image.clone())).is_err());
+        let mut udf = UDF::options().strict(false).open(Cursor::new(image))?;
+        let icb = udf.find_icb(Path::new("/f.bin"))?;
+        assert_ne!(icb.read_data(&mut udf)?, content);
+        Ok(())
+    }
+
+    #[test]
+    fn read_rate_limit() -> Result<(), Box<dyn Error>> {
+        use std::time::{Duration, Instant};
+        use testimage::Node;
+        init_logger();
+        let data = vec![0x5A; 96 * 1024];
+        let root = Node::dir(vec![("data.bin", Node::file(&data))]);
+        let image = testimage::build(&root);
+        let options = UDF::options().max_read_rate(1 << 30);
+        let mut udf = options.open(Cursor::new(image))?;
+        // after an idle period a second worth of reads may be issued at once, the rest of the
+        // file is held back to the rate
+        udf.set_max_read_rate(Some(64 * 1024));
+        let start = Instant::now();
+        let mut read = Vec::new();
+        udf.open(Path::new("/data.bin"))?.read_to_end(&mut read)?;
+        assert_eq!(read, data);
+        assert!(start.elapsed() >= Duration::from_millis(400));
+
+        udf.set_max_read_rate(None);
+        let start = Instant::now();
+        udf.open(Path::new("/data.bin"))?.read_to_end(&mut read)?;
+        assert!(start.elapsed() < Duration::from_millis(400));
+        Ok(())
+    }
+
+    #[test]
+    fn udf150_vat() -> Result<(), Box<dyn Error>> {
+        use testimage::Node;
+        init_logger();
+        let gen0 = Node::dir(vec![("a.txt", Node::file(b"first"))]);
+        let gen1 = Node::dir(vec![("a.txt", Node::file(b"second"))]);
+        let img = testimage::build_vat_with_revision(&[gen0, gen1], 0x0150);
+        let mut udf = UDF::new(Cursor::new(img))?;
+        let a = udf.find_icb(Path::new("/a.txt"))?;
+        assert_eq!(a.read_data(&mut udf)?, b"second");
+
+        let history = udf.vat_history()?;
+        assert_eq!(history.len(), 2);
+        assert!(history.iter().all(|vat| vat.header.is_none()));
+        udf.use_vat(history[1].clone());
+        let a = udf.find_icb(Path::new("/a.txt"))?;
+        assert_eq!(a.read_data(&mut udf)?, b"first");
+        Ok(())
+    }
+
+    #[test]
+    fn metadata_mirror_fallback() -> Result<(), Box<dyn Error>> {
+        use testimage::{Node, META_BLOCKS, PART_START};
+        init_logger();
+        let root = Node::dir(vec![("a.txt", Node::file(b"metadata"))]);
+        let image = testimage::build_metadata(&root);
+        let read_a = |image: &[u8], options: UdfOptions| -> Result<Vec<u8>, Box<dyn Error>> {
+            let mut udf = UDF::new_with_options(Cursor::new(image.to_vec()), options)?;
+            let a = udf.find_icb(Path::new("/a.txt"))?;
+            a.read_data(&mut udf)
+        };
+        assert_eq!(read_a(&image, UdfOptions::default())?, b"metadata");
+
+        let block = |lbn: u32| (PART_START + lbn) as usize * 2048;
+        // the ICB of the metadata file fails its tag check
+        let mut damaged = image.clone();
+        damaged[block(0) + 4] ^= 0xFF;
+        assert_eq!(read_a(&damaged, UdfOptions::default())?, b"metadata");
+        // the first block of the metadata file can't be read
+        let faulty =
+            faults::FaultyReader::new(Cursor::new(image.clone())).bad_sector(PART_START + 2);
+        let mut udf = UDF::new(faulty)?;
+        let a = udf.find_icb(Path::new("/a.txt"))?;
+        assert_eq!(a.read_data(&mut udf)?, b"metadata");
+        // a copy asked for explicitly is read without falling back to the other one
+        let copy = |copy| UdfOptions {
+            metadata_copy: Some(copy),
+            ..Default::default()
+        };
+        assert_eq!(read_a(&damaged, copy(StructureCopy::Backup))?, b"metadata");
+        assert!(read_a(&damaged, copy(StructureCopy::Primary)).is_err());
+        let mut damaged = image.clone();
+        damaged[block(1) + 4] ^= 0xFF;
+        damaged[block(2 + META_BLOCKS)..block(2 + 2 * META_BLOCKS)].fill(0);
+        assert!(read_a(&damaged, copy(StructureCopy::Backup)).is_err());
+        assert_eq!(read_a(&damaged, UdfOptions::default())?, b"metadata");
+
+        damaged[block(0) + 4] ^= 0xFF;
+        assert!(UDF::new(Cursor::new(damaged)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn read_priorities() -> Result<(), Box<dyn Error>> {
+        use backend::{PositionedReader, ReadAt, ReadPriority, ReadScheduler};
+        use std::sync::{mpsc, Mutex};
+        use std::time::Duration;
+        init_logger();
+
+        /// records the positions read, holding up the first read until it is released
+        struct Gated {
+            order: Mutex<Vec<u64>>,
+            gate: Mutex<Option<mpsc::Receiver<()>>>,
+        }
+        impl ReadAt for Gated {
+            fn read_at(&self, pos: u64, buf: &mut [u8]) -> std::io::Result<usize> {
+                let gate = self.gate.lock().unwrap().take();
+                if let Some(gate) = gate {
+                    gate.recv().unwrap();
+                }
+                self.order.lock().unwrap().push(pos);
+                buf.fill(0);
+                Ok(buf.len())
+            }
+
+            fn size(&self) -> std::io::Result<u64> {
+                Ok(1 << 20)
+            }
+        }
+
+        let (release, gate) = mpsc::channel();
+        let scheduler = ReadScheduler::new(Gated {
+            order: Mutex::new(Vec::new()),
+            gate: Mutex::new(Some(gate)),
+        });
+        let mut threads = Vec::new();
+        // the background read queues up first, but the foreground read arriving later is
+        // served before it once the backend is free
+        for (pos, priority) in [
+            (0, ReadPriority::Foreground),
+            (1, ReadPriority::Background),
+            (2, ReadPriority::Foreground),
+        ] {
+            let reader = scheduler.reader(priority);
+            threads.push(std::thread::spawn(move || {
+                reader.read_at(pos, &mut [0; 16]).unwrap();
+            }));
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        release.send(())?;
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(*scheduler.get_ref().order.lock().unwrap(), [0, 2, 1]);
+
+        let scheduler = ReadScheduler::new(std::fs::read("./tests/test.iso")?);
+        let mut udf = UDF::new(PositionedReader::new(
+            scheduler.reader(ReadPriority::Background),
+        ))?;
+        let icb = udf.find_icb(Path::new("/LICENSE.md"))?;
+        assert_eq!(icb.read_data(&mut udf)?, include_bytes!("../LICENSE.md"));
+        Ok(())
+    }
+
+    #[test]
+    fn aligned_reads() -> Result<(), Box<dyn Error>> {
+        use faults::FaultyReader;
+        use testimage::Node;
+        init_logger();
+        let data: Vec<u8> = (0..40 * 2048).map(|i| (i / 2048) as u8).collect();
+        let root = Node::dir(vec![
+            ("data.bin", Node::file(&data)),
+            ("z.bin", Node::file(b"z")),
+        ]);
+        let image = testimage::build(&root);
+        let read_in_sectors = |udf: &mut UDF<FaultyReader<Cursor<Vec<u8>>>>| {
+            let mut file = udf.open(Path::new("/data.bin")).unwrap();
+            let mut read: Vec<u8> = Vec::new();
+            let mut buf = [0; 2048];
+            while let Ok(n @ 1..) = file.read(&mut buf) {
+                read.extend(&buf[..n]);
+            }
+            read
+        };
+
+        let mut udf = UDF::new(FaultyReader::new(Cursor::new(image.clone())))?;
+        let reads = udf.get_ref().reads();
+        assert_eq!(read_in_sectors(&mut udf), data);
+        let unaligned = udf.get_ref().reads() - reads;
+
+        let options = UDF::options().read_alignment(ECC_BLOCK_SIZE);
+        let mut udf = options
+            .clone()
+            .open(FaultyReader::new(Cursor::new(image.clone())))?;
+        let reads = udf.get_ref().reads();
+        assert_eq!(read_in_sectors(&mut udf), data);
+        // 40 sectors span at most 4 ECC blocks
+        let aligned = udf.get_ref().reads() - reads;
+        assert!(aligned < unaligned / 4);
+
+        // a bad sector sharing an ECC block with the file only fails reads including it
+        let icb = udf.find_icb(Path::new("/data.bin"))?;
+        let ad = &icb.get_alloc_descs()[0];
+        let last = udf.lb_to_sector(&ad.lb_addr(icb.loc.part_ref_nr))? + 39;
+        let icb = udf.find_icb(Path::new("/z.bin"))?;
+        let ad = &icb.get_alloc_descs()[0];
+        let z = udf.lb_to_sector(&ad.lb_addr(icb.loc.part_ref_nr))?;
+        assert_eq!(last / 16, z / 16);
+        let faulty = FaultyReader::new(Cursor::new(image)).bad_sector(z);
+        let mut udf = options.open(faulty)?;
+        assert_eq!(read_in_sectors(&mut udf), data);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "writer")]
+    fn writer_preflight() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let image = std::fs::read("./tests/test.iso")?;
+        let mut udf = UDF::new(Cursor::new(image.clone()))?;
+        assert!(udf.preflight()?.is_empty());
+        let lvid_lsn = udf.logical_vol_desc.integr_seq_ext.loc as usize;
+        let reserve = u32::from_le_bytes(image[256 * 2048 + 28..][..4].try_into()?) as usize;
+
+        // the volume was left open and the reserve PVD differs
+        let mut damaged = image;
+        let lvid = &mut damaged[lvid_lsn * 2048..(lvid_lsn + 1) * 2048];
+        lvid[28..32].copy_from_slice(&0u32.to_le_bytes());
+        retag(lvid);
+        damaged[reserve * 2048 + 100] ^= 0xFF;
+        let mut udf = UDF::new(Cursor::new(damaged))?;
+        let problems = udf.preflight()?;
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        let mtime = udf.volume_info().record_time;
+        let data = b"forced";
+        assert!(udf
+            .add_file(Path::new("/a"), &mut &data[..], 6, mtime.clone(), false)
+            .is_err());
+        assert!(udf.find_icb(Path::new("/a")).is_err());
+        udf.add_file(Path::new("/a"), &mut &data[..], 6, mtime, true)?;
+        let a = udf.find_icb(Path::new("/a"))?;
+        assert_eq!(a.read_data(&mut udf)?, data);
+        Ok(())
+    }
+
+    #[test]
+    fn integrity_space_tables() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let mut image = std::fs::read("./tests/test.iso")?;
+        let mut udf = UDF::new(Cursor::new(image.clone()))?;
+        let lvid = udf.integrity_sequence()?.current.unwrap();
+        assert_eq!(lvid.integ_type, IntegrityType::CLOSE);
+        assert_eq!(lvid.free_blocks(0), Some(0));
+        assert_eq!(lvid.partition_blocks(0), Some(13));
+        assert_eq!(lvid.free_blocks(1), None);
+
+        // an unknown amount of free space is recorded as all ones
+        let lsn = udf.logical_vol_desc.integr_seq_ext.loc as usize;
+        let lvid = &mut image[lsn * 2048..(lsn + 1) * 2048];
+        lvid[80..84].copy_from_slice(&u32::MAX.to_le_bytes());
+        retag(lvid);
+        let mut udf = UDF::new(Cursor::new(image))?;
+        let lvid = udf.integrity_sequence()?.current.unwrap();
+        assert_eq!(lvid.free_blocks(0), None);
+        assert_eq!(lvid.partition_blocks(0), Some(13));
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "writer")]
+    fn undo_journal() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let image = std::fs::read("./tests/test.iso")?;
+        let dir = std::env::temp_dir().join(format!("libudf-undo-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let journal = dir.join("test.iso.undo");
+        let mut udf = UDF::new(Cursor::new(image.clone()))?;
+        assert!(udf.rollback().is_err());
+        udf.start_undo_journal(&journal)?;
+        assert!(udf.start_undo_journal(&journal).is_err());
+        let mtime = udf.volume_info().record_time;
+        let data = vec![0x5A; 5000];
+        udf.add_file(Path::new("/big"), &mut &data[..], 5000, mtime, false)?;
+        assert_ne!(udf.get_ref().get_ref(), &image);
+        let len = udf.rollback()?;
+        assert_eq!(len, image.len() as u64);
+        assert!(udf.find_icb(Path::new("/big")).is_err());
+        let mut restored = udf.get_ref().get_ref().clone();
+        restored.truncate(len as usize);
+        assert_eq!(restored, image);
+
+        // a record cut short by a crash is ignored
+        let record = std::fs::read(&journal)?;
+        let cut = &record[..record.len() - 100];
+        let mut modified = Cursor::new(vec![0xFF; image.len()]);
+        assert_eq!(undo::rollback(&mut modified, &mut &cut[..])?, len);
+        // the header is followed by the offset of the first block recorded
+        let first = u64::from_le_bytes(record[25..33].try_into()?) as usize;
+        assert!(modified.get_ref()[first..first + 2048] == image[first..first + 2048]);
+        assert!(undo::rollback(&mut modified, &mut &b"libudf"[..]).is_err());
+
+        // the image file is truncated to its original length
+        let file = dir.join("test.iso");
+        std::fs::write(&file, [&restored[..], &[1; 4096]].concat())?;
+        undo::rollback_file(&file, &journal)?;
+        assert!(std::fs::read(&file)? == image);
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "writer")]
+    fn modify_copy_swap() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let dir = std::env::temp_dir().join(format!("libudf-swap-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join("test.iso");
+        std::fs::copy("./tests/test.iso", &path)?;
+        let original = std::fs::read(&path)?;
+
+        // a failed modification leaves neither changes nor the copy behind
+        let result = swap::modify_copy(&path, UdfOptions::default(), |udf| {
+            let mtime = udf.volume_info().record_time;
+            udf.add_file(Path::new("/a"), &mut &b"a"[..], 1, mtime, false)?;
+            Err::<(), _>("failed".into())
+        });
+        assert!(result.is_err());
+        assert!(std::fs::read(&path)? == original);
+        assert_eq!(std::fs::read_dir(&dir)?.count(), 1);
+
+        let (report, _) = swap::modify_copy(&path, UdfOptions::default(), |udf| {
+            let mtime = udf.volume_info().record_time;
+            udf.add_file(Path::new("/a"), &mut &b"a"[..], 1, mtime, false)
+        })?;
+        assert_eq!(report.files, 1);
+        assert_eq!(std::fs::read_dir(&dir)?.count(), 1);
+        let mut udf = UDF::new(File::open(&path)?)?;
+        let a = udf.find_icb(Path::new("/a"))?;
+        assert_eq!(a.read_data(&mut udf)?, b"a");
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn file_slack() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let mut image = std::fs::read("./tests/test.iso")?;
+        let mut udf = UDF::new(Cursor::new(image.clone()))?;
+        let license = udf.find_icb(Path::new("/LICENSE.md"))?;
+        let info_len = license.file_entry().unwrap().info_len() as usize;
+        let slack = license.read_slack(&mut udf)?;
+        assert_eq!(slack.len(), 2048 - info_len % 2048);
+        assert!(slack.iter().all(|b| *b == 0));
+
+        // the data of LICENSE.md is recorded in block 11 of the partition
+        let end = 268 * 2048 + info_len % 2048;
+        image[end..end + 7].copy_from_slice(b"deleted");
+        let mut udf = UDF::new(Cursor::new(image.clone()))?;
+        let slack = license.read_slack(&mut udf)?;
+        assert_eq!(&slack[..7], b"deleted");
+        assert_eq!(license.read_data(&mut udf)?.len(), info_len);
+
+        // extents of 100 and 1960 bytes, the first not block-aligned, end short of the data
+        let fe = &mut image[261 * 2048..262 * 2048];
+        let crc_len = u16::from_le_bytes([fe[10], fe[11]]) + 8;
+        fe[10..12].copy_from_slice(&crc_len.to_le_bytes());
+        fe[56..64].copy_from_slice(&4000u64.to_le_bytes());
+        fe[172..176].copy_from_slice(&16u32.to_le_bytes());
+        fe[428..432].copy_from_slice(&100u32.to_le_bytes());
+        fe[432..436].copy_from_slice(&11u32.to_le_bytes());
+        fe[436..440].copy_from_slice(&1960u32.to_le_bytes());
+        fe[440..444].copy_from_slice(&12u32.to_le_bytes());
+        retag(fe);
+        let mut udf = UDF::new(Cursor::new(image))?;
+        let license = udf.find_icb(Path::new("/LICENSE.md"))?;
+        assert_eq!(license.get_alloc_descs().len(), 2);
+        assert!(license.read_slack(&mut udf)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn iuvd_lv_info() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let mut image = std::fs::read("./tests/test.iso")?;
+        let mut udf = UDF::new(Cursor::new(image.clone()))?;
+        let iuvds = udf.implementation_use_descs()?;
+        assert_eq!(iuvds.len(), 1);
+        let info = udf.lv_info()?.unwrap();
+        assert_eq!(info.lv_ident.to_string(), "TestISO");
+        assert_eq!(info.owner_name.to_string(), "");
+        assert_eq!(info.impl_id.ident_str(), "*mkisofs");
+
+        // the IUVD of the main VDS is recorded in sector 33
+        let iuvd = &mut image[33 * 2048..34 * 2048];
+        let owner = &mut iuvd[244..280];
+        owner[..6].copy_from_slice(b"\x08Alice");
+        owner[35] = 6;
+        iuvd[316..320].copy_from_slice(b"\x08a@b");
+        iuvd[351] = 4;
+        retag(iuvd);
+        let mut udf = UDF::new(Cursor::new(image.clone()))?;
+        let info = udf.lv_info()?.unwrap();
+        assert_eq!(info.owner_name.to_string(), "Alice");
+        assert_eq!(info.organization.to_string(), "");
+        assert_eq!(info.contact_info.to_string(), "a@b");
+
+        // descriptors of other implementations aren't decoded
+        let iuvd = &mut image[33 * 2048..34 * 2048];
+        iuvd[21..33].copy_from_slice(b"*Other Impl\0");
+        retag(iuvd);
+        let mut udf = UDF::new(Cursor::new(image))?;
+        assert_eq!(udf.implementation_use_descs()?.len(), 1);
+        assert!(udf.lv_info()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "writer")]
+    fn scrub_slack_and_free_blocks() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let mut image = std::fs::read("./tests/test.iso")?;
+        let mut udf = UDF::new(Cursor::new(image.clone()))?;
+        let license = udf.find_icb(Path::new("/LICENSE.md"))?;
+        let data = license.read_data(&mut udf)?;
+        let slack_end = 268 * 2048 + data.len() % 2048;
+        image[slack_end..slack_end + 7].copy_from_slice(b"deleted");
+        // blocks 6 and 8 of the partition aren't in use
+        image[263 * 2048..263 * 2048 + 8].copy_from_slice(b"%PDF-1.4");
+        image[265 * 2048..265 * 2048 + 8].copy_from_slice(b"%PDF-1.4");
+
+        // without a space bitmap nothing tells which blocks are free
+        let mut udf = UDF::new(Cursor::new(image.clone()))?;
+        let report = udf.scrub(false)?;
+        assert!(report.slack_bytes >= (2048 - data.len() % 2048) as u64);
+        assert_eq!(report.unallocated_bytes, 0);
+        assert!(license.read_slack(&mut udf)?.iter().all(|&b| b == 0));
+        assert_eq!(license.read_data(&mut udf)?, data);
+        let scrubbed = udf.get_ref().get_ref();
+        assert_eq!(scrubbed[263 * 2048..][..8], *b"%PDF-1.4");
+        assert_eq!(scrubbed[..257 * 2048], image[..257 * 2048]);
+        assert_eq!(udf.scrub(false)?, report);
+
+        // the bitmap marks 7 to 12 as free, of which only 11 is in use, and is only added to
+        // the main VDS
+        add_space_bitmap(&mut image);
+        let mut udf = UDF::new(Cursor::new(image.clone()))?;
+        assert!(udf.scrub(false).is_err());
+        let report = udf.scrub(true)?;
+        assert_eq!(report.unallocated_bytes, 5 * 2048);
+        let scrubbed = udf.get_ref().get_ref();
+        assert_eq!(scrubbed[263 * 2048..][..8], *b"%PDF-1.4");
+        assert!(scrubbed[264 * 2048..268 * 2048].iter().all(|&b| b == 0));
+        assert_eq!(license.read_data(&mut udf)?, data);
+        Ok(())
+    }
+
+    #[test]
+    fn boot_descriptor() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let mut image = std::fs::read("./tests/test.iso")?;
+        let mut udf = UDF::new(Cursor::new(image.clone()))?;
+        assert!(udf.recognition_sequence()?.boot.is_empty());
+
+        // a boot descriptor in place of TEA01, which moves to the next sector, pointing at
+        // the data of LICENSE.md
+        image.copy_within(20 * 2048..21 * 2048, 21 * 2048);
+        let bd = &mut image[20 * 2048..21 * 2048];
+        bd.fill(0);
+        bd[1..7].copy_from_slice(b"BOOT2\x01");
+        bd[9..13].copy_from_slice(b"*x86");
+        bd[72..76].copy_from_slice(&268u32.to_le_bytes());
+        bd[76..80].copy_from_slice(&16u32.to_le_bytes());
+        bd[108] = 1;
+        let mut udf = UDF::new(Cursor::new(image.clone()))?;
+        let vrs = udf.recognition_sequence()?;
+        assert_eq!(vrs.nsr_version(), Some(2));
+        assert_eq!(vrs.descriptors[4], (20, vrs::VolumeStructure::Boot));
+        assert_eq!(vrs.boot.len(), 1);
+        let bd = &vrs.boot[0];
+        assert_eq!(bd.arch.ident_str(), "*x86");
+        assert_eq!((bd.boot_ext_loc, bd.boot_ext_len), (268, 16));
+        assert!(bd.erase());
+        assert_eq!(udf.read_boot_extent(bd)?, &image[268 * 2048..][..16]);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "writer")]
+    fn clone_image() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let source = std::env::temp_dir().join(format!("libudf-clone-{}", std::process::id()));
+        std::fs::create_dir_all(source.join("AACS/DUPLICATE"))?;
+        std::fs::create_dir_all(source.join("BDMV"))?;
+        std::fs::write(source.join("AACS/Unit_Key_RO.inf"), vec![1; 3000])?;
+        std::fs::write(source.join("BDMV/index.bdmv"), vec![2; 5000])?;
+        std::fs::write(source.join("Thumbs.db"), b"junk")?;
+        let mut image = Cursor::new(Vec::new());
+        let options = writer::CreateOptions {
+            revision: 0x0200,
+            label: "MOVIE".to_string(),
+        };
+        let report = writer::create_image(&mut image, &source, &options);
+        std::fs::remove_dir_all(&source)?;
+        report?;
+
+        let mut udf = UDF::new(Cursor::new(image.into_inner()))?;
+        let options = udf.clone_options();
+        assert_eq!(
+            (options.revision, options.label.as_str()),
+            (0x0200, "MOVIE")
+        );
+        let mut clone = Cursor::new(Vec::new());
+        let report = udf.clone_image(&mut clone, &["/AACS/", "Thumbs.db", "/missing"], &options)?;
+        assert_eq!(
+            (report.files, report.directories, report.bytes),
+            (1, 2, 5000)
+        );
+
+        let options = UdfOptions {
+            strict: true,
+            ..Default::default()
+        };
+        let mut clone = UDF::new_with_options(Cursor::new(clone.into_inner()), options)?;
+        assert_eq!(clone.udf_revision(), 0x0200);
+        assert_eq!(clone.volume_info().vol_ident, "MOVIE");
+        let paths: Vec<String> = clone
+            .catalogue()?
+            .entries
+            .into_iter()
+            .map(|e| e.path)
+            .collect();
+        assert_eq!(paths, ["/", "/BDMV", "/BDMV/index.bdmv"]);
+        let index = clone.find_icb(Path::new("/BDMV/index.bdmv"))?;
+        assert_eq!(index.read_data(&mut clone)?, vec![2; 5000]);
+
+        // the file of test.iso
+        let mut udf = UDF::new(File::open("./tests/test.iso")?)?;
+        let options = udf.clone_options();
+        assert_eq!(
+            (options.revision, options.label.as_str()),
+            (0x0102, "TestISO")
+        );
+        let mut clone = Cursor::new(Vec::new());
+        udf.clone_image(&mut clone, &[], &options)?;
+        let mut clone = UDF::new(Cursor::new(clone.into_inner()))?;
+        let original = udf
+            .find_icb(Path::new("/LICENSE.md"))?
+            .read_data(&mut udf)?;
+        let copy = clone.find_icb(Path::new("/LICENSE.md"))?;
+        assert_eq!(copy.read_data(&mut clone)?, original);
+        Ok(())
+    }
+
+    #[test]
+    fn follow_vds_pointers() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let mut image = std::fs::read("./tests/test.iso")?;
+        // the logical volume, unallocated space and terminating descriptors of both sequences
+        // move to sectors 80 and 96, a pointer takes the place of the first
+        for (from, to) in [(35, 80), (51, 96)] {
+            for i in 0..3 {
+                let mut desc = image[(from + i) * 2048..][..2048].to_vec();
+                desc[12..16].copy_from_slice(&((to + i) as u32).to_le_bytes());
+                retag(&mut desc);
+                image[(to + i) * 2048..][..2048].copy_from_slice(&desc);
+            }
+            image[from * 2048..(from + 3) * 2048].fill(0);
+            let vd = &mut image[from * 2048..][..512];
+            vd[16..20].copy_from_slice(&7u32.to_le_bytes());
+            vd[20..24].copy_from_slice(&(3 * 2048u32).to_le_bytes());
+            vd[24..28].copy_from_slice(&(to as u32).to_le_bytes());
+            write_tag(vd, 3, 2, from as u32, 512);
+        }
+        let options = UdfOptions {
+            strict: true,
+            ..Default::default()
+        };
+        let mut udf = UDF::new_with_options(Cursor::new(image.clone()), options.clone())?;
+        assert_eq!(udf.logical_vol_desc.lvid.to_string(), "TestISO");
+        let license = udf.find_icb(Path::new("/LICENSE.md"))?;
+        assert_eq!(license.read_data(&mut udf)?.len(), 1069);
+        let vds = read_vds(&mut Cursor::new(&image), 32, 16 * 2048, &options)?;
+        let locs: Vec<LSN> = vds.extents.iter().map(|ext| ext.loc).collect();
+        assert_eq!(locs, [32, 80]);
+
+        // a pointer leading back into the sequence
+        let vd = image[35 * 2048..][..2048].to_vec();
+        let looped = &mut image[82 * 2048..][..2048];
+        looped.copy_from_slice(&vd);
+        looped[24..28].copy_from_slice(&32u32.to_le_bytes());
+        write_tag(looped, 3, 2, 82, 512);
+        assert!(read_vds(&mut Cursor::new(&image), 32, 16 * 2048, &options).is_err());
+        let lenient = UdfOptions {
+            strict: false,
+            ..Default::default()
+        };
+        let vds = read_vds(&mut Cursor::new(&image), 32, 16 * 2048, &lenient)?;
+        assert_eq!(vds.extents.len(), 2);
+        assert!(vds.lvd.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn strict_damaged_fid() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let mut image = std::fs::read("./tests/test.iso")?;
+        // a byte of the name of the FID of LICENSE.md in the root directory
+        image[260 * 2048 + 40 + 40] ^= 0xff;
+        let mut udf = UDF::new(Cursor::new(image.clone()))?;
+        let root = udf.get_root_dir()?;
+        assert!(root.read_entries(&mut udf).is_err());
+        assert!(root.read_entries(&mut udf).is_err());
+        let options = UdfOptions {
+            strict: false,
+            ..Default::default()
+        };
+        let mut udf = UDF::new_with_options(Cursor::new(image), options)?;
+        let root = udf.get_root_dir()?;
+        assert_eq!(root.read_entries(&mut udf)?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn wide_directory() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let image = testimage::build_wide_dir(10_000);
+        let mut udf = UDF::new(Cursor::new(image))?;
+        let root = udf.get_root_dir()?;
+        let entries = root.read_entries(&mut udf)?;
+        assert_eq!(entries.len(), 10_000);
+        assert_eq!(entries[9_999].name, "file009999");
+        udf.invalidate_all();
+        let last = udf.find_icb(Path::new("/file009999"))?;
+        assert_eq!(last.loc, entries[0].icb);
+        assert!(udf.find_icb(Path::new("/file010000")).is_err());
+        Ok(())
+    }
+
+    /// times listing and looking up entries of a directory of 100k entries, run with
+    /// `cargo test --release -- --ignored --nocapture bench_wide_directory`
+    #[test]
+    #[ignore]
+    fn bench_wide_directory() -> Result<(), Box<dyn Error>> {
+        type Image = UDF<Cursor<Vec<u8>>>;
+        /// best time of 5 runs of `f`, each starting with empty caches
+        fn time(
+            udf: &mut Image,
+            name: &str,
+            mut f: impl FnMut(&mut Image) -> Result<(), Box<dyn Error>>,
+        ) -> Result<(), Box<dyn Error>> {
+            let mut best = std::time::Duration::MAX;
+            for _ in 0..5 {
+                udf.invalidate_all();
+                let start = std::time::Instant::now();
+                f(udf)?;
+                best = best.min(start.elapsed());
+            }
+            println!("{:<16} {:>8.3} ms", name, best.as_secs_f64() * 1e3);
+            Ok(())
+        }
+        let mut udf = UDF::new(Cursor::new(testimage::build_wide_dir(100_000)))?;
+        let root = udf.get_root_dir()?;
+        time(&mut udf, "get_fids", |udf| {
+            assert_eq!(root.get_fids(udf).len(), 100_001);
+            Ok(())
+        })?;
+        time(&mut udf, "read_entries", |udf| {
+            assert_eq!(root.read_entries(udf)?.len(), 100_000);
+            Ok(())
+        })?;
+        time(&mut udf, "lookup", |udf| {
+            udf.find_icb(Path::new("/file099999"))?;
+            Ok(())
+        })?;
+        time(&mut udf, "100 lookups", |udf| {
+            for i in 0..100 {
+                udf.find_icb(Path::new(&format!("/file{:06}", i * 1000)))?;
+            }
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn hard_links() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let image = testimage::build_links(3, &[5; 5000]);
+        let mut udf = UDF::new(Cursor::new(image))?;
+        let (catalogue, icbs) = udf.catalogue_icbs()?;
+        let paths: Vec<&str> = catalogue.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["/", "/file000000", "/file000001", "/file000002"]);
+        assert!(icbs[1..].iter().all(|icb| icb.loc == icbs[1].loc));
+        assert!(catalogue.entries[1..]
+            .iter()
+            .all(|e| e.icb == Some(icbs[1].loc) && e.extents.len() == 1));
+        assert!(catalogue.extent_overlaps().is_empty());
+        assert_eq!(udf.size_report(10)?.total_files, 1);
+        let check = udf.check_file_counts()?.unwrap();
+        assert!(check.is_consistent());
+        assert_eq!((check.files, check.dirs), (1, 1));
+
+        let read = catalogue::Catalogue::from_json(&catalogue.to_json())?;
+        assert_eq!(read.entries[3].icb, Some(icbs[1].loc));
+        assert!(read.extent_overlaps().is_empty());
+        // without recorded ICBs the links can't be told from cross-linked files
+        let legacy = catalogue
+            .to_json()
+            .replace(r#","icb":{"part_ref_nr":0,"lbn":"#, r#","x":{"y":"#);
+        let read = catalogue::Catalogue::from_json(&legacy)?;
+        assert!(read.entries.iter().all(|e| e.icb.is_none()));
+        assert_eq!(read.extent_overlaps().len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "writer")]
+    fn rebuild_hard_links() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let mut image = testimage::build_links(3, &[5; 3000]);
+        let mut udf = UDF::new(Cursor::new(image.clone()))?;
+        let snapshot = udf.tree_snapshot()?;
+        assert_eq!(snapshot.nodes.len(), 4);
+        // wipe the file entry shared by the links
+        let linked = snapshot.nodes[1].icb;
+        let lsn = udf.lb_to_sector(&linked)? as usize;
+        image[lsn * 2048..(lsn + 1) * 2048].fill(0);
+
+        let mut udf = UDF::new(Cursor::new(image))?;
+        let report = udf.rebuild_metadata(&snapshot)?;
+        assert_eq!((report.file_entries, report.directories), (2, 1));
+        let root = udf.get_root_dir()?;
+        let fids = root.get_fids(&mut udf);
+        assert_eq!(fids.len(), 4);
+        assert!(fids[1..].iter().all(|fid| fid.icb.loc == linked));
+        let icb = udf.find_icb(Path::new("/file000002"))?;
+        assert_eq!(icb.file_entry().unwrap().file_link_count(), 3);
+        assert_eq!(icb.read_data(&mut udf)?, [5; 3000]);
+        assert_eq!(udf.tree_snapshot()?.to_bytes(), snapshot.to_bytes());
+        Ok(())
+    }
+
+    #[test]
+    fn catalogue_json_malformed() -> Result<(), Box<dyn Error>> {
+        init_logger();
+        let mut udf = UDF::new(File::open("./tests/test.iso")?)?;
+        let json = udf.catalogue()?.to_json();
+        let pair = json.replace("/LICENSE.md", r"/\ud83d\ude00.md");
+        let read = catalogue::Catalogue::from_json(&pair)?;
+        assert_eq!(read.entries[1].path, "/\u{1f600}.md");
+        for unpaired in [r"/\ud83d.mdA", r"/\ud83dxxde00.md", r"/\ud83d"] {
+            assert!(
+                catalogue::Catalogue::from_json(&json.replace("/LICENSE.md", unpaired)).is_err()
+            );
+        }
+
+        let deep = "[".repeat(100_000) + &"]".repeat(100_000);
+        assert!(catalogue::Catalogue::from_json(&deep).is_err());
+        let nested = "[".repeat(100) + &"]".repeat(100);
+        let within = json.replacen("{", &format!(r#"{{"extra":{},"#, nested), 1);
+        catalogue::Catalogue::from_json(&within)?;
         Ok(())
     }
 }
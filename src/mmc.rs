@@ -0,0 +1,202 @@
+/*
+    Queries optical drives for the layout of the inserted disc using SCSI Multimedia Commands
+    sent through the Linux SG_IO interface.
+
+    Multisession discs record the anchor of their current state relative to the start of the
+    last session, which can only be learned from the drive, not from the data it returns.
+*/
+
+use std::error::Error;
+use std::fs::File;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+use crate::backend::PositionedReader;
+use crate::blockdev::BlockDevice;
+use crate::volume::LSN;
+use crate::{UdfOptions, UDF};
+
+const SG_IO: libc::c_ulong = 0x2285;
+const SG_DXFER_FROM_DEV: libc::c_int = -3;
+const TIMEOUT_MS: u32 = 30_000;
+
+const READ_CAPACITY: u8 = 0x25;
+const READ_DISC_INFORMATION: u8 = 0x51;
+const READ_TRACK_INFORMATION: u8 = 0x52;
+
+/// `sg_io_hdr` from <scsi/sg.h>
+#[repr(C)]
+struct SgIoHdr {
+    interface_id: libc::c_int,
+    dxfer_direction: libc::c_int,
+    cmd_len: u8,
+    mx_sb_len: u8,
+    iovec_count: u16,
+    dxfer_len: u32,
+    dxferp: *mut libc::c_void,
+    cmdp: *const u8,
+    sbp: *mut u8,
+    timeout: u32,
+    flags: u32,
+    pack_id: libc::c_int,
+    usr_ptr: *mut libc::c_void,
+    status: u8,
+    masked_status: u8,
+    msg_status: u8,
+    sb_len_wr: u8,
+    host_status: u16,
+    driver_status: u16,
+    resid: libc::c_int,
+    duration: u32,
+    info: u32,
+}
+
+/// sends the command `cdb` to the drive, reading the response into `buf`
+fn command(file: &File, cdb: &[u8], buf: &mut [u8]) -> io::Result<()> {
+    let mut sense = [0u8; 32];
+    let mut hdr = SgIoHdr {
+        interface_id: b'S' as libc::c_int,
+        dxfer_direction: SG_DXFER_FROM_DEV,
+        cmd_len: cdb.len() as u8,
+        mx_sb_len: sense.len() as u8,
+        iovec_count: 0,
+        dxfer_len: buf.len() as u32,
+        dxferp: buf.as_mut_ptr().cast(),
+        cmdp: cdb.as_ptr(),
+        sbp: sense.as_mut_ptr(),
+        timeout: TIMEOUT_MS,
+        flags: 0,
+        pack_id: 0,
+        usr_ptr: std::ptr::null_mut(),
+        status: 0,
+        masked_status: 0,
+        msg_status: 0,
+        sb_len_wr: 0,
+        host_status: 0,
+        driver_status: 0,
+        resid: 0,
+        duration: 0,
+        info: 0,
+    };
+    // SAFETY: the header points to buffers that outlive the call and match the given lengths
+    if unsafe { libc::ioctl(file.as_raw_fd(), SG_IO as _, &mut hdr) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if hdr.status != 0 || hdr.host_status != 0 || hdr.driver_status != 0 {
+        let key = if hdr.sb_len_wr > 2 {
+            sense[2] & 0x0f
+        } else {
+            0
+        };
+        return Err(io::Error::other(format!(
+            "command {:#04x} failed with status {:#04x}, sense key {:#x}",
+            cdb[0], hdr.status, key
+        )));
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Session {
+    pub number: u16,
+    /// address of the first track of the session
+    pub start: LSN,
+}
+
+/// layout of the disc in the drive
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiscInfo {
+    /// recorded sessions in ascending order
+    pub sessions: Vec<Session>,
+    /// number of readable sectors reported by READ CAPACITY
+    pub capacity: u32,
+}
+
+impl DiscInfo {
+    /// asks the drive behind `file` about the disc it holds
+    pub fn query(file: &File) -> io::Result<Self> {
+        let mut buf = [0u8; 34];
+        command(
+            file,
+            &[READ_DISC_INFORMATION, 0, 0, 0, 0, 0, 0, 0, 34, 0],
+            &mut buf,
+        )?;
+        let (_, last_track) = parse_disc_info(&buf)?;
+
+        let mut sessions: Vec<Session> = Vec::new();
+        for track in 1..=last_track as u32 {
+            let t = track.to_be_bytes();
+            let cdb = [
+                READ_TRACK_INFORMATION,
+                1, // address is a track number
+                t[0],
+                t[1],
+                t[2],
+                t[3],
+                0,
+                0,
+                34,
+                0,
+            ];
+            command(file, &cdb, &mut buf)?;
+            let session = parse_track_info(&buf)?;
+            // tracks are numbered in recording order, so the first one seen starts the session
+            if sessions.last().map(|s| s.number) != Some(session.number) {
+                sessions.push(session);
+            }
+        }
+
+        let mut cap = [0u8; 8];
+        command(file, &[READ_CAPACITY, 0, 0, 0, 0, 0, 0, 0, 0, 0], &mut cap)?;
+        let last_lba = u32::from_be_bytes(cap[0..4].try_into().unwrap());
+        Ok(Self {
+            sessions,
+            capacity: last_lba + 1,
+        })
+    }
+
+    /// start of the last session, 0 for discs without any recorded session
+    pub fn last_session_start(&self) -> LSN {
+        self.sessions.last().map(|s| s.start).unwrap_or(0)
+    }
+}
+
+/// number of sessions and number of the last track from a disc information block
+pub(crate) fn parse_disc_info(buf: &[u8]) -> io::Result<(u16, u16)> {
+    if buf.len() < 12 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    let num_sessions = u16::from_le_bytes([buf[4], buf[9]]);
+    let last_track = u16::from_le_bytes([buf[6], buf[11]]);
+    Ok((num_sessions, last_track))
+}
+
+/// session number and start address from a track information block
+pub(crate) fn parse_track_info(buf: &[u8]) -> io::Result<Session> {
+    if buf.len() < 34 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(Session {
+        number: u16::from_le_bytes([buf[3], buf[33]]),
+        start: u32::from_be_bytes(buf[8..12].try_into().unwrap()),
+    })
+}
+
+/// opens the volume on the disc in the drive at `path`, starting from the last session
+pub fn open_disc<P: AsRef<Path>>(
+    path: P,
+) -> Result<UDF<PositionedReader<BlockDevice>>, Box<dyn Error>> {
+    open_disc_with_options(path, UdfOptions::default())
+}
+
+/// like `open_disc`, with `options.session_start` taken from the drive
+pub fn open_disc_with_options<P: AsRef<Path>>(
+    path: P,
+    mut options: UdfOptions,
+) -> Result<UDF<PositionedReader<BlockDevice>>, Box<dyn Error>> {
+    let dev = BlockDevice::open(path)?;
+    let info = DiscInfo::query(dev.file())?;
+    options.session_start = info.last_session_start();
+    UDF::new_with_options(PositionedReader::new(dev), options)
+}
@@ -0,0 +1,147 @@
+/*
+    Mount table of many read-only volumes, e.g. the library of a media server. Volumes are
+    exposed below `/discs/<label>` in one virtual namespace and share one metadata cache, so
+    that memory use is bounded independently of the number of mounted volumes.
+*/
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::{ErrorKind, Read, Seek};
+use std::path::{Path, PathBuf};
+
+use crate::cache::MetaCache;
+use crate::file::ICB;
+use crate::path::UdfPath;
+use crate::UDF;
+
+/// directory below which the volumes appear
+pub const MOUNT_ROOT: &str = "discs";
+
+pub struct Mounts<IO: Read + Seek> {
+    volumes: BTreeMap<String, UDF<IO>>,
+    cache: MetaCache,
+}
+
+/// a path of the virtual namespace
+enum Location {
+    /// the root or the mount directory
+    Virtual(Vec<String>),
+    Volume(String, PathBuf),
+}
+
+fn not_found() -> Box<dyn Error> {
+    Box::new(std::io::Error::from(ErrorKind::NotFound))
+}
+
+impl<IO: Read + Seek> Mounts<IO> {
+    /// creates an empty table whose volumes share a cache of `cache_size` ICBs and
+    /// directories
+    pub fn new(cache_size: usize) -> Self {
+        Self {
+            volumes: BTreeMap::new(),
+            cache: MetaCache::new(cache_size, None),
+        }
+    }
+
+    /// opens the volume on `io` and mounts it under its volume identifier
+    pub fn mount(&mut self, io: IO) -> Result<String, Box<dyn Error>> {
+        Ok(self.mount_volume(UDF::new(io)?))
+    }
+
+    /// mounts an opened volume under its volume identifier, returning the label it was
+    /// mounted as. Slashes are replaced and labels already in use get a numeric suffix.
+    pub fn mount_volume(&mut self, udf: UDF<IO>) -> String {
+        let ident = udf.primary_vol_desc.vol_ident.to_string();
+        let mut base = ident.trim().replace('/', "_");
+        if base.is_empty() || base == "." || base == ".." {
+            base = "disc".to_string();
+        }
+        let mut label = base.clone();
+        let mut n = 1;
+        while self.volumes.contains_key(&label) {
+            n += 1;
+            label = format!("{}_{}", base, n);
+        }
+        self.mount_as(label.clone(), udf);
+        label
+    }
+
+    /// mounts `udf` under `label`, replacing the volume mounted there before
+    pub fn mount_as(&mut self, label: String, mut udf: UDF<IO>) -> Option<UDF<IO>> {
+        udf.cache.clear();
+        udf.cache = self.cache.attach();
+        let old = self.volumes.insert(label, udf);
+        old.map(Self::detach)
+    }
+
+    pub fn unmount(&mut self, label: &str) -> Option<UDF<IO>> {
+        self.volumes.remove(label).map(Self::detach)
+    }
+
+    /// gives a volume leaving the table a cache of its own
+    fn detach(mut udf: UDF<IO>) -> UDF<IO> {
+        udf.cache.clear();
+        udf.cache = MetaCache::new(udf.options.cache_size, udf.options.cache_bytes());
+        udf
+    }
+
+    pub fn labels(&self) -> impl Iterator<Item = &str> {
+        self.volumes.keys().map(String::as_str)
+    }
+
+    pub fn volume(&mut self, label: &str) -> Option<&mut UDF<IO>> {
+        self.volumes.get_mut(label)
+    }
+
+    /// resolves `path` lexically, `..` can lead out of a volume into the virtual directories
+    fn locate(&self, path: &Path) -> Result<Location, Box<dyn Error>> {
+        let path = UdfPath::from_path(path)?;
+        let parts = path.lexical()?;
+        match parts[..] {
+            [] => Ok(Location::Virtual(vec![MOUNT_ROOT.to_string()])),
+            [root] if root == MOUNT_ROOT => {
+                Ok(Location::Virtual(self.volumes.keys().cloned().collect()))
+            }
+            [root, label, ..] if root == MOUNT_ROOT && self.volumes.contains_key(label) => {
+                let inner = Path::new("/").join(parts[2..].join("/"));
+                Ok(Location::Volume(label.to_string(), inner))
+            }
+            _ => Err(not_found()),
+        }
+    }
+
+    /// names of the entries of the directory at `path`, either a directory of a mounted
+    /// volume or one of the virtual directories above them
+    pub fn list_dir(&mut self, path: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+        match self.locate(path)? {
+            Location::Virtual(names) => Ok(names),
+            Location::Volume(label, inner) => {
+                let udf = self.volumes.get_mut(&label).unwrap();
+                let icb = udf.find_icb(&inner)?;
+                Ok(icb
+                    .read_entries(udf)?
+                    .into_iter()
+                    .filter(|e| !e.is_deleted() && !e.is_parent())
+                    .map(|e| e.name)
+                    .collect())
+            }
+        }
+    }
+
+    /// looks up the ICB at `path`, returning it together with the volume it was found on
+    pub fn find_icb(&mut self, path: &Path) -> Result<(&mut UDF<IO>, ICB), Box<dyn Error>> {
+        match self.locate(path)? {
+            Location::Virtual(_) => Err("path is a virtual directory")?,
+            Location::Volume(label, inner) => {
+                let udf = self.volumes.get_mut(&label).unwrap();
+                let icb = udf.find_icb(&inner)?;
+                Ok((udf, icb))
+            }
+        }
+    }
+
+    pub fn read_file(&mut self, path: &Path) -> Result<Vec<u8>, Box<dyn Error>> {
+        let (udf, icb) = self.find_icb(path)?;
+        icb.read_data(udf)
+    }
+}
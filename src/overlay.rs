@@ -0,0 +1,179 @@
+/*
+    Copy-on-write overlay over a read-only base image.
+
+    Writes are kept per sector in memory and shadow the base image on reads, so a volume can be
+    modified without touching the original. The delta can be saved to a side file and loaded
+    again later.
+*/
+
+use std::collections::BTreeMap;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::BLOCKSIZE;
+
+const BS: usize = BLOCKSIZE as usize;
+
+pub struct Overlay<B: Read + Seek> {
+    base: B,
+    base_len: u64,
+    /// modified sectors by sector number
+    delta: BTreeMap<u64, Box<[u8; BS]>>,
+    /// size of the overlaid image, grows when writing past the end of the base image
+    len: u64,
+    pos: u64,
+}
+
+impl<B: Read + Seek> Overlay<B> {
+    pub fn new(mut base: B) -> io::Result<Self> {
+        let base_len = base.seek(SeekFrom::End(0))?;
+        Ok(Self {
+            base,
+            base_len,
+            delta: BTreeMap::new(),
+            len: base_len,
+            pos: 0,
+        })
+    }
+
+    pub fn into_base(self) -> B {
+        self.base
+    }
+
+    /// numbers of the sectors that differ from the base image
+    pub fn modified_sectors(&self) -> impl Iterator<Item = u64> + '_ {
+        self.delta.keys().copied()
+    }
+
+    /// drops all modifications
+    pub fn discard(&mut self) {
+        self.delta.clear();
+        self.len = self.base_len;
+    }
+
+    /// writes the delta as a sequence of little endian sector numbers each followed by the
+    /// sector contents, preceded by the size of the overlaid image
+    pub fn save_delta<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(&self.len.to_le_bytes())?;
+        for (sector, data) in &self.delta {
+            out.write_all(&sector.to_le_bytes())?;
+            out.write_all(&data[..])?;
+        }
+        Ok(())
+    }
+
+    /// replaces the modifications with a delta written by `save_delta`
+    pub fn load_delta<R: Read>(&mut self, input: &mut R) -> io::Result<()> {
+        let mut num = [0; 8];
+        input.read_exact(&mut num)?;
+        let len = u64::from_le_bytes(num);
+        let mut delta = BTreeMap::new();
+        loop {
+            match input.read_exact(&mut num) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            }
+            let mut data = Box::new([0; BS]);
+            input.read_exact(&mut data[..])?;
+            delta.insert(u64::from_le_bytes(num), data);
+        }
+        self.delta = delta;
+        self.len = len;
+        Ok(())
+    }
+
+    /// writes the overlaid image, i.e. the base image with all modifications applied, to `out`
+    pub fn export<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        let mut buf = [0; BS];
+        for sector in 0..self.len.div_ceil(BLOCKSIZE) {
+            let n = (self.len - sector * BLOCKSIZE).min(BLOCKSIZE) as usize;
+            self.read_sector(sector, &mut buf)?;
+            out.write_all(&buf[..n])?;
+        }
+        Ok(())
+    }
+
+    /// reads a whole sector of the overlaid image, zero-filled past the end of the base image
+    fn read_sector(&mut self, sector: u64, buf: &mut [u8; BS]) -> io::Result<()> {
+        if let Some(data) = self.delta.get(&sector) {
+            buf.copy_from_slice(&data[..]);
+            return Ok(());
+        }
+        buf.fill(0);
+        let start = sector * BLOCKSIZE;
+        if start < self.base_len {
+            let n = (self.base_len - start).min(BLOCKSIZE) as usize;
+            self.base.seek(SeekFrom::Start(start))?;
+            self.base.read_exact(&mut buf[..n])?;
+        }
+        Ok(())
+    }
+}
+
+impl<B: Read + Seek> Read for Overlay<B> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let sector = self.pos / BLOCKSIZE;
+        let off = (self.pos % BLOCKSIZE) as usize;
+        let n = buf.len().min(BS - off).min((self.len - self.pos) as usize);
+        match self.delta.get(&sector) {
+            Some(data) => buf[..n].copy_from_slice(&data[off..off + n]),
+            None if self.pos < self.base_len => {
+                let n = n.min((self.base_len - self.pos) as usize);
+                self.base.seek(SeekFrom::Start(self.pos))?;
+                let n = self.base.read(&mut buf[..n])?;
+                self.pos += n as u64;
+                return Ok(n);
+            }
+            None => buf[..n].fill(0),
+        }
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<B: Read + Seek> Write for Overlay<B> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let sector = self.pos / BLOCKSIZE;
+        let off = (self.pos % BLOCKSIZE) as usize;
+        let n = buf.len().min(BS - off);
+        if !self.delta.contains_key(&sector) {
+            let mut data = Box::new([0; BS]);
+            self.read_sector(sector, &mut data)?;
+            self.delta.insert(sector, data);
+        }
+        self.delta.get_mut(&sector).unwrap()[off..off + n].copy_from_slice(&buf[..n]);
+        self.pos += n as u64;
+        self.len = self.len.max(self.pos);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<B: Read + Seek> Seek for Overlay<B> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(pos) => {
+                self.pos = pos;
+                return Ok(pos);
+            }
+            SeekFrom::Current(offset) => (self.pos, offset),
+            SeekFrom::End(offset) => (self.len, offset),
+        };
+        self.pos = base.checked_add_signed(offset).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        Ok(self.pos)
+    }
+}
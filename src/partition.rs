@@ -0,0 +1,348 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::io::{Read, Seek, SeekFrom};
+
+use log::{info, warn};
+use nom_derive::Parse;
+
+use crate::file::{LBAddr, ICB, VAT};
+use crate::volume::{
+    MetaPartMap, PartMapType, SparablePartMap, SparingTable, Timestamp, LSN, LVD, PD,
+};
+use crate::{check_tag_crc, read_sector, UdfOptions, UDF};
+
+/// The kind of a partition map together with its type specific parameters
+#[derive(Debug, Clone)]
+pub enum PartitionKind {
+    /// Type 1 map of a physical partition
+    Physical,
+    /// Virtual partition of an incrementally recorded volume, translated by the VAT
+    Virtual,
+    /// Sparable partition with sparing tables remapping defective packets
+    Sparable(SparablePartMap),
+    /// Metadata partition whose blocks are located through the metadata file
+    Metadata(MetaPartMap),
+    /// map of a type or with an identifier this crate does not know
+    Unknown(String),
+}
+
+/// A partition map of the logical volume, resolved against the partition descriptors
+#[derive(Clone)]
+pub struct PartitionMap {
+    /// partition reference number used by `LBAddr::part_ref_nr`
+    pub part_ref: u16,
+    pub kind: PartitionKind,
+    pub vol_seq_num: u16,
+    pub part_num: u16,
+    /// the partition descriptor with a matching partition number, if any was recorded
+    pub pd: Option<PD>,
+    /// the current sparing table of a sparable partition, if one could be read
+    pub sparing_table: Option<SparingTable>,
+}
+
+impl PartitionMap {
+    /// whether blocks can be read through this map, i.e. it is of a known type and its
+    /// partition was recorded
+    pub fn is_usable(&self) -> bool {
+        self.pd.is_some() && !matches!(self.kind, PartitionKind::Unknown(_))
+    }
+}
+
+/// resolves the partition maps of `lvd` against the recorded partition descriptors
+pub(crate) fn resolve_partition_maps(lvd: &LVD, partitions: &[PD]) -> Vec<PartitionMap> {
+    lvd.part_maps
+        .iter()
+        .enumerate()
+        .map(|(i, map)| {
+            let (kind, vol_seq_num, part_num) = match &map.part_map {
+                PartMapType::Type1(pm) => (PartitionKind::Physical, pm.vol_seq_num, pm.part_num),
+                PartMapType::Type2(pm) => {
+                    let kind = if pm.is_virtual() {
+                        PartitionKind::Virtual
+                    } else if let Some(sparable) = pm.sparable() {
+                        PartitionKind::Sparable(sparable)
+                    } else if let Some(meta) = pm.metadata() {
+                        PartitionKind::Metadata(meta)
+                    } else {
+                        PartitionKind::Unknown(pm.part_ident.ident_str().to_string())
+                    };
+                    (kind, pm.vol_seq_nr, pm.part_num)
+                }
+                PartMapType::UNK { .. } => (PartitionKind::Unknown(String::new()), 0, 0),
+            };
+            let pd = match kind {
+                PartitionKind::Unknown(_) => None,
+                _ => partitions
+                    .iter()
+                    .find(|pd| pd.part_num == part_num)
+                    .cloned(),
+            };
+            PartitionMap {
+                part_ref: i as u16,
+                kind,
+                vol_seq_num,
+                part_num,
+                pd,
+                sparing_table: None,
+            }
+        })
+        .collect()
+}
+
+/// largest sparing table, with the maximum number of entries
+const MAX_SPARING_TABLE: usize = 56 + 0xFFFF * 8;
+
+/// reads the copies of the sparing table of `map` recorded at the locations of its sparable
+/// partition map, returning the one with the highest sequence number. Copies that can't be
+/// read or fail their checks are logged and skipped.
+pub(crate) fn read_sparing_table<IO: Read + Seek>(
+    io: &mut IO,
+    sector_size: u64,
+    map: &SparablePartMap,
+    options: &UdfOptions,
+) -> Option<SparingTable> {
+    let len = (map.sparing_tbl_size as usize).clamp(56, MAX_SPARING_TABLE);
+    let mut buf = vec![0; len.div_ceil(sector_size as usize) * sector_size as usize];
+    let mut best: Option<SparingTable> = None;
+    for &lsn in &map.sparing_tbl_locs {
+        let table = read_sector(io, sector_size, lsn, &mut buf)
+            .map_err(|e| e.to_string())
+            .and_then(|_| {
+                check_tag_crc(options, "sparing table", &buf).map_err(|e| e.to_string())?;
+                let (_, table) =
+                    SparingTable::parse(&buf).or(Err("error parsing sparing table"))?;
+                if table.sparing_ident.ident_str() != "*UDF Sparing Table" {
+                    Err("invalid sparing table identifier")?
+                }
+                Ok(table)
+            });
+        match table {
+            Ok(mut table) if best.as_ref().is_none_or(|b| table.seq_num > b.seq_num) => {
+                table.entries.sort_by_key(|e| e.original);
+                best = Some(table);
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Sparing table at sector {} is unusable: {}", lsn, e),
+        }
+    }
+    best
+}
+
+impl<IO: Read + Seek> UDF<IO> {
+    /// all partition maps of the logical volume in partition reference number order
+    pub fn partition_maps(&self) -> &[PartitionMap] {
+        &self.part_maps
+    }
+
+    /// translates a logical block address into the absolute sector it is recorded in
+    pub fn lb_to_sector(&self, loc: &LBAddr) -> Result<LSN, Box<dyn Error>> {
+        let map = match self.part_maps.get(loc.part_ref_nr as usize) {
+            Some(map) => map,
+            None if self.options.strict => Err("invalid partition reference number")?,
+            None => {
+                warn!(
+                    "Invalid partition reference number {}, assuming partition 0",
+                    loc.part_ref_nr
+                );
+                self.part_maps.first().ok_or("no partition maps")?
+            }
+        };
+        let pd = map
+            .pd
+            .as_ref()
+            .ok_or("no partition descriptor for partition map")?;
+        let lbn = match &map.kind {
+            PartitionKind::Physical => loc.lbn,
+            PartitionKind::Sparable(sparable) => {
+                let remapped = map.sparing_table.as_ref().and_then(|table| {
+                    table.remap(loc.lbn, sparable.packet_len, self.block_sectors())
+                });
+                if let Some(lsn) = remapped {
+                    return Ok(lsn);
+                }
+                loc.lbn
+            }
+            PartitionKind::Virtual => {
+                let vat = self.vat.as_ref().ok_or("virtual partition without VAT")?;
+                match vat.entries.get(loc.lbn as usize) {
+                    Some(&lbn) if lbn != VAT::UNUSED => lbn,
+                    _ => Err("virtual block is not mapped by the VAT")?,
+                }
+            }
+            PartitionKind::Metadata(_) => loc
+                .lbn
+                .checked_add(self.meta_file_offset.unwrap_or(0))
+                .ok_or("block address beyond the metadata partition")?,
+            PartitionKind::Unknown(_) => Err("unknown partition type")?,
+        };
+        Ok(lbn
+            .checked_mul(self.block_sectors())
+            .and_then(|sector| pd.part_start.checked_add(sector))
+            .ok_or("block address beyond the partition")?)
+    }
+
+    /// number of bytes from the start of the block `loc` on that are recorded contiguously,
+    /// up to `len`. Only packets of sparable partitions relocated by the sparing table break
+    /// the blocks of a partition up.
+    pub(crate) fn contiguous_len(&self, loc: &LBAddr, len: u64) -> u64 {
+        let Some(map) = self.part_maps.get(loc.part_ref_nr as usize) else {
+            return len;
+        };
+        let (PartitionKind::Sparable(sparable), Some(table)) = (&map.kind, &map.sparing_table)
+        else {
+            return len;
+        };
+        let packet_len = sparable.packet_len.max(1) as u32;
+        let mut end = (loc.lbn - loc.lbn % packet_len).saturating_add(packet_len);
+        if table.remap(loc.lbn, sparable.packet_len, 1).is_none() {
+            // packets that weren't relocated follow each other up to the next relocated one
+            let next = table.entries.partition_point(|e| e.original < end);
+            end = table.entries.get(next).map_or(u32::MAX, |e| e.original);
+        }
+        len.min((end - loc.lbn) as u64 * self.block_size())
+    }
+
+    /// reads the VAT whose ICB is recorded at `icb_loc` in the physical partition
+    pub fn read_vat(&mut self, icb_loc: &LBAddr) -> Result<VAT, Box<dyn Error>> {
+        Ok(self.read_vat_icb(icb_loc)?.1)
+    }
+
+    fn read_vat_icb(&mut self, icb_loc: &LBAddr) -> Result<(ICB, VAT), Box<dyn Error>> {
+        let icb = self.read_icb(icb_loc)?;
+        let data = icb.read_data(self)?;
+        let vat = VAT::parse(&data, icb.icb_tag.file_type, *icb_loc)?;
+        Ok((icb, vat))
+    }
+
+    /// lists the recorded states of an incrementally written volume, newest first. Volumes
+    /// without a virtual partition are rewritten in place and only have their current state.
+    pub fn snapshots(&mut self) -> Result<Vec<Snapshot>, Box<dyn Error>> {
+        let mut snapshots = Vec::new();
+        for vat in self.vat_history()? {
+            let (icb, _) = self.read_vat_icb(&vat.icb_loc)?;
+            let rec_time = icb.file_entry().map(|fe| fe.mtime().clone());
+            snapshots.push(Snapshot { vat, rec_time });
+        }
+        Ok(snapshots)
+    }
+
+    /// returns a view of the volume as it was when `snapshot` was recorded, sharing the
+    /// underlying reader. The state of `self` is left untouched.
+    pub fn snapshot(&mut self, snapshot: &Snapshot) -> UDF<&mut IO> {
+        let mut io = crate::backend::Window::new(&mut self.io.inner, self.options.start_offset);
+        io.set_read_rate(self.options.max_read_rate);
+        io.set_read_alignment(self.options.read_alignment);
+        UDF {
+            io: Box::new(io),
+            options: self.options.clone(),
+            primary_vol_desc: self.primary_vol_desc.clone(),
+            part_desc: self.part_desc.clone(),
+            partitions: self.partitions.clone(),
+            logical_vol_desc: self.logical_vol_desc.clone(),
+            file_set_desc: None,
+            part_maps: self.part_maps.clone(),
+            meta_file_offset: self.meta_file_offset,
+            vat: Some(snapshot.vat.clone()),
+            root_icb: None,
+            buf_pool: Vec::new(),
+            name_decoder: self.name_decoder.clone(),
+            cache: crate::cache::MetaCache::new(
+                self.options.cache_size,
+                self.options.cache_bytes(),
+            ),
+            metadata_read: self.metadata_read,
+            stats: crate::stats::IoStats::default(),
+            io_pos: None,
+            truncation: self.truncation.clone(),
+        }
+    }
+
+    /// reads every VAT generation still reachable through the previous VAT ICB pointers,
+    /// starting with the one currently in use
+    pub fn vat_history(&mut self) -> Result<Vec<VAT>, Box<dyn Error>> {
+        let mut history: Vec<VAT> = Vec::new();
+        let mut visited = HashSet::new();
+        let mut next = self.vat.clone();
+        while let Some(vat) = next.take() {
+            if !visited.insert(vat.icb_loc) {
+                break;
+            }
+            if let Some(prev) = vat.prev_vat_icb {
+                let loc = LBAddr {
+                    lbn: prev,
+                    part_ref_nr: vat.icb_loc.part_ref_nr,
+                };
+                next = Some(self.read_vat(&loc)?);
+            }
+            history.push(vat);
+        }
+        Ok(history)
+    }
+
+    /// switches the volume to the state described by `vat`, e.g. an older generation returned
+    /// by `vat_history`. Cached file set and directory information is discarded.
+    pub fn use_vat(&mut self, vat: VAT) {
+        self.vat = Some(vat);
+        self.file_set_desc = None;
+        self.root_icb = None;
+        // virtual addresses point elsewhere now
+        self.cache.clear();
+    }
+
+    /// searches the end of the volume for the VAT ICB of a virtual partition. The VAT ICB is the
+    /// last recorded sector, possibly followed by a few run-out sectors.
+    pub(crate) fn find_vat(&mut self) -> Result<Option<VAT>, Box<dyn Error>> {
+        let Some(virt) = self
+            .part_maps
+            .iter()
+            .find(|map| matches!(map.kind, PartitionKind::Virtual))
+        else {
+            return Ok(None);
+        };
+        let phys = self
+            .part_maps
+            .iter()
+            .find(|map| {
+                matches!(map.kind, PartitionKind::Physical) && map.part_num == virt.part_num
+            })
+            .ok_or("no physical partition map for virtual partition")?;
+        let part_start = phys
+            .pd
+            .as_ref()
+            .ok_or("no partition descriptor")?
+            .part_start;
+        let part_ref_nr = phys.part_ref;
+
+        let num_sectors = (self.io.seek(SeekFrom::End(0))? / self.sector_size()) as LSN;
+        let last = num_sectors.checked_sub(1).ok_or("empty volume")?;
+        let block_sectors = self.block_sectors();
+        for lsn in (last.saturating_sub(MAX_VAT_RUNOUT)..=last).rev() {
+            if lsn < part_start {
+                break;
+            }
+            if (lsn - part_start) % block_sectors != 0 {
+                continue;
+            }
+            let loc = LBAddr {
+                lbn: (lsn - part_start) / block_sectors,
+                part_ref_nr,
+            };
+            if let Ok(vat) = self.read_vat(&loc) {
+                info!("Found VAT ICB at sector {}", lsn);
+                return Ok(Some(vat));
+            }
+        }
+        Err("no VAT ICB found for virtual partition")?
+    }
+}
+
+/// State of an incrementally recorded volume as of one of its sessions
+#[derive(Clone)]
+pub struct Snapshot {
+    pub vat: VAT,
+    /// modification time of the VAT ICB, i.e. when the session was recorded
+    pub rec_time: Option<Timestamp>,
+}
+
+/// number of sectors before the end of the volume searched for the VAT ICB
+const MAX_VAT_RUNOUT: LSN = 256;
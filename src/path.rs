@@ -0,0 +1,144 @@
+/*
+    Canonical path syntax of the lookup API, shared by everything that resolves paths on a
+    volume:
+
+    - paths are absolute and `/` is the only separator, repeated separators count as one
+    - `.` components are dropped
+    - `..` is resolved while looking the path up, through the parent FID of the directory
+      reached so far; going above the root directory is an error
+    - a trailing separator, `.` or `..` requires the path to name a directory
+    - components hold at most `MAX_COMPONENT_LEN` characters and no NUL characters
+
+    `parse_with_stream` additionally accepts the syntax Windows exposes named streams with,
+    `/dir/file:stream` or `/dir/file:stream:$DATA`.
+*/
+
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+
+pub const SEPARATOR: char = '/';
+/// longest file identifier in characters, the recorded length is stored in a single byte
+pub const MAX_COMPONENT_LEN: usize = 255;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PathComponent {
+    /// `..`, the parent of the directory reached so far
+    Parent,
+    Name(String),
+}
+
+/// A parsed path in canonical form
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UdfPath {
+    pub components: Vec<PathComponent>,
+    /// whether the path has to name a directory
+    pub dir_only: bool,
+    /// named stream of the file or directory the path leads to
+    pub stream: Option<String>,
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidInput, msg)
+}
+
+impl UdfPath {
+    /// the root directory
+    pub fn root() -> Self {
+        Self {
+            components: Vec::new(),
+            dir_only: true,
+            stream: None,
+        }
+    }
+
+    pub fn parse(path: &str) -> io::Result<Self> {
+        let Some(rest) = path.strip_prefix(SEPARATOR) else {
+            return Err(invalid("path must be absolute"));
+        };
+        let mut result = Self::root();
+        for part in rest.split(SEPARATOR) {
+            // a trailing separator leaves an empty last part
+            result.dir_only = matches!(part, "" | "." | "..");
+            match part {
+                "" | "." => {}
+                ".." => result.components.push(PathComponent::Parent),
+                name if name.contains('\0') => {
+                    return Err(invalid("path component contains a NUL character"))
+                }
+                name if name.chars().count() > MAX_COMPONENT_LEN => {
+                    return Err(invalid("path component too long"))
+                }
+                name => result
+                    .components
+                    .push(PathComponent::Name(name.to_string())),
+            }
+        }
+        Ok(result)
+    }
+
+    /// parses `path`, taking everything after a `:` in the last component as the name of a
+    /// stream. Files whose names contain a `:` can't be looked up this way.
+    pub fn parse_with_stream(path: &str) -> io::Result<Self> {
+        let last = path.rfind(SEPARATOR).map_or(0, |i| i + 1);
+        let Some(colon) = path[last..].find(':').map(|i| last + i) else {
+            return Self::parse(path);
+        };
+        let mut result = Self::parse(&path[..colon])?;
+        let stream = &path[colon + 1..];
+        let stream = stream.strip_suffix(":$DATA").unwrap_or(stream);
+        if stream.is_empty() || stream.contains([':', '\0']) {
+            return Err(invalid("invalid stream name"));
+        }
+        if stream.chars().count() > MAX_COMPONENT_LEN {
+            return Err(invalid("stream name too long"));
+        }
+        result.stream = Some(stream.to_string());
+        Ok(result)
+    }
+
+    /// parses `path`, which has to be valid UTF-8
+    pub fn from_path(path: &Path) -> io::Result<Self> {
+        Self::parse(
+            path.to_str()
+                .ok_or_else(|| invalid("path is not valid UTF-8"))?,
+        )
+    }
+
+    /// the names of the path with `..` resolved against the preceding names, for paths that
+    /// aren't looked up in a directory tree
+    pub fn lexical(&self) -> io::Result<Vec<&str>> {
+        let mut names = Vec::new();
+        for c in &self.components {
+            match c {
+                PathComponent::Parent => {
+                    names
+                        .pop()
+                        .ok_or_else(|| invalid("path tried to go above root directory"))?;
+                }
+                PathComponent::Name(name) => names.push(name.as_str()),
+            }
+        }
+        Ok(names)
+    }
+}
+
+impl fmt::Display for UdfPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in &self.components {
+            match c {
+                PathComponent::Parent => write!(f, "/..")?,
+                PathComponent::Name(name) => write!(f, "/{}", name)?,
+            }
+        }
+        if self.components.is_empty()
+            || (self.dir_only && !matches!(self.components.last(), Some(PathComponent::Parent)))
+        {
+            write!(f, "/")?;
+        }
+        if let Some(stream) = &self.stream {
+            write!(f, ":{}", stream)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,16 @@
+/*
+    The types most applications need, meant to be imported with `use libudf_rs::prelude::*`.
+    These names stay put while the parser beneath them changes; the on-disc structures are
+    found in `raw` instead.
+*/
+
+pub use crate::catalogue::CatalogueEntry as Metadata;
+pub use crate::file::EntryKind;
+pub use crate::handle::{UdfDir as Dir, UdfFile as File};
+pub use crate::volume::Timestamp;
+pub use crate::{FileSetSelector, UdfOptions, UDF as Volume};
+
+/// the error type of the crate
+pub type Error = Box<dyn std::error::Error>;
+
+pub type Result<T> = std::result::Result<T, Error>;
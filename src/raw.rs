@@ -0,0 +1,7 @@
+/*
+    The on-disc structures as the parser decodes them, for tools that inspect volumes at the
+    level of descriptors. They follow the parser and may change with it, unlike `prelude`.
+*/
+
+pub use crate::file::*;
+pub use crate::volume::*;
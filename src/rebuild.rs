@@ -0,0 +1,333 @@
+/*
+    Regenerates the metadata of a file set from a tree snapshot: the file set descriptor,
+    the file entries of all files and directories at their recorded addresses, and the file
+    identifiers of every directory, linking files to their original data extents. Used to
+    repair volumes whose metadata has been destroyed while the file data survived.
+*/
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::file::{LBAddr, LongAD};
+use crate::tree::{TreeNode, TreeSnapshot};
+use crate::volume::{encode_cs0, Timestamp};
+use crate::{write_tag, BLOCKSIZE, UDF};
+
+const BS: usize = BLOCKSIZE as usize;
+/// offset of the allocation descriptors in a file entry without extended attributes
+pub(crate) const FE_AD_OFFSET: usize = 176;
+
+/// what `rebuild_metadata` wrote
+#[derive(Clone, Debug, Default)]
+pub struct RebuildReport {
+    pub file_entries: usize,
+    pub directories: usize,
+    /// blocks written, including the file set descriptor
+    pub blocks: usize,
+}
+
+pub(crate) fn put_u16(buf: &mut [u8], off: usize, v: u16) {
+    buf[off..off + 2].copy_from_slice(&v.to_le_bytes());
+}
+
+pub(crate) fn put_u32(buf: &mut [u8], off: usize, v: u32) {
+    buf[off..off + 4].copy_from_slice(&v.to_le_bytes());
+}
+
+pub(crate) fn put_u64(buf: &mut [u8], off: usize, v: u64) {
+    buf[off..off + 8].copy_from_slice(&v.to_le_bytes());
+}
+
+pub(crate) fn put_long_ad(buf: &mut [u8], len: u32, loc: LBAddr) {
+    put_u32(buf, 0, len);
+    put_u32(buf, 4, loc.lbn);
+    put_u16(buf, 8, loc.part_ref_nr);
+}
+
+pub(crate) fn put_timestamp(buf: &mut [u8], t: &Timestamp) {
+    buf.copy_from_slice(&t.to_bytes());
+}
+
+pub(crate) fn put_dstring(buf: &mut [u8], s: &str) -> Result<(), Box<dyn Error>> {
+    let raw = encode_cs0(s);
+    let last = buf.len() - 1;
+    if raw.len() > last {
+        Err(format!("identifier {} is too long", s))?
+    }
+    buf[..raw.len()].copy_from_slice(&raw);
+    buf[last] = raw.len() as u8;
+    Ok(())
+}
+
+/// the "OSTA Compressed Unicode" character set specification
+pub(crate) fn put_osta_charspec(buf: &mut [u8]) {
+    buf[0] = 0;
+    buf[1..24].copy_from_slice(b"OSTA Compressed Unicode");
+}
+
+pub(crate) fn put_regid(buf: &mut [u8], ident: &str, suffix: &[u8]) {
+    buf[1..1 + ident.len()].copy_from_slice(ident.as_bytes());
+    buf[24..24 + suffix.len()].copy_from_slice(suffix);
+}
+
+/// builds a file identifier descriptor, tagged once its location is known
+pub(crate) fn fid(chars: u8, icb: LBAddr, name: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let raw = encode_cs0(name);
+    if raw.len() > 255 {
+        Err(format!("file name {} is too long", name))?
+    }
+    let len = (38 + raw.len()).next_multiple_of(4);
+    let mut fid = vec![0; len];
+    put_u16(&mut fid, 16, 1);
+    fid[18] = chars;
+    fid[19] = raw.len() as u8;
+    put_long_ad(&mut fid[20..36], BS as u32, icb);
+    fid[38..38 + raw.len()].copy_from_slice(&raw);
+    Ok(fid)
+}
+
+impl<IO: Read + Seek + Write> UDF<IO> {
+    /// rewrites the file set descriptor, the file entries and the directories of `snapshot`
+    /// at the addresses recorded in it. Files keep referring to their recorded extents and
+    /// directories are written into their recorded extents, failing if they no longer fit.
+    /// Cached metadata is dropped afterwards.
+    pub fn rebuild_metadata(
+        &mut self,
+        snapshot: &TreeSnapshot,
+    ) -> Result<RebuildReport, Box<dyn Error>> {
+        if self.sector_size() != BLOCKSIZE
+            || self.block_size() != BLOCKSIZE
+            || snapshot.volume.sector_size != BLOCKSIZE
+        {
+            Err("metadata can only be rebuilt on volumes with 2048 byte sectors and blocks")?
+        }
+        self.warn_if_protected();
+        let index: HashMap<&str, usize> = snapshot
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, n)| (n.path.as_str(), i))
+            .collect();
+        let root = *index.get("/").ok_or("snapshot has no root directory")?;
+        let mut children: Vec<Vec<usize>> = vec![Vec::new(); snapshot.nodes.len()];
+        let mut parents = vec![root; snapshot.nodes.len()];
+        for (i, node) in snapshot.nodes.iter().enumerate() {
+            if i == root {
+                continue;
+            }
+            let parent = match node.path.rsplit_once('/') {
+                Some(("", _)) => "/",
+                Some((parent, _)) => parent,
+                None => Err(format!("invalid path {} in snapshot", node.path))?,
+            };
+            let parent = *index.get(parent).ok_or(format!(
+                "parent directory of {} missing in snapshot",
+                node.path
+            ))?;
+            children[parent].push(i);
+            parents[i] = parent;
+        }
+
+        // the file entry of a file with several links is written once, with their number,
+        // and the FIDs of all links point at it
+        let mut links: HashMap<LBAddr, u16> = HashMap::new();
+        for node in snapshot.nodes.iter().filter(|n| !n.is_dir()) {
+            *links.entry(node.icb).or_default() += 1;
+        }
+
+        let mut report = RebuildReport::default();
+        for (i, node) in snapshot.nodes.iter().enumerate() {
+            if node.is_dir() {
+                let parent = &snapshot.nodes[parents[i]];
+                let mut fids = vec![fid(0x0a, parent.icb, "")?];
+                for &child in &children[i] {
+                    let child = &snapshot.nodes[child];
+                    let chars = if child.is_dir() { 0x02 } else { 0 };
+                    let name = child.path.rsplit_once('/').unwrap().1;
+                    fids.push(fid(chars, child.icb, name)?);
+                }
+                let subdirs = children[i]
+                    .iter()
+                    .filter(|&&c| snapshot.nodes[c].is_dir())
+                    .count() as u16;
+                self.write_dir(node, fids, 1 + subdirs, &mut report)?;
+            } else if let Some(link_count) = links.remove(&node.icb) {
+                let (ads, embedded) = match &node.embedded {
+                    Some(data) => (data.clone(), true),
+                    None => {
+                        let mut ads = vec![0; 16 * node.extents.len()];
+                        for (ext, ad) in node.extents.iter().zip(ads.chunks_mut(16)) {
+                            let len = ext.len | (ext.ext_type as u32) << 30;
+                            put_long_ad(ad, len, ext.loc);
+                        }
+                        (ads, false)
+                    }
+                };
+                self.write_fe(node, link_count, node.info_len, &ads, embedded, &mut report)?;
+            }
+        }
+        self.write_fsd(snapshot, &snapshot.nodes[root])?;
+        report.blocks += 1;
+
+        self.invalidate_all();
+        self.file_set_desc = None;
+        self.root_icb = None;
+        Ok(report)
+    }
+
+    /// writes the FIDs of the directory `node` into its recorded extents, or embedded in its
+    /// file entry if it had none, followed by the file entry itself
+    fn write_dir(
+        &mut self,
+        node: &TreeNode,
+        fids: Vec<Vec<u8>>,
+        link_count: u16,
+        report: &mut RebuildReport,
+    ) -> Result<(), Box<dyn Error>> {
+        let len: usize = fids.iter().map(Vec::len).sum();
+        report.directories += 1;
+        if node.extents.is_empty() {
+            if FE_AD_OFFSET + len > BS {
+                Err(format!(
+                    "directory {} doesn't fit into its file entry",
+                    node.path
+                ))?
+            }
+            let mut data = Vec::with_capacity(len);
+            for mut fid in fids {
+                let real_len = 38 + fid[19] as usize;
+                write_tag(&mut fid, 257, self.desc_version(), node.icb.lbn, real_len);
+                data.extend(fid);
+            }
+            return self.write_fe(node, link_count, len as u64, &data, true, report);
+        }
+
+        // block addresses of the recorded extents in order, each with its byte offset
+        let mut blocks = Vec::new();
+        for ext in node.extents.iter().filter(|e| e.ext_type == 0) {
+            for i in 0..(ext.len as u64).div_ceil(BLOCKSIZE) {
+                blocks.push((ext.loc.lbn + i as u32, ext.offset + i * BLOCKSIZE));
+            }
+        }
+        if len > blocks.len() * BS {
+            Err(format!(
+                "directory {} no longer fits its extents",
+                node.path
+            ))?
+        }
+        let mut data = vec![0; len.next_multiple_of(BS)];
+        let mut pos = 0;
+        for mut fid in fids {
+            let real_len = 38 + fid[19] as usize;
+            let loc = blocks[pos / BS].0;
+            write_tag(&mut fid, 257, self.desc_version(), loc, real_len);
+            data[pos..pos + fid.len()].copy_from_slice(&fid);
+            pos += fid.len();
+        }
+        for (block, (_, offset)) in data.chunks(BS).zip(&blocks) {
+            self.io.seek(SeekFrom::Start(*offset))?;
+            self.io.write_all(block)?;
+            report.blocks += 1;
+        }
+
+        // shorten the extents to the new size of the directory
+        let mut ads = Vec::new();
+        let mut remaining = len as u64;
+        for ext in node.extents.iter().filter(|e| e.ext_type == 0) {
+            if remaining == 0 {
+                break;
+            }
+            let ext_len = remaining.min(ext.len as u64);
+            let mut ad = [0; 16];
+            put_long_ad(&mut ad, ext_len as u32, ext.loc);
+            ads.extend(ad);
+            remaining -= ext_len;
+        }
+        self.write_fe(node, link_count, len as u64, &ads, false, report)
+    }
+
+    /// writes the file entry of `node` holding either long allocation descriptors or
+    /// embedded data
+    fn write_fe(
+        &mut self,
+        node: &TreeNode,
+        link_count: u16,
+        info_len: u64,
+        ads: &[u8],
+        embedded: bool,
+        report: &mut RebuildReport,
+    ) -> Result<(), Box<dyn Error>> {
+        if FE_AD_OFFSET + ads.len() > BS {
+            Err(format!("allocation descriptors of {} don't fit", node.path))?
+        }
+        let mut fe = [0; BS];
+        // ICB tag: strategy 4, a single entry
+        put_u16(&mut fe, 20, 4);
+        put_u16(&mut fe, 24, 1);
+        fe[27] = node.file_type as u8;
+        put_u16(&mut fe, 34, if embedded { 3 } else { 1 });
+        put_u32(&mut fe, 36, node.uid);
+        put_u32(&mut fe, 40, node.gid);
+        put_u32(&mut fe, 44, node.permissions);
+        put_u16(&mut fe, 48, link_count);
+        put_u64(&mut fe, 56, info_len);
+        let recorded: u64 = node
+            .extents
+            .iter()
+            .filter(|e| e.ext_type == 0)
+            .map(|e| (e.len as u64).div_ceil(BLOCKSIZE))
+            .sum();
+        put_u64(&mut fe, 64, recorded);
+        put_timestamp(&mut fe[72..84], &node.atime);
+        put_timestamp(&mut fe[84..96], &node.mtime);
+        put_timestamp(&mut fe[96..108], &node.attrtime);
+        put_u32(&mut fe, 108, 1);
+        put_regid(&mut fe[128..160], "*libudf-rs", &[]);
+        put_u64(&mut fe, 160, report.file_entries as u64 + 16);
+        put_u32(&mut fe, 172, ads.len() as u32);
+        fe[FE_AD_OFFSET..FE_AD_OFFSET + ads.len()].copy_from_slice(ads);
+        write_tag(
+            &mut fe,
+            261,
+            self.desc_version(),
+            node.icb.lbn,
+            FE_AD_OFFSET + ads.len(),
+        );
+        let lsn = self.lb_to_sector(&node.icb)?;
+        self.write_sectors(lsn, &fe)?;
+        report.file_entries += 1;
+        report.blocks += 1;
+        Ok(())
+    }
+
+    /// writes the file set descriptor pointed to by the logical volume descriptor
+    fn write_fsd(
+        &mut self,
+        snapshot: &TreeSnapshot,
+        root: &TreeNode,
+    ) -> Result<(), Box<dyn Error>> {
+        let fsd_ext = LongAD::parse_le(&self.logical_vol_desc.lv_contents_use)
+            .or(Err("error parsing FSD pointer."))?
+            .1;
+        let mut fsd = [0; BS];
+        put_timestamp(&mut fsd[16..28], &snapshot.volume.record_time);
+        put_u16(&mut fsd, 28, 3);
+        put_u16(&mut fsd, 30, 3);
+        put_u32(&mut fsd, 32, 1);
+        put_u32(&mut fsd, 36, 1);
+        put_osta_charspec(&mut fsd[48..112]);
+        put_dstring(&mut fsd[112..240], &snapshot.volume.lv_ident)?;
+        put_osta_charspec(&mut fsd[240..304]);
+        put_dstring(&mut fsd[304..336], &snapshot.fs_ident)?;
+        put_long_ad(&mut fsd[400..416], BS as u32, root.icb);
+        put_regid(
+            &mut fsd[416..448],
+            "*OSTA UDF Compliant",
+            &snapshot.volume.udf_revision.to_le_bytes(),
+        );
+        write_tag(&mut fsd, 256, self.desc_version(), fsd_ext.loc.lbn, 512);
+        let lsn = self.lb_to_sector(&fsd_ext.loc)?;
+        self.write_sectors(lsn, &fsd)
+    }
+}
@@ -0,0 +1,154 @@
+/*
+    Saving the blocks found by `UDF::orphan_blocks` to be allocated but unused, which often
+    hold the data of deleted files. Every contiguous run of such blocks is written to a file
+    of its own, named after its number and the type its first bytes suggest.
+*/
+
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{Read, Seek, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::stats::IoCategory;
+use crate::{tag_checksum, BLOCKSIZE, UDF};
+
+/// bytes read and written at once while exporting a run
+const EXPORT_CHUNK: usize = 256 * BLOCKSIZE as usize;
+
+/// the kind of data a run of blocks probably holds
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TypeHint {
+    pub name: &'static str,
+    pub extension: &'static str,
+}
+
+/// signatures at the start of common file formats, with the name and extension of the format.
+/// Two byte signatures label too much random data, gzip is only recognised with deflate as
+/// its compression method.
+const SIGNATURES: [(&[u8], &str, &str); 9] = [
+    (b"\x89PNG\r\n\x1a\n", "PNG image", "png"),
+    (b"\xff\xd8\xff", "JPEG image", "jpg"),
+    (b"GIF8", "GIF image", "gif"),
+    (b"%PDF-", "PDF document", "pdf"),
+    (b"PK\x03\x04", "ZIP archive", "zip"),
+    (b"\x1f\x8b\x08", "gzip data", "gz"),
+    (b"RIFF", "RIFF media", "riff"),
+    (b"\x00\x00\x01\xba", "MPEG program stream", "mpg"),
+    (b"\x7fELF", "ELF executable", "elf"),
+];
+
+const EXECUTABLE: TypeHint = TypeHint {
+    name: "Windows executable",
+    extension: "exe",
+};
+
+const DESCRIPTOR: TypeHint = TypeHint {
+    name: "UDF descriptor",
+    extension: "desc",
+};
+
+const TEXT: TypeHint = TypeHint {
+    name: "text",
+    extension: "txt",
+};
+
+/// guesses the type of data starting with `data`, `None` if nothing is recognised
+pub fn type_hint(data: &[u8]) -> Option<TypeHint> {
+    if let Some(&(_, name, extension)) = SIGNATURES.iter().find(|(sig, ..)| data.starts_with(sig)) {
+        return Some(TypeHint { name, extension });
+    }
+    // an MZ header only counts with the PE header it points to
+    if data.starts_with(b"MZ") && data.len() >= 0x40 {
+        let pe = u32::from_le_bytes(data[0x3c..0x40].try_into().unwrap()) as usize;
+        if data.get(pe..pe.saturating_add(4)) == Some(b"PE\0\0") {
+            return Some(EXECUTABLE);
+        }
+    }
+    if data.len() >= 16 {
+        let id = u16::from_le_bytes([data[0], data[1]]);
+        if matches!(id, 1..=9 | 256..=266) && tag_checksum(data) == data[4] {
+            return Some(DESCRIPTOR);
+        }
+    }
+    // text up to the first NUL, allowing for a character cut off at the end
+    let text = &data[..data.iter().position(|&b| b == 0).unwrap_or(data.len())];
+    let valid = match std::str::from_utf8(text) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    };
+    let printable = text
+        .iter()
+        .all(|&b| b >= 0x20 || b == b'\n' || b == b'\r' || b == b'\t');
+    if !text.is_empty() && valid && printable {
+        return Some(TEXT);
+    }
+    None
+}
+
+/// a run of orphaned blocks saved to a file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecoveredRun {
+    /// number of the run, counting from 1
+    pub index: usize,
+    pub part_num: u16,
+    /// blocks within the partition
+    pub blocks: Range<u32>,
+    pub hint: Option<TypeHint>,
+    pub path: PathBuf,
+}
+
+impl<IO: Read + Seek> UDF<IO> {
+    /// writes every run of blocks that is allocated but unused to a file in `dest`, which is
+    /// created if needed. The files are named after the number of the run, with the
+    /// extension of its type hint or `bin`.
+    pub fn export_orphans(&mut self, dest: &Path) -> Result<Vec<RecoveredRun>, Box<dyn Error>> {
+        let orphans = self.orphan_blocks()?;
+        fs::create_dir_all(dest)?;
+        let mut runs = Vec::new();
+        for part in orphans {
+            let part_start = self
+                .part_maps
+                .iter()
+                .filter_map(|map| map.pd.as_ref())
+                .find(|pd| pd.part_num == part.part_num)
+                .ok_or("partition of orphaned blocks not found")?
+                .part_start;
+            for blocks in part.unreferenced {
+                let index = runs.len() + 1;
+                let part_pos = part_start as u64 * self.sector_size();
+                let bs = self.block_size();
+                let block_pos = |block: u32| {
+                    part_pos
+                        .checked_add(block as u64 * bs)
+                        .ok_or("orphaned blocks beyond the end of the medium")
+                };
+                let mut pos = block_pos(blocks.start)?;
+                let end = block_pos(blocks.end)?;
+                let mut buf = vec![0; EXPORT_CHUNK.min((end - pos) as usize)];
+                self.read_bytes(IoCategory::Data, pos, &mut buf)?;
+                let hint = type_hint(&buf);
+                let extension = hint.map_or("bin", |hint| hint.extension);
+                let path = dest.join(format!("{:04}.{}", index, extension));
+                let mut file = File::create(&path)?;
+                loop {
+                    file.write_all(&buf)?;
+                    pos += buf.len() as u64;
+                    if pos >= end {
+                        break;
+                    }
+                    buf.truncate(EXPORT_CHUNK.min((end - pos) as usize));
+                    self.read_bytes(IoCategory::Data, pos, &mut buf)?;
+                }
+                runs.push(RecoveredRun {
+                    index,
+                    part_num: part.part_num,
+                    blocks,
+                    hint,
+                    path,
+                });
+            }
+        }
+        Ok(runs)
+    }
+}
@@ -0,0 +1,141 @@
+/*
+    Summaries of the contents of a volume for quick triage: the largest files, the space
+    taken up per file extension and files with duplicate contents.
+*/
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::io::{Read, Seek};
+
+use crate::catalogue::{CatalogueEntry, ContentHasher};
+use crate::file::ICB;
+use crate::UDF;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExtensionStats {
+    /// lowercase extension without the dot, empty for files without one
+    pub extension: String,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct SizeReport {
+    /// the largest files, largest first
+    pub largest: Vec<CatalogueEntry>,
+    /// all extensions, those taking up the most space first
+    pub extensions: Vec<ExtensionStats>,
+    pub total_files: usize,
+    pub total_bytes: u64,
+}
+
+/// files with identical contents
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DuplicateGroup {
+    pub size: u64,
+    pub hash: String,
+    /// paths of all files in the group, in catalogue order
+    pub paths: Vec<String>,
+}
+
+impl DuplicateGroup {
+    /// bytes that could be saved by storing the contents only once
+    pub fn redundant_bytes(&self) -> u64 {
+        self.size * (self.paths.len() as u64 - 1)
+    }
+}
+
+/// lowercase extension of the last component of `path`, ignoring leading dots
+pub fn extension(path: &str) -> String {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    match name.trim_start_matches('.').rsplit_once('.') {
+        Some((_, ext)) => ext.to_lowercase(),
+        None => String::new(),
+    }
+}
+
+impl<IO: Read + Seek> UDF<IO> {
+    /// lists the `n` largest files of the current file set and the number and size of
+    /// files per extension. A file with several links counts once, under its first path.
+    pub fn size_report(&mut self, n: usize) -> Result<SizeReport, Box<dyn Error>> {
+        let (catalogue, icbs) = self.catalogue_icbs()?;
+        let mut seen = HashSet::new();
+        let mut files: Vec<CatalogueEntry> = catalogue
+            .entries
+            .into_iter()
+            .zip(icbs)
+            .filter(|(e, icb)| !e.is_dir && seen.insert(icb.loc))
+            .map(|(e, _)| e)
+            .collect();
+        let mut by_ext: HashMap<String, ExtensionStats> = HashMap::new();
+        for file in &files {
+            let ext = extension(&file.path);
+            let stats = by_ext.entry(ext.clone()).or_insert(ExtensionStats {
+                extension: ext,
+                files: 0,
+                bytes: 0,
+            });
+            stats.files += 1;
+            stats.bytes += file.size;
+        }
+        let total_files = files.len();
+        let total_bytes = files.iter().map(|f| f.size).sum();
+        files.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+        files.truncate(n);
+        let mut extensions: Vec<ExtensionStats> = by_ext.into_values().collect();
+        extensions.sort_by(|a, b| {
+            b.bytes
+                .cmp(&a.bytes)
+                .then_with(|| a.extension.cmp(&b.extension))
+        });
+        Ok(SizeReport {
+            largest: files,
+            extensions,
+            total_files,
+            total_bytes,
+        })
+    }
+
+    /// finds files with identical contents. Files are grouped by size first, so that only
+    /// files sharing their size with another file are hashed with `hasher`. Empty files are
+    /// ignored, as are further links of a file, which share its data rather than copy it.
+    /// Groups with the most redundant bytes come first.
+    pub fn duplicates(
+        &mut self,
+        hasher: &mut dyn ContentHasher,
+    ) -> Result<Vec<DuplicateGroup>, Box<dyn Error>> {
+        let mut by_size: HashMap<u64, Vec<(String, ICB)>> = HashMap::new();
+        let mut files = HashSet::new();
+        let (catalogue, icbs) = self.catalogue_icbs()?;
+        for (entry, icb) in catalogue.entries.into_iter().zip(icbs) {
+            if !entry.is_dir && entry.size > 0 && files.insert(icb.loc) {
+                by_size
+                    .entry(entry.size)
+                    .or_default()
+                    .push((entry.path, icb));
+            }
+        }
+        let mut groups = Vec::new();
+        for (size, paths) in by_size.into_iter().filter(|(_, p)| p.len() > 1) {
+            let mut by_hash: Vec<DuplicateGroup> = Vec::new();
+            for (path, icb) in paths {
+                let hash = self.hash_contents(&icb, hasher)?;
+                match by_hash.iter_mut().find(|g| g.hash == hash) {
+                    Some(group) => group.paths.push(path),
+                    None => by_hash.push(DuplicateGroup {
+                        size,
+                        hash,
+                        paths: vec![path],
+                    }),
+                }
+            }
+            groups.extend(by_hash.into_iter().filter(|g| g.paths.len() > 1));
+        }
+        groups.sort_by(|a, b| {
+            b.redundant_bytes()
+                .cmp(&a.redundant_bytes())
+                .then_with(|| a.paths.cmp(&b.paths))
+        });
+        Ok(groups)
+    }
+}
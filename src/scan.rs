@@ -0,0 +1,203 @@
+/*
+    Low-level traversal of a volume reporting every descriptor read as an event, with the
+    sector it is recorded in: the recognition sequence, the anchors, both volume descriptor
+    sequences, the integrity sequence, the file set descriptors and then the ICBs and file
+    identifiers of the current file set, directory by directory. Tools like visualizers or
+    anomaly detectors can build on this one traversal instead of knowing the layout.
+*/
+
+use std::collections::{HashSet, VecDeque};
+use std::error::Error;
+use std::io::{Read, Seek, SeekFrom};
+
+use log::error;
+use nom_derive::Parse;
+
+use crate::file::{EntryKind, FID, FSD, ICB};
+use crate::stats::IoCategory;
+use crate::volume::{Tag, TagID, AVD, LSN, LVD, LVID, PD, PVD};
+use crate::{check_tag_crc, check_tag_loc, read_avd, read_avd_at, read_sector, UDF};
+
+/// a descriptor found while scanning a volume
+pub enum Descriptor {
+    /// a volume structure descriptor of the recognition sequence, with its identifier
+    VolumeStructure([u8; 5]),
+    Anchor(AVD),
+    Primary(PVD),
+    Partition(PD),
+    LogicalVolume(LVD),
+    /// any other descriptor of a volume descriptor sequence, which is not parsed further
+    Volume(Tag),
+    /// the terminating descriptor of a volume descriptor sequence
+    Terminating,
+    Integrity(LVID),
+    FileSet(FSD),
+    Icb(ICB),
+    FileIdentifier(FID),
+}
+
+impl Descriptor {
+    /// short name of the kind of descriptor
+    pub fn name(&self) -> &'static str {
+        match self {
+            Descriptor::VolumeStructure(_) => "VSD",
+            Descriptor::Anchor(_) => "AVD",
+            Descriptor::Primary(_) => "PVD",
+            Descriptor::Partition(_) => "PD",
+            Descriptor::LogicalVolume(_) => "LVD",
+            Descriptor::Volume(tag) => match tag.tag_id {
+                TagID::VD => "VDP",
+                TagID::IUVD => "IUVD",
+                TagID::USD => "USD",
+                _ => "unknown",
+            },
+            Descriptor::Terminating => "TD",
+            Descriptor::Integrity(_) => "LVID",
+            Descriptor::FileSet(_) => "FSD",
+            Descriptor::Icb(_) => "ICB",
+            Descriptor::FileIdentifier(_) => "FID",
+        }
+    }
+}
+
+/// a descriptor and the sector it starts in
+pub struct ScanEvent {
+    /// sector the descriptor was read from, which the location recorded in its tag may
+    /// contradict
+    pub lsn: LSN,
+    pub descriptor: Descriptor,
+}
+
+impl<IO: Read + Seek> UDF<IO> {
+    /// walks the whole volume, calling `f` with every descriptor read, from the recognition
+    /// sequence to the file set. Directories and ICBs that can't be read are logged and
+    /// skipped, as are volume descriptors failing their tag checks.
+    pub fn scan(&mut self, mut f: impl FnMut(ScanEvent)) -> Result<(), Box<dyn Error>> {
+        self.scan_vrs(&mut f)?;
+        self.scan_volume(&mut f)?;
+        self.walk_integrity_seq(|lsn, lvid| {
+            f(ScanEvent {
+                lsn,
+                descriptor: Descriptor::Integrity(lvid),
+            })
+        })?;
+        for (loc, fsd) in self.located_file_sets()? {
+            f(ScanEvent {
+                lsn: self.lb_to_sector(&loc)?,
+                descriptor: Descriptor::FileSet(fsd),
+            });
+        }
+        self.scan_file_set(&mut f)
+    }
+
+    fn scan_vrs(&mut self, f: &mut impl FnMut(ScanEvent)) -> Result<(), Box<dyn Error>> {
+        for (lsn, desc) in self.recognition_sequence()?.descriptors {
+            f(ScanEvent {
+                lsn,
+                descriptor: Descriptor::VolumeStructure(*desc.ident()),
+            });
+        }
+        Ok(())
+    }
+
+    fn scan_volume(&mut self, f: &mut impl FnMut(ScanEvent)) -> Result<(), Box<dyn Error>> {
+        let num_sectors = (self.io.seek(SeekFrom::End(0))? / self.sector_size()) as LSN;
+        let anchors = [
+            Some(self.options.session_start + 256),
+            num_sectors.checked_sub(256),
+            num_sectors.checked_sub(1),
+        ];
+        let mut seen = HashSet::new();
+        for lsn in anchors.into_iter().flatten() {
+            if !seen.insert(lsn) {
+                continue;
+            }
+            if let Ok(avd) = read_avd_at(&mut self.io, lsn, &self.options) {
+                f(ScanEvent {
+                    lsn,
+                    descriptor: Descriptor::Anchor(avd),
+                });
+            }
+        }
+        let avd = read_avd(&mut self.io, &self.options)?;
+        for vds in [&avd.main_vds, &avd.reserve_vds] {
+            let num_sectors = (vds.len as u64).div_ceil(self.sector_size()) as u32;
+            for lsn in vds.loc..vds.loc.saturating_add(num_sectors) {
+                match self.scan_volume_desc(lsn) {
+                    Ok(Some(Descriptor::Terminating)) => {
+                        f(ScanEvent {
+                            lsn,
+                            descriptor: Descriptor::Terminating,
+                        });
+                        break;
+                    }
+                    Ok(Some(descriptor)) => f(ScanEvent { lsn, descriptor }),
+                    Ok(None) => break,
+                    Err(e) => error!("Error reading volume descriptor at {}: {}", lsn, e),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// the volume descriptor at `lsn`, `None` at the end of the sequence
+    fn scan_volume_desc(&mut self, lsn: LSN) -> Result<Option<Descriptor>, Box<dyn Error>> {
+        let sector_size = self.sector_size();
+        let mut buf = vec![0; sector_size as usize];
+        self.record_read(IoCategory::Metadata, lsn as u64 * sector_size, buf.len());
+        read_sector(&mut self.io, sector_size, lsn, &mut buf)?;
+        let tag = match Tag::parse(&buf) {
+            Ok((_, tag)) if tag.tag_id != TagID::UNK => tag,
+            _ => return Ok(None),
+        };
+        check_tag_crc(&self.options, "volume descriptor", &buf)?;
+        check_tag_loc(self.options.strict, "volume descriptor", tag.tag_loc, lsn)?;
+        Ok(Some(match tag.tag_id {
+            TagID::PVD => Descriptor::Primary(PVD::parse(&buf).or(Err("error parsing PVD"))?.1),
+            TagID::PD => Descriptor::Partition(PD::parse(&buf).or(Err("error parsing PD"))?.1),
+            TagID::LVD => {
+                Descriptor::LogicalVolume(LVD::parse(&buf).or(Err("error parsing LVD"))?.1)
+            }
+            TagID::TD => Descriptor::Terminating,
+            _ => Descriptor::Volume(tag),
+        }))
+    }
+
+    /// the ICBs and FIDs of the current file set, breadth first
+    fn scan_file_set(&mut self, f: &mut impl FnMut(ScanEvent)) -> Result<(), Box<dyn Error>> {
+        let root = self.get_root_dir()?;
+        let mut visited = HashSet::from([root.loc]);
+        let mut queue = VecDeque::from([root]);
+        while let Some(icb) = queue.pop_front() {
+            let is_dir = icb.kind() == EntryKind::Dir;
+            let dir_loc = icb.loc;
+            f(ScanEvent {
+                lsn: self.lb_to_sector(&dir_loc)?,
+                descriptor: Descriptor::Icb(icb.clone()),
+            });
+            if !is_dir {
+                continue;
+            }
+            let sector_size = self.sector_size();
+            for (fid, loc, in_block) in icb.located_fids(self) {
+                let child = fid.icb.loc;
+                // neither deleted nor the parent directory
+                let follow = fid.file_bits & 0x0c == 0 && visited.insert(child);
+                match self.lb_to_sector(&loc) {
+                    Ok(lsn) => f(ScanEvent {
+                        lsn: lsn + (in_block / sector_size) as LSN,
+                        descriptor: Descriptor::FileIdentifier(fid),
+                    }),
+                    Err(e) => error!("Error locating FID of {:?}: {}", child, e),
+                }
+                if follow {
+                    match self.read_icb(&child) {
+                        Ok(icb) => queue.push_back(icb),
+                        Err(e) => error!("Error reading ICB at {:?}: {}", child, e),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,90 @@
+/*
+    Guessing where the last session of a multisession image starts, for dumps that lost the
+    table of contents of the disc. Every session records an anchor 256 sectors after its
+    start and usually a volume recognition sequence 16 sectors after it, so every sector
+    holding an anchor hints at a session start 256 sectors before it.
+*/
+
+use std::error::Error;
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::volume::LSN;
+use crate::vrs::read_vrs;
+use crate::{is_anchor, read_sector, read_volume, UdfOptions, BLOCKSIZE};
+
+/// sectors read at once while looking for anchors
+const SCAN_CHUNK: usize = 64;
+
+/// a sector a session might start at
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SessionCandidate {
+    pub start: LSN,
+    /// a volume recognition sequence with an NSR descriptor is recorded 16 sectors after
+    /// `start`
+    pub vrs: bool,
+    /// the volume descriptors the anchor points to can be read in strict mode
+    pub opens: bool,
+}
+
+impl SessionCandidate {
+    /// whether the candidate looks like an actual session start rather than e.g. one of the
+    /// anchors at the end of a volume
+    pub fn is_plausible(&self) -> bool {
+        self.vrs && self.opens
+    }
+}
+
+/// looks for anchors at every sector `step` sectors apart, starting at 256, and returns the
+/// session starts they imply in ascending order. With a `step` of e.g. 16 or 32, the packet
+/// size of the disc, only sessions aligned to packets are found but far less is read. The
+/// last plausible candidate is the best guess for `UdfOptions::session_start`.
+pub fn scan_session_starts<IO: Read + Seek>(
+    io: &mut IO,
+    step: u32,
+) -> Result<Vec<SessionCandidate>, Box<dyn Error>> {
+    if step == 0 {
+        Err("the scan step has to be at least one sector")?
+    }
+    let num_sectors = (io.seek(SeekFrom::End(0))? / BLOCKSIZE) as LSN;
+    let mut starts = Vec::new();
+    let mut buf = vec![0; SCAN_CHUNK * BLOCKSIZE as usize];
+    let mut lsn = 256;
+    while lsn < num_sectors {
+        if step == 1 {
+            // read consecutive sectors in chunks instead of one by one
+            let n = (num_sectors - lsn).min(SCAN_CHUNK as LSN);
+            let chunk = &mut buf[..n as usize * BLOCKSIZE as usize];
+            read_sector(io, BLOCKSIZE, lsn, chunk)?;
+            for (i, sector) in chunk.chunks(BLOCKSIZE as usize).enumerate() {
+                if is_anchor(sector, lsn + i as LSN) {
+                    starts.push(lsn + i as LSN - 256);
+                }
+            }
+            lsn += n;
+        } else {
+            let sector = &mut buf[..BLOCKSIZE as usize];
+            read_sector(io, BLOCKSIZE, lsn, sector)?;
+            if is_anchor(sector, lsn) {
+                starts.push(lsn - 256);
+            }
+            lsn = match lsn.checked_add(step) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+    }
+
+    let mut candidates = Vec::with_capacity(starts.len());
+    for start in starts {
+        let options = UdfOptions {
+            session_start: start,
+            ..Default::default()
+        };
+        candidates.push(SessionCandidate {
+            start,
+            vrs: read_vrs(io, start, BLOCKSIZE).is_ok_and(|vrs| vrs.is_udf()),
+            opens: read_volume(io, &options).is_ok(),
+        });
+    }
+    Ok(candidates)
+}
@@ -0,0 +1,102 @@
+/*
+    I/O statistics of a volume: reads, blocks, bytes and seeks issued to the medium, split into
+    metadata (descriptors, ICBs, directories) and file data, and the hit rate of the metadata
+    cache. Reads of the volume structures while opening the volume aren't counted.
+*/
+
+use std::io::{Read, Seek};
+
+use crate::file::{FileType, ICB};
+use crate::UDF;
+
+/// what a read was issued for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IoCategory {
+    Metadata,
+    Data,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct IoCounters {
+    /// read requests issued to the medium
+    pub reads: u64,
+    /// blocks touched by the reads
+    pub blocks: u64,
+    pub bytes: u64,
+    /// reads that didn't continue where the previous read ended
+    pub seeks: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct IoStats {
+    pub metadata: IoCounters,
+    pub data: IoCounters,
+    /// ICBs and directory listings found in the metadata cache
+    pub cache_hits: u64,
+    /// ICBs and directory listings that had to be read from the medium
+    pub cache_misses: u64,
+    /// reads repeated after a failed read
+    pub retries: u64,
+}
+
+impl IoStats {
+    /// counters of both categories added up
+    pub fn total(&self) -> IoCounters {
+        IoCounters {
+            reads: self.metadata.reads + self.data.reads,
+            blocks: self.metadata.blocks + self.data.blocks,
+            bytes: self.metadata.bytes + self.data.bytes,
+            seeks: self.metadata.seeks + self.data.seeks,
+        }
+    }
+}
+
+impl ICB {
+    /// the category reads of the data described by this ICB are counted in
+    pub(crate) fn io_category(&self) -> IoCategory {
+        match self.icb_tag.file_type {
+            FileType::DIR
+            | FileType::STREAMDIR
+            | FileType::VAT
+            | FileType::METAMAIN
+            | FileType::METAMIRROR
+            | FileType::METABITMAP => IoCategory::Metadata,
+            _ => IoCategory::Data,
+        }
+    }
+}
+
+impl<IO: Read + Seek> UDF<IO> {
+    pub fn io_stats(&self) -> IoStats {
+        self.stats
+    }
+
+    pub fn reset_io_stats(&mut self) {
+        self.stats = IoStats::default();
+    }
+
+    /// counts a read of `len` bytes at byte offset `pos` of the medium
+    pub(crate) fn record_read(&mut self, category: IoCategory, pos: u64, len: usize) {
+        let bs = self.options.sector_bytes();
+        let counters = match category {
+            IoCategory::Metadata => &mut self.stats.metadata,
+            IoCategory::Data => &mut self.stats.data,
+        };
+        let end = pos + len as u64;
+        counters.reads += 1;
+        counters.bytes += len as u64;
+        counters.blocks += end.div_ceil(bs) - pos / bs;
+        if self.io_pos != Some(pos) {
+            counters.seeks += 1;
+        }
+        self.io_pos = Some(end);
+    }
+
+    pub(crate) fn record_cache(&mut self, hit: bool) {
+        if hit {
+            self.stats.cache_hits += 1;
+        } else {
+            self.stats.cache_misses += 1;
+        }
+    }
+}
@@ -0,0 +1,85 @@
+/*
+    Named streams (UDF 2.00 3.3.5): files and directories recorded with an Extended File Entry
+    can point to a stream directory, whose entries are the streams of the file. Streams are
+    addressed like Windows does, `/dir/file:stream`.
+*/
+
+use std::error::Error;
+use std::io::{ErrorKind, Read, Seek};
+use std::path::Path;
+
+use crate::file::{LBAddr, ICB};
+use crate::path::{PathComponent, UdfPath};
+use crate::UDF;
+
+impl<IO: Read + Seek> UDF<IO> {
+    /// looks up `path` in the syntax of `UdfPath::parse_with_stream`, i.e. a plain path or a
+    /// path followed by `:` and the name of one of its streams
+    pub fn open_path(&mut self, path: &str) -> Result<ICB, Box<dyn Error>> {
+        self.lookup(&UdfPath::parse_with_stream(path)?)
+    }
+
+    /// looks up the stream `stream` of the file or directory at `path`
+    pub fn find_stream(&mut self, path: &Path, stream: &str) -> Result<ICB, Box<dyn Error>> {
+        let mut path = UdfPath::from_path(path)?;
+        path.stream = Some(stream.to_string());
+        self.lookup(&path)
+    }
+
+    /// names of the streams of the file or directory at `path`
+    pub fn streams(&mut self, path: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut path = UdfPath::from_path(path)?;
+        let loc = match path.components.pop() {
+            // the entry is located through its directory, without reading its own ICB
+            Some(PathComponent::Name(name)) => {
+                let dir_only = std::mem::replace(&mut path.dir_only, true);
+                let dir = self.lookup(&path)?;
+                let entry = dir
+                    .read_entries(self)?
+                    .into_iter()
+                    .find(|e| !e.is_parent() && e.matches(self.options.name_domain, &name))
+                    .ok_or_else(|| std::io::Error::from(ErrorKind::NotFound))?;
+                if dir_only && !entry.is_dir() {
+                    Err(std::io::Error::from(ErrorKind::NotADirectory))?
+                }
+                entry.icb
+            }
+            Some(PathComponent::Parent) => {
+                path.components.push(PathComponent::Parent);
+                self.lookup(&path)?.loc
+            }
+            None => self.get_root_dir()?.loc,
+        };
+        let Some(dir) = self.stream_dir(&loc)? else {
+            return Ok(Vec::new());
+        };
+        Ok(dir
+            .read_entries(self)?
+            .into_iter()
+            .filter(|e| !e.is_deleted() && !e.is_parent())
+            .map(|e| e.name)
+            .collect())
+    }
+
+    /// reads the ICB of the stream `name` of the file entry at `loc`
+    pub(crate) fn stream_icb(&mut self, loc: &LBAddr, name: &str) -> Result<ICB, Box<dyn Error>> {
+        let dir = self
+            .stream_dir(loc)?
+            .ok_or_else(|| std::io::Error::new(ErrorKind::NotFound, "file has no streams"))?;
+        dir.read_entries(self)?
+            .into_iter()
+            .find(|e| !e.is_deleted() && !e.is_parent() && e.name == name)
+            .ok_or_else(|| std::io::Error::new(ErrorKind::NotFound, "no such stream"))?
+            .resolve(self)
+    }
+
+    /// reads the stream directory ICB of the file entry at `loc`. Only Extended File Entries
+    /// record one.
+    fn stream_dir(&mut self, loc: &LBAddr) -> Result<Option<ICB>, Box<dyn Error>> {
+        let icb = self.read_icb(loc)?;
+        let Some(ad) = icb.file_entry().and_then(|fe| fe.stream_dir_icb()) else {
+            return Ok(None);
+        };
+        Ok(Some(self.read_icb(&ad.loc)?))
+    }
+}
@@ -0,0 +1,101 @@
+/*
+    Modification of images through a copy: the image is copied to a temporary file next to it,
+    the copy is modified and only once that succeeded and the copy is synced to disk, it
+    replaces the original with a rename. A failure or crash at any point leaves the original
+    untouched.
+
+    On Linux the copy is made as a reflink where the file system supports them, e.g. Btrfs and
+    XFS, which shares the data of both files until it is modified, so that even images of many
+    GB are copied instantly and without taking up space.
+*/
+
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{UdfOptions, UDF};
+
+/// how a file was copied
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CopyMethod {
+    /// a copy-on-write clone sharing the data of the original
+    Reflink,
+    /// a full copy of the data
+    Copy,
+}
+
+/// clones `from` into the new file `to` where the file system supports reflinks, or copies it
+/// otherwise. The copy gets the permissions of `from`.
+pub fn clone_file(from: &Path, to: &Path) -> io::Result<CopyMethod> {
+    let source = File::open(from)?;
+    let dest = OpenOptions::new().write(true).create_new(true).open(to)?;
+    let result = copy_data(&source, dest).and_then(|method| {
+        fs::set_permissions(to, source.metadata()?.permissions())?;
+        Ok(method)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(to);
+    }
+    result
+}
+
+fn copy_data(mut source: &File, mut dest: File) -> io::Result<CopyMethod> {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::io::AsRawFd;
+        // SAFETY: FICLONE takes the descriptor of the source file as its argument
+        if unsafe { libc::ioctl(dest.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) } == 0 {
+            return Ok(CopyMethod::Reflink);
+        }
+    }
+    io::copy(&mut source, &mut dest)?;
+    Ok(CopyMethod::Copy)
+}
+
+/// the temporary file next to `path` modifications are made in
+fn temp_path(path: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| format!("{}: not a file", path.display()))?;
+    let mut temp = std::ffi::OsString::from(".");
+    temp.push(name);
+    temp.push(format!(".tmp{}", std::process::id()));
+    Ok(path.with_file_name(temp))
+}
+
+/// runs `modify` on the volume of a copy of the image at `path` and, if it succeeds, replaces
+/// the image with the copy. The original is left untouched if opening the copy or `modify`
+/// fails. Returns the result of `modify` and how the copy was made.
+pub fn modify_copy<T, F>(
+    path: &Path,
+    options: UdfOptions,
+    modify: F,
+) -> Result<(T, CopyMethod), Box<dyn Error>>
+where
+    F: FnOnce(&mut UDF<File>) -> Result<T, Box<dyn Error>>,
+{
+    let temp = temp_path(path)?;
+    let method = clone_file(path, &temp).map_err(|e| format!("{}: {}", temp.display(), e))?;
+    let result = (|| -> Result<T, Box<dyn Error>> {
+        let file = OpenOptions::new().read(true).write(true).open(&temp)?;
+        let mut udf = UDF::new_with_options(file, options)?;
+        let result = modify(&mut udf)?;
+        udf.get_mut().sync_all()?;
+        Ok(result)
+    })();
+    match result {
+        Ok(result) => {
+            fs::rename(&temp, path)?;
+            // make the rename itself durable, not supported on every platform
+            if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                let _ = File::open(dir).and_then(|dir| dir.sync_all());
+            }
+            Ok((result, method))
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&temp);
+            Err(e)
+        }
+    }
+}
@@ -0,0 +1,599 @@
+/*
+    Synthesizes small UDF images in memory for tests.
+
+    Layout in units of 2048 bytes: VRS at 16, main VDS at 32, reserve VDS at 48 and LVID
+    at 64, or at 40 for sectors smaller than 2048 bytes, whose sector 256 is the AVD. The AVD
+    is at sector 256 and the partition starts at sector 257, whatever the sector size.
+    Logical blocks default to one sector but may span several.
+    Virtual (VAT) images append one session per generation, each ending with its VAT ICB.
+    Images with a metadata partition record the ICBs of the metadata file and its mirror in
+    blocks 0 and 1 of the partition, followed by the two copies of the metadata.
+*/
+
+use crate::{write_tag, BLOCKSIZE};
+
+const BS: usize = BLOCKSIZE as usize;
+pub const PART_START: u32 = 257;
+/// blocks of the metadata file and of its mirror
+pub const META_BLOCKS: u32 = 32;
+
+pub enum Node {
+    File(Vec<u8>),
+    Dir(Vec<(String, Node)>),
+}
+impl Node {
+    pub fn file(data: &[u8]) -> Self {
+        Node::File(data.to_vec())
+    }
+
+    pub fn dir(entries: Vec<(&str, Node)>) -> Self {
+        Node::Dir(
+            entries
+                .into_iter()
+                .map(|(name, node)| (name.to_string(), node))
+                .collect(),
+        )
+    }
+
+    fn count(&self) -> (u32, u32) {
+        match self {
+            Node::File(_) => (1, 0),
+            Node::Dir(entries) => entries.iter().fold((0, 1), |acc, (_, node)| {
+                let (files, dirs) = node.count();
+                (acc.0 + files, acc.1 + dirs)
+            }),
+        }
+    }
+}
+
+fn put_u16(buf: &mut [u8], off: usize, v: u16) {
+    buf[off..off + 2].copy_from_slice(&v.to_le_bytes());
+}
+
+fn put_u32(buf: &mut [u8], off: usize, v: u32) {
+    buf[off..off + 4].copy_from_slice(&v.to_le_bytes());
+}
+
+fn put_u64(buf: &mut [u8], off: usize, v: u64) {
+    buf[off..off + 8].copy_from_slice(&v.to_le_bytes());
+}
+
+fn put_dstring(buf: &mut [u8], s: &str) {
+    buf[0] = 8;
+    buf[1..1 + s.len()].copy_from_slice(s.as_bytes());
+    let last = buf.len() - 1;
+    buf[last] = s.len() as u8 + 1;
+}
+
+fn put_regid(buf: &mut [u8], ident: &str, suffix: &[u8]) {
+    buf[1..1 + ident.len()].copy_from_slice(ident.as_bytes());
+    buf[24..24 + suffix.len()].copy_from_slice(suffix);
+}
+
+fn put_long_ad(buf: &mut [u8], len: u32, lbn: u32, part_ref: u16) {
+    put_u32(buf, 0, len);
+    put_u32(buf, 4, lbn);
+    put_u16(buf, 8, part_ref);
+}
+
+/// Image under construction, together with the allocation state of the current session
+pub struct ImageBuilder {
+    pub data: Vec<u8>,
+    /// descriptor version, 2 for NSR02 and 3 for NSR03 volumes
+    pub version: u16,
+    /// UDF revision recorded in the domain identifier
+    pub revision: u16,
+    /// sector size
+    bs: usize,
+    /// logical block size, a multiple of the sector size
+    lbs: usize,
+    /// next free block of the physical partition
+    next_lbn: u32,
+    /// VAT of the session being written, if the volume has a virtual partition
+    vat: Option<Vec<u32>>,
+    /// packet length and relocated packets of a sparable partition, with the first sector of
+    /// the spare packet each is relocated to
+    sparing: Option<(u16, Vec<(u32, u32)>)>,
+    /// next free block of the metadata file, if the volume has a metadata partition
+    metadata: Option<u32>,
+    unique_id: u64,
+}
+
+impl ImageBuilder {
+    pub fn new(version: u16, revision: u16) -> Self {
+        Self {
+            data: Vec::new(),
+            version,
+            revision,
+            bs: BS,
+            lbs: BS,
+            next_lbn: 0,
+            vat: None,
+            sparing: None,
+            metadata: None,
+            unique_id: 16,
+        }
+    }
+
+    pub fn sector(&mut self, lsn: u32) -> &mut [u8] {
+        let end = (lsn as usize + 1) * self.bs;
+        if self.data.len() < end {
+            self.data.resize(end, 0);
+        }
+        &mut self.data[end - self.bs..end]
+    }
+
+    /// the sector at `unit` units of 2048 bytes
+    fn unit(&self, unit: u32) -> u32 {
+        (unit as usize * BS / self.bs) as u32
+    }
+
+    /// byte offset of the physical block `lbn`
+    fn block_pos(&self, lbn: u32) -> usize {
+        PART_START as usize * self.bs + lbn as usize * self.lbs
+    }
+
+    fn block(&mut self, lbn: u32) -> &mut [u8] {
+        let start = self.block_pos(lbn);
+        if self.data.len() < start + self.lbs {
+            self.data.resize(start + self.lbs, 0);
+        }
+        &mut self.data[start..start + self.lbs]
+    }
+
+    /// allocates `n` contiguous blocks for metadata (ICBs and directories), returning the
+    /// address used to refer to them and the physical block
+    fn alloc_meta(&mut self, n: u32) -> ((u32, u16), u32) {
+        if let Some(next) = &mut self.metadata {
+            let lbn = *next;
+            *next += n;
+            assert!(*next <= META_BLOCKS, "metadata file full");
+            return ((lbn, 1), 2 + lbn);
+        }
+        let phys = self.alloc_data(n);
+        match &mut self.vat {
+            Some(vat) => {
+                let virt = vat.len() as u32;
+                vat.extend(phys..phys + n);
+                ((virt, 1), phys)
+            }
+            None => ((phys, 0), phys),
+        }
+    }
+
+    fn alloc_data(&mut self, n: u32) -> u32 {
+        let lbn = self.next_lbn;
+        self.next_lbn += n;
+        self.block(lbn + n.max(1) - 1);
+        lbn
+    }
+
+    /// writes a file entry at physical block `phys` with tag location `tag_loc`
+    #[allow(clippy::too_many_arguments)]
+    fn write_fe(
+        &mut self,
+        phys: u32,
+        tag_loc: u32,
+        file_type: u8,
+        flags: u16,
+        link_count: u16,
+        info_len: u64,
+        ads: &[u8],
+    ) {
+        let (version, lbs) = (self.version, self.lbs);
+        self.unique_id += 1;
+        let unique_id = self.unique_id;
+        let fe = self.block(phys);
+        fe.fill(0);
+        // ICB tag
+        put_u16(fe, 20, 4);
+        put_u16(fe, 24, 1);
+        fe[27] = file_type;
+        put_u16(fe, 34, flags);
+        put_u32(fe, 36, u32::MAX);
+        put_u32(fe, 40, u32::MAX);
+        put_u32(fe, 44, 0x14a5);
+        put_u16(fe, 48, link_count);
+        put_u64(fe, 56, info_len);
+        put_u64(fe, 64, info_len.div_ceil(lbs as u64));
+        put_regid(&mut fe[128..160], "*libudf-rs", &[]);
+        put_u64(fe, 160, unique_id);
+        put_u32(fe, 172, ads.len() as u32);
+        fe[176..176 + ads.len()].copy_from_slice(ads);
+        write_tag(fe, 261, version, tag_loc, 176 + ads.len());
+    }
+
+    /// writes `node` and returns the address of its file entry
+    fn write_node(&mut self, node: &Node, parent: Option<(u32, u16)>) -> (u32, u16) {
+        let (addr, fe_phys) = self.alloc_meta(1);
+        match node {
+            Node::File(content) => {
+                let ads = if content.is_empty() {
+                    Vec::new()
+                } else {
+                    let lbn = self.alloc_data(content.len().div_ceil(self.lbs) as u32);
+                    let start = self.block_pos(lbn);
+                    self.data[start..start + content.len()].copy_from_slice(content);
+                    let mut ad = vec![0; 16];
+                    put_long_ad(&mut ad, content.len() as u32, lbn, 0);
+                    ad
+                };
+                self.write_fe(fe_phys, addr.0, 5, 1, 1, content.len() as u64, &ads);
+            }
+            Node::Dir(entries) => {
+                let mut fids = vec![self.fid(0x0a, parent.unwrap_or(addr), "")];
+                let mut subdirs = 0;
+                for (name, child) in entries {
+                    let child_addr = self.write_node(child, Some(addr));
+                    let chars = match child {
+                        Node::Dir(_) => {
+                            subdirs += 1;
+                            0x02
+                        }
+                        Node::File(_) => 0x00,
+                    };
+                    fids.push(self.fid(chars, child_addr, name));
+                }
+                self.write_dir((addr, fe_phys), fids, subdirs);
+            }
+        }
+        addr
+    }
+
+    /// writes the directory data of `fids` and the file entry of the directory at `fe`
+    fn write_dir(&mut self, fe: ((u32, u16), u32), mut fids: Vec<Vec<u8>>, subdirs: u16) {
+        let len: usize = fids.iter().map(Vec::len).sum();
+        let n = len.div_ceil(self.lbs) as u32;
+        let (dir_addr, dir_phys) = self.alloc_meta(n);
+        // FIDs are tagged with the block they start in
+        let mut pos = 0;
+        for fid in &mut fids {
+            let loc = dir_addr.0 + (pos / self.lbs) as u32;
+            let real_len = 38 + fid[19] as usize;
+            write_tag(fid, 257, self.version, loc, real_len);
+            let start = self.block_pos(dir_phys) + pos;
+            self.data[start..start + fid.len()].copy_from_slice(fid);
+            pos += fid.len();
+        }
+        let mut ad = vec![0; 8];
+        put_u32(&mut ad, 0, len as u32);
+        put_u32(&mut ad, 4, dir_addr.0);
+        self.write_fe(fe.1, fe.0 .0, 4, 0, 1 + subdirs, len as u64, &ad);
+    }
+
+    /// builds an untagged FID, the tag is written once its location is known
+    fn fid(&self, chars: u8, icb: (u32, u16), name: &str) -> Vec<u8> {
+        let name_len = if name.is_empty() { 0 } else { name.len() + 1 };
+        let len = (38 + name_len).next_multiple_of(4);
+        let mut fid = vec![0; len];
+        put_u16(&mut fid, 16, 1);
+        fid[18] = chars;
+        fid[19] = name_len as u8;
+        put_long_ad(&mut fid[20..36], self.lbs as u32, icb.0, icb.1);
+        if !name.is_empty() {
+            fid[38] = 8;
+            fid[39..39 + name.len()].copy_from_slice(name.as_bytes());
+        }
+        fid
+    }
+
+    /// writes the file set descriptor and the tree below `root`, returning the FSD address
+    pub fn write_file_set(&mut self, root: &Node) -> (u32, u16) {
+        let (fsd_addr, fsd_phys) = self.alloc_meta(1);
+        let root_addr = self.write_node(root, None);
+        self.write_fsd(fsd_addr, fsd_phys, root_addr);
+        fsd_addr
+    }
+
+    /// writes the file set descriptor at `fsd_addr` for the root directory at `root_addr`
+    fn write_fsd(&mut self, fsd_addr: (u32, u16), fsd_phys: u32, root_addr: (u32, u16)) {
+        let (version, revision, lbs) = (self.version, self.revision, self.lbs);
+        let fsd = self.block(fsd_phys);
+        put_u16(fsd, 28, 3);
+        put_u16(fsd, 30, 3);
+        put_u32(fsd, 32, 1);
+        put_u32(fsd, 36, 1);
+        put_dstring(&mut fsd[112..240], "TESTVOL");
+        put_dstring(&mut fsd[304..336], "TESTFS");
+        put_long_ad(&mut fsd[400..416], lbs as u32, root_addr.0, root_addr.1);
+        put_regid(
+            &mut fsd[416..448],
+            "*OSTA UDF Compliant",
+            &revision.to_le_bytes(),
+        );
+        write_tag(fsd, 256, version, fsd_addr.0, 512);
+    }
+
+    /// writes the recognition sequence, volume descriptor sequences, LVID and anchor for a
+    /// partition spanning all blocks allocated so far
+    pub fn write_volume(&mut self, fsd: (u32, u16), virtual_part: bool, num: (u32, u32)) {
+        let (version, revision, bs, lbs) = (self.version, self.revision, self.bs, self.lbs);
+        let part_len = self.next_lbn;
+        let nsr = if version == 2 { "NSR02" } else { "NSR03" };
+        // volume structure descriptors are 2048 bytes long, or one sector if larger
+        let vsd_sectors = BS.div_ceil(bs) as u32;
+        let vrs_start = self.unit(16);
+        for (i, ident) in ["BEA01", nsr, "TEA01"].into_iter().enumerate() {
+            let vrs = self.sector(vrs_start + i as u32 * vsd_sectors);
+            vrs[1..6].copy_from_slice(ident.as_bytes());
+            vrs[6] = 1;
+        }
+        let lvid_lsn = self.unit(if bs < BS { 40 } else { 64 });
+
+        let (main, reserve) = (self.unit(32), self.unit(48));
+        let sparing_tables = [self.unit(80), self.unit(96)];
+        let sparing = self.sparing.clone();
+        let metadata = self.metadata.is_some();
+        for start in [main, reserve] {
+            let pvd = self.sector(start);
+            put_u16(pvd, 56, 1);
+            put_u16(pvd, 58, 1);
+            put_u16(pvd, 60, 2);
+            put_u16(pvd, 62, 3);
+            put_dstring(&mut pvd[24..56], "TESTVOL");
+            put_dstring(&mut pvd[72..200], "TESTSET");
+            write_tag(pvd, 1, version, start, 512);
+
+            let pd = self.sector(start + 1);
+            put_u16(pd, 20, 1);
+            put_regid(&mut pd[24..56], &format!("+{}", nsr), &[]);
+            put_u32(pd, 184, 1);
+            put_u32(pd, 188, PART_START);
+            put_u32(pd, 192, part_len * (lbs / bs) as u32);
+            write_tag(pd, 5, version, start + 1, 512);
+
+            let lvd = self.sector(start + 2);
+            put_dstring(&mut lvd[84..212], "TESTVOL");
+            put_u32(lvd, 212, lbs as u32);
+            put_regid(
+                &mut lvd[216..248],
+                "*OSTA UDF Compliant",
+                &revision.to_le_bytes(),
+            );
+            put_long_ad(&mut lvd[248..264], lbs as u32, fsd.0, fsd.1);
+            put_u32(lvd, 432, 2 * bs as u32);
+            put_u32(lvd, 436, lvid_lsn);
+            lvd[440..446].copy_from_slice(&[1, 6, 1, 0, 0, 0]);
+            let map_len = if virtual_part {
+                let map = &mut lvd[446..510];
+                map[0] = 2;
+                map[1] = 64;
+                put_regid(
+                    &mut map[4..36],
+                    "*UDF Virtual Partition",
+                    &revision.to_le_bytes(),
+                );
+                put_u16(map, 36, 1);
+                70
+            } else if let Some((packet_len, _)) = &sparing {
+                // the sparable map replaces the type 1 map
+                let map = &mut lvd[440..504];
+                map.fill(0);
+                map[0] = 2;
+                map[1] = 64;
+                put_regid(
+                    &mut map[4..36],
+                    "*UDF Sparable Partition",
+                    &revision.to_le_bytes(),
+                );
+                put_u16(map, 36, 1);
+                put_u16(map, 40, *packet_len);
+                map[42] = sparing_tables.len() as u8;
+                put_u32(map, 44, bs as u32);
+                for (i, lsn) in sparing_tables.iter().enumerate() {
+                    put_u32(map, 48 + 4 * i, *lsn);
+                }
+                64
+            } else if metadata {
+                let map = &mut lvd[446..510];
+                map[0] = 2;
+                map[1] = 64;
+                put_regid(
+                    &mut map[4..36],
+                    "*UDF Metadata Partition",
+                    &revision.to_le_bytes(),
+                );
+                put_u16(map, 36, 1);
+                put_u32(map, 40, 0);
+                put_u32(map, 44, 1);
+                put_u32(map, 48, u32::MAX);
+                put_u32(map, 52, META_BLOCKS);
+                put_u16(map, 56, 1);
+                70
+            } else {
+                6
+            };
+            put_u32(lvd, 264, map_len);
+            put_u32(lvd, 268, if virtual_part || metadata { 2 } else { 1 });
+            write_tag(lvd, 6, version, start + 2, 440 + map_len as usize);
+
+            let td = self.sector(start + 3);
+            write_tag(td, 8, version, start + 3, 512);
+        }
+
+        if let Some((_, entries)) = &sparing {
+            for lsn in sparing_tables {
+                let table = self.sector(lsn);
+                put_regid(
+                    &mut table[16..48],
+                    "*UDF Sparing Table",
+                    &revision.to_le_bytes(),
+                );
+                put_u16(table, 48, entries.len() as u16);
+                put_u32(table, 52, 1);
+                for (i, (original, mapped)) in entries.iter().enumerate() {
+                    put_u32(table, 56 + 8 * i, *original);
+                    put_u32(table, 60 + 8 * i, *mapped);
+                }
+                write_tag(table, 0, version, lsn, 56 + 8 * entries.len());
+            }
+        }
+
+        let lvid = self.sector(lvid_lsn);
+        put_u32(lvid, 28, 1);
+        put_u32(lvid, 72, 1);
+        put_u32(lvid, 76, 46);
+        put_u32(lvid, 84, part_len);
+        put_regid(&mut lvid[88..120], "*libudf-rs", &[]);
+        put_u32(lvid, 120, num.0);
+        put_u32(lvid, 124, num.1);
+        put_u16(lvid, 128, revision);
+        put_u16(lvid, 130, revision);
+        put_u16(lvid, 132, revision);
+        write_tag(lvid, 9, version, lvid_lsn, 134);
+        let td = self.sector(lvid_lsn + 1);
+        write_tag(td, 8, version, lvid_lsn + 1, 512);
+
+        let avd = self.sector(256);
+        put_u32(avd, 16, 16 * BS as u32);
+        put_u32(avd, 20, main);
+        put_u32(avd, 24, 16 * BS as u32);
+        put_u32(avd, 28, reserve);
+        write_tag(avd, 2, version, 256, 512);
+    }
+
+    /// writes the VAT of the current session as the last block, returning its location. UDF
+    /// 1.50 VATs have no header, the entries are followed by an identifier and the location of
+    /// the previous VAT.
+    fn write_vat(&mut self, prev: Option<u32>, num: (u32, u32)) -> u32 {
+        let entries = self.vat.take().unwrap_or_default();
+        if self.revision < 0x0200 {
+            let mut vat: Vec<u8> = entries.iter().flat_map(|e| e.to_le_bytes()).collect();
+            let mut regid = [0; 32];
+            put_regid(
+                &mut regid,
+                "*UDF Virtual Alloc Tbl",
+                &self.revision.to_le_bytes(),
+            );
+            vat.extend(regid);
+            vat.extend(prev.unwrap_or(u32::MAX).to_le_bytes());
+            let lbn = self.alloc_data(1);
+            self.write_fe(lbn, lbn, 0, 3, 1, vat.len() as u64, &vat);
+            return lbn;
+        }
+        let mut vat = vec![0; 152];
+        put_u16(&mut vat, 0, 152);
+        put_dstring(&mut vat[4..132], "TESTVOL");
+        put_u32(&mut vat, 132, prev.unwrap_or(u32::MAX));
+        put_u32(&mut vat, 136, num.0);
+        put_u32(&mut vat, 140, num.1);
+        put_u16(&mut vat, 144, self.revision);
+        put_u16(&mut vat, 146, self.revision);
+        put_u16(&mut vat, 148, self.revision);
+        for entry in entries {
+            vat.extend(entry.to_le_bytes());
+        }
+        let lbn = self.alloc_data(1);
+        // embedded allocation, the VAT is recorded inside its ICB
+        self.write_fe(lbn, lbn, 248, 3, 1, vat.len() as u64, &vat);
+        lbn
+    }
+}
+
+/// builds a single session UDF 2.01 image containing `root`
+pub fn build(root: &Node) -> Vec<u8> {
+    build_with_sector_size(root, BS)
+}
+
+/// builds a single session UDF 2.01 image containing `root` with sectors of `bs` bytes
+pub fn build_with_sector_size(root: &Node, bs: usize) -> Vec<u8> {
+    build_with_block_size(root, bs, bs)
+}
+
+/// builds a single session UDF 2.01 image containing `root` with sectors of `bs` bytes and
+/// logical blocks of `lbs` bytes
+pub fn build_with_block_size(root: &Node, bs: usize, lbs: usize) -> Vec<u8> {
+    let mut img = ImageBuilder::new(3, 0x0201);
+    img.bs = bs;
+    img.lbs = lbs;
+    let fsd = img.write_file_set(root);
+    img.write_volume(fsd, false, root.count());
+    img.data
+}
+
+/// builds a single session UDF 2.01 image whose root directory holds `n` entries named
+/// `file000000` and so on, all links to the same empty file
+pub fn build_wide_dir(n: usize) -> Vec<u8> {
+    build_links(n, b"")
+}
+
+/// builds an image like `build_wide_dir` whose entries are links to a file holding `data`
+pub fn build_links(n: usize, data: &[u8]) -> Vec<u8> {
+    let mut img = ImageBuilder::new(3, 0x0201);
+    let (fsd_addr, fsd_phys) = img.alloc_meta(1);
+    let root = img.alloc_meta(1);
+    let target = img.write_node(&Node::file(data), Some(root.0));
+    let mut fids = vec![img.fid(0x0a, root.0, "")];
+    fids.extend((0..n).map(|i| img.fid(0, target, &format!("file{:06}", i))));
+    img.write_dir(root, fids, 0);
+    img.write_fsd(fsd_addr, fsd_phys, root.0);
+    img.write_volume(fsd_addr, false, (1, 1));
+    img.data
+}
+
+/// builds an image like `build` with a sparable partition of packets of `packet_len` blocks.
+/// The packets starting at the blocks `relocated` are moved to spare packets following the
+/// partition, leaving zeros behind.
+pub fn build_sparable(root: &Node, packet_len: u16, relocated: &[u32]) -> Vec<u8> {
+    let mut img = ImageBuilder::new(3, 0x0201);
+    let fsd = img.write_file_set(root);
+    let mut entries = Vec::new();
+    let spare_start = PART_START + img.next_lbn;
+    for (i, &lbn) in relocated.iter().enumerate() {
+        let spare = spare_start + (i as u32) * packet_len as u32;
+        for block in 0..packet_len as u32 {
+            let data = img.block(lbn + block).to_vec();
+            img.block(lbn + block).fill(0);
+            img.sector(spare + block).copy_from_slice(&data);
+        }
+        entries.push((lbn, spare));
+    }
+    entries.sort();
+    img.sparing = Some((packet_len, entries));
+    img.write_volume(fsd, false, root.count());
+    img.data
+}
+
+/// builds a single session UDF 2.50 image containing `root` with a metadata partition whose
+/// metadata file and mirror hold the same data
+pub fn build_metadata(root: &Node) -> Vec<u8> {
+    let mut img = ImageBuilder::new(3, 0x0250);
+    img.next_lbn = 2 + 2 * META_BLOCKS;
+    img.metadata = Some(0);
+    img.block(img.next_lbn - 1);
+    let fsd = img.write_file_set(root);
+    let len = META_BLOCKS as usize * img.lbs;
+    let main = img.block_pos(2);
+    img.data.copy_within(main..main + len, main + len);
+    for (phys, file_type, start) in [(0, 250, 2), (1, 251, 2 + META_BLOCKS)] {
+        let mut ad = vec![0; 8];
+        put_u32(&mut ad, 0, len as u32);
+        put_u32(&mut ad, 4, start);
+        img.write_fe(phys, phys, file_type, 0, 1, len as u64, &ad);
+    }
+    img.write_volume(fsd, false, root.count());
+    img.data
+}
+
+/// builds a sequentially recorded image with a virtual partition, recording every tree of
+/// `generations` in its own session with its own VAT. Each session rewrites the complete tree.
+pub fn build_vat(generations: &[Node]) -> Vec<u8> {
+    build_vat_with_revision(generations, 0x0201)
+}
+
+/// builds an image like `build_vat` following UDF `revision`, e.g. 0x0150 for the VAT layout
+/// of UDF 1.50
+pub fn build_vat_with_revision(generations: &[Node], revision: u16) -> Vec<u8> {
+    let version = if revision < 0x0200 { 2 } else { 3 };
+    let mut img = ImageBuilder::new(version, revision);
+    let mut prev_vat = None;
+    let mut fsd = (0, 1);
+    for root in generations {
+        img.vat = Some(Vec::new());
+        fsd = img.write_file_set(root);
+        prev_vat = Some(img.write_vat(prev_vat, root.count()));
+    }
+    let num = generations.last().map(Node::count).unwrap_or_default();
+    img.write_volume(fsd, true, num);
+    img.data
+}
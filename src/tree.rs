@@ -0,0 +1,396 @@
+/*
+    Serialized snapshot of the directory tree and file metadata of a volume, without file
+    data. Snapshots can be compared over time, and since they record where the data of every
+    file is located, they can be used to recover files from a volume whose metadata has been
+    damaged since.
+*/
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::io::{Read, Seek};
+use std::path::{Path, PathBuf};
+
+use log::warn;
+use nom_derive::Parse;
+
+use crate::catalogue::VolumeInfo;
+use crate::extract::is_safe_name;
+use crate::file::{disambiguate, AllocType, EntryKind, FileType, LBAddr, ICB};
+use crate::stats::IoCategory;
+use crate::volume::Timestamp;
+use crate::{BLOCKSIZE, UDF};
+
+const MAGIC: &[u8; 8] = b"UDFTREE\x02";
+/// snapshots written before the sector size was recorded, all of 2048 byte sectors
+const MAGIC_V1: &[u8; 8] = b"UDFTREE\x01";
+
+/// an extent of a file as recorded in its allocation descriptors
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TreeExtent {
+    /// byte offset on the medium
+    pub offset: u64,
+    pub len: u32,
+    /// address of the first block
+    pub loc: LBAddr,
+    /// extent type of the allocation descriptor, extents other than recorded ones (0) read
+    /// as zeros
+    pub ext_type: u8,
+}
+
+#[derive(Clone, Debug)]
+pub struct TreeNode {
+    /// absolute path, `/` for the root directory
+    pub path: String,
+    pub file_type: FileType,
+    pub icb: LBAddr,
+    pub uid: u32,
+    pub gid: u32,
+    pub permissions: u32,
+    pub info_len: u64,
+    pub atime: Timestamp,
+    pub mtime: Timestamp,
+    pub attrtime: Timestamp,
+    pub extents: Vec<TreeExtent>,
+    /// data embedded in the ICB of files, which is lost together with the ICB
+    pub embedded: Option<Vec<u8>>,
+}
+
+impl TreeNode {
+    pub fn kind(&self) -> EntryKind {
+        self.file_type.into()
+    }
+
+    pub fn is_dir(&self) -> bool {
+        matches!(self.file_type, FileType::DIR)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TreeSnapshot {
+    pub volume: VolumeInfo,
+    pub fs_ident: String,
+    /// all files and directories, each directory followed by its contents
+    pub nodes: Vec<TreeNode>,
+}
+
+fn put_str(out: &mut Vec<u8>, s: &str) {
+    out.extend((s.len() as u32).to_le_bytes());
+    out.extend(s.as_bytes());
+}
+
+fn put_timestamp(out: &mut Vec<u8>, t: &Timestamp) {
+    out.extend(t.to_bytes());
+}
+
+/// cursor over a serialized snapshot
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Box<dyn Error>> {
+        if self.0.len() < n {
+            Err("truncated tree snapshot")?
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, Box<dyn Error>> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Box<dyn Error>> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, Box<dyn Error>> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, Box<dyn Error>> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    fn str(&mut self) -> Result<String, Box<dyn Error>> {
+        let len = self.u32()? as usize;
+        Ok(String::from_utf8(self.take(len)?.to_vec())?)
+    }
+
+    fn timestamp(&mut self) -> Result<Timestamp, Box<dyn Error>> {
+        let (_, t) = Timestamp::parse(self.take(12)?).or(Err("error parsing timestamp"))?;
+        Ok(t)
+    }
+}
+
+impl TreeSnapshot {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        put_str(&mut out, &self.volume.vol_ident);
+        put_str(&mut out, &self.volume.vol_set_ident);
+        put_str(&mut out, &self.volume.lv_ident);
+        out.extend(self.volume.udf_revision.to_le_bytes());
+        put_timestamp(&mut out, &self.volume.record_time);
+        out.extend((self.volume.sector_size as u32).to_le_bytes());
+        put_str(&mut out, &self.fs_ident);
+        out.extend((self.nodes.len() as u32).to_le_bytes());
+        for node in &self.nodes {
+            put_str(&mut out, &node.path);
+            out.push(node.file_type as u8);
+            out.extend(node.icb.lbn.to_le_bytes());
+            out.extend(node.icb.part_ref_nr.to_le_bytes());
+            out.extend(node.uid.to_le_bytes());
+            out.extend(node.gid.to_le_bytes());
+            out.extend(node.permissions.to_le_bytes());
+            out.extend(node.info_len.to_le_bytes());
+            put_timestamp(&mut out, &node.atime);
+            put_timestamp(&mut out, &node.mtime);
+            put_timestamp(&mut out, &node.attrtime);
+            out.extend((node.extents.len() as u32).to_le_bytes());
+            for ext in &node.extents {
+                out.extend(ext.offset.to_le_bytes());
+                out.extend(ext.len.to_le_bytes());
+                out.extend(ext.loc.lbn.to_le_bytes());
+                out.extend(ext.loc.part_ref_nr.to_le_bytes());
+                out.push(ext.ext_type);
+            }
+            match &node.embedded {
+                Some(data) => {
+                    out.push(1);
+                    out.extend((data.len() as u32).to_le_bytes());
+                    out.extend(data);
+                }
+                None => out.push(0),
+            }
+        }
+        out
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let mut r = Reader(data);
+        let v1 = match r.take(MAGIC.len())? {
+            magic if magic == MAGIC => false,
+            magic if magic == MAGIC_V1 => true,
+            _ => return Err("not a tree snapshot".into()),
+        };
+        let mut volume = VolumeInfo {
+            vol_ident: r.str()?,
+            vol_set_ident: r.str()?,
+            lv_ident: r.str()?,
+            udf_revision: r.u16()?,
+            record_time: r.timestamp()?,
+            sector_size: BLOCKSIZE,
+        };
+        if !v1 {
+            volume.sector_size = r.u32()? as u64;
+        }
+        let fs_ident = r.str()?;
+        let count = r.u32()?;
+        let mut nodes = Vec::new();
+        for _ in 0..count {
+            let path = r.str()?;
+            let (_, file_type) =
+                FileType::parse(r.take(1)?).or(Err("invalid file type in tree snapshot"))?;
+            let icb = LBAddr {
+                lbn: r.u32()?,
+                part_ref_nr: r.u16()?,
+            };
+            let uid = r.u32()?;
+            let gid = r.u32()?;
+            let permissions = r.u32()?;
+            let info_len = r.u64()?;
+            let atime = r.timestamp()?;
+            let mtime = r.timestamp()?;
+            let attrtime = r.timestamp()?;
+            let mut extents = Vec::new();
+            for _ in 0..r.u32()? {
+                extents.push(TreeExtent {
+                    offset: r.u64()?,
+                    len: r.u32()?,
+                    loc: LBAddr {
+                        lbn: r.u32()?,
+                        part_ref_nr: r.u16()?,
+                    },
+                    ext_type: r.u8()?,
+                });
+            }
+            let embedded = match r.u8()? {
+                0 => None,
+                _ => {
+                    let len = r.u32()? as usize;
+                    Some(r.take(len)?.to_vec())
+                }
+            };
+            nodes.push(TreeNode {
+                path,
+                file_type,
+                icb,
+                uid,
+                gid,
+                permissions,
+                info_len,
+                atime,
+                mtime,
+                attrtime,
+                extents,
+                embedded,
+            });
+        }
+        if !r.0.is_empty() {
+            Err("trailing data after tree snapshot")?
+        }
+        Ok(Self {
+            volume,
+            fs_ident,
+            nodes,
+        })
+    }
+}
+
+impl<IO: Read + Seek> UDF<IO> {
+    /// records the directory tree of the current file set with the metadata of every file
+    /// and directory and the location of its data
+    pub fn tree_snapshot(&mut self) -> Result<TreeSnapshot, Box<dyn Error>> {
+        let (catalogue, icbs) = self.catalogue_icbs()?;
+        let fs_ident = match &self.file_set_desc {
+            Some(fsd) => fsd.fs_id.to_string(),
+            None => String::new(),
+        };
+        let mut nodes = Vec::new();
+        for (entry, icb) in catalogue.entries.into_iter().zip(&icbs) {
+            nodes.push(self.tree_node(entry.path, icb)?);
+        }
+        Ok(TreeSnapshot {
+            volume: catalogue.volume,
+            fs_ident,
+            nodes,
+        })
+    }
+
+    /// recreates the directories and regular files of `snapshot` below `dest`, reading file
+    /// data from the extents recorded in the snapshot instead of the current metadata of the
+    /// volume. Names that only differ in case clash on hosts that ignore case, the later of
+    /// two clashing entries is restored under a name made unique by `disambiguate`, as are
+    /// entries recorded more than once. Entries whose names can't be used on the host are
+    /// logged and left out together with their contents.
+    pub fn restore_tree(
+        &mut self,
+        snapshot: &TreeSnapshot,
+        dest: &Path,
+    ) -> Result<(), Box<dyn Error>> {
+        // where the directories of the snapshot were restored to
+        let mut dirs: HashMap<&str, PathBuf> = HashMap::from([("", dest.to_path_buf())]);
+        // the paths restored so far in lower case
+        let mut restored = HashSet::new();
+        for node in &snapshot.nodes {
+            let (parent, name) = node.path.rsplit_once('/').unwrap_or(("", &node.path));
+            let Some(parent_path) = dirs.get(parent) else {
+                warn!("not restoring {}, its directory wasn't restored", node.path);
+                continue;
+            };
+            if node.path != "/" && !is_safe_name(name) {
+                warn!("not restoring {}, its name can't be used", node.path);
+                continue;
+            }
+            let mut path = parent_path.join(name);
+            let mut n = 1;
+            // a path restored before in another case only exists if the host ignores case
+            while restored.contains(&path.to_string_lossy().to_lowercase())
+                && path.symlink_metadata().is_ok()
+            {
+                n += 1;
+                path = parent_path.join(disambiguate(name, n));
+            }
+            if n > 1 {
+                warn!(
+                    "{} clashes with another entry, restoring it as {:?}",
+                    node.path, path
+                );
+            }
+            restored.insert(path.to_string_lossy().to_lowercase());
+            if matches!(node.file_type, FileType::DIR) {
+                dirs.insert(node.path.trim_end_matches('/'), path.clone());
+            }
+            match node.file_type {
+                FileType::DIR => fs::create_dir_all(&path)?,
+                FileType::BYTES => fs::write(&path, self.snapshot_data(node)?)?,
+                ty => warn!("not restoring {} of type {:?}", node.path, ty),
+            }
+        }
+        Ok(())
+    }
+
+    fn snapshot_data(&mut self, node: &TreeNode) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut data = match &node.embedded {
+            Some(data) => data.clone(),
+            None => {
+                // neither the information length nor the extent lengths are trusted to
+                // allocate more than the medium holds, as in `read_data_into`
+                let medium_len = self.medium_len()?;
+                let mut data = Vec::with_capacity(node.info_len.min(medium_len) as usize);
+                for ext in &node.extents {
+                    if data.len() as u64 >= node.info_len {
+                        break;
+                    }
+                    let start = data.len();
+                    let len = (start as u64 + ext.len as u64).min(node.info_len);
+                    if len > medium_len {
+                        Err(format!(
+                            "extents of {} exceed the size of the medium",
+                            node.path
+                        ))?
+                    }
+                    data.resize(len as usize, 0);
+                    if ext.ext_type == 0 {
+                        self.read_bytes(IoCategory::Data, ext.offset, &mut data[start..])?;
+                    }
+                }
+                data
+            }
+        };
+        data.truncate(node.info_len as usize);
+        Ok(data)
+    }
+
+    fn tree_node(&mut self, path: String, icb: &ICB) -> Result<TreeNode, Box<dyn Error>> {
+        let Some(fe) = icb.file_entry() else {
+            return Err(format!("{} has no file entry", path).into());
+        };
+        let file_type = icb.icb_tag.file_type;
+        let mut extents = Vec::new();
+        let mut embedded = None;
+        if matches!(icb.icb_tag.flags.get_alloc_type()?, AllocType::EMBEDDED) {
+            if !matches!(file_type, FileType::DIR) {
+                embedded = Some(icb.read_data(self)?);
+            }
+        } else {
+            for ad in icb.get_alloc_descs() {
+                // continuation extents hold further allocation descriptors, not data
+                if ad.ext_type() == 3 || ad.is_empty() {
+                    continue;
+                }
+                let (offset, len) = self.alloc_desc_to_offset_len(&ad, icb.loc.part_ref_nr)?;
+                extents.push(TreeExtent {
+                    offset,
+                    len,
+                    loc: ad.lb_addr(icb.loc.part_ref_nr),
+                    ext_type: ad.ext_type(),
+                });
+            }
+        }
+        Ok(TreeNode {
+            path,
+            file_type,
+            icb: icb.loc,
+            uid: fe.uid(),
+            gid: fe.gid(),
+            permissions: fe.permissions(),
+            info_len: fe.info_len(),
+            atime: fe.atime().clone(),
+            mtime: fe.mtime().clone(),
+            attrtime: fe.attrtime().clone(),
+            extents,
+            embedded,
+        })
+    }
+}
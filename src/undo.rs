@@ -0,0 +1,154 @@
+/*
+    Undo journal of in-place modifications: before a block of the image is overwritten for the
+    first time, its original contents are appended to a journal file and synced, so that the
+    image can be restored after a failed or unwanted modification, even after a crash.
+
+    The journal starts with a header line and the length of the image before it was modified,
+    followed by records of the byte offset, the length and the original contents of a block.
+    A record cut short by a crash is ignored, its block was never overwritten.
+*/
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use log::warn;
+
+use crate::{BLOCKSIZE, UDF};
+
+const MAGIC: &[u8] = b"libudf-rs undo 1\n";
+
+/// journal the original contents of overwritten blocks are recorded in
+#[derive(Debug)]
+pub(crate) struct UndoJournal {
+    file: File,
+    path: PathBuf,
+    /// length of the image before it was modified, blocks beyond it aren't recorded
+    original_len: u64,
+    /// blocks whose original contents are recorded
+    saved: HashSet<u64>,
+}
+
+impl UndoJournal {
+    fn create(path: &Path, original_len: u64) -> io::Result<Self> {
+        let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
+        file.write_all(MAGIC)?;
+        file.write_all(&original_len.to_le_bytes())?;
+        file.sync_data()?;
+        Ok(Self {
+            file,
+            path: path.to_path_buf(),
+            original_len,
+            saved: HashSet::new(),
+        })
+    }
+
+    /// records the original contents of the blocks touched by a write of `len` bytes at `pos`
+    /// that weren't recorded yet, reading them from `image`. Returns once the records are
+    /// synced to disk, the position of `image` is left undefined.
+    pub(crate) fn save<R: Read + Seek>(
+        &mut self,
+        image: &mut R,
+        pos: u64,
+        len: usize,
+    ) -> io::Result<()> {
+        let mut records = Vec::new();
+        let end = (pos + len as u64).min(self.original_len);
+        for block in pos / BLOCKSIZE..end.div_ceil(BLOCKSIZE) {
+            if !self.saved.insert(block) {
+                continue;
+            }
+            let start = block * BLOCKSIZE;
+            let mut data = vec![0; BLOCKSIZE.min(self.original_len - start) as usize];
+            image.seek(SeekFrom::Start(start))?;
+            image.read_exact(&mut data)?;
+            records.extend(start.to_le_bytes());
+            records.extend((data.len() as u32).to_le_bytes());
+            records.extend(data);
+        }
+        if !records.is_empty() {
+            self.file.write_all(&records)?;
+            self.file.sync_data()?;
+        }
+        Ok(())
+    }
+}
+
+/// restores the blocks recorded in `journal` in `image`. Returns the length of the image
+/// before it was modified, images that grew have to be truncated to it by the caller.
+pub fn rollback<W: Write + Seek>(
+    image: &mut W,
+    journal: &mut dyn Read,
+) -> Result<u64, Box<dyn Error>> {
+    let mut header = [0; MAGIC.len() + 8];
+    journal
+        .read_exact(&mut header)
+        .or(Err("not an undo journal"))?;
+    if &header[..MAGIC.len()] != MAGIC {
+        Err("not an undo journal")?
+    }
+    let original_len = u64::from_le_bytes(header[MAGIC.len()..].try_into().unwrap());
+    let mut records = Vec::new();
+    journal.read_to_end(&mut records)?;
+    let mut rest = &records[..];
+    while !rest.is_empty() {
+        let len = rest
+            .get(8..12)
+            .map(|len| u32::from_le_bytes(len.try_into().unwrap()) as usize);
+        let Some(data) = len.and_then(|len| rest.get(12..12 + len)) else {
+            warn!("Ignoring the incomplete last record of the undo journal");
+            break;
+        };
+        let pos = u64::from_le_bytes(rest[..8].try_into().unwrap());
+        image.seek(SeekFrom::Start(pos))?;
+        image.write_all(data)?;
+        rest = &rest[12 + data.len()..];
+    }
+    image.flush()?;
+    Ok(original_len)
+}
+
+/// restores the image file at `image` from the undo journal at `journal`, including its
+/// original length
+pub fn rollback_file(image: &Path, journal: &Path) -> Result<(), Box<dyn Error>> {
+    let mut file = OpenOptions::new().read(true).write(true).open(image)?;
+    let len = rollback(&mut file, &mut File::open(journal)?)?;
+    file.set_len(len)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+impl<IO: Read + Seek> UDF<IO> {
+    /// records the original contents of every block before it is first overwritten in a new
+    /// undo journal at `path`, until `end_undo_journal` is called. `rollback` and
+    /// `rollback_file` restore the image from the journal.
+    pub fn start_undo_journal(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let len = self.io.seek(SeekFrom::End(0))?;
+        let journal =
+            UndoJournal::create(path, len).map_err(|e| format!("{}: {}", path.display(), e))?;
+        self.io.set_journal(Some(Arc::new(Mutex::new(journal))));
+        Ok(())
+    }
+
+    /// stops recording overwritten blocks, returning the path of the journal
+    pub fn end_undo_journal(&mut self) -> Option<PathBuf> {
+        let journal = self.io.set_journal(None)?;
+        let path = journal.lock().unwrap().path.clone();
+        Some(path)
+    }
+}
+
+impl<IO: Read + Seek + Write> UDF<IO> {
+    /// restores the blocks recorded in the undo journal started with `start_undo_journal` and
+    /// re-reads the volume. Returns the length of the image before it was modified, images
+    /// that grew have to be truncated to it, e.g. with `File::set_len`.
+    pub fn rollback(&mut self) -> Result<u64, Box<dyn Error>> {
+        let path = self.end_undo_journal().ok_or("no undo journal")?;
+        let len = rollback(&mut *self.io, &mut File::open(&path)?)?;
+        self.refresh()?;
+        Ok(len)
+    }
+}
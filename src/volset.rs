@@ -0,0 +1,77 @@
+/*
+    Volume sets (ECMA-167 3/8.6): a logical collection recorded over several volumes, e.g. the
+    discs of a multi-disc backup, shares one volume set identifier while each volume records
+    its position in the set as the volume sequence number.
+*/
+
+use std::io::{Read, Seek};
+
+use crate::UDF;
+
+/// volumes of one volume set, in sequence number order
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VolumeSet {
+    pub ident: String,
+    /// highest sequence number any member claims the set to have
+    pub max_vol_seq_num: u16,
+    /// sequence numbers and the indices of the volumes recording them, as passed to
+    /// `group_volume_sets`
+    pub members: Vec<(u16, usize)>,
+}
+
+impl VolumeSet {
+    /// sequence numbers of the set none of the volumes records
+    pub fn missing(&self) -> Vec<u16> {
+        (1..=self.max_vol_seq_num)
+            .filter(|n| !self.members.iter().any(|(seq, _)| seq == n))
+            .collect()
+    }
+
+    /// sequence numbers recorded by more than one volume
+    pub fn duplicates(&self) -> Vec<u16> {
+        let mut dups: Vec<u16> = self
+            .members
+            .windows(2)
+            .filter(|w| w[0].0 == w[1].0)
+            .map(|w| w[0].0)
+            .collect();
+        dups.dedup();
+        dups
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.missing().is_empty()
+    }
+}
+
+/// groups `volumes` by their volume set identifier. Volumes without an identifier form sets
+/// of their own. Sets are returned in the order their first volume was passed in.
+pub fn group_volume_sets<'a, IO: Read + Seek + 'a>(
+    volumes: impl IntoIterator<Item = &'a UDF<IO>>,
+) -> Vec<VolumeSet> {
+    let mut sets: Vec<VolumeSet> = Vec::new();
+    for (i, udf) in volumes.into_iter().enumerate() {
+        let pvd = &udf.primary_vol_desc;
+        let ident = pvd.vol_set_ident.to_string().trim().to_string();
+        let existing = if ident.is_empty() {
+            None
+        } else {
+            sets.iter_mut().find(|set| set.ident == ident)
+        };
+        match existing {
+            Some(set) => {
+                set.max_vol_seq_num = set.max_vol_seq_num.max(pvd.max_vol_seq_num);
+                set.members.push((pvd.vol_seq_num, i));
+            }
+            None => sets.push(VolumeSet {
+                ident,
+                max_vol_seq_num: pvd.max_vol_seq_num,
+                members: vec![(pvd.vol_seq_num, i)],
+            }),
+        }
+    }
+    for set in &mut sets {
+        set.members.sort();
+    }
+    sets
+}
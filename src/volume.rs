@@ -7,8 +7,10 @@
 */
 
 use std::fmt::Display;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use bytemuck::cast_slice;
+use bitflags::bitflags;
 use nom::bytes::complete::take;
 use nom::number::complete::le_u8;
 use nom_derive::Nom;
@@ -70,16 +72,15 @@ pub struct CharSpec {
 #[derive(Clone, Debug)]
 pub struct DString<const T: u8>(String);
 impl<const T: u8> DString<T> {
-    pub fn parse_le<'nom>(i: &'nom [u8]) -> nom::IResult<&'nom [u8], Self> {
+    pub fn parse_le(i: &[u8]) -> nom::IResult<&[u8], Self> {
         if T == 0 {
             return Ok((i, Self(String::new())));
         }
-        let (i, mut s) = parse_dynamic_dstring(i, T - 1)?;
+        let (i, raw) = take(T - 1)(i)?;
         let (i, len) = le_u8(i)?;
-        if len == 0 {
-            return Ok((i, Self(String::new())));
-        }
-        s.truncate(len as _);
+        // the recorded length counts the bytes in use including the compression ID
+        let len = (len as usize).min(raw.len());
+        let (_, s) = parse_dynamic_dstring(&raw[..len], len as u8)?;
         Ok((i, Self(s)))
     }
 }
@@ -89,51 +90,94 @@ impl<const T: u8> Display for DString<T> {
     }
 }
 
-pub fn parse_dynamic_dstring<'nom>(i: &'nom [u8], len: u8) -> nom::IResult<&'nom [u8], String> {
+pub fn parse_dynamic_dstring(i: &[u8], len: u8) -> nom::IResult<&[u8], String> {
     if len == 0 {
         return Ok((i, String::new()));
     }
     let (i, raw) = take(len)(i)?;
-    let content: String;
-    // TODO: avoid unwrap
-    match raw[0] {
-        // UCS2-BE
-        16 => {
-            content = String::from_utf16(
-                cast_slice(&raw[1..len as usize])
-                    .iter()
-                    .map(|x| u16::from_be(*x))
-                    .collect::<Vec<_>>()
-                    .as_slice(),
-            )
-            .unwrap()
-        }
-        // Latin-1
-        8 | _ => {
-            content = std::str::from_utf8(&raw[1..len as usize])
-                .unwrap()
-                .to_string()
-        }
+    Ok((i, decode_cs0(raw)))
+}
+
+/// decodes OSTA CS0 d-characters, `raw` starting with the compression ID: 8 for Latin-1 and
+/// 16 for UCS-2BE characters. Characters that can't be decoded are replaced by U+FFFD.
+pub fn decode_cs0(raw: &[u8]) -> String {
+    match raw.first() {
+        Some(16) => char::decode_utf16(
+            raw[1..]
+                .chunks_exact(2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]])),
+        )
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect(),
+        // ASCII is the same in Latin-1 and UTF-8 and taken over as it is
+        Some(_) if raw[1..].is_ascii() => String::from_utf8(raw[1..].to_vec()).unwrap(),
+        Some(_) => raw[1..].iter().map(|&b| b as char).collect(),
+        None => String::new(),
+    }
+}
+
+/// encodes `s` as OSTA CS0 d-characters, using 8 bit characters where possible and UCS-2BE
+/// otherwise. Characters outside the Basic Multilingual Plane are stored as surrogate pairs.
+pub fn encode_cs0(s: &str) -> Vec<u8> {
+    if s.is_empty() {
+        return Vec::new();
+    }
+    if s.chars().all(|c| (c as u32) < 0x100) {
+        let mut raw = vec![8];
+        raw.extend(s.chars().map(|c| c as u8));
+        raw
+    } else {
+        let mut raw = vec![16];
+        raw.extend(s.encode_utf16().flat_map(u16::to_be_bytes));
+        raw
     }
-    Ok((i, content))
 }
 
-/* bitflags! {
-    struct RegIDFlags: u8 {
+bitflags! {
+    pub struct RegIDFlags: u8 {
+        /// the structure containing the identifier was modified by an implementation that
+        /// didn't update the identifier
         const DIRTY = 0b00000001;
+        /// the structure containing the identifier shall not be modified
         const PROTECTED = 0b00000010;
     }
-} */
+}
 
-#[derive(Nom, Clone, Debug)]
+#[derive(Nom, Clone, Debug, PartialEq, Eq)]
 #[nom(LittleEndian)]
 pub struct RegID {
     pub flags: u8,
     pub ident: [u8; 23],
     pub ident_suffix: [u8; 8],
 }
+impl RegID {
+    /// the identifier with trailing NUL bytes removed
+    pub fn ident_str(&self) -> &str {
+        std::str::from_utf8(&self.ident)
+            .unwrap_or_default()
+            .trim_end_matches('\0')
+    }
 
-#[derive(Nom, Clone)]
+    /// the flags of the identifier, undefined bits are dropped
+    pub fn reg_flags(&self) -> RegIDFlags {
+        RegIDFlags::from_bits_truncate(self.flags)
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.reg_flags().contains(RegIDFlags::DIRTY)
+    }
+
+    pub fn is_protected(&self) -> bool {
+        self.reg_flags().contains(RegIDFlags::PROTECTED)
+    }
+
+    /// the UDF revision (e.g. 0x0102 for UDF 1.02) recorded in a domain or UDF identifier suffix
+    pub fn udf_revision(&self) -> u16 {
+        u16::from_le_bytes([self.ident_suffix[0], self.ident_suffix[1]])
+    }
+}
+
+#[derive(Nom, Clone, Debug, PartialEq, Eq)]
 #[nom(LittleEndian)]
 pub struct Timestamp {
     pub type_tz: u16,
@@ -147,15 +191,185 @@ pub struct Timestamp {
     pub centims: u8,
     pub microsecond: u8,
 }
+impl Timestamp {
+    /// offset from UTC in minutes, `None` if the timestamp doesn't specify one
+    pub fn tz_offset(&self) -> Option<i16> {
+        match self.type_tz >> 12 {
+            // coordinated universal time
+            0 => Some(0),
+            1 => {
+                // 12 bit two's complement, -2047 meaning unspecified
+                let offset = ((self.type_tz << 4) as i16) >> 4;
+                (-1440..=1440).contains(&offset).then_some(offset)
+            }
+            // the meaning of other types is subject to agreement
+            _ => None,
+        }
+    }
+
+    /// the point in time of the timestamp, `None` if a field is out of range. Timestamps that
+    /// don't specify an offset from UTC are taken to be in UTC.
+    pub fn to_system_time(&self) -> Option<SystemTime> {
+        let valid = (1..=12).contains(&self.month)
+            && (1..=31).contains(&self.day)
+            && self.hour < 24
+            && self.minute < 60
+            && self.second < 60
+            && self.centisecond < 100
+            && self.centims < 100
+            && self.microsecond < 100;
+        if !valid {
+            return None;
+        }
+        // days since 1970-01-01 of the civil date, the inverse of `from_system_time`
+        let (month, day) = (self.month as i64, self.day as i64);
+        let year = self.year as i64 - (month <= 2) as i64;
+        let era = year.div_euclid(400);
+        let yoe = year.rem_euclid(400);
+        let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146097 + doe - 719468;
+        let minutes =
+            self.hour as i64 * 60 + self.minute as i64 - self.tz_offset().unwrap_or(0) as i64;
+        let secs = days * 86400 + minutes * 60 + self.second as i64;
+        let micros = secs as i128 * 1_000_000
+            + self.centisecond as i128 * 10000
+            + self.centims as i128 * 100
+            + self.microsecond as i128;
+        let d = Duration::from_micros(micros.unsigned_abs() as u64);
+        if micros < 0 {
+            UNIX_EPOCH.checked_sub(d)
+        } else {
+            UNIX_EPOCH.checked_add(d)
+        }
+    }
 
+    /// `time` as a timestamp in UTC
+    pub fn from_system_time(time: SystemTime) -> Self {
+        let (secs, micros) = match time.duration_since(UNIX_EPOCH) {
+            Ok(d) => (d.as_secs() as i64, d.subsec_micros()),
+            Err(e) => {
+                let d = e.duration();
+                match d.subsec_micros() {
+                    0 => (-(d.as_secs() as i64), 0),
+                    us => (-(d.as_secs() as i64) - 1, 1_000_000 - us),
+                }
+            }
+        };
+        let (days, secs) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+        // civil date from days since 1970-01-01, in eras of 400 years starting on March 1st
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + (month <= 2) as i64;
+        Timestamp {
+            // local time with an offset of 0
+            type_tz: 1 << 12,
+            year: year.clamp(i16::MIN as i64, i16::MAX as i64) as i16,
+            month: month as u8,
+            day: day as u8,
+            hour: (secs / 3600) as u8,
+            minute: (secs / 60 % 60) as u8,
+            second: (secs % 60) as u8,
+            centisecond: (micros / 10000) as u8,
+            centims: (micros / 100 % 100) as u8,
+            microsecond: (micros % 100) as u8,
+        }
+    }
+
+    /// the recorded form of the timestamp
+    pub fn to_bytes(&self) -> [u8; 12] {
+        let mut raw = [0; 12];
+        raw[0..2].copy_from_slice(&self.type_tz.to_le_bytes());
+        raw[2..4].copy_from_slice(&self.year.to_le_bytes());
+        raw[4..].copy_from_slice(&[
+            self.month,
+            self.day,
+            self.hour,
+            self.minute,
+            self.second,
+            self.centisecond,
+            self.centims,
+            self.microsecond,
+        ]);
+        raw
+    }
+}
+/// formats the timestamp according to ISO 8601, e.g. `2023-04-01T12:30:00.250000+02:00`
+impl Display for Timestamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        crate::format::write_timestamp(f, self)
+    }
+}
+/// parses the format written by `Display`, giving a local time timestamp
+impl FromStr for Timestamp {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        const ERR: &str = "invalid timestamp";
+        fn num<T: FromStr>(s: Option<&str>) -> Result<T, &'static str> {
+            s.ok_or(ERR)?.parse().or(Err(ERR))
+        }
+        let (date, time) = s.split_once('T').ok_or(ERR)?;
+        // years outside of 0 to 9999 carry a sign
+        let (negative, date) = match date.strip_prefix('-') {
+            Some(date) => (true, date),
+            None => (false, date.strip_prefix('+').unwrap_or(date)),
+        };
+        let mut date = date.splitn(3, '-');
+        let (year, month, day): (i16, _, _) =
+            (num(date.next())?, num(date.next())?, num(date.next())?);
+        let year = if negative { -year } else { year };
+        let (time, tz_offset) = if let Some(time) = time.strip_suffix('Z') {
+            (time, 0)
+        } else if let Some(i) = time.rfind(['+', '-']) {
+            let (hours, minutes) = time[i + 1..].split_once(':').ok_or(ERR)?;
+            let offset: i16 = num::<i16>(Some(hours))? * 60 + num::<i16>(Some(minutes))?;
+            (
+                &time[..i],
+                if &time[i..=i] == "-" { -offset } else { offset },
+            )
+        } else {
+            (time, -2047)
+        };
+        let (time, fraction) = time.split_once('.').unwrap_or((time, "0"));
+        let mut time = time.splitn(3, ':');
+        let (hour, minute, second) = (num(time.next())?, num(time.next())?, num(time.next())?);
+        let micros: u32 = num(format!("{:0<6}", fraction).get(..6))?;
+        Ok(Self {
+            type_tz: 1 << 12 | (tz_offset as u16 & 0x0fff),
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+            centisecond: (micros / 10000) as u8,
+            centims: (micros / 100 % 100) as u8,
+            microsecond: (micros % 100) as u8,
+        })
+    }
+}
+
+/// Boot Descriptor (ECMA-167 2/9.4), recorded in the volume recognition sequence
+#[derive(Nom, Clone, Debug, PartialEq, Eq)]
+#[nom(LittleEndian)]
 pub struct BD {
     pub struct_type: u8, // should always be 0
     pub ident: [u8; 5],
     pub version: u8, // should always be 1
     _res: u8,
+    /// the architecture the boot extent is meant for
     pub arch: RegID,
     pub boot_ident: RegID,
+    /// first sector of the boot extent
     pub boot_ext_loc: LSN,
+    /// length of the boot extent in bytes
     pub boot_ext_len: u32,
     pub load_addr: u64,
     pub start_addr: u64,
@@ -165,6 +379,13 @@ pub struct BD {
     pub boot_raw: [u8; 1906],
 }
 
+impl BD {
+    /// whether the boot extent is to be erased before it is reused for another purpose
+    pub fn erase(&self) -> bool {
+        self.flags & 1 != 0
+    }
+}
+
 #[derive(Nom, Clone)]
 #[nom(LittleEndian)]
 pub struct PVD {
@@ -203,20 +424,52 @@ pub struct AVD {
     _res: [u8; 480],
 }
 
+/// volume descriptor pointer, continues the volume descriptor sequence in another extent
+#[derive(Nom, Clone)]
+#[nom(LittleEndian)]
 pub struct VD {
+    #[nom(Verify = "tag.tag_id == TagID::VD")]
     pub tag: Tag,
     pub vds_num: u32,
     pub next_vds: ExtentAD,
     _res: [u8; 484],
 }
 
+#[derive(Nom, Clone)]
+#[nom(LittleEndian)]
 pub struct IUVD {
+    #[nom(Verify = "tag.tag_id == TagID::IUVD")]
     pub tag: Tag,
     pub vds_num: u32,
     pub impl_id: RegID,
     pub impl_use: [u8; 460],
 }
 
+impl IUVD {
+    /// the implementation use area decoded as UDF LV Info, `None` if the descriptor was
+    /// recorded by an implementation for its own use
+    pub fn lv_info(&self) -> Option<LVInfo> {
+        if self.impl_id.ident_str() != "*UDF LV Info" {
+            return None;
+        }
+        LVInfo::parse(&self.impl_use).ok().map(|(_, info)| info)
+    }
+}
+
+/// the implementation use area of an IUVD recording who owns a logical volume (UDF 2.2.7.2)
+#[derive(Nom, Clone)]
+#[nom(LittleEndian)]
+pub struct LVInfo {
+    pub charset: CharSpec,
+    pub lv_ident: DString<128>,
+    pub owner_name: DString<36>,
+    pub organization: DString<36>,
+    pub contact_info: DString<36>,
+    /// the implementation that recorded the descriptor
+    pub impl_id: RegID,
+    pub impl_use: [u8; 128],
+}
+
 #[derive(Nom, Clone)]
 #[nom(LittleEndian)]
 pub struct PD {
@@ -235,7 +488,52 @@ pub struct PD {
     _res: [u8; 156],
 }
 
-#[derive(Nom, Debug)]
+impl PD {
+    /// classifies the partition contents identifier
+    pub fn contents(&self) -> PartContents {
+        match self.part_cont.ident_str() {
+            "+NSR02" => PartContents::NSR02,
+            "+NSR03" => PartContents::NSR03,
+            "+CD001" => PartContents::CD001,
+            "+CDW02" => PartContents::CDW02,
+            "+FDC01" => PartContents::FDC01,
+            ident => PartContents::UNK(ident.to_string()),
+        }
+    }
+}
+
+/// Partition contents as declared by the partition descriptor (ECMA-167 3/10.5.6)
+#[derive(PartialEq, Debug, Clone)]
+pub enum PartContents {
+    /// UDF / ECMA-167 2nd edition file structure
+    NSR02,
+    /// UDF / ECMA-167 3rd edition file structure
+    NSR03,
+    /// ISO 9660 file structure
+    CD001,
+    /// ECMA-168 file structure
+    CDW02,
+    /// ECMA-107 (FAT) file structure
+    FDC01,
+    UNK(String),
+}
+impl PartContents {
+    /// whether the partition holds a UDF file structure
+    pub fn is_nsr(&self) -> bool {
+        matches!(self, PartContents::NSR02 | PartContents::NSR03)
+    }
+
+    /// descriptor tag version matching the contents, 2 for ECMA-167 2nd edition
+    /// structures and 3 for 3rd edition ones
+    pub fn desc_version(&self) -> u16 {
+        match self {
+            PartContents::NSR02 => 2,
+            _ => 3,
+        }
+    }
+}
+
+#[derive(Nom, Debug, Clone)]
 #[nom(LittleEndian)]
 pub struct PMType1 {
     #[nom(Verify = "*len == 6")]
@@ -244,7 +542,7 @@ pub struct PMType1 {
     pub part_num: u16,
 }
 
-#[derive(Nom, Debug)]
+#[derive(Nom, Debug, Clone)]
 #[nom(LittleEndian)]
 pub struct PMType2 {
     #[nom(Verify = "*len == 64")]
@@ -253,22 +551,107 @@ pub struct PMType2 {
     pub part_ident: RegID,
     pub vol_seq_nr: u16,
     pub part_num: u16,
+    pub part_use: [u8; 24],
+}
+impl PMType2 {
+    pub fn is_virtual(&self) -> bool {
+        self.part_ident.ident_str() == "*UDF Virtual Partition"
+    }
+
+    /// decodes the sparable partition map specific fields
+    pub fn sparable(&self) -> Option<SparablePartMap> {
+        if self.part_ident.ident_str() != "*UDF Sparable Partition" {
+            return None;
+        }
+        SparablePartMap::parse(&self.part_use).ok().map(|r| r.1)
+    }
+
+    /// decodes the metadata partition map specific fields
+    pub fn metadata(&self) -> Option<MetaPartMap> {
+        if self.part_ident.ident_str() != "*UDF Metadata Partition" {
+            return None;
+        }
+        MetaPartMap::parse(&self.part_use).ok().map(|r| r.1)
+    }
+}
+
+#[derive(Nom, Debug, Clone)]
+#[nom(LittleEndian)]
+pub struct SparablePartMap {
+    pub packet_len: u16,
+    #[nom(Verify = "*num_sparing_tbls <= 4")]
+    pub num_sparing_tbls: u8,
+    _res: u8,
+    pub sparing_tbl_size: u32,
+    #[nom(Count = "num_sparing_tbls")]
+    pub sparing_tbl_locs: Vec<LSN>,
+}
+
+/// an entry of a sparing table
+#[derive(Nom, Debug, Clone, Copy, PartialEq, Eq)]
+#[nom(LittleEndian)]
+pub struct SparingEntry {
+    /// first block of the relocated packet, or `AVAILABLE` or `DEFECTIVE` for spare packets
+    pub original: u32,
+    /// first sector of the spare packet
+    pub mapped: LSN,
+}
+impl SparingEntry {
+    /// a spare packet that can still be used
+    pub const AVAILABLE: u32 = 0xFFFFFFF0;
+    /// a spare packet that is defective itself
+    pub const DEFECTIVE: u32 = 0xFFFFFFF1;
+}
+
+/// Sparing Table (UDF 2.2.12), relocating defective packets of a sparable partition
+#[derive(Nom, Clone)]
+#[nom(LittleEndian)]
+pub struct SparingTable {
+    pub tag: Tag,
+    pub sparing_ident: RegID,
+    pub rt_len: u16,
+    _res: u16,
+    /// incremented whenever the table is rewritten
+    pub seq_num: u32,
+    #[nom(Count = "rt_len")]
+    pub entries: Vec<SparingEntry>,
+}
+impl SparingTable {
+    /// the sector the block `lbn` of the partition is relocated to, if its packet of
+    /// `packet_len` blocks of `block_sectors` sectors each was relocated
+    pub fn remap(&self, lbn: u32, packet_len: u16, block_sectors: u32) -> Option<LSN> {
+        let packet_len = packet_len.max(1) as u32;
+        let packet = lbn - lbn % packet_len;
+        // the entries are sorted by their original location
+        let i = self
+            .entries
+            .binary_search_by_key(&packet, |e| e.original)
+            .ok()?;
+        self.entries[i]
+            .mapped
+            .checked_add((lbn - packet) * block_sectors)
+    }
+}
+
+#[derive(Nom, Debug, Clone)]
+#[nom(LittleEndian)]
+pub struct MetaPartMap {
     pub meta_file_loc: u32,
     pub meta_mirror_loc: u32,
     pub meta_bmp_loc: u32,
     pub alloc_usize: u32,
     pub align_usize: u16,
     pub flags: u8,
-    _res2: [u8; 5],
+    _res: [u8; 5],
 }
 
-#[derive(Nom, Debug)]
+#[derive(Nom, Debug, Clone)]
 #[nom(LittleEndian, Selector = "u8")]
 pub enum PartMapType {
     #[nom(Selector = "0")]
     UNK {
         len: u8,
-        #[nom(Count = "len as usize - 2")]
+        #[nom(Count = "(len as usize).saturating_sub(2)")]
         data: Vec<u8>,
     },
     #[nom(Selector = "1")]
@@ -277,15 +660,25 @@ pub enum PartMapType {
     Type2(PMType2),
 }
 
-#[derive(Nom)]
+#[derive(Nom, Clone)]
 #[nom(LittleEndian)]
 pub struct PartMap {
     _pm_type: u8,
     #[nom(Parse = "{ |i| PartMapType::parse(i, _pm_type) }")]
     pub part_map: PartMapType,
 }
+impl PartMap {
+    /// recorded length of the map in bytes
+    pub fn map_len(&self) -> u32 {
+        match &self.part_map {
+            PartMapType::UNK { len, .. } => *len as u32,
+            PartMapType::Type1(pm) => pm.len as u32,
+            PartMapType::Type2(pm) => pm.len as u32,
+        }
+    }
+}
 
-#[derive(Nom)]
+#[derive(Nom, Clone)]
 #[nom(LittleEndian)]
 pub struct LVD {
     #[nom(Verify = "tag.tag_id == TagID::LVD")]
@@ -293,7 +686,6 @@ pub struct LVD {
     pub vds_num: u32,
     pub desc_charset: CharSpec,
     pub lvid: DString<128>,
-    #[nom(Verify = "*lbs as u64 == crate::BLOCKSIZE")]
     pub lbs: u32,
     pub domain_id: RegID,
     pub lv_contents_use: [u8; 16],
@@ -321,15 +713,76 @@ pub struct TD {
     _res: [u8; 496],
 }
 
+#[derive(Nom, PartialEq, Debug, Clone, Copy)]
+#[nom(LittleEndian)]
+#[repr(u32)]
+pub enum IntegrityType {
+    OPEN = 0,
+    CLOSE,
+}
+
+#[derive(Nom, Clone)]
+#[nom(LittleEndian)]
 pub struct LVID {
+    #[nom(Verify = "tag.tag_id == TagID::LVID")]
     pub tag: Tag,
     pub rec_time: Timestamp,
-    pub integ_type: u8,
+    pub integ_type: IntegrityType,
     pub next_integ_ext: ExtentAD,
     pub lvc_use: [u8; 32],
     pub num_part: u32,
     pub len_impl_use: u32,
-    pub free_space_tbl: Vec<u32>, // of length num_part
-    pub size_tbl: Vec<u32>,       // of length num_part
-    pub impl_use: Vec<u8>,        // of length len_impl_use
+    #[nom(Count = "num_part")]
+    pub free_space_tbl: Vec<u32>,
+    #[nom(Count = "num_part")]
+    pub size_tbl: Vec<u32>,
+    #[nom(Parse = "{ |i| parse_lvid_impl_use(i, len_impl_use) }")]
+    pub impl_use: Option<LVIDImplUse>,
+}
+impl LVID {
+    /// number of free blocks recorded for the partition with reference number `part_ref`,
+    /// `None` if the descriptor doesn't record it
+    pub fn free_blocks(&self, part_ref: u16) -> Option<u32> {
+        // all ones stands for an unknown amount of free space
+        self.free_space_tbl
+            .get(part_ref as usize)
+            .copied()
+            .filter(|&free| free != u32::MAX)
+    }
+
+    /// size in blocks recorded for the partition with reference number `part_ref`
+    pub fn partition_blocks(&self, part_ref: u16) -> Option<u32> {
+        self.size_tbl.get(part_ref as usize).copied()
+    }
+}
+
+/// Implementation use area of the LVID as defined by UDF (2.2.6.4)
+#[derive(Nom, Clone, Debug)]
+#[nom(LittleEndian)]
+pub struct LVIDImplUse {
+    /// the implementation that last wrote the volume
+    pub impl_ident: RegID,
+    pub num_files: u32,
+    pub num_dirs: u32,
+    /// UDF revisions in the form of `RegID::udf_revision`, e.g. 0x0201 for UDF 2.01
+    pub min_udf_read_rev: u16,
+    pub min_udf_write_rev: u16,
+    pub max_udf_write_rev: u16,
+    /// implementation specific data following the fields defined by UDF
+    #[nom(Parse = "parse_rest")]
+    pub impl_data: Vec<u8>,
+}
+
+fn parse_rest(i: &[u8]) -> nom::IResult<&[u8], Vec<u8>> {
+    Ok((&i[i.len()..], i.to_vec()))
+}
+
+/// the implementation use area of `len` bytes, `None` if it is too short to hold the fields
+/// UDF defines
+fn parse_lvid_impl_use(i: &[u8], len: u32) -> nom::IResult<&[u8], Option<LVIDImplUse>> {
+    let (i, raw) = take(len)(i)?;
+    Ok((
+        i,
+        LVIDImplUse::parse(raw).ok().map(|(_, impl_use)| impl_use),
+    ))
 }
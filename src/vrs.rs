@@ -0,0 +1,248 @@
+/*
+    Parsing of the volume recognition sequence (ECMA-167 2/8), recorded 16 sectors of 2048
+    bytes into a session. It tells whether a medium holds a UDF volume at all and which
+    edition of ECMA-167 it follows before any anchor is looked for, and reveals bridge discs
+    that carry an ISO 9660 file system next to the UDF one.
+*/
+
+use std::error::Error;
+use std::io::{Read, Seek, SeekFrom};
+
+use log::warn;
+use nom_derive::Parse;
+
+use crate::stats::IoCategory;
+use crate::volume::{BD, LSN};
+use crate::{BLOCKSIZE, UDF};
+
+/// descriptors read at most, the sequence ends at the first sector holding none
+const MAX_VRS_DESCS: usize = 64;
+
+/// length of the volume identifier of an ISO 9660 primary volume descriptor
+const ISO_VOL_IDENT_LEN: usize = 32;
+
+/// `ident` as ISO 9660 d-characters, which authoring tools derive the ISO 9660 identifier
+/// of bridge discs from: upper case, with every character but letters and digits replaced by
+/// `_`, and cut to the length of the ISO 9660 field
+fn d_characters(ident: &str) -> String {
+    ident
+        .chars()
+        .take(ISO_VOL_IDENT_LEN)
+        .map(|c| match c.to_ascii_uppercase() {
+            c @ ('A'..='Z' | '0'..='9') => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// a volume structure descriptor of the recognition sequence
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VolumeStructure {
+    /// BEA01, beginning of an extended area
+    ExtendedAreaStart,
+    /// TEA01, end of an extended area
+    ExtendedAreaEnd,
+    /// NSR02 or NSR03 with the version, 2 or 3, of the ECMA-167 structures on the volume
+    Nsr(u8),
+    /// BOOT2, a boot descriptor
+    Boot,
+    /// CD001, a volume descriptor of ISO 9660 with its type, e.g. 1 for the primary and 255
+    /// for the terminator
+    Iso9660(u8),
+    /// CDW02, a descriptor of ECMA-168
+    Ecma168,
+}
+
+impl VolumeStructure {
+    /// the descriptor in the sector `buf`, `None` if it holds none
+    fn parse(buf: &[u8]) -> Option<Self> {
+        Some(match &buf[1..6] {
+            b"BEA01" => VolumeStructure::ExtendedAreaStart,
+            b"TEA01" => VolumeStructure::ExtendedAreaEnd,
+            b"NSR02" => VolumeStructure::Nsr(2),
+            b"NSR03" => VolumeStructure::Nsr(3),
+            b"BOOT2" => VolumeStructure::Boot,
+            b"CD001" => VolumeStructure::Iso9660(buf[0]),
+            b"CDW02" => VolumeStructure::Ecma168,
+            _ => return None,
+        })
+    }
+
+    /// the standard identifier of the descriptor
+    pub fn ident(&self) -> &'static [u8; 5] {
+        match self {
+            VolumeStructure::ExtendedAreaStart => b"BEA01",
+            VolumeStructure::ExtendedAreaEnd => b"TEA01",
+            VolumeStructure::Nsr(2) => b"NSR02",
+            VolumeStructure::Nsr(_) => b"NSR03",
+            VolumeStructure::Boot => b"BOOT2",
+            VolumeStructure::Iso9660(_) => b"CD001",
+            VolumeStructure::Ecma168 => b"CDW02",
+        }
+    }
+}
+
+/// the fields of an ISO 9660 primary volume descriptor (ECMA-119 8.4) needed to compare it
+/// with the UDF volume of a bridge disc
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Iso9660Volume {
+    /// sector the descriptor is recorded in
+    pub lsn: LSN,
+    pub system_ident: String,
+    pub vol_ident: String,
+    /// size of the volume in logical blocks
+    pub space_size: u32,
+    pub block_size: u16,
+}
+
+impl Iso9660Volume {
+    fn parse(lsn: LSN, buf: &[u8]) -> Self {
+        let text = |b: &[u8]| String::from_utf8_lossy(b).trim_end().to_string();
+        Self {
+            lsn,
+            system_ident: text(&buf[8..40]),
+            vol_ident: text(&buf[40..72]),
+            // both-byte order fields, the little endian half comes first
+            space_size: u32::from_le_bytes(buf[80..84].try_into().unwrap()),
+            block_size: u16::from_le_bytes([buf[128], buf[129]]),
+        }
+    }
+
+    /// size of the volume in bytes
+    pub fn len(&self) -> u64 {
+        self.space_size as u64 * self.block_size as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// the descriptors of a volume recognition sequence with the sectors they are recorded in
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RecognitionSequence {
+    pub descriptors: Vec<(LSN, VolumeStructure)>,
+    /// the first ISO 9660 primary volume descriptor of the sequence
+    pub iso9660: Option<Iso9660Volume>,
+    /// the boot descriptors of the sequence
+    pub boot: Vec<BD>,
+}
+
+impl RecognitionSequence {
+    /// the version of the NSR descriptor recorded in an extended area, 2 or 3, `None` if the
+    /// sequence doesn't identify a UDF volume
+    pub fn nsr_version(&self) -> Option<u8> {
+        let mut in_extended_area = false;
+        for (_, desc) in &self.descriptors {
+            match desc {
+                VolumeStructure::ExtendedAreaStart => in_extended_area = true,
+                VolumeStructure::ExtendedAreaEnd => in_extended_area = false,
+                VolumeStructure::Nsr(version) if in_extended_area => return Some(*version),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// whether the sequence identifies a UDF volume
+    pub fn is_udf(&self) -> bool {
+        self.nsr_version().is_some()
+    }
+
+    /// whether the medium also holds an ISO 9660 file system, i.e. is a UDF bridge disc
+    pub fn is_bridge(&self) -> bool {
+        self.is_udf() && self.iso9660.is_some()
+    }
+}
+
+/// reads the volume recognition sequence of the session starting at sector `session_start`,
+/// with sectors of `sector_size` bytes. Descriptors are recorded every 2048 bytes, or in
+/// every sector of larger sectors. Reading stops at the first sector that holds no descriptor
+/// or can't be read, so a medium without a sequence gives an empty one.
+pub fn read_vrs<IO: Read + Seek>(
+    io: &mut IO,
+    session_start: LSN,
+    sector_size: u64,
+) -> Result<RecognitionSequence, Box<dyn Error>> {
+    let mut pos = session_start as u64 * sector_size + 16 * BLOCKSIZE;
+    let mut buf = [0; BLOCKSIZE as usize];
+    let mut vrs = RecognitionSequence::default();
+    while vrs.descriptors.len() < MAX_VRS_DESCS {
+        io.seek(SeekFrom::Start(pos))?;
+        if io.read_exact(&mut buf).is_err() {
+            break;
+        }
+        let Some(desc) = VolumeStructure::parse(&buf) else {
+            break;
+        };
+        let lsn = (pos / sector_size) as LSN;
+        if desc == VolumeStructure::Iso9660(1) && vrs.iso9660.is_none() {
+            vrs.iso9660 = Some(Iso9660Volume::parse(lsn, &buf));
+        }
+        if desc == VolumeStructure::Boot {
+            match BD::parse(&buf) {
+                Ok((_, bd)) => vrs.boot.push(bd),
+                Err(_) => warn!("Invalid boot descriptor at sector {}", lsn),
+            }
+        }
+        vrs.descriptors.push((lsn, desc));
+        pos += sector_size.max(BLOCKSIZE);
+    }
+    Ok(vrs)
+}
+
+impl<IO: Read + Seek> UDF<IO> {
+    /// reads the volume recognition sequence of the session the volume was opened in
+    pub fn recognition_sequence(&mut self) -> Result<RecognitionSequence, Box<dyn Error>> {
+        let sector_size = self.sector_size();
+        let vrs = read_vrs(&mut self.io, self.options.session_start, sector_size)?;
+        // every descriptor was read, and the sector ending the sequence
+        let start = self.options.session_start as u64 * sector_size + 16 * BLOCKSIZE;
+        for i in 0..=vrs.descriptors.len() as u64 {
+            let pos = start + i * sector_size.max(BLOCKSIZE);
+            self.record_read(IoCategory::Metadata, pos, BLOCKSIZE as usize);
+        }
+        Ok(vrs)
+    }
+
+    /// reads the boot extent of the boot descriptor `bd`, which holds the code `bd.arch`
+    /// boots from
+    pub fn read_boot_extent(&mut self, bd: &BD) -> Result<Vec<u8>, Box<dyn Error>> {
+        let pos = bd.boot_ext_loc as u64 * self.sector_size();
+        if pos + bd.boot_ext_len as u64 > self.medium_len()? {
+            Err("boot extent beyond the end of the medium")?
+        }
+        let mut buf = vec![0; bd.boot_ext_len as usize];
+        self.read_bytes(IoCategory::Data, pos, &mut buf)?;
+        Ok(buf)
+    }
+
+    /// the ways the ISO 9660 volume of a bridge disc disagrees with the UDF volume, which
+    /// suggest that the two file systems show different content: another volume identifier,
+    /// or a volume ending before the UDF partition. Empty if there is no ISO 9660 volume.
+    pub fn bridge_mismatches(&mut self) -> Result<Vec<String>, Box<dyn Error>> {
+        let Some(iso) = self.recognition_sequence()?.iso9660 else {
+            return Ok(Vec::new());
+        };
+        let mut mismatches = Vec::new();
+        // ISO 9660 identifiers are usually d-characters, which can't express every UDF
+        // identifier
+        let vol_ident = self.primary_vol_desc.vol_ident.to_string();
+        if d_characters(&iso.vol_ident) != d_characters(&vol_ident) {
+            mismatches.push(format!(
+                "ISO 9660 volume identifier {} differs from UDF volume identifier {}",
+                iso.vol_ident, vol_ident
+            ));
+        }
+        let part_end = (self.part_desc.part_start as u64 + self.part_desc.part_len as u64)
+            * self.sector_size();
+        if iso.len() < part_end {
+            mismatches.push(format!(
+                "ISO 9660 volume of {} bytes ends before the UDF partition at {} bytes",
+                iso.len(),
+                part_end
+            ));
+        }
+        Ok(mismatches)
+    }
+}
@@ -0,0 +1,1229 @@
+/*
+    Writing volumes: creating an image from a directory of the local file system or from
+    another volume leaving out some of its paths, and adding files to an existing image in
+    place. Volumes are written with a single physical partition
+    and without a metadata partition, which limits them to UDF revisions up to 2.01.
+
+    Layout of created images: VRS at sector 16, main VDS at 32, reserve VDS at 48, LVID at
+    64, anchors at 256 and in the last sector, and the partition in between, starting at 257
+    with the file set descriptor, followed by all file entries and directories and then the
+    file data.
+*/
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::warn;
+
+use crate::file::{EntryKind, LBAddr, ICB};
+use crate::partition::PartitionKind;
+use crate::path::{PathComponent, UdfPath};
+use crate::rebuild::{
+    fid, put_dstring, put_long_ad, put_osta_charspec, put_regid, put_timestamp, put_u16, put_u32,
+    put_u64, FE_AD_OFFSET,
+};
+use crate::volume::{encode_cs0, IntegrityType, RegIDFlags, Timestamp};
+use crate::{
+    read_avd, read_avd_at, read_sector, retag, tag_checksum, write_tag, UdfOptions, BLOCKSIZE, LSN,
+    UDF,
+};
+
+const BS: usize = BLOCKSIZE as usize;
+/// first sector of the partition of created images
+const PART_START: LSN = 257;
+/// longest extent a single allocation descriptor describes, a multiple of the block size
+const MAX_EXTENT: u64 = (1 << 30) - BLOCKSIZE;
+/// readable by everyone, directories are also searchable
+const FILE_PERMISSIONS: u32 = 0x1084;
+const DIR_PERMISSIONS: u32 = 0x14a5;
+/// unique IDs below 16 are reserved, the root directory has 0
+const FIRST_UNIQUE_ID: u64 = 16;
+/// UDF revisions that can be recorded without a metadata partition
+const REVISIONS: [u16; 4] = [0x0102, 0x0150, 0x0200, 0x0201];
+/// size of the reads copying file data from another volume
+const COPY_CHUNK: usize = 1 << 20;
+
+#[derive(Clone, Debug)]
+pub struct CreateOptions {
+    /// UDF revision to record, one of 1.02, 1.50, 2.00 and 2.01
+    pub revision: u16,
+    /// volume identifier, also used as logical volume and file set identifier
+    pub label: String,
+}
+
+impl Default for CreateOptions {
+    fn default() -> Self {
+        Self {
+            revision: 0x0201,
+            label: "UDF Volume".to_string(),
+        }
+    }
+}
+
+impl CreateOptions {
+    /// fails unless a volume can be written with these options, checked before anything is
+    /// written
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if !REVISIONS.contains(&self.revision) {
+            Err(format!(
+                "UDF revision {:x}.{:02x} can't be written",
+                self.revision >> 8,
+                self.revision & 0xff
+            ))?
+        }
+        // the volume identifier is the shortest field the label is recorded in
+        if encode_cs0(&self.label).len() > 31 {
+            Err(format!("label {} is too long", self.label))?
+        }
+        Ok(())
+    }
+}
+
+/// what a write operation recorded
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WriteReport {
+    pub files: usize,
+    pub directories: usize,
+    /// size of the file data written
+    pub bytes: u64,
+}
+
+/// what `scrub` zero-filled
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ScrubReport {
+    /// bytes after the data of files and directories up to the end of their last block
+    pub slack_bytes: u64,
+    /// bytes of blocks neither in use nor marked as allocated
+    pub unallocated_bytes: u64,
+}
+
+impl ScrubReport {
+    /// all bytes zero-filled
+    pub fn bytes(&self) -> u64 {
+        self.slack_bytes + self.unallocated_bytes
+    }
+}
+
+enum SourceKind {
+    File(u64),
+    Dir(Vec<SourceNode>),
+}
+
+/// a file or directory of the local file system or of the volume a clone is made of, with
+/// the blocks allocated for it
+struct SourceNode {
+    name: String,
+    path: PathBuf,
+    kind: SourceKind,
+    /// the ICB on the volume a clone is made of, `None` for local files
+    icb: Option<ICB>,
+    atime: Timestamp,
+    mtime: Timestamp,
+    unique_id: u64,
+    fe_lbn: u32,
+    /// first block of the directory or file data
+    data_lbn: u32,
+}
+
+impl SourceNode {
+    fn scan(name: String, path: PathBuf) -> Result<Self, Box<dyn Error>> {
+        let meta = fs::metadata(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let kind = if meta.is_dir() {
+            let mut entries = Vec::new();
+            for entry in fs::read_dir(&path)? {
+                let entry = entry?;
+                let Ok(name) = entry.file_name().into_string() else {
+                    Err(format!(
+                        "{}: name is not valid UTF-8",
+                        entry.path().display()
+                    ))?
+                };
+                let file_type = entry.file_type()?;
+                if !file_type.is_dir() && !file_type.is_file() && !file_type.is_symlink() {
+                    warn!(
+                        "Skipping {}, not a file or directory",
+                        entry.path().display()
+                    );
+                    continue;
+                }
+                entries.push(SourceNode::scan(name, entry.path())?);
+            }
+            entries.sort_by(|a, b| a.name.cmp(&b.name));
+            SourceKind::Dir(entries)
+        } else {
+            SourceKind::File(meta.len())
+        };
+        let time = |t: io::Result<SystemTime>| Timestamp::from_system_time(t.unwrap_or(UNIX_EPOCH));
+        Ok(SourceNode {
+            name,
+            path,
+            kind,
+            icb: None,
+            atime: time(meta.accessed()),
+            mtime: time(meta.modified()),
+            unique_id: 0,
+            fe_lbn: 0,
+            data_lbn: 0,
+        })
+    }
+
+    /// length of the directory data, the FIDs of the parent and of every entry
+    fn dir_len(entries: &[SourceNode]) -> Result<u64, Box<dyn Error>> {
+        let mut len = 40;
+        for entry in entries {
+            let name_len = encode_cs0(&entry.name).len();
+            if name_len > 255 {
+                Err(format!("{}: name is too long", entry.path.display()))?
+            }
+            len += (38 + name_len as u64).next_multiple_of(4);
+        }
+        Ok(len)
+    }
+
+    /// allocates the file entries and directories of the tree in depth-first order
+    fn plan_metadata(
+        &mut self,
+        next_lbn: &mut u32,
+        next_id: &mut u64,
+    ) -> Result<(), Box<dyn Error>> {
+        self.fe_lbn = alloc(next_lbn, 1)?;
+        if let SourceKind::Dir(entries) = &mut self.kind {
+            let len = Self::dir_len(entries)?;
+            self.data_lbn = alloc(next_lbn, len.div_ceil(BLOCKSIZE))?;
+            for entry in entries {
+                entry.unique_id = *next_id;
+                *next_id += 1;
+                entry.plan_metadata(next_lbn, next_id)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// allocates the data of all files, following the metadata
+    fn plan_data(&mut self, next_lbn: &mut u32) -> Result<(), Box<dyn Error>> {
+        match &mut self.kind {
+            SourceKind::File(size) => self.data_lbn = alloc(next_lbn, size.div_ceil(BLOCKSIZE))?,
+            SourceKind::Dir(entries) => {
+                for entry in entries {
+                    entry.plan_data(next_lbn)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// allocates `n` blocks at `next_lbn`
+fn alloc(next_lbn: &mut u32, n: u64) -> Result<u32, Box<dyn Error>> {
+    let lbn = *next_lbn;
+    *next_lbn = u32::try_from(n)
+        .ok()
+        .and_then(|n| lbn.checked_add(n))
+        .filter(|&end| end < u32::MAX - PART_START)
+        .ok_or("volume too large")?;
+    Ok(lbn)
+}
+
+/// long allocation descriptors of `len` bytes recorded from block `lbn` on
+fn long_ads(len: u64, lbn: u32, part_ref: u16) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut ads = Vec::new();
+    let mut offset = 0;
+    while offset < len {
+        let ext_len = (len - offset).min(MAX_EXTENT);
+        let mut ad = [0; 16];
+        let loc = LBAddr {
+            lbn: lbn + (offset / BLOCKSIZE) as u32,
+            part_ref_nr: part_ref,
+        };
+        put_long_ad(&mut ad, ext_len as u32, loc);
+        ads.extend(ad);
+        offset += ext_len;
+    }
+    if FE_AD_OFFSET + ads.len() > BS {
+        Err("file too large for the allocation descriptors of a file entry")?
+    }
+    Ok(ads)
+}
+
+/// contents of a file entry at block `lbn` whose data is described by long allocation
+/// descriptors `ads`
+#[allow(clippy::too_many_arguments)]
+fn file_entry(
+    version: u16,
+    lbn: u32,
+    kind: EntryKind,
+    link_count: u16,
+    info_len: u64,
+    unique_id: u64,
+    times: (&Timestamp, &Timestamp),
+    ads: &[u8],
+) -> [u8; BS] {
+    let mut fe = [0; BS];
+    // ICB tag: strategy 4, a single entry, long allocation descriptors
+    put_u16(&mut fe, 20, 4);
+    put_u16(&mut fe, 24, 1);
+    let (file_type, permissions) = match kind {
+        EntryKind::Dir => (4, DIR_PERMISSIONS),
+        _ => (5, FILE_PERMISSIONS),
+    };
+    fe[27] = file_type;
+    put_u16(&mut fe, 34, 1);
+    put_u32(&mut fe, 36, u32::MAX);
+    put_u32(&mut fe, 40, u32::MAX);
+    put_u32(&mut fe, 44, permissions);
+    put_u16(&mut fe, 48, link_count);
+    put_u64(&mut fe, 56, info_len);
+    put_u64(&mut fe, 64, info_len.div_ceil(BLOCKSIZE));
+    put_timestamp(&mut fe[72..84], times.0);
+    put_timestamp(&mut fe[84..96], times.1);
+    put_timestamp(&mut fe[96..108], times.1);
+    put_u32(&mut fe, 108, 1);
+    put_regid(&mut fe[128..160], "*libudf-rs", &[]);
+    put_u64(&mut fe, 160, unique_id);
+    put_u32(&mut fe, 172, ads.len() as u32);
+    fe[FE_AD_OFFSET..FE_AD_OFFSET + ads.len()].copy_from_slice(ads);
+    write_tag(&mut fe, 261, version, lbn, FE_AD_OFFSET + ads.len());
+    fe
+}
+
+/// address of block `lbn` of the partition of created images
+fn part0(lbn: u32) -> LBAddr {
+    LBAddr {
+        lbn,
+        part_ref_nr: 0,
+    }
+}
+
+/// a FID of `name` pointing to the file entry at `icb`, untagged
+fn entry_fid(
+    chars: u8,
+    icb: LBAddr,
+    unique_id: u64,
+    name: &str,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut fid = fid(chars, icb, name)?;
+    // UDF unique ID in the implementation use of the ICB address
+    put_u32(&mut fid, 32, unique_id as u32);
+    Ok(fid)
+}
+
+/// concatenates the FIDs `fids` of a directory recorded from block `lbn` on, tagging each
+/// with the block it starts in
+fn dir_data(version: u16, lbn: u32, fids: Vec<Vec<u8>>) -> Vec<u8> {
+    let mut data = Vec::new();
+    for mut fid in fids {
+        let loc = lbn + (data.len() / BS) as u32;
+        let len = 38 + u16::from_le_bytes([fid[36], fid[37]]) as usize + fid[19] as usize;
+        write_tag(&mut fid, 257, version, loc, len);
+        data.extend(fid);
+    }
+    data
+}
+
+fn write_at<W: Write + Seek>(out: &mut W, lsn: LSN, data: &[u8]) -> io::Result<()> {
+    out.seek(SeekFrom::Start(lsn as u64 * BLOCKSIZE))?;
+    out.write_all(data)
+}
+
+/// copies exactly `len` bytes from `data` to `out`
+fn copy_exact<W: Write>(data: &mut dyn Read, out: &mut W, len: u64) -> Result<(), Box<dyn Error>> {
+    let copied = io::copy(&mut data.take(len), out)?;
+    if copied != len {
+        Err(format!("expected {} bytes of data, got {}", len, copied))?
+    }
+    Ok(())
+}
+
+/// copies the data of a file to the current position of the image
+type CopyData<'a, W> = &'a mut dyn FnMut(&SourceNode, &mut W) -> Result<(), Box<dyn Error>>;
+
+struct Creator<'a, W: Write + Seek> {
+    out: &'a mut W,
+    version: u16,
+    report: WriteReport,
+    copy: CopyData<'a, W>,
+}
+
+impl<W: Write + Seek> Creator<'_, W> {
+    /// writes the file entries and directories below `node`
+    fn write_metadata(
+        &mut self,
+        node: &SourceNode,
+        parent: (u32, u64),
+    ) -> Result<(), Box<dyn Error>> {
+        let times = (&node.atime, &node.mtime);
+        let fe = match &node.kind {
+            SourceKind::File(size) => {
+                self.report.files += 1;
+                let ads = long_ads(*size, node.data_lbn, 0)?;
+                file_entry(
+                    self.version,
+                    node.fe_lbn,
+                    EntryKind::File,
+                    1,
+                    *size,
+                    node.unique_id,
+                    times,
+                    &ads,
+                )
+            }
+            SourceKind::Dir(entries) => {
+                self.report.directories += 1;
+                let mut fids = vec![entry_fid(0x0a, part0(parent.0), parent.1, "")?];
+                for entry in entries {
+                    let chars = match entry.kind {
+                        SourceKind::Dir(_) => 0x02,
+                        SourceKind::File(_) => 0,
+                    };
+                    fids.push(entry_fid(
+                        chars,
+                        part0(entry.fe_lbn),
+                        entry.unique_id,
+                        &entry.name,
+                    )?);
+                }
+                let data = dir_data(self.version, node.data_lbn, fids);
+                write_at(self.out, PART_START + node.data_lbn, &data)?;
+                let subdirs = entries
+                    .iter()
+                    .filter(|e| matches!(e.kind, SourceKind::Dir(_)))
+                    .count();
+                let link_count =
+                    u16::try_from(1 + subdirs).map_err(|_| "too many subdirectories")?;
+                let ads = long_ads(data.len() as u64, node.data_lbn, 0)?;
+                let fe = file_entry(
+                    self.version,
+                    node.fe_lbn,
+                    EntryKind::Dir,
+                    link_count,
+                    data.len() as u64,
+                    node.unique_id,
+                    times,
+                    &ads,
+                );
+                for entry in entries {
+                    self.write_metadata(entry, (node.fe_lbn, node.unique_id))?;
+                }
+                fe
+            }
+        };
+        write_at(self.out, PART_START + node.fe_lbn, &fe)?;
+        Ok(())
+    }
+
+    fn write_data(&mut self, node: &SourceNode) -> Result<(), Box<dyn Error>> {
+        match &node.kind {
+            SourceKind::File(size) => {
+                self.out.seek(SeekFrom::Start(
+                    (PART_START + node.data_lbn) as u64 * BLOCKSIZE,
+                ))?;
+                (self.copy)(node, self.out)
+                    .map_err(|e| format!("{}: {}", node.path.display(), e))?;
+                self.report.bytes += size;
+            }
+            SourceKind::Dir(entries) => {
+                for entry in entries {
+                    self.write_data(entry)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// writes the volume recognition sequence, both volume descriptor sequences, the integrity
+/// sequence and the anchors of a volume whose partition has `part_len` blocks
+fn write_volume<W: Write + Seek>(
+    out: &mut W,
+    options: &CreateOptions,
+    part_len: u32,
+    report: &WriteReport,
+    next_unique_id: u64,
+) -> Result<(), Box<dyn Error>> {
+    let version = if options.revision >= 0x0200 { 3 } else { 2 };
+    let revision = options.revision.to_le_bytes();
+    let now = SystemTime::now();
+    let record_time = Timestamp::from_system_time(now);
+    let nsr = if version == 2 { "NSR02" } else { "NSR03" };
+    for (lsn, ident) in [(16, "BEA01"), (17, nsr), (18, "TEA01")] {
+        let mut vrs = [0; BS];
+        vrs[1..6].copy_from_slice(ident.as_bytes());
+        vrs[6] = 1;
+        write_at(out, lsn, &vrs)?;
+    }
+
+    // the volume set identifier starts with 16 unique hex digits
+    let stamp = now
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64;
+    let vol_set_ident = format!("{:016X}{}", stamp, options.label);
+    for start in [32, 48] {
+        let mut pvd = [0; BS];
+        put_dstring(&mut pvd[24..56], &options.label)?;
+        put_u16(&mut pvd, 56, 1);
+        put_u16(&mut pvd, 58, 1);
+        put_u16(&mut pvd, 60, 2);
+        put_u16(&mut pvd, 62, 3);
+        put_u32(&mut pvd, 64, 1);
+        put_u32(&mut pvd, 68, 1);
+        put_dstring(&mut pvd[72..200], &vol_set_ident)
+            .or_else(|_| put_dstring(&mut pvd[72..200], &vol_set_ident[..16]))?;
+        put_osta_charspec(&mut pvd[200..264]);
+        put_osta_charspec(&mut pvd[264..328]);
+        put_regid(&mut pvd[344..376], "*libudf-rs", &[]);
+        put_timestamp(&mut pvd[376..388], &record_time);
+        put_regid(&mut pvd[388..420], "*libudf-rs", &[]);
+        write_tag(&mut pvd, 1, version, start, 512);
+        write_at(out, start, &pvd)?;
+
+        let mut iuvd = [0; BS];
+        put_u32(&mut iuvd, 16, 1);
+        put_regid(&mut iuvd[20..52], "*UDF LV Info", &revision);
+        put_osta_charspec(&mut iuvd[52..116]);
+        put_dstring(&mut iuvd[116..244], &options.label)?;
+        put_regid(&mut iuvd[352..384], "*libudf-rs", &[]);
+        write_tag(&mut iuvd, 4, version, start + 1, 512);
+        write_at(out, start + 1, &iuvd)?;
+
+        let mut pd = [0; BS];
+        put_u32(&mut pd, 16, 2);
+        put_u16(&mut pd, 20, 1);
+        put_regid(&mut pd[24..56], &format!("+{}", nsr), &[]);
+        // overwritable, files can be added in place
+        put_u32(&mut pd, 184, 4);
+        put_u32(&mut pd, 188, PART_START);
+        put_u32(&mut pd, 192, part_len);
+        put_regid(&mut pd[196..228], "*libudf-rs", &[]);
+        write_tag(&mut pd, 5, version, start + 2, 512);
+        write_at(out, start + 2, &pd)?;
+
+        let mut lvd = [0; BS];
+        put_u32(&mut lvd, 16, 3);
+        put_osta_charspec(&mut lvd[20..84]);
+        put_dstring(&mut lvd[84..212], &options.label)?;
+        put_u32(&mut lvd, 212, BS as u32);
+        put_regid(&mut lvd[216..248], "*OSTA UDF Compliant", &revision);
+        put_long_ad(&mut lvd[248..264], BS as u32, part0(0));
+        put_u32(&mut lvd, 264, 6);
+        put_u32(&mut lvd, 268, 1);
+        put_regid(&mut lvd[272..304], "*libudf-rs", &[]);
+        put_u32(&mut lvd, 432, 2 * BS as u32);
+        put_u32(&mut lvd, 436, 64);
+        lvd[440..446].copy_from_slice(&[1, 6, 1, 0, 0, 0]);
+        write_tag(&mut lvd, 6, version, start + 3, 446);
+        write_at(out, start + 3, &lvd)?;
+
+        let mut usd = [0; BS];
+        put_u32(&mut usd, 16, 4);
+        write_tag(&mut usd, 7, version, start + 4, 24);
+        write_at(out, start + 4, &usd)?;
+
+        let mut td = [0; BS];
+        write_tag(&mut td, 8, version, start + 5, 512);
+        write_at(out, start + 5, &td)?;
+    }
+
+    let mut lvid = [0; BS];
+    put_timestamp(&mut lvid[16..28], &record_time);
+    put_u32(&mut lvid, 28, 1);
+    put_u64(&mut lvid, 40, next_unique_id);
+    put_u32(&mut lvid, 72, 1);
+    put_u32(&mut lvid, 76, 46);
+    put_u32(&mut lvid, 84, part_len);
+    put_regid(&mut lvid[88..120], "*libudf-rs", &[]);
+    put_u32(&mut lvid, 120, report.files as u32);
+    put_u32(&mut lvid, 124, report.directories as u32);
+    put_u16(&mut lvid, 128, options.revision);
+    put_u16(&mut lvid, 130, options.revision);
+    put_u16(&mut lvid, 132, options.revision);
+    write_tag(&mut lvid, 9, version, 64, 134);
+    write_at(out, 64, &lvid)?;
+    let mut td = [0; BS];
+    write_tag(&mut td, 8, version, 65, 512);
+    write_at(out, 65, &td)?;
+
+    for lsn in [256, PART_START + part_len] {
+        write_at(out, lsn, &anchor(version, lsn, (32, 6), (48, 6)))?;
+    }
+    Ok(())
+}
+
+/// an anchor at `lsn` pointing to the volume descriptor sequences at the given sectors and
+/// lengths in sectors
+fn anchor(version: u16, lsn: LSN, main: (LSN, u32), reserve: (LSN, u32)) -> [u8; BS] {
+    let mut avd = [0; BS];
+    put_u32(&mut avd, 16, main.1 * BS as u32);
+    put_u32(&mut avd, 20, main.0);
+    put_u32(&mut avd, 24, reserve.1 * BS as u32);
+    put_u32(&mut avd, 28, reserve.0);
+    write_tag(&mut avd, 2, version, lsn, 512);
+    avd
+}
+
+/// creates a volume holding the contents of the local directory `source` on `out`
+pub fn create_image<W: Write + Seek>(
+    out: &mut W,
+    source: &Path,
+    options: &CreateOptions,
+) -> Result<WriteReport, Box<dyn Error>> {
+    options.validate()?;
+    let root = SourceNode::scan(String::new(), source.to_path_buf())?;
+    if !matches!(root.kind, SourceKind::Dir(_)) {
+        Err(format!("{} is not a directory", source.display()))?
+    }
+    let mut copy = |node: &SourceNode, out: &mut W| -> Result<(), Box<dyn Error>> {
+        let SourceKind::File(size) = node.kind else {
+            return Ok(());
+        };
+        copy_exact(&mut fs::File::open(&node.path)?, out, size)
+    };
+    write_tree(out, root, options, &mut copy)
+}
+
+/// writes a volume holding the tree below the directory `root` on `out`, with the file data
+/// copied by `copy`
+fn write_tree<W: Write + Seek>(
+    out: &mut W,
+    mut root: SourceNode,
+    options: &CreateOptions,
+    copy: CopyData<W>,
+) -> Result<WriteReport, Box<dyn Error>> {
+    // the file set descriptor and its terminator come first
+    let mut next_lbn = 2;
+    let mut next_id = FIRST_UNIQUE_ID;
+    root.plan_metadata(&mut next_lbn, &mut next_id)?;
+    root.plan_data(&mut next_lbn)?;
+
+    let version = if options.revision >= 0x0200 { 3 } else { 2 };
+    let mut creator = Creator {
+        out,
+        version,
+        report: WriteReport::default(),
+        copy,
+    };
+    creator.write_metadata(&root, (root.fe_lbn, 0))?;
+    creator.write_data(&root)?;
+    let report = creator.report;
+
+    let mut fsd = [0; BS];
+    put_timestamp(
+        &mut fsd[16..28],
+        &Timestamp::from_system_time(SystemTime::now()),
+    );
+    put_u16(&mut fsd, 28, 3);
+    put_u16(&mut fsd, 30, 3);
+    put_u32(&mut fsd, 32, 1);
+    put_u32(&mut fsd, 36, 1);
+    put_osta_charspec(&mut fsd[48..112]);
+    put_dstring(&mut fsd[112..240], &options.label)?;
+    put_osta_charspec(&mut fsd[240..304]);
+    put_dstring(&mut fsd[304..336], &options.label)?;
+    put_long_ad(&mut fsd[400..416], BS as u32, part0(root.fe_lbn));
+    put_regid(
+        &mut fsd[416..448],
+        "*OSTA UDF Compliant",
+        &options.revision.to_le_bytes(),
+    );
+    write_tag(&mut fsd, 256, version, 0, 512);
+    write_at(out, PART_START, &fsd)?;
+    let mut td = [0; BS];
+    write_tag(&mut td, 8, version, 1, 512);
+    write_at(out, PART_START + 1, &td)?;
+
+    write_volume(out, options, next_lbn, &report, next_id)?;
+    out.flush()?;
+    Ok(report)
+}
+
+impl<IO: Read + Seek> UDF<IO> {
+    /// the options recording a clone of this volume with its label and, where it can be
+    /// written, its revision
+    pub fn clone_options(&self) -> CreateOptions {
+        let revision = self.udf_revision();
+        CreateOptions {
+            revision: if REVISIONS.contains(&revision) {
+                revision
+            } else {
+                CreateOptions::default().revision
+            },
+            label: self.primary_vol_desc.vol_ident.to_string(),
+        }
+    }
+
+    /// writes a new volume with the files and directories of this one on `out`, leaving out
+    /// the paths in `exclude` with everything below them. File data is copied straight from
+    /// the extents of this volume, the metadata is recorded anew: files with several links
+    /// become separate files, and named streams, extended attributes and entries that are
+    /// neither files nor directories are left out.
+    pub fn clone_image<W: Write + Seek>(
+        &mut self,
+        out: &mut W,
+        exclude: &[&str],
+        options: &CreateOptions,
+    ) -> Result<WriteReport, Box<dyn Error>> {
+        options.validate()?;
+        let mut exclude: HashSet<String> = exclude
+            .iter()
+            .map(|path| format!("/{}", path.trim_matches('/')))
+            .collect();
+        let root = self.get_root_dir()?;
+        let mut visited = HashSet::new();
+        let root = self.scan_tree(root, String::new(), "/", 0, &mut exclude, &mut visited)?;
+        for path in exclude {
+            warn!("Nothing to exclude at {}", path);
+        }
+        let mut copy = |node: &SourceNode, out: &mut W| -> Result<(), Box<dyn Error>> {
+            match (&node.icb, &node.kind) {
+                (Some(icb), SourceKind::File(size)) => self.copy_data(icb, out, *size),
+                _ => Ok(()),
+            }
+        };
+        write_tree(out, root, options, &mut copy)
+    }
+
+    /// the tree below `icb` recorded at `path`, without the paths left in `exclude`, which
+    /// are removed from it once found
+    fn scan_tree(
+        &mut self,
+        icb: ICB,
+        name: String,
+        path: &str,
+        depth: usize,
+        exclude: &mut HashSet<String>,
+        visited: &mut HashSet<LBAddr>,
+    ) -> Result<SourceNode, Box<dyn Error>> {
+        let fe = icb
+            .file_entry()
+            .ok_or_else(|| format!("{} has no file entry", path))?;
+        let (atime, mtime, info_len) = (fe.atime().clone(), fe.mtime().clone(), fe.info_len());
+        let kind = if icb.kind() == EntryKind::Dir {
+            if !visited.insert(icb.loc) {
+                Err(format!("{}: directory is linked more than once", path))?
+            }
+            if depth >= self.options.limits.max_depth.unwrap_or(usize::MAX) {
+                Err(format!("directory depth limit exceeded at {}", path))?
+            }
+            let mut entries = Vec::new();
+            for entry in icb.read_entries(self)? {
+                if entry.is_deleted() || entry.is_parent() {
+                    continue;
+                }
+                let prefix = if path == "/" { "" } else { path };
+                let child_path = format!("{}/{}", prefix, entry.name);
+                if exclude.remove(&child_path) {
+                    continue;
+                }
+                let child = entry.resolve(self)?;
+                if !matches!(child.kind(), EntryKind::File | EntryKind::Dir) {
+                    warn!("Skipping {}, not a file or directory", child_path);
+                    continue;
+                }
+                let name = entry.name.clone();
+                entries.push(self.scan_tree(
+                    child,
+                    name,
+                    &child_path,
+                    depth + 1,
+                    exclude,
+                    visited,
+                )?);
+            }
+            entries.sort_by(|a, b| a.name.cmp(&b.name));
+            SourceKind::Dir(entries)
+        } else {
+            SourceKind::File(info_len)
+        };
+        Ok(SourceNode {
+            name,
+            path: PathBuf::from(path),
+            kind,
+            icb: Some(icb),
+            atime,
+            mtime,
+            unique_id: 0,
+            fe_lbn: 0,
+            data_lbn: 0,
+        })
+    }
+
+    /// copies the first `len` bytes of the data of `icb` to `out`
+    fn copy_data<W: Write>(
+        &mut self,
+        icb: &ICB,
+        out: &mut W,
+        len: u64,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut buf = vec![0; COPY_CHUNK.min(len as usize)];
+        let mut pos = 0;
+        while pos < len {
+            let n = icb.read_at(self, pos, &mut buf)?;
+            if n == 0 {
+                Err(format!("expected {} bytes of data, got {}", len, pos))?
+            }
+            out.write_all(&buf[..n])?;
+            pos += n as u64;
+        }
+        Ok(())
+    }
+}
+
+/// the tag identifier and the contents covered by the CRC of the descriptor in `buf`, `None`
+/// if its tag is damaged
+fn desc_body(buf: &[u8]) -> Option<(&[u8], &[u8])> {
+    let crc_len = u16::from_le_bytes([buf[10], buf[11]]) as usize;
+    (tag_checksum(buf) == buf[4] && 16 + crc_len <= buf.len())
+        .then(|| (&buf[0..2], &buf[16..16 + crc_len]))
+}
+
+impl<IO: Read + Seek> UDF<IO> {
+    /// checks that the volume looks sound before it is modified: the anchors are valid and
+    /// agree, the two volume descriptor sequences hold the same descriptors, the logical
+    /// volume is closed and its integrity descriptor describes the partitions as recorded.
+    /// Returns the problems found, modifying a volume with problems may well damage it more.
+    pub fn preflight(&mut self) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut problems = Vec::new();
+        let options = UdfOptions {
+            strict: true,
+            verify_checksums: true,
+            ..self.options.clone()
+        };
+        let first = self.options.session_start + 256;
+        let avd = match read_avd_at(&mut self.io, first, &options) {
+            Ok(avd) => Some(avd),
+            Err(e) => {
+                problems.push(format!("no valid anchor at sector {}: {}", first, e));
+                None
+            }
+        };
+        let sector_size = self.sector_size();
+        let num_sectors = (self.io.seek(SeekFrom::End(0))? / sector_size) as LSN;
+        let mut buf = vec![0; sector_size as usize];
+        for lsn in [num_sectors.checked_sub(256), num_sectors.checked_sub(1)]
+            .into_iter()
+            .flatten()
+            .filter(|&lsn| lsn > first)
+        {
+            // the other anchors are optional, but have to be valid if recorded
+            read_sector(&mut self.io, sector_size, lsn, &mut buf)?;
+            if u16::from_le_bytes([buf[0], buf[1]]) != 2 {
+                continue;
+            }
+            match (read_avd_at(&mut self.io, lsn, &options), &avd) {
+                (Err(e), _) => problems.push(format!("damaged anchor at sector {}: {}", lsn, e)),
+                (Ok(other), Some(avd))
+                    if (other.main_vds.loc, other.reserve_vds.loc)
+                        != (avd.main_vds.loc, avd.reserve_vds.loc) =>
+                {
+                    problems.push(format!(
+                        "anchor at sector {} points to other volume descriptor sequences than the one at {}",
+                        lsn, first
+                    ))
+                }
+                _ => {}
+            }
+        }
+        if let Some(avd) = &avd {
+            if let Some(problem) = self.compare_vds_copies(
+                avd.main_vds.loc,
+                avd.reserve_vds.loc,
+                avd.main_vds.len.min(avd.reserve_vds.len),
+            )? {
+                problems.push(problem);
+            }
+        }
+
+        match self.integrity_sequence() {
+            Ok(seq) => match seq.current {
+                None => problems.push("no logical volume integrity descriptor".to_string()),
+                Some(lvid) => {
+                    if lvid.integ_type == IntegrityType::OPEN {
+                        problems.push(
+                            "the logical volume is open, it may not have been closed after it was last written"
+                                .to_string(),
+                        );
+                    }
+                    for map in &self.part_maps {
+                        let Some(pd) = &map.pd else {
+                            continue;
+                        };
+                        let Some(size) = lvid.partition_blocks(map.part_ref) else {
+                            problems.push(format!(
+                                "the integrity descriptor doesn't describe partition {}",
+                                map.part_ref
+                            ));
+                            continue;
+                        };
+                        if size != pd.part_len {
+                            problems.push(format!(
+                                "the integrity descriptor records {} blocks for partition {} of {} blocks",
+                                size, map.part_ref, pd.part_len
+                            ));
+                        }
+                        let free = lvid.free_blocks(map.part_ref);
+                        if let Some(free) = free.filter(|&free| free > pd.part_len) {
+                            problems.push(format!(
+                                "the integrity descriptor records {} free blocks in partition {} of {} blocks",
+                                free, map.part_ref, pd.part_len
+                            ));
+                        }
+                    }
+                }
+            },
+            Err(e) => problems.push(format!("integrity sequence can't be read: {}", e)),
+        }
+        Ok(problems)
+    }
+
+    /// compares the descriptors of the main and reserve volume descriptor sequences, which
+    /// may only differ in their tags, returning how they differ
+    fn compare_vds_copies(
+        &mut self,
+        main: LSN,
+        reserve: LSN,
+        len: u32,
+    ) -> Result<Option<String>, Box<dyn Error>> {
+        let sector_size = self.sector_size();
+        let mut bufs = [vec![0; sector_size as usize], vec![0; sector_size as usize]];
+        for i in 0..len / sector_size as u32 {
+            for (buf, start) in bufs.iter_mut().zip([main, reserve]) {
+                if let Err(e) = read_sector(&mut self.io, sector_size, start + i, buf) {
+                    return Ok(Some(format!(
+                        "volume descriptor at sector {} can't be read: {}",
+                        start + i,
+                        e
+                    )));
+                }
+            }
+            let [a, b] = &bufs;
+            match (desc_body(a), desc_body(b)) {
+                (Some(a), Some(b)) if a == b => {}
+                _ => {
+                    return Ok(Some(format!(
+                    "the main and reserve volume descriptor sequences differ at sectors {} and {}",
+                    main + i,
+                    reserve + i
+                )))
+                }
+            }
+            // terminating descriptor
+            if u16::from_le_bytes([a[0], a[1]]) == 8 {
+                break;
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<IO: Read + Seek + Write> UDF<IO> {
+    /// warns if the domain identifier of the volume asks for it not to be modified, called
+    /// by everything that modifies a volume
+    pub(crate) fn warn_if_protected(&self) {
+        if self.domain_flags().contains(RegIDFlags::PROTECTED) {
+            warn!("the domain identifier of the volume is protected against modification");
+        }
+    }
+
+    /// records the `len` bytes read from `data` as a new file at `path`, whose parent
+    /// directory has to exist. The data, the file entry and the rewritten parent directory are
+    /// appended to the partition, which grows accordingly. This needs a volume with a single
+    /// physical partition followed by nothing but anchors. Volumes that fail the `preflight`
+    /// check are refused unless `force` is set.
+    pub fn add_file(
+        &mut self,
+        path: &Path,
+        data: &mut dyn Read,
+        len: u64,
+        mtime: Timestamp,
+        force: bool,
+    ) -> Result<WriteReport, Box<dyn Error>> {
+        let pd = match &self.part_maps[..] {
+            [map] if matches!(map.kind, PartitionKind::Physical) && self.vat.is_none() => {
+                map.pd.clone().ok_or("partition not recorded")?
+            }
+            _ => Err("files can only be added to volumes with a single physical partition")?,
+        };
+        let problems = self.preflight()?;
+        if !problems.is_empty() {
+            if !force {
+                Err(format!(
+                    "the volume looks damaged, refusing to modify it: {}",
+                    problems.join(", ")
+                ))?
+            }
+            for problem in problems {
+                warn!("Modifying a damaged volume: {}", problem);
+            }
+        }
+        if self.truncation.is_some() {
+            Err("files can't be added to a truncated image")?
+        }
+        if self.sector_size() != BLOCKSIZE || self.block_size() != BLOCKSIZE {
+            Err("files can only be added to volumes with 2048 byte sectors and blocks")?
+        }
+        self.warn_if_protected();
+        let mut parent = UdfPath::from_path(path)?;
+        let Some(PathComponent::Name(name)) = parent.components.pop() else {
+            Err(std::io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "path doesn't name a file",
+            ))?
+        };
+        parent.dir_only = true;
+        let dir = self.lookup(&parent)?;
+        if dir.read_entries(self)?.iter().any(|e| {
+            !e.is_deleted() && !e.is_parent() && e.matches(self.options.name_domain, &name)
+        }) {
+            Err(std::io::Error::from(io::ErrorKind::AlreadyExists))?
+        }
+        let mut fids = Vec::new();
+        if !dir.for_each_raw_fid(self, |raw| fids.push(raw.to_vec()))? {
+            Err("parent directory couldn't be read completely")?
+        }
+
+        // everything after the partition gets overwritten, only anchors may be recorded there
+        let part_end = pd.part_start + pd.part_len;
+        let num_sectors = (self.io.seek(SeekFrom::End(0))? / BLOCKSIZE) as LSN;
+        let mut buf = [0; BS];
+        for lsn in part_end..num_sectors {
+            read_sector(&mut self.io, BLOCKSIZE, lsn, &mut buf)?;
+            if buf.iter().any(|&b| b != 0) && u16::from_le_bytes([buf[0], buf[1]]) != 2 {
+                Err(format!("sector {} after the partition is in use", lsn))?
+            }
+        }
+        let lvid_lsn = self.current_lvid_sector()?;
+        let next_id = match lvid_lsn {
+            Some(lsn) => {
+                read_sector(&mut self.io, BLOCKSIZE, lsn, &mut buf)?;
+                u64::from_le_bytes(buf[40..48].try_into().unwrap()).max(FIRST_UNIQUE_ID)
+            }
+            None => FIRST_UNIQUE_ID,
+        };
+
+        let version = self.desc_version();
+        let part_ref = dir.loc.part_ref_nr;
+        let mut next_lbn = pd.part_len;
+        let data_lbn = alloc(&mut next_lbn, len.div_ceil(BLOCKSIZE))?;
+        let fe_lbn = alloc(&mut next_lbn, 1)?;
+        let fe_loc = LBAddr {
+            lbn: fe_lbn,
+            part_ref_nr: part_ref,
+        };
+        fids.push(entry_fid(0, fe_loc, next_id, &name)?);
+        let dir_len: usize = fids.iter().map(Vec::len).sum();
+        let dir_lbn = alloc(&mut next_lbn, dir_len.div_ceil(BS) as u64)?;
+
+        self.io_pos = None;
+        self.io.seek(SeekFrom::Start(
+            (pd.part_start + data_lbn) as u64 * BLOCKSIZE,
+        ))?;
+        copy_exact(data, &mut self.io, len)?;
+        let now = Timestamp::from_system_time(SystemTime::now());
+        let ads = long_ads(len, data_lbn, part_ref)?;
+        let fe = file_entry(
+            version,
+            fe_lbn,
+            EntryKind::File,
+            1,
+            len,
+            next_id,
+            (&now, &mtime),
+            &ads,
+        );
+        write_at(&mut self.io, pd.part_start + fe_lbn, &fe)?;
+        // existing FIDs keep their contents, they only move
+        let mut data = Vec::new();
+        for mut fid in fids.into_iter() {
+            let loc = dir_lbn + (data.len() / BS) as u32;
+            if u16::from_le_bytes([fid[0], fid[1]]) == 0 {
+                let len = 38 + u16::from_le_bytes([fid[36], fid[37]]) as usize + fid[19] as usize;
+                write_tag(&mut fid, 257, version, loc, len);
+            } else {
+                fid[12..16].copy_from_slice(&loc.to_le_bytes());
+                retag(&mut fid);
+            }
+            data.extend(fid);
+        }
+        data.resize(data.len().next_multiple_of(BS), 0);
+        write_at(&mut self.io, pd.part_start + dir_lbn, &data)?;
+
+        // point the parent directory to its new data
+        let dir_lsn = self.lb_to_sector(&dir.loc)?;
+        read_sector(&mut self.io, BLOCKSIZE, dir_lsn, &mut buf)?;
+        if u16::from_le_bytes([buf[0], buf[1]]) != 261 {
+            Err("parent directory isn't recorded in a plain file entry")?
+        }
+        let ea_len = u32::from_le_bytes(buf[168..172].try_into().unwrap()) as usize;
+        let ad_start = FE_AD_OFFSET + ea_len;
+        let ads = long_ads(dir_len as u64, dir_lbn, part_ref)?;
+        if ad_start + ads.len() > BS {
+            Err("allocation descriptors of the parent directory don't fit")?
+        }
+        let flags = u16::from_le_bytes([buf[34], buf[35]]) & !7 | 1;
+        put_u16(&mut buf, 34, flags);
+        put_u64(&mut buf, 56, dir_len as u64);
+        put_u64(&mut buf, 64, dir_len.div_ceil(BS) as u64);
+        put_timestamp(&mut buf[84..96], &now);
+        put_timestamp(&mut buf[96..108], &now);
+        put_u32(&mut buf, 172, ads.len() as u32);
+        buf[ad_start..].fill(0);
+        buf[ad_start..ad_start + ads.len()].copy_from_slice(&ads);
+        let tag_loc = u32::from_le_bytes(buf[12..16].try_into().unwrap());
+        write_tag(&mut buf, 261, version, tag_loc, ad_start + ads.len());
+        write_at(&mut self.io, dir_lsn, &buf)?;
+
+        self.grow_partition(&pd, next_lbn, lvid_lsn, next_id + 1)?;
+        if pd.part_start + next_lbn >= num_sectors.saturating_sub(1) {
+            let avd = read_avd(&mut self.io, &self.options)?;
+            let lsn = pd.part_start + next_lbn;
+            let main = (avd.main_vds.loc, avd.main_vds.len / BS as u32);
+            let reserve = (avd.reserve_vds.loc, avd.reserve_vds.len / BS as u32);
+            write_at(&mut self.io, lsn, &anchor(version, lsn, main, reserve))?;
+        }
+        self.io.flush()?;
+
+        self.invalidate_all();
+        self.root_icb = None;
+        if let Some(map_pd) = self.part_maps[0].pd.as_mut() {
+            map_pd.part_len = next_lbn;
+        }
+        Ok(WriteReport {
+            files: 1,
+            directories: 0,
+            bytes: len,
+        })
+    }
+
+    /// sector of the last descriptor of the integrity sequence, if it is an LVID
+    fn current_lvid_sector(&mut self) -> Result<Option<LSN>, Box<dyn Error>> {
+        let ext = self.logical_vol_desc.integr_seq_ext.clone();
+        let mut buf = [0; BS];
+        let mut last = None;
+        for lsn in ext.loc..ext.loc.saturating_add(ext.len / BS as u32) {
+            read_sector(&mut self.io, BLOCKSIZE, lsn, &mut buf)?;
+            match u16::from_le_bytes([buf[0], buf[1]]) {
+                9 => last = Some(lsn),
+                _ => break,
+            }
+        }
+        Ok(last)
+    }
+
+    /// records the new length `part_len` of the partition in its descriptors and the
+    /// integrity descriptor at `lvid_lsn`, which also gets the next unique ID and one more
+    /// file counted
+    fn grow_partition(
+        &mut self,
+        pd: &crate::volume::PD,
+        part_len: u32,
+        lvid_lsn: Option<LSN>,
+        next_id: u64,
+    ) -> Result<(), Box<dyn Error>> {
+        let avd = read_avd(&mut self.io, &self.options)?;
+        let mut buf = [0; BS];
+        for vds in [&avd.main_vds, &avd.reserve_vds] {
+            for lsn in vds.loc..vds.loc.saturating_add(vds.len / BS as u32) {
+                read_sector(&mut self.io, BLOCKSIZE, lsn, &mut buf)?;
+                let tag_id = u16::from_le_bytes([buf[0], buf[1]]);
+                if tag_id == 8 {
+                    break;
+                }
+                if tag_id == 5 && u16::from_le_bytes([buf[22], buf[23]]) == pd.part_num {
+                    put_u32(&mut buf, 192, part_len);
+                    retag(&mut buf);
+                    write_at(&mut self.io, lsn, &buf)?;
+                }
+            }
+        }
+        let Some(lsn) = lvid_lsn else {
+            warn!("No integrity descriptor to update");
+            return Ok(());
+        };
+        read_sector(&mut self.io, BLOCKSIZE, lsn, &mut buf)?;
+        let num_part = u32::from_le_bytes(buf[72..76].try_into().unwrap()) as usize;
+        let size_tbl = 80 + 4 * num_part;
+        let impl_use = 80 + 8 * num_part;
+        let part_ref = self.part_maps[0].part_ref as usize;
+        if impl_use + 40 > BS || part_ref >= num_part {
+            Err("integrity descriptor doesn't describe the partition")?
+        }
+        put_u64(&mut buf, 40, next_id);
+        put_u32(&mut buf, size_tbl + 4 * part_ref, part_len);
+        let files = u32::from_le_bytes(buf[impl_use + 32..impl_use + 36].try_into().unwrap());
+        put_u32(&mut buf, impl_use + 32, files + 1);
+        retag(&mut buf);
+        write_at(&mut self.io, lsn, &buf)?;
+        Ok(())
+    }
+
+    /// zero-fills the slack of every file and directory and every block of the partitions
+    /// that is neither in use nor marked as allocated, so that no remnants of deleted data
+    /// are left, e.g. before publishing an image. Blocks that are allocated though nothing
+    /// refers to them are kept, see `orphan_blocks`, and so are all blocks of partitions
+    /// without a space bitmap or table, which don't record what is free. Volumes that fail
+    /// the `preflight` check are refused unless `force` is set, as their unreachable files
+    /// would be wiped.
+    pub fn scrub(&mut self, force: bool) -> Result<ScrubReport, Box<dyn Error>> {
+        if self.vat.is_some() {
+            Err("volumes with a virtual allocation table can't be modified in place")?
+        }
+        if self.truncation.is_some() {
+            Err("a truncated image can't be scrubbed")?
+        }
+        let problems = self.preflight()?;
+        if !problems.is_empty() {
+            if !force {
+                Err(format!(
+                    "the volume looks damaged, refusing to modify it: {}",
+                    problems.join(", ")
+                ))?
+            }
+            for problem in problems {
+                warn!("Scrubbing a damaged volume: {}", problem);
+            }
+        }
+        self.warn_if_protected();
+        let mut report = ScrubReport::default();
+        let mut ranges = Vec::new();
+        for node in self.tree_snapshot()?.nodes {
+            let icb = self.read_icb(&node.icb)?;
+            for range in icb.slack_ranges(self)? {
+                report.slack_bytes += range.end - range.start;
+                ranges.push(range);
+            }
+        }
+        let sector_size = self.sector_size();
+        let block_sectors = self.block_sectors() as u64;
+        for unused in self.unused_blocks()? {
+            for blocks in unused.blocks {
+                let sector = |lbn: u32| unused.part_start as u64 + lbn as u64 * block_sectors;
+                let range = sector(blocks.start) * sector_size..sector(blocks.end) * sector_size;
+                report.unallocated_bytes += range.end - range.start;
+                ranges.push(range);
+            }
+        }
+        let mut buf = vec![0; BS];
+        for range in ranges {
+            let mut pos = range.start;
+            while pos < range.end {
+                let len = (range.end - pos).min(BS as u64) as usize;
+                self.io.seek(SeekFrom::Start(pos))?;
+                self.io.read_exact(&mut buf[..len])?;
+                // blocks that are zero already aren't rewritten
+                if buf[..len].iter().any(|&b| b != 0) {
+                    buf[..len].fill(0);
+                    self.io.seek(SeekFrom::Start(pos))?;
+                    self.io.write_all(&buf[..len])?;
+                }
+                pos += len as u64;
+            }
+        }
+        self.io.flush()?;
+        self.invalidate_all();
+        Ok(report)
+    }
+}
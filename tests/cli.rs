@@ -0,0 +1,143 @@
+/*
+    Tests of the udf command line tool, run as a separate process on images in `tests`.
+*/
+
+use std::error::Error;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+/// sector of the logical volume integrity descriptor of test.iso
+const LVID: usize = 64;
+
+/// runs the udf command line tool with `args`, feeding it `input` on stdin
+fn udf(args: &[&str], input: &str) -> Result<Output, Box<dyn Error>> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_udf"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    child.stdin.take().unwrap().write_all(input.as_bytes())?;
+    Ok(child.wait_with_output()?)
+}
+
+/// writes `data` to a temporary image named after `name`
+fn temp_image(name: &str, data: &[u8]) -> Result<PathBuf, Box<dyn Error>> {
+    let path = std::env::temp_dir().join(format!("udf-cli-{}-{}.iso", name, std::process::id()));
+    std::fs::write(&path, data)?;
+    Ok(path)
+}
+
+/// recomputes the CRC and the checksum of the descriptor tag at the start of `desc`
+fn retag(desc: &mut [u8]) {
+    let len = 16 + u16::from_le_bytes([desc[10], desc[11]]) as usize;
+    let mut crc = 0u16;
+    for &b in &desc[16..len] {
+        crc ^= (b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    desc[8..10].copy_from_slice(&crc.to_le_bytes());
+    desc[4] = (0..16)
+        .filter(|&i| i != 4)
+        .fold(0u8, |sum, i| sum.wrapping_add(desc[i]));
+}
+
+/// runs `udf verify` with `args` on `image`, returning the exit code and the output
+fn verify(args: &[&str], image: &str) -> Result<(i32, String), Box<dyn Error>> {
+    let output = udf(&[&["verify"], args, &[image]].concat(), "")?;
+    let code = output.status.code().ok_or("udf was killed")?;
+    Ok((code, String::from_utf8(output.stdout)?))
+}
+
+#[test]
+fn verify_clean() -> Result<(), Box<dyn Error>> {
+    let (code, report) = verify(&["--json"], "tests/test.iso")?;
+    assert_eq!(code, 0);
+    assert_eq!(
+        report,
+        "{\"status\":\"clean\",\"dirs\":1,\"files\":1,\"bytes\":1069,\
+         \"warnings\":[],\"errors\":[]}\n"
+    );
+    let (code, report) = verify(&["--level=strict"], "tests/test.iso")?;
+    assert_eq!(code, 0);
+    assert!(report.ends_with("0 warnings, 0 errors: clean\n"));
+    Ok(())
+}
+
+#[test]
+fn verify_warnings() -> Result<(), Box<dyn Error>> {
+    // an open integrity descriptor is only a warning, except at level strict
+    let mut image = std::fs::read("tests/test.iso")?;
+    let lvid = &mut image[LVID * 2048..(LVID + 1) * 2048];
+    lvid[28..32].copy_from_slice(&0u32.to_le_bytes());
+    retag(lvid);
+    let path = temp_image("open", &image)?;
+    let path = path.to_str().unwrap();
+    let json = verify(&["--json"], path);
+    let strict = verify(&["--level=strict"], path);
+    std::fs::remove_file(path)?;
+
+    let (code, report) = json?;
+    assert_eq!(code, 1);
+    assert_eq!(
+        report,
+        "{\"status\":\"warnings\",\"dirs\":1,\"files\":1,\"bytes\":1069,\
+         \"warnings\":[\"logical volume integrity descriptor is open\"],\"errors\":[]}\n"
+    );
+    let (code, report) = strict?;
+    assert_eq!(code, 2);
+    assert!(report.starts_with("warning: logical volume integrity descriptor is open\n"));
+    assert!(report.ends_with("1 warnings, 0 errors: errors\n"));
+    Ok(())
+}
+
+#[test]
+fn verify_errors() -> Result<(), Box<dyn Error>> {
+    // the data of LICENSE.md starts in sector 268
+    let image = std::fs::read("tests/test.iso")?;
+    let path = temp_image("truncated", &image[..262 * 2048])?;
+    let path = path.to_str().unwrap();
+    let json = verify(&["--json"], path);
+    let text = verify(&[], path);
+    std::fs::remove_file(path)?;
+
+    let (code, report) = json?;
+    assert_eq!(code, 2);
+    assert!(report.starts_with("{\"status\":\"errors\",\"dirs\":1,\"files\":1,\"bytes\":0,"));
+    assert!(report.ends_with(
+        "\"errors\":[{\"path\":\"/LICENSE.md\",\
+         \"error\":\"data lies beyond the end of the truncated image\"}]}\n"
+    ));
+    let (code, report) = text?;
+    assert_eq!(code, 2);
+    assert!(
+        report.contains("error: /LICENSE.md: data lies beyond the end of the truncated image\n")
+    );
+
+    // images that can't be opened at all fail the command
+    let (code, report) = verify(&[], "tests/missing.iso")?;
+    assert_eq!((code, report.as_str()), (3, ""));
+    Ok(())
+}
+
+#[test]
+fn shell_parent_dirs() -> Result<(), Box<dyn Error>> {
+    // `..` is followed on the volume by every command, a file has no parent directory to go
+    // back to
+    let input = "stat LICENSE.md/..\ncd LICENSE.md/..\nstat ./LICENSE.md\npwd\n";
+    let output = udf(&["shell", "tests/test.iso"], input)?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    let stderr = String::from_utf8(output.stderr)?;
+    assert_eq!(stderr.matches("not a directory").count(), 2);
+    assert!(stdout.contains("Path:     /LICENSE.md\n"));
+    assert!(stdout.ends_with("/> /\n/> \n"));
+    Ok(())
+}